@@ -1,27 +1,57 @@
+/// Whether the `rust-backend` or `nusb-backend` feature replaces the C
+/// library
+fn rust_backend() -> bool {
+    std::env::var_os("CARGO_FEATURE_RUST_BACKEND").is_some()
+        || std::env::var_os("CARGO_FEATURE_NUSB_BACKEND").is_some()
+}
+
 #[cfg(not(target_env = "msvc"))]
 fn main() {
+    if rust_backend() {
+        return;
+    }
     println!("cargo:rerun-if-changed=src/SiUSBXp.c");
+    println!("cargo:rerun-if-env-changed=LIBUSB_DIR");
+    println!("cargo:rerun-if-env-changed=LIBUSB_STATIC");
 
-    let mut config = pkg_config::Config::new();
-    config.print_system_libs(false);
     let mut gcc = cc::Build::new();
+    let statik = link_statically();
 
-    match config.find("libusb") {
-        Ok(lib) => lib.include_paths.iter().for_each(|include| {
-            gcc.include(include);
-        }),
-        Err(e) => {
-            panic!("run pkg_config fail: {:?}", e);
-        }
-    };
+    if let Some(dir) = std::env::var_os("LIBUSB_DIR") {
+        // a libusb prebuilt for the target, e.g. in a cross sysroot, which
+        // needs no pkg-config at all
+        link_prefix(&mut gcc, std::path::Path::new(&dir), statik);
+    } else {
+        let mut config = pkg_config::Config::new();
+        config.print_system_libs(false).statik(statik);
 
-    // nowadays libusb of most OS use libusb-1.0 as backend
-    match config.find("libusb-1.0") {
-        Ok(lib) => lib.include_paths.iter().for_each(|include| {
-            gcc.include(include);
-        }),
-        Err(_e) => {}
-    };
+        match config.find("libusb").or_else(|_| config.find("libusb-0.1")) {
+            Ok(lib) => {
+                // reported by `version::library_version`
+                println!("cargo:rustc-env=SIUSBXP_LIBUSB_VERSION={}", lib.version);
+                lib.include_paths.iter().for_each(|include| {
+                    gcc.include(include);
+                })
+            }
+            Err(e) => match find_in_prefixes() {
+                Some(prefix) => link_prefix(&mut gcc, &prefix, statik),
+                None => panic!("run pkg_config fail: {:?}", e),
+            },
+        };
+
+        // nowadays libusb of most OS use libusb-1.0 as backend
+        match config.find("libusb-1.0") {
+            Ok(lib) => lib.include_paths.iter().for_each(|include| {
+                gcc.include(include);
+            }),
+            Err(_e) => {}
+        };
+    }
+
+    if target_os() == "windows" && !has_lusb0_header() {
+        // MinGW builds against libusb-compat unless pointed at libusb-win32
+        gcc.define("SIUSBXP_USB_H", None);
+    }
 
     gcc.file("src/SiUSBXp.c")
         .flag("-Wno-unused-parameter")
@@ -29,8 +59,77 @@ fn main() {
         .compile("SiUSBXp");
 }
 
+#[cfg(not(target_env = "msvc"))]
+fn target_os() -> String {
+    std::env::var("CARGO_CFG_TARGET_OS").unwrap_or_default()
+}
+
+/// Whether `LIBUSB_DIR` holds libusb-win32, whose header is `lusb0_usb.h`
+#[cfg(not(target_env = "msvc"))]
+fn has_lusb0_header() -> bool {
+    std::env::var_os("LIBUSB_DIR").is_some_and(|dir| {
+        std::path::Path::new(&dir)
+            .join("include/lusb0_usb.h")
+            .is_file()
+    })
+}
+
+/// Whether to link libusb statically
+///
+/// `LIBUSB_STATIC` decides if set; otherwise musl targets link statically,
+/// since they are mostly built to produce self-contained binaries.
+#[cfg(not(target_env = "msvc"))]
+fn link_statically() -> bool {
+    match std::env::var("LIBUSB_STATIC") {
+        Ok(value) => value != "0",
+        Err(_) => std::env::var("CARGO_CFG_TARGET_ENV").is_ok_and(|env| env == "musl"),
+    }
+}
+
+/// Links the libusb installed under `prefix`
+#[cfg(not(target_env = "msvc"))]
+fn link_prefix(gcc: &mut cc::Build, prefix: &std::path::Path, statik: bool) {
+    gcc.include(prefix.join("include"));
+    println!(
+        "cargo:rustc-link-search=native={}",
+        prefix.join("lib").display()
+    );
+    let kind = if statik { "static=" } else { "" };
+    println!("cargo:rustc-link-lib={}usb", kind);
+    // libusb-compat forwards to libusb-1.0, which a static link has to name
+    if statik && prefix.join("lib/libusb-1.0.a").is_file() {
+        println!("cargo:rustc-link-lib=static=usb-1.0");
+    }
+}
+
+/// Looks for libusb-compat where the target's package managers install it
+///
+/// Homebrew and MacPorts install `libusb.pc`, but their pkgconfig directory
+/// is often missing from `PKG_CONFIG_PATH`, e.g. in IDE builds. FreeBSD ships
+/// the 0.1 API in base as `libusb-0.1.pc`, or without a `.pc` file at all on
+/// older releases, and the other BSDs install `libusb-compat` under their
+/// ports prefix.
+#[cfg(not(target_env = "msvc"))]
+fn find_in_prefixes() -> Option<std::path::PathBuf> {
+    let prefixes: &[&str] = match target_os().as_str() {
+        // Homebrew on Apple silicon, Homebrew on Intel, MacPorts
+        "macos" => &["/opt/homebrew", "/usr/local", "/opt/local"],
+        "freebsd" | "dragonfly" => &["/usr"],
+        "openbsd" => &["/usr/local"],
+        "netbsd" => &["/usr/pkg"],
+        _ => return None,
+    };
+    prefixes
+        .iter()
+        .map(std::path::PathBuf::from)
+        .find(|prefix| prefix.join("include/usb.h").is_file())
+}
+
 #[cfg(target_env = "msvc")]
 fn main() {
+    if rust_backend() {
+        return;
+    }
     println!("cargo:rerun-if-changed=src/SiUSBXp.c");
     if std::env::var_os("VCPKGRS_DYNAMIC").is_none() {
         std::env::set_var("VCPKGRS_DYNAMIC", "1");
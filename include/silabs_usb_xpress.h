@@ -0,0 +1,58 @@
+/*
+ * C API of the silabs_usb_xpress crate, built with the `capi` feature.
+ *
+ * Keep in sync with src/capi.rs.
+ */
+
+#ifndef SILABS_USB_XPRESS_H
+#define SILABS_USB_XPRESS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Status codes, identical to those of SiUSBXp.dll */
+#define SIXP_SUCCESS 0x00
+#define SIXP_DEVICE_NOT_FOUND 0xFF
+#define SIXP_INVALID_HANDLE 0x01
+#define SIXP_READ_ERROR 0x02
+#define SIXP_WRITE_ERROR 0x04
+#define SIXP_INVALID_PARAMETER 0x06
+#define SIXP_INVALID_REQUEST_LENGTH 0x07
+#define SIXP_DEVICE_IO_FAILED 0x08
+#define SIXP_INVALID_BAUDRATE 0x09
+#define SIXP_GLOBAL_DATA_ERROR 0x0b
+#define SIXP_SYSTEM_ERROR_CODE 0x0c
+#define SIXP_READ_TIMED_OUT 0x0d
+#define SIXP_WRITE_TIMED_OUT 0x0e
+#define SIXP_IO_PENDING 0x0f
+
+/* sixp_product_string() flags */
+#define SIXP_RETURN_SERIAL_NUMBER 0x00
+#define SIXP_RETURN_DESCRIPTION 0x01
+#define SIXP_RETURN_LINK_NAME 0x02
+#define SIXP_RETURN_VID 0x03
+#define SIXP_RETURN_PID 0x04
+
+typedef struct SixpHandle SixpHandle;
+
+int sixp_devices_count(size_t *count);
+
+int sixp_product_string(size_t index, int flags, char *buffer, size_t len);
+
+int sixp_open(size_t index, SixpHandle **handle);
+
+int sixp_read(SixpHandle *handle, uint8_t *buffer, size_t len, size_t *read);
+
+int sixp_write(SixpHandle *handle, const uint8_t *buffer, size_t len, size_t *written);
+
+int sixp_close(SixpHandle *handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* SILABS_USB_XPRESS_H */
@@ -50,12 +50,22 @@
 #include <errno.h>
 
 #if defined(_WIN32) || defined(WIN32) 
+#ifdef SIUSBXP_USB_H
+/*libusb-compat, e.g. from MSYS2*/
+#include <usb.h>
+#else
 #include <lusb0_usb.h>
+#endif
 #else
 #include <unistd.h>
 #include <usb.h>
 #endif
 
+#if defined(__linux__)
+#include <dirent.h>
+#include <sys/stat.h>
+#endif
+
 
 /*Vendor ID / Product ID*/
 #define SI_USB_VID 0x10c4
@@ -80,6 +90,16 @@
 #define SI_WRITE_TIMED_OUT 0x0e
 #define SI_IO_PENDING 0x0f
 
+/*Status codes of this library only*/
+#define SI_DRIVER_DETACH_FAILED 0x10
+#define SI_DEVICE_REENUMERATED 0x11
+#define SI_IO_CANCELLED 0x12
+
+/*SI_OpenEx() flags*/
+#define SI_OPEN_DETACH_KERNEL_DRIVER 0x01
+/*libusb 0.1 cannot reattach drivers, they are bound again through sysfs*/
+#define SI_OPEN_REATTACH_KERNEL_DRIVER 0x02
+
 /*GetProductString() function flags*/
 #define SI_RETURN_SERIAL_NUMBER 0x00
 #define SI_RETURN_DESCRIPTION 0x01
@@ -87,18 +107,32 @@
 #define SI_RETURN_VID 0x03
 #define SI_RETURN_PID 0x04
 
+/*GetDeviceDetails() capability flags*/
+#define SI_CAP_BULK_IN 0x01
+#define SI_CAP_BULK_OUT 0x02
+#define SI_CAP_INTERRUPT_IN 0x04
+
 /*RX Queue status flags*/
 #define SI_RX_NO_OVERRUN 0x00
 #define SI_RX_EMPTY 0x00
 #define SI_RX_OVERRUN 0x01
 #define SI_RX_READY 0x02
 
+/*CP210x vendor request reading the part number*/
+#define CP210X_VENDOR_SPECIFIC 0xFF
+#define CP210X_GET_PARTNUM 0x370B
+
+/*CP210x vendor request clearing the UART queues*/
+#define CP210X_PURGE 0x12
+#define CP210X_PURGE_TX 0x0005
+#define CP210X_PURGE_RX 0x000A
+
 /*Buffer size limits*/
 #define SI_MAX_DEVICE_STRLEN 256
 #define SI_MAX_READ_SIZE 4096 * 16
 #define SI_MAX_WRITE_SIZE 4096
 
-#if defined(_WIN32) || defined(WIN32) 
+#if defined(_MSC_VER)
     #define ERR(format, ...) fprintf (stderr, format, __VA_ARGS__)
     #ifdef DEBUG
         #define DBG(format, ...) fprintf (stderr, format, __VA_ARGS__)
@@ -116,7 +150,15 @@
 
 #define MAGIC 12939485
 #define BUF_SIZE 4096
+/*Longest a transfer waits on the device between checks for SI_CancelIo()*/
+#define CANCEL_POLL 50
+
+/*Vendor ID / Product ID pairs whose strings are read, SI_USB_VID/PID first*/
+#define SI_MAX_DEVICE_IDS 32
+int DeviceIds[SI_MAX_DEVICE_IDS][2] = {{SI_USB_VID, SI_USB_PID}};
+int NumDeviceIds = 1;
 
+/*Timeouts given to handles opened by SI_Open()/SI_OpenInterface(), SI_OpenEx() takes its own*/
 int RXTimeout = 1000;
 int TXTimeout = 1000;
 
@@ -131,6 +173,16 @@ struct SI_Private {
     int ep_in;
     int bufsize;
     char buffer[BUF_SIZE];
+    /*Kernel driver detached by SI_OpenEx(), bound again by SI_Close()*/
+    int reattach;
+    char driver[SI_MAX_DEVICE_STRLEN];
+    /*Used by SI_Read()/SI_Write() and the control requests of this handle*/
+    int rxtimeout;
+    int txtimeout;
+    /*Set by SI_CancelIo(), possibly from another thread*/
+    volatile int cancelled;
+    /*Descriptor libusb opened the usbfs node with, -1 if unknown*/
+    int fd;
 };
 
 void init(void) {
@@ -142,6 +194,44 @@ void init(void) {
     }
 }
 
+int is_si_device(struct usb_device *dev) {
+    int i;
+    for (i = 0; i < NumDeviceIds; i++) {
+        if (dev->descriptor.idVendor == DeviceIds[i][0] && dev->descriptor.idProduct == DeviceIds[i][1])
+            return 1;
+    }
+    return 0;
+}
+
+int SI_AddDeviceId(int Vid, int Pid) {
+    int i;
+    DBG("SI_AddDeviceId(Vid=0x%04X, Pid=0x%04X)\n", Vid, Pid);
+
+    if (Vid < 0 || Vid > 0xFFFF || Pid < 0 || Pid > 0xFFFF)
+        return SI_INVALID_PARAMETER;
+    for (i = 0; i < NumDeviceIds; i++) {
+        if (DeviceIds[i][0] == Vid && DeviceIds[i][1] == Pid)
+            return SI_SUCCESS;
+    }
+    if (NumDeviceIds == SI_MAX_DEVICE_IDS)
+        return SI_GLOBAL_DATA_ERROR;
+
+    DeviceIds[NumDeviceIds][0] = Vid;
+    DeviceIds[NumDeviceIds][1] = Pid;
+    NumDeviceIds++;
+
+    return SI_SUCCESS;
+}
+
+int SI_ClearDeviceIds(void) {
+    DBG("SI_ClearDeviceIds()\n");
+
+    /*SI_USB_VID/PID stays*/
+    NumDeviceIds = 1;
+
+    return SI_SUCCESS;
+}
+
 int SI_GetNumDevices(int *NumDevices) {
     struct usb_bus *bus;
     struct usb_device *dev;
@@ -220,8 +310,7 @@ int SI_GetProductString(int DeviceNum, char *DeviceString, int Flags) {
                 return SI_INVALID_PARAMETER;
         };
         DBG("  Vendor=0x%04X Product=0x%04X\n", pdev->descriptor.idVendor, pdev->descriptor.idProduct);
-        if (pdev->descriptor.idVendor == SI_USB_VID &&
-            pdev->descriptor.idProduct == SI_USB_PID) {
+        if (is_si_device(pdev)) {
             DBG("  Device Found!\n");
 
             udev = usb_open(pdev);
@@ -247,7 +336,51 @@ int SI_GetProductString(int DeviceNum, char *DeviceString, int Flags) {
     return SI_SUCCESS;
 }
 
-static void SI_FillBuffer(struct SI_Private *Handle, int timeout) {
+int SI_GetDeviceNumByPath(const char *Path, int *DeviceNum) {
+    struct usb_bus *bus;
+    struct usb_device *dev;
+    const char *dirname, *filename;
+    size_t dirlen;
+    int devcount;
+
+    DBG("SI_GetDeviceNumByPath(Path=%s, DeviceNum=%p)\n", Path, DeviceNum);
+    init();
+
+    if (Path == NULL || DeviceNum == NULL)
+        return SI_INVALID_PARAMETER;
+
+    /*The last two components name the bus and the device, e.g. /dev/bus/usb/001/004*/
+    filename = strrchr(Path, '/');
+    if (filename == NULL || filename == Path)
+        return SI_INVALID_PARAMETER;
+    dirname = filename;
+    while (dirname > Path && *(dirname - 1) != '/')
+        dirname--;
+    dirlen = filename - dirname;
+    filename++;
+
+    usb_find_busses();
+    usb_find_devices();
+
+    busses = usb_get_busses();
+
+    devcount = 0;
+    for (bus = busses; bus; bus = bus->next) {
+        for (dev = bus->devices; dev; dev = dev->next) {
+            if (strlen(bus->dirname) == dirlen && strncmp(bus->dirname, dirname, dirlen) == 0 &&
+                strcmp(dev->filename, filename) == 0) {
+                *DeviceNum = devcount;
+                DBG("  DeviceNum=%i\n", *DeviceNum);
+                return SI_SUCCESS;
+            }
+            devcount++;
+        }
+    }
+
+    return SI_DEVICE_NOT_FOUND;
+}
+
+static int SI_FillBuffer(struct SI_Private *Handle, int timeout) {
     int bytestoread, nread;
     bytestoread = BUF_SIZE - Handle->bufsize;
     DBG("  SI_FillBuffer BytesToRead=%i\n", bytestoread);
@@ -257,6 +390,35 @@ static void SI_FillBuffer(struct SI_Private *Handle, int timeout) {
         Handle->bufsize += nread;
     }
     DBG("  SI_FillBuffer Handle->bufsize=%i\n", Handle->bufsize);
+    return nread;
+}
+
+/*Returns whether SI_CancelIo() was called since the last check, clearing it*/
+static int SI_TakeCancel(struct SI_Private *Handle) {
+    if (!Handle->cancelled)
+        return 0;
+    Handle->cancelled = 0;
+    return 1;
+}
+
+/*Length of the next wait on the device, at most CANCEL_POLL. A Timeout of 0 waits forever*/
+static int SI_Slice(int Timeout) {
+    return (Timeout <= 0 || Timeout > CANCEL_POLL) ? CANCEL_POLL : Timeout;
+}
+
+/*Like SI_FillBuffer(), but waits in slices, returning -1 once SI_CancelIo() is seen*/
+static int SI_WaitBuffer(struct SI_Private *Handle, int Timeout) {
+    int forever = Timeout <= 0;
+    int slice;
+    do {
+        if (SI_TakeCancel(Handle))
+            return -1;
+        slice = SI_Slice(Timeout);
+        if (SI_FillBuffer(Handle, slice) != -ETIMEDOUT)
+            break;
+        Timeout -= slice;
+    } while (forever || Timeout > 0);
+    return 0;
 }
 
 static int SI_GetBuffer(struct SI_Private *Handle, char *Buffer, int BytesToGet) {
@@ -280,16 +442,165 @@ static int SI_GetBuffer(struct SI_Private *Handle, char *Buffer, int BytesToGet)
     return retval;
 }
 
-int SI_Open(int DeviceNum, struct SI_Private **pHandle) {
+#if defined(__linux__)
+/*Reads the number in attribute Attribute of USB device Device in sysfs*/
+static int read_sysfs_int(const char *Device, const char *Attribute, int *Value) {
+    char path[512];
+    FILE *f;
+    int ret;
+
+    snprintf(path, sizeof(path), "/sys/bus/usb/devices/%s/%s", Device, Attribute);
+    f = fopen(path, "r");
+    if (f == NULL)
+        return -1;
+    ret = fscanf(f, "%d", Value) == 1 ? 0 : -1;
+    fclose(f);
+    return ret;
+}
+
+/*Finds the sysfs name of interface Interface of dev, like 1-1.2:1.0*/
+static int sysfs_interface_name(struct usb_device *dev, int Interface, char *Name, size_t Length) {
+    DIR *dir;
+    struct dirent *entry;
+    int busnum = atoi(dev->bus->dirname);
+    int devnum = atoi(dev->filename);
+    int ret = -1;
+
+    dir = opendir("/sys/bus/usb/devices");
+    if (dir == NULL)
+        return -1;
+    while (ret < 0 && (entry = readdir(dir)) != NULL) {
+        int bus, num, config;
+        /*Interfaces have a colon in their name, devices do not*/
+        if (entry->d_name[0] == '.' || strchr(entry->d_name, ':') != NULL)
+            continue;
+        if (read_sysfs_int(entry->d_name, "busnum", &bus) || read_sysfs_int(entry->d_name, "devnum", &num))
+            continue;
+        if (bus != busnum || num != devnum)
+            continue;
+        if (read_sysfs_int(entry->d_name, "bConfigurationValue", &config))
+            continue;
+        snprintf(Name, Length, "%s:%d.%d", entry->d_name, config, Interface);
+        ret = 0;
+    }
+    closedir(dir);
+    return ret;
+}
+#endif
+
+#if defined(__linux__)
+/*Maximum number of descriptors of one usbfs node told apart by SI_OpenEx()*/
+#define MAX_NODE_FDS 16
+
+/*Reads the device number of the usbfs node of dev*/
+static int node_rdev(struct usb_device *dev, dev_t *Rdev) {
+    char path[64];
+    struct stat st;
+
+    snprintf(path, sizeof(path), "/dev/bus/usb/%03d/%03d", atoi(dev->bus->dirname), atoi(dev->filename));
+    if (stat(path, &st) < 0 || !S_ISCHR(st.st_mode))
+        return -1;
+    *Rdev = st.st_rdev;
+    return 0;
+}
+
+/*Returns whether Fd is open on the usbfs node Rdev*/
+static int is_node_fd(int Fd, dev_t Rdev) {
+    struct stat st;
+
+    return Fd >= 0 && fstat(Fd, &st) == 0 && S_ISCHR(st.st_mode) && st.st_rdev == Rdev;
+}
+
+/*Lists up to Max descriptors of this process open on the usbfs node Rdev*/
+static int node_fds(dev_t Rdev, int *Fds, int Max) {
+    DIR *dir;
+    struct dirent *entry;
+    int count = 0;
+
+    dir = opendir("/proc/self/fd");
+    if (dir == NULL)
+        return 0;
+    while (count < Max && (entry = readdir(dir)) != NULL) {
+        int fd;
+        if (entry->d_name[0] == '.')
+            continue;
+        fd = atoi(entry->d_name);
+        if (fd != dirfd(dir) && is_node_fd(fd, Rdev))
+            Fds[count++] = fd;
+    }
+    closedir(dir);
+    return count;
+}
+
+static int contains_fd(const int *Fds, int Count, int Fd) {
+    int i;
+
+    for (i = 0; i < Count; i++)
+        if (Fds[i] == Fd)
+            return 1;
+    return 0;
+}
+
+/*Finds the descriptor usb_open() opened the node Rdev with, given the ones open before it*/
+static int opened_fd(usb_dev_handle *udev, dev_t Rdev, const int *Before, int NumBefore) {
+    int after[MAX_NODE_FDS];
+    int count, i;
+    /*libusb 0.1 keeps it first in usb_dev_handle*/
+    int fd = *(int *) udev;
+
+    if (is_node_fd(fd, Rdev) && !contains_fd(Before, NumBefore, fd))
+        return fd;
+    /*libusb-compat leaves it to libusb-1.0, it is the one that was not open before*/
+    count = node_fds(Rdev, after, MAX_NODE_FDS);
+    for (i = 0; i < count; i++)
+        if (!contains_fd(Before, NumBefore, after[i]))
+            return after[i];
+    return -1;
+}
+#endif
+
+/*Binds the kernel driver detached by SI_OpenEx() to the released interface again*/
+static void reattach_kernel_driver(struct SI_Private *Handle) {
+#if defined(__linux__)
+    char name[SI_MAX_DEVICE_STRLEN];
+    char path[512];
+    FILE *f;
+
+    /*libusb 0.1 has no call for this, the driver is bound through sysfs*/
+    if (sysfs_interface_name(usb_device(Handle->udev), Handle->interface, name, sizeof(name)) == 0) {
+        snprintf(path, sizeof(path), "/sys/bus/usb/drivers/%s/bind", Handle->driver);
+        f = fopen(path, "w");
+        if (f != NULL) {
+            int written = fputs(name, f) >= 0;
+            /*sysfs reports a failed bind when the file is closed*/
+            if (fclose(f) == 0 && written) {
+                DBG("  Reattached kernel driver \"%s\" to %s\n", Handle->driver, name);
+                return;
+            }
+        }
+    }
+    /*Binding needs root; a port reset makes the kernel probe the device again instead*/
+    if (usb_reset(Handle->udev) == 0) {
+        DBG("  Reset the device to reattach kernel driver \"%s\"\n", Handle->driver);
+        return;
+    }
+#endif
+    ERR("  **ERROR** Unable to reattach kernel driver \"%s\"\n", Handle->driver);
+}
+
+int SI_OpenEx(int DeviceNum, int Interface, int Flags, int ReadTimeout, int WriteTimeout,
+              struct SI_Private **pHandle) {
     struct usb_bus *bus;
     struct usb_device *dev, *pdev;
     struct SI_Private *Handle;
+    struct usb_interface_descriptor *altsetting;
     int devcount;
     int i;
-    DBG("SI_Open(DeviceNum=%i, pHandle=%p)\n", DeviceNum, pHandle);
+    DBG("SI_OpenEx(DeviceNum=%i, Interface=%i, Flags=%i, ReadTimeout=%i, WriteTimeout=%i, pHandle=%p)\n", DeviceNum,
+        Interface, Flags, ReadTimeout, WriteTimeout, pHandle);
     init();
 
-    if (pHandle == NULL)
+    if (pHandle == NULL || ReadTimeout < 0 || WriteTimeout < 0)
         return SI_INVALID_PARAMETER;
 
     /*Find the device*/
@@ -303,22 +614,37 @@ int SI_Open(int DeviceNum, struct SI_Private **pHandle) {
         }
     }
 
+    /*Interfaces of the first configuration, one per UART on CP2105/CP2108*/
+    if (pdev != NULL && (Interface < 0 || pdev->config == NULL || Interface >= pdev->config[0].bNumInterfaces ||
+                         pdev->config[0].interface[Interface].num_altsetting < 1))
+        return SI_INVALID_PARAMETER;
+
     Handle = NULL;
+    altsetting = NULL;
     if (pdev != NULL) {
         Handle = (struct SI_Private *) malloc(sizeof(struct SI_Private));
+        altsetting = &pdev->config[0].interface[Interface].altsetting[0];
+    }
+    if (Handle != NULL) {
+        Handle->reattach = 0;
+        Handle->driver[0] = '\0';
+        Handle->rxtimeout = ReadTimeout;
+        Handle->txtimeout = WriteTimeout;
+        Handle->cancelled = 0;
+        Handle->fd = -1;
     }
 
     /*Find the bulk in/out endpoints*/
     if (Handle != NULL) {
         Handle->ep_out = -1;
         Handle->ep_in = -1;
-        for (i = 0; i < pdev->config[0].interface[0].altsetting[0].bNumEndpoints; i++) {
-            if (pdev->config[0].interface[0].altsetting[0].endpoint[i].bmAttributes == USB_ENDPOINT_TYPE_BULK) {
-                if ((pdev->config[0].interface[0].altsetting[0].endpoint[i].bEndpointAddress & USB_ENDPOINT_DIR_MASK) !=
+        for (i = 0; i < altsetting->bNumEndpoints; i++) {
+            if (altsetting->endpoint[i].bmAttributes == USB_ENDPOINT_TYPE_BULK) {
+                if ((altsetting->endpoint[i].bEndpointAddress & USB_ENDPOINT_DIR_MASK) !=
                     0) {
-                    Handle->ep_in = pdev->config[0].interface[0].altsetting[0].endpoint[i].bEndpointAddress;
+                    Handle->ep_in = altsetting->endpoint[i].bEndpointAddress;
                 } else {
-                    Handle->ep_out = pdev->config[0].interface[0].altsetting[0].endpoint[i].bEndpointAddress;
+                    Handle->ep_out = altsetting->endpoint[i].bEndpointAddress;
                 }
             }
         }
@@ -331,34 +657,80 @@ int SI_Open(int DeviceNum, struct SI_Private **pHandle) {
     }
 
     if (Handle != NULL) {
+#if defined(__linux__)
+        int before[MAX_NODE_FDS];
+        int numbefore = 0;
+        dev_t rdev;
+        int known = node_rdev(pdev, &rdev) == 0;
+        if (known)
+            numbefore = node_fds(rdev, before, MAX_NODE_FDS);
+#endif
         Handle->udev = usb_open(pdev);
         if (Handle->udev == NULL) {
             free(Handle);
             Handle = NULL;
             ERR("  **ERROR** Unable to open USB device\n");
         }
+#if defined(__linux__)
+        else if (known) {
+            Handle->fd = opened_fd(Handle->udev, rdev, before, numbefore);
+        }
+#endif
+    }
+
+    /*Detach a kernel driver bound to the interface, like cp210x on Linux*/
+    if (Handle != NULL) {
+        Handle->interface = altsetting->bInterfaceNumber;
+#if defined(LIBUSB_HAS_GET_DRIVER_NP) && defined(LIBUSB_HAS_DETACH_KERNEL_DRIVER_NP)
+        if (Flags & SI_OPEN_DETACH_KERNEL_DRIVER) {
+            char *driver = Handle->driver;
+            /*usbfs means another libusb user, which is left alone*/
+            if (usb_get_driver_np(Handle->udev, Handle->interface, driver, SI_MAX_DEVICE_STRLEN) == 0
+                    && strcmp(driver, "usbfs") != 0) {
+                DBG("  Detaching kernel driver \"%s\"\n", driver);
+                if (usb_detach_kernel_driver_np(Handle->udev, Handle->interface) < 0) {
+                    usb_close(Handle->udev);
+                    ERR("  **ERROR** Unable to detach kernel driver \"%s\"\n", driver);
+                    free(Handle);
+                    return SI_DRIVER_DETACH_FAILED;
+                }
+                Handle->reattach = (Flags & SI_OPEN_REATTACH_KERNEL_DRIVER) != 0;
+            }
+        }
+#endif
     }
 
     /*Claim the interface*/
     if (Handle != NULL) {
-        Handle->interface = pdev->config[0].interface[0].altsetting[0].bInterfaceNumber;
         if (usb_claim_interface(Handle->udev, Handle->interface)) {
+            if (Handle->reattach)
+                reattach_kernel_driver(Handle);
             usb_close(Handle->udev);
             free(Handle);
-            Handle = NULL;
+#if defined(__APPLE__)
+            ERR("  **ERROR** Unable to claim interface. On macOS the CP210x driver (Apple's or the SiLabs VCP driver) claims the device; unload it or use the serial port instead.\n");
+#elif defined(__FreeBSD__) || defined(__DragonFly__)
+            ERR("  **ERROR** Unable to claim interface. Ensure the uslcom driver is not attached (kldunload uslcom). Check permissions of /dev/ugen* (devfs.rules).\n");
+#elif defined(__OpenBSD__) || defined(__NetBSD__)
+            ERR("  **ERROR** Unable to claim interface. Ensure the uslcom driver is disabled for the device. Check permissions of /dev/ugen* and /dev/usb*.\n");
+#elif defined(_WIN32) || defined(WIN32)
+            ERR("  **ERROR** Unable to claim interface. Ensure a libusb-win32 driver is installed for the device.\n");
+#else
             ERR("  **ERROR** Unable to claim interface. Ensure device is not claimed by any kernel modules. Check permissions of /dev/bus/usb/...\n");
+#endif
+            return SI_DEVICE_IO_FAILED;
         }
     }
 
     if (Handle != NULL) {
         DBG("  USB Ctrl Message1 retval=%i\n",
-            usb_control_msg(Handle->udev, 0x40, 0x00, 0xFFFF, 0, NULL, 0, TXTimeout));
+            usb_control_msg(Handle->udev, 0x40, 0x00, 0xFFFF, Handle->interface, NULL, 0, Handle->txtimeout));
         DBG("  USB Reset Endpoint IN retval=%i\n", usb_resetep(Handle->udev, Handle->ep_in));
         DBG("  USB Reset Endpoint OUT retval=%i\n", usb_resetep(Handle->udev, Handle->ep_out));
         DBG("  USB Clear Halt IN retval=%i\n", usb_clear_halt(Handle->udev, Handle->ep_in));
         DBG("  USB Clear Halt OUT retval=%i\n", usb_clear_halt(Handle->udev, Handle->ep_out));
         DBG("  USB Ctrl Message2 retval=%i\n",
-            usb_control_msg(Handle->udev, 0x40, 0x02, 0x0002, 0, NULL, 0, TXTimeout));
+            usb_control_msg(Handle->udev, 0x40, 0x02, 0x0002, Handle->interface, NULL, 0, Handle->txtimeout));
 
         Handle->bufsize = 0;
 
@@ -375,6 +747,41 @@ int SI_Open(int DeviceNum, struct SI_Private **pHandle) {
     }
 }
 
+int SI_OpenInterface(int DeviceNum, int Interface, struct SI_Private **pHandle) {
+    return SI_OpenEx(DeviceNum, Interface, 0, RXTimeout, TXTimeout, pHandle);
+}
+
+int SI_Open(int DeviceNum, struct SI_Private **pHandle) {
+    return SI_OpenInterface(DeviceNum, 0, pHandle);
+}
+
+int SI_GetNumInterfaces(int DeviceNum, int *NumInterfaces) {
+    struct usb_bus *bus;
+    struct usb_device *dev, *pdev;
+    int devcount;
+    DBG("SI_GetNumInterfaces(DeviceNum=%i, NumInterfaces=%p)\n", DeviceNum, NumInterfaces);
+    init();
+
+    if (NumInterfaces == NULL)
+        return SI_INVALID_PARAMETER;
+
+    devcount = 0;
+    pdev = NULL;
+    for (bus = busses; bus; bus = bus->next) {
+        for (dev = bus->devices; dev; dev = dev->next) {
+            if (devcount == DeviceNum)
+                pdev = dev;
+            devcount++;
+        }
+    }
+    if (pdev == NULL)
+        return SI_DEVICE_NOT_FOUND;
+
+    *NumInterfaces = pdev->config != NULL ? pdev->config[0].bNumInterfaces : 0;
+
+    return SI_SUCCESS;
+}
+
 int SI_Close(struct SI_Private *Handle) {
     DBG("SI_Close(Handle=%p)\n", Handle);
     init();
@@ -384,9 +791,11 @@ int SI_Close(struct SI_Private *Handle) {
     if (Handle->magic != MAGIC)
         return SI_INVALID_HANDLE;
     DBG("  Valid Handle\n");
-    DBG("  USB Ctrl Message retval=%i\n", usb_control_msg(Handle->udev, 0x40, 0x02, 0x0004, 0, NULL, 0, TXTimeout));
+    DBG("  USB Ctrl Message retval=%i\n", usb_control_msg(Handle->udev, 0x40, 0x02, 0x0004, Handle->interface, NULL, 0, Handle->txtimeout));
 
     usb_release_interface(Handle->udev, Handle->interface);
+    if (Handle->reattach)
+        reattach_kernel_driver(Handle);
     usb_close(Handle->udev);
 
     Handle->magic = 0;
@@ -395,10 +804,10 @@ int SI_Close(struct SI_Private *Handle) {
     return SI_SUCCESS;
 }
 
-int SI_Read(struct SI_Private *Handle, char *Buffer, int BytesToRead, int *BytesReturned, void *o) {
+int SI_ReadWithTimeout(struct SI_Private *Handle, char *Buffer, int BytesToRead, int *BytesReturned, int Timeout) {
     int i;
-    DBG("SI_Read(Handle=%p, Buffer=%p, BytesToRead=%i, BytesReturned=%p)\n", Handle, Buffer, BytesToRead,
-        BytesReturned);
+    DBG("SI_ReadWithTimeout(Handle=%p, Buffer=%p, BytesToRead=%i, BytesReturned=%p, Timeout=%i)\n", Handle, Buffer,
+        BytesToRead, BytesReturned, Timeout);
     init();
 
     if (Handle == NULL)
@@ -407,13 +816,15 @@ int SI_Read(struct SI_Private *Handle, char *Buffer, int BytesToRead, int *Bytes
         return SI_INVALID_HANDLE;
     DBG("  Valid Handle\n");
 
-    if (Buffer == NULL)
-        return SI_INVALID_PARAMETER;
-    if (Buffer == NULL || BytesReturned == NULL)
+    if (Buffer == NULL || BytesReturned == NULL || BytesToRead < 0)
         return SI_INVALID_PARAMETER;
 
-    if (Handle->bufsize < BytesToRead)
-        SI_FillBuffer(Handle, RXTimeout);
+    /*Data that arrives after a cancellation stays buffered for the next read*/
+    if (Handle->bufsize < BytesToRead && SI_WaitBuffer(Handle, Timeout) < 0) {
+        *BytesReturned = 0;
+        DBG("  Cancelled\n");
+        return SI_IO_CANCELLED;
+    }
     *BytesReturned = SI_GetBuffer(Handle, Buffer, BytesToRead);
     DBG("  ReadBytes \"");
     for (i = 0; i < *BytesReturned; i++) {
@@ -428,10 +839,17 @@ int SI_Read(struct SI_Private *Handle, char *Buffer, int BytesToRead, int *Bytes
     return *BytesReturned > 0 ? SI_SUCCESS : SI_READ_TIMED_OUT;
 }
 
-int SI_Write(struct SI_Private *Handle, char *Buffer, int BytesToWrite, int *BytesWritten, void *o) {
-    int i;
-    DBG("SI_Write(Handle=%p, Buffer=%p, BytesToWrite=%i, BytesWritten=%p)\n", Handle, Buffer, BytesToWrite,
-        BytesWritten);
+int SI_Read(struct SI_Private *Handle, char *Buffer, int BytesToRead, int *BytesReturned, void *o) {
+    if (Handle == NULL || Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    return SI_ReadWithTimeout(Handle, Buffer, BytesToRead, BytesReturned, Handle->rxtimeout);
+}
+
+int SI_WriteWithTimeout(struct SI_Private *Handle, char *Buffer, int BytesToWrite, int *BytesWritten, int Timeout) {
+    int i, ret, filltimeout, slice, status;
+    int forever = Timeout <= 0;
+    DBG("SI_WriteWithTimeout(Handle=%p, Buffer=%p, BytesToWrite=%i, BytesWritten=%p, Timeout=%i)\n", Handle, Buffer,
+        BytesToWrite, BytesWritten, Timeout);
     init();
 
     if (Handle == NULL)
@@ -440,9 +858,12 @@ int SI_Write(struct SI_Private *Handle, char *Buffer, int BytesToWrite, int *Byt
         return SI_INVALID_HANDLE;
     DBG("  Valid Handle\n");
 
-    if (Buffer == NULL || BytesWritten == NULL)
+    if (Buffer == NULL || BytesWritten == NULL || BytesToWrite < 0)
         return SI_INVALID_PARAMETER;
 
+    /*Never let the opportunistic RX drain eat more than the caller's budget*/
+    filltimeout = (Timeout > 0 && Timeout < 100) ? Timeout : 100;
+
     DBG("  Writing \"");
     for (i = 0; i < BytesToWrite; i++) {
         if (i > 0) {
@@ -451,16 +872,61 @@ int SI_Write(struct SI_Private *Handle, char *Buffer, int BytesToWrite, int *Byt
         DBG("%02X", (unsigned char) Buffer[i]);
     }
     DBG("\"\n");
-    SI_FillBuffer(Handle, 100);
+    SI_FillBuffer(Handle, filltimeout);
     DBG("  Writing to device...\n");
-    *BytesWritten = usb_bulk_write(Handle->udev, Handle->ep_out, Buffer, BytesToWrite, TXTimeout);
-    SI_FillBuffer(Handle, 100);
+    /*libusb-compat reports what went out before a slice timed out as a short write, so no data is sent twice*/
+    *BytesWritten = 0;
+    status = SI_SUCCESS;
+    do {
+        if (SI_TakeCancel(Handle)) {
+            status = SI_IO_CANCELLED;
+            break;
+        }
+        if (!forever && Timeout <= 0) {
+            status = SI_WRITE_TIMED_OUT;
+            break;
+        }
+        slice = SI_Slice(Timeout);
+        ret = usb_bulk_write(Handle->udev, Handle->ep_out, Buffer + *BytesWritten, BytesToWrite - *BytesWritten, slice);
+        if (ret < 0 && ret != -ETIMEDOUT) {
+            status = SI_WRITE_ERROR;
+            break;
+        }
+        if (ret < BytesToWrite - *BytesWritten)
+            Timeout -= slice;
+        if (ret > 0)
+            *BytesWritten += ret;
+    } while (*BytesWritten < BytesToWrite);
+    SI_FillBuffer(Handle, filltimeout);
     DBG("  Wrote %i bytes\n", *BytesWritten);
 
+    return status;
+}
+
+int SI_Write(struct SI_Private *Handle, char *Buffer, int BytesToWrite, int *BytesWritten, void *o) {
+    if (Handle == NULL || Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    return SI_WriteWithTimeout(Handle, Buffer, BytesToWrite, BytesWritten, Handle->txtimeout);
+}
+
+int SI_CancelIo(struct SI_Private *Handle) {
+    DBG("SI_CancelIo(Handle=%p)\n", Handle);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    /*Picked up by the pending read or write, or else the next one*/
+    Handle->cancelled = 1;
+
     return SI_SUCCESS;
 }
 
 int SI_ResetDevice(struct SI_Private *Handle) {
+    int ret;
     DBG("SI_ResetDevice(Handle=%p)\n", Handle);
     init();
 
@@ -470,6 +936,298 @@ int SI_ResetDevice(struct SI_Private *Handle) {
         return SI_INVALID_HANDLE;
     DBG("  Valid Handle\n");
 
+    ret = usb_reset(Handle->udev);
+    DBG("  USB Reset retval=%i\n", ret);
+    if (ret < 0)
+        return SI_DEVICE_IO_FAILED;
+
+    /*With libusb 0.1 the device re-enumerates and the handle stops working*/
+    Handle->bufsize = 0;
+    return SI_DEVICE_REENUMERATED;
+}
+
+int SI_GetDeviceStatus(struct SI_Private *Handle, int *Status, int Timeout) {
+    int ret;
+    unsigned char status[2];
+    DBG("SI_GetDeviceStatus(Handle=%p, Status=%p, Timeout=%i)\n", Handle, Status, Timeout);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    if (Status == NULL)
+        return SI_INVALID_PARAMETER;
+
+    /*Standard GET_STATUS request, answered by every USB device*/
+    ret = usb_control_msg(Handle->udev, USB_ENDPOINT_IN, 0x00, 0, 0, (char *) status, sizeof(status), Timeout);
+    DBG("  USB Get Status retval=%i\n", ret);
+    if (ret < 0)
+        return SI_DEVICE_IO_FAILED;
+
+    *Status = status[0] | (status[1] << 8);
+
+    return SI_SUCCESS;
+}
+
+int SI_GetDeviceDetails(int DeviceNum, char *Path, char *Driver, int *Capabilities) {
+    struct usb_bus *bus;
+    struct usb_device *dev, *pdev;
+    struct usb_interface_descriptor *altsetting;
+    int devcount;
+    int i;
+#ifdef LIBUSB_HAS_GET_DRIVER_NP
+    usb_dev_handle *udev;
+#endif
+    DBG("SI_GetDeviceDetails(DeviceNum=%i, Path=%p, Driver=%p, Capabilities=%p)\n", DeviceNum, Path, Driver,
+        Capabilities);
+    init();
+
+    if (Path == NULL || Driver == NULL || Capabilities == NULL)
+        return SI_INVALID_PARAMETER;
+
+    devcount = 0;
+    pdev = NULL;
+    for (bus = busses; bus; bus = bus->next) {
+        for (dev = bus->devices; dev; dev = dev->next) {
+            if (devcount == DeviceNum)
+                pdev = dev;
+            devcount++;
+        }
+    }
+    if (pdev == NULL)
+        return SI_DEVICE_NOT_FOUND;
+
+    /*Same form as accepted by SI_GetDeviceNumByPath()*/
+    snprintf(Path, SI_MAX_DEVICE_STRLEN, "%.127s/%.127s", pdev->bus->dirname, pdev->filename);
+
+    /*Endpoints of the first interface, the one SI_Open() claims*/
+    *Capabilities = 0;
+    if (pdev->config != NULL && pdev->config[0].bNumInterfaces > 0 &&
+        pdev->config[0].interface[0].num_altsetting > 0) {
+        altsetting = &pdev->config[0].interface[0].altsetting[0];
+        for (i = 0; i < altsetting->bNumEndpoints; i++) {
+            int in = (altsetting->endpoint[i].bEndpointAddress & USB_ENDPOINT_DIR_MASK) != 0;
+            switch (altsetting->endpoint[i].bmAttributes & USB_ENDPOINT_TYPE_MASK) {
+                case USB_ENDPOINT_TYPE_BULK:
+                    *Capabilities |= in ? SI_CAP_BULK_IN : SI_CAP_BULK_OUT;
+                    break;
+                case USB_ENDPOINT_TYPE_INTERRUPT:
+                    if (in)
+                        *Capabilities |= SI_CAP_INTERRUPT_IN;
+                    break;
+            }
+        }
+    }
+
+    /*The kernel driver bound to the interface, where libusb can tell*/
+    strcpy(Driver, "");
+#ifdef LIBUSB_HAS_GET_DRIVER_NP
+    udev = usb_open(pdev);
+    if (udev) {
+        if (usb_get_driver_np(udev, 0, Driver, SI_MAX_DEVICE_STRLEN) < 0)
+            strcpy(Driver, "");
+        usb_close(udev);
+    }
+#endif
+
+    DBG("  Path=\"%s\" Driver=\"%s\" Capabilities=%i\n", Path, Driver, *Capabilities);
+
+    return SI_SUCCESS;
+}
+
+int SI_GetDeviceInfo(struct SI_Private *Handle, char *Serial, char *Description, int *Vid, int *Pid, char *Path) {
+    struct usb_device *dev;
+    DBG("SI_GetDeviceInfo(Handle=%p, Serial=%p, Description=%p, Vid=%p, Pid=%p, Path=%p)\n", Handle, Serial,
+        Description, Vid, Pid, Path);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    if (Serial == NULL || Description == NULL || Vid == NULL || Pid == NULL || Path == NULL)
+        return SI_INVALID_PARAMETER;
+
+    dev = usb_device(Handle->udev);
+    *Vid = dev->descriptor.idVendor;
+    *Pid = dev->descriptor.idProduct;
+
+    /*Strings are optional, a device without them gets empty ones*/
+    strcpy(Serial, "");
+    strcpy(Description, "");
+    if (dev->descriptor.iSerialNumber &&
+        usb_get_string_simple(Handle->udev, dev->descriptor.iSerialNumber, Serial, SI_MAX_DEVICE_STRLEN) < 0)
+        strcpy(Serial, "");
+    if (dev->descriptor.iProduct &&
+        usb_get_string_simple(Handle->udev, dev->descriptor.iProduct, Description, SI_MAX_DEVICE_STRLEN) < 0)
+        strcpy(Description, "");
+
+    /*Same form as accepted by SI_GetDeviceNumByPath()*/
+    snprintf(Path, SI_MAX_DEVICE_STRLEN, "%.127s/%.127s", dev->bus->dirname, dev->filename);
+
+    DBG("  Vid=0x%04X Pid=0x%04X Serial=\"%s\" Path=\"%s\"\n", *Vid, *Pid, Serial, Path);
+
+    return SI_SUCCESS;
+}
+
+int SI_GetDeviceProductString(struct SI_Private *Handle, void *Product, unsigned char *Length, int ConvertToASCII) {
+    struct usb_device *dev;
+    unsigned char desc[SI_MAX_DEVICE_STRLEN];
+    unsigned char *out;
+    int ret, len, i;
+    DBG("SI_GetDeviceProductString(Handle=%p, Product=%p, Length=%p, ConvertToASCII=%i)\n", Handle, Product, Length,
+        ConvertToASCII);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    if (Product == NULL || Length == NULL)
+        return SI_INVALID_PARAMETER;
+
+    /*A device without a product string gets an empty one*/
+    *Length = 0;
+    dev = usb_device(Handle->udev);
+    if (!dev->descriptor.iProduct)
+        return SI_SUCCESS;
+
+    /*The raw string descriptor in US English, UTF-16LE after a 2 byte header*/
+    ret = usb_get_string(Handle->udev, dev->descriptor.iProduct, 0x0409, (char *) desc, sizeof(desc));
+    DBG("  USB Get String retval=%i\n", ret);
+    if (ret < 2 || desc[1] != USB_DT_STRING)
+        return SI_DEVICE_IO_FAILED;
+    len = (desc[0] < ret ? desc[0] : ret) - 2;
+    if (len < 0)
+        len = 0;
+
+    /*Neither form is null terminated*/
+    out = Product;
+    if (ConvertToASCII) {
+        for (i = 0; i < len / 2; i++)
+            out[i] = desc[2 + 2 * i + 1] ? '?' : desc[2 + 2 * i];
+        *Length = len / 2;
+    } else {
+        memcpy(out, desc + 2, len);
+        *Length = len;
+    }
+
+    return SI_SUCCESS;
+}
+
+int SI_GetInterfaceNumber(struct SI_Private *Handle, unsigned char *Interface) {
+    DBG("SI_GetInterfaceNumber(Handle=%p, Interface=%p)\n", Handle, Interface);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    if (Interface == NULL)
+        return SI_INVALID_PARAMETER;
+
+    *Interface = Handle->interface;
+
+    return SI_SUCCESS;
+}
+
+int SI_GetLibusbHandle(struct SI_Private *Handle, void **Udev) {
+    DBG("SI_GetLibusbHandle(Handle=%p, Udev=%p)\n", Handle, Udev);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    if (Udev == NULL)
+        return SI_INVALID_PARAMETER;
+
+    *Udev = Handle->udev;
+
+    return SI_SUCCESS;
+}
+
+int SI_GetDeviceFd(struct SI_Private *Handle, int *Fd) {
+    DBG("SI_GetDeviceFd(Handle=%p, Fd=%p)\n", Handle, Fd);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    if (Fd == NULL)
+        return SI_INVALID_PARAMETER;
+
+    *Fd = Handle->fd;
+
+    return SI_SUCCESS;
+}
+
+int SI_GetPartNumber(struct SI_Private *Handle, unsigned char *PartNum) {
+    int ret;
+    DBG("SI_GetPartNumber(Handle=%p, PartNum=%p)\n", Handle, PartNum);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    if (PartNum == NULL)
+        return SI_INVALID_PARAMETER;
+
+    ret = usb_control_msg(Handle->udev, USB_TYPE_VENDOR | USB_RECIP_INTERFACE | USB_ENDPOINT_IN,
+                          CP210X_VENDOR_SPECIFIC, CP210X_GET_PARTNUM, Handle->interface, (char *) PartNum, 1,
+                          Handle->txtimeout);
+    DBG("  USB Ctrl Message retval=%i\n", ret);
+    if (ret != 1)
+        return SI_DEVICE_IO_FAILED;
+
+    return SI_SUCCESS;
+}
+
+int SI_ControlTransfer(struct SI_Private *Handle, int RequestType, int Request, int Value, int Index, char *Data,
+                       int Length, int *BytesTransferred, int Timeout) {
+    int ret;
+    DBG("SI_ControlTransfer(Handle=%p, RequestType=%i, Request=%i, Value=%i, Index=%i, Data=%p, Length=%i, BytesTransferred=%p, Timeout=%i)\n",
+        Handle, RequestType, Request, Value, Index, Data, Length, BytesTransferred, Timeout);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    if (BytesTransferred == NULL || (Length > 0 && Data == NULL))
+        return SI_INVALID_PARAMETER;
+
+    /*A negative Index addresses the claimed interface*/
+    if (Index < 0)
+        Index = Handle->interface;
+    ret = usb_control_msg(Handle->udev, RequestType, Request, Value, Index, Data, Length, Timeout);
+    DBG("  USB Ctrl Message retval=%i\n", ret);
+    if (ret < 0) {
+        *BytesTransferred = 0;
+        return SI_DEVICE_IO_FAILED;
+    }
+
+    *BytesTransferred = ret;
+
     return SI_SUCCESS;
 }
 
@@ -490,7 +1248,9 @@ int SI_DeviceIOControl(struct SI_Private *Handle, int IoControlCode, char *InBuf
 
 
 int SI_FlushBuffers(struct SI_Private *Handle, char FlushTransmit, char FlushReceive) {
-    DBG("SI_FlushTransmit(Handle=%p, FlushTransmit=%i, FlushReceive=%i)\n", Handle, FlushTransmit, FlushReceive);
+    int purge = 0;
+    int ret;
+    DBG("SI_FlushBuffers(Handle=%p, FlushTransmit=%i, FlushReceive=%i)\n", Handle, FlushTransmit, FlushReceive);
     init();
 
     if (Handle == NULL)
@@ -499,7 +1259,21 @@ int SI_FlushBuffers(struct SI_Private *Handle, char FlushTransmit, char FlushRec
         return SI_INVALID_HANDLE;
     DBG("  Valid Handle\n");
 
-    Handle->bufsize = 0;
+    if (FlushReceive) {
+        Handle->bufsize = 0;
+        purge |= CP210X_PURGE_RX;
+    }
+    if (FlushTransmit)
+        purge |= CP210X_PURGE_TX;
+    if (purge == 0)
+        return SI_SUCCESS;
+
+    /*Clear the queues of the device's UART as well*/
+    ret = usb_control_msg(Handle->udev, USB_TYPE_VENDOR | USB_RECIP_INTERFACE, CP210X_PURGE, purge,
+                          Handle->interface, NULL, 0, Handle->txtimeout);
+    DBG("  USB Ctrl Message retval=%i\n", ret);
+    if (ret < 0)
+        return SI_DEVICE_IO_FAILED;
 
     return SI_SUCCESS;
 }
@@ -508,12 +1282,35 @@ int SI_SetTimeouts(int ReadTimeout, int WriteTimeout) {
     DBG("SI_SetTimeouts(ReadTimeout=%i, WriteTimeout=%i)\n", ReadTimeout, WriteTimeout);
     init();
 
+    if (ReadTimeout < 0 || WriteTimeout < 0)
+        return SI_INVALID_PARAMETER;
+
+    /*Open handles keep theirs, see SI_SetHandleTimeouts()*/
     RXTimeout = ReadTimeout;
     TXTimeout = WriteTimeout;
 
     return SI_SUCCESS;
 }
 
+int SI_SetHandleTimeouts(struct SI_Private *Handle, int ReadTimeout, int WriteTimeout) {
+    DBG("SI_SetHandleTimeouts(Handle=%p, ReadTimeout=%i, WriteTimeout=%i)\n", Handle, ReadTimeout, WriteTimeout);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    if (ReadTimeout < 0 || WriteTimeout < 0)
+        return SI_INVALID_PARAMETER;
+
+    Handle->rxtimeout = ReadTimeout;
+    Handle->txtimeout = WriteTimeout;
+
+    return SI_SUCCESS;
+}
+
 int SI_GetTimeouts(int *ReadTimeout, int *WriteTimeout) {
     DBG("SI_GetTimeouts(ReadTimeout=%p, WriteTimeout=%p)\n", ReadTimeout, WriteTimeout);
     init();
@@ -527,6 +1324,29 @@ int SI_GetTimeouts(int *ReadTimeout, int *WriteTimeout) {
     return SI_SUCCESS;
 }
 
+int SI_FillRXQueue(struct SI_Private *Handle, int Timeout, int *NumBytesInQueue) {
+    DBG("SI_FillRXQueue(Handle=%p, Timeout=%i, NumBytesInQueue=%p)\n", Handle, Timeout, NumBytesInQueue);
+    init();
+
+    if (Handle == NULL)
+        return SI_INVALID_HANDLE;
+    if (Handle->magic != MAGIC)
+        return SI_INVALID_HANDLE;
+    DBG("  Valid Handle\n");
+
+    if (NumBytesInQueue == NULL)
+        return SI_INVALID_PARAMETER;
+
+    /*Pull whatever the device has ready into the buffer, waiting at most Timeout*/
+    if (Handle->bufsize < BUF_SIZE)
+        SI_FillBuffer(Handle, Timeout);
+    *NumBytesInQueue = Handle->bufsize;
+
+    DBG("  NumBytesInQueue=%i\n", *NumBytesInQueue);
+
+    return SI_SUCCESS;
+}
+
 int SI_CheckRXQueue(struct SI_Private *Handle, int *NumBytesInQueue, int *QueueStatus) {
     DBG("SI_CheckRXQueue(Handle=%p, NumBytesInQueue=%p, QueueStatus=%p)\n", Handle, NumBytesInQueue, QueueStatus);
     init();
@@ -0,0 +1,89 @@
+//! Friendly names for devices
+//!
+//! An alias store maps device serial numbers to names such as
+//! `thermal-chamber-left`. Once a store is installed with [`set_store`],
+//! payload logs, [`UsbXpress`](crate::UsbXpress)'s `Debug` output and
+//! production reports name devices by their alias.
+//!
+//! Any `HashMap` or `BTreeMap` from serial numbers to names works as a store;
+//! other sources, like an asset database, can implement [`AliasStore`].
+//! With the `profiles` feature, [`load`] reads an `aliases.toml` file of
+//! `serial = "name"` lines:
+//!
+//! ```toml
+//! "0001A2F3" = "thermal-chamber-left"
+//! "0001A2F4" = "thermal-chamber-right"
+//! ```
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::RwLock,
+};
+
+/// A source of device aliases
+pub trait AliasStore: Send + Sync {
+    /// Returns the alias of the device with serial number `serial`
+    fn alias(&self, serial: &str) -> Option<String>;
+}
+
+impl AliasStore for HashMap<String, String> {
+    fn alias(&self, serial: &str) -> Option<String> {
+        self.get(serial).cloned()
+    }
+}
+
+impl AliasStore for BTreeMap<String, String> {
+    fn alias(&self, serial: &str) -> Option<String> {
+        self.get(serial).cloned()
+    }
+}
+
+static STORE: RwLock<Option<Box<dyn AliasStore>>> = RwLock::new(None);
+
+/// Installs `store` as the process-wide alias store, replacing any previous
+/// one
+pub fn set_store<S: AliasStore + 'static>(store: S) {
+    *STORE.write().unwrap() = Some(Box::new(store));
+}
+
+/// Removes the alias store
+pub fn clear_store() {
+    *STORE.write().unwrap() = None;
+}
+
+/// Returns the alias of the device with serial number `serial`, if an alias
+/// store is installed and knows it
+pub fn alias(serial: &str) -> Option<String> {
+    if serial.is_empty() {
+        return None;
+    }
+    STORE.read().unwrap().as_ref()?.alias(serial)
+}
+
+/// Loads aliases from the TOML file at `path`, see the
+/// [module documentation](self)
+#[cfg(feature = "profiles")]
+pub fn load<P: AsRef<std::path::Path>>(
+    path: P,
+) -> Result<BTreeMap<String, String>, crate::profiles::ProfileError> {
+    use crate::profiles::ProfileError;
+
+    let aliases = std::fs::read_to_string(path).map_err(ProfileError::Io)?;
+    toml::from_str(&aliases).map_err(ProfileError::Parse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aliases_are_looked_up_by_serial() {
+        let mut store = BTreeMap::new();
+        store.insert("0001A2F3".to_owned(), "thermal-chamber-left".to_owned());
+        set_store(store);
+        assert_eq!(alias("0001A2F3").as_deref(), Some("thermal-chamber-left"));
+        assert_eq!(alias("0042"), None);
+        assert_eq!(alias(""), None);
+        clear_store();
+        assert_eq!(alias("0001A2F3"), None);
+    }
+}
@@ -0,0 +1,405 @@
+//! Using devices from async code
+//!
+//! Device transfers block, so an [`AsyncUsbXpress`] hands them to a worker
+//! thread of its own and lets the task wait for the outcome without
+//! blocking the executor. With the `tokio` feature it implements tokio's
+//! `AsyncRead` and `AsyncWrite`, with the `futures` feature those of
+//! `futures-io`, which async-std and smol use. Neither needs a particular
+//! runtime:
+//!
+//! ```rust, ignore
+//! use tokio::io::{AsyncReadExt, AsyncWriteExt};
+//!
+//! let mut device = AsyncUsbXpress::new(UsbXpress::open(0)?);
+//! device.write_all(b"PING\n").await?;
+//! let n = device.read(&mut response).await?;
+//! ```
+//!
+//! Writes are passed on to the worker and reported as complete right away;
+//! a failing write is reported by the next read, write or flush. Only one
+//! write is queued at a time, so a writer faster than the device waits for
+//! the previous write to finish. Reads return as soon as some data arrives,
+//! they never time out.
+//!
+//! Flush waits until everything written so far has left the device, not
+//! just reached it: the worker polls the transport's
+//! [`pending_output`](Transport::pending_output), for USB devices the
+//! transmit queue level from the CP210x `GET_COMM_STATUS` request, until it
+//! is empty. A queue that does not shrink for a second, e.g. held up by flow
+//! control, fails the flush with `WriteTimeOut`.
+use std::{
+    io,
+    sync::{mpsc, Arc, Mutex},
+    task::{Context, Poll, Waker},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{transport::Transport, SilabsUsbXpressError, UsbXpress};
+
+/// Largest read handed to the worker at once
+const MAX_READ: usize = 65536;
+
+/// How often a flush asks how much the device has yet to send
+const DRAIN_POLL: Duration = Duration::from_millis(5);
+
+/// How long a flush waits for the device's transmit queue to shrink
+const DRAIN_STALL: Duration = Duration::from_secs(1);
+
+enum Request {
+    Read(usize),
+    Write(Vec<u8>),
+    /// Waits until the device has sent everything written
+    Drain,
+}
+
+enum Outcome {
+    Read(Result<Vec<u8>, SilabsUsbXpressError>),
+    Write(Result<usize, SilabsUsbXpressError>),
+    Drain(Result<(), SilabsUsbXpressError>),
+}
+
+#[derive(Default)]
+struct Completion {
+    outcome: Option<Outcome>,
+    waker: Option<Waker>,
+}
+
+/// A device whose transfers run on a worker thread, see the
+/// [module documentation](self)
+///
+/// Any [`Transport`] can be wrapped, a [`UsbXpress`] handle by default.
+pub struct AsyncUsbXpress<T: Transport + 'static = UsbXpress> {
+    requests: Option<mpsc::Sender<Request>>,
+    completion: Arc<Mutex<Completion>>,
+    worker: Option<thread::JoinHandle<T>>,
+    in_flight: bool,
+    /// Whether the request in flight is a flush's `Drain`
+    draining: bool,
+    /// Data read by the worker but not returned yet
+    unread: Vec<u8>,
+}
+
+impl<T: Transport + 'static> AsyncUsbXpress<T> {
+    pub fn new(device: T) -> Self {
+        let (requests, rx) = mpsc::channel();
+        let completion = Arc::new(Mutex::new(Completion::default()));
+        let done = completion.clone();
+        let worker = thread::spawn(move || {
+            let mut device = device;
+            for request in rx {
+                let outcome = match request {
+                    Request::Read(max) => Outcome::Read(device.read(max)),
+                    Request::Write(data) => Outcome::Write(device.write(&data)),
+                    Request::Drain => Outcome::Drain(drain(&mut device)),
+                };
+                let mut done = done.lock().unwrap();
+                done.outcome = Some(outcome);
+                if let Some(waker) = done.waker.take() {
+                    waker.wake();
+                }
+            }
+            device
+        });
+        AsyncUsbXpress {
+            requests: Some(requests),
+            completion,
+            worker: Some(worker),
+            in_flight: false,
+            draining: false,
+            unread: Vec::new(),
+        }
+    }
+
+    /// Waits for the worker to finish and returns the device
+    ///
+    /// This blocks for as long as the transfer in progress, if any, takes;
+    /// data read but not returned yet is lost.
+    pub fn into_inner(mut self) -> T {
+        self.requests = None;
+        let worker = self.worker.take().unwrap();
+        worker.join().expect("device worker panicked")
+    }
+
+    fn send(&mut self, request: Request) {
+        if let Some(requests) = &self.requests {
+            self.draining = matches!(request, Request::Drain);
+            // The worker only stops once `requests` is dropped.
+            let _ = requests.send(request);
+            self.in_flight = true;
+        }
+    }
+
+    /// Waits for the transfer in progress, keeping what a read returned
+    fn poll_idle(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if !self.in_flight {
+            return Poll::Ready(Ok(()));
+        }
+        let outcome = {
+            let mut completion = self.completion.lock().unwrap();
+            match completion.outcome.take() {
+                Some(outcome) => outcome,
+                None => {
+                    completion.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+            }
+        };
+        self.in_flight = false;
+        Poll::Ready(match outcome {
+            Outcome::Read(Ok(data)) => {
+                self.unread.extend_from_slice(&data);
+                Ok(())
+            }
+            Outcome::Read(Err(SilabsUsbXpressError::ReadTimeOut { .. })) => Ok(()),
+            Outcome::Read(Err(err)) | Outcome::Write(Err(err)) | Outcome::Drain(Err(err)) => {
+                Err(err.into())
+            }
+            Outcome::Write(Ok(_)) | Outcome::Drain(Ok(())) => Ok(()),
+        })
+    }
+
+    /// Waits until the device has sent everything written, the common part
+    /// of the `poll_flush` impls
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        loop {
+            let drained = self.draining;
+            match self.poll_idle(cx) {
+                Poll::Ready(Ok(())) if drained => {
+                    self.draining = false;
+                    return Poll::Ready(Ok(()));
+                }
+                Poll::Ready(Ok(())) => self.send(Request::Drain),
+                Poll::Ready(Err(err)) => {
+                    self.draining = false;
+                    return Poll::Ready(Err(err));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+
+    /// Reads into `buf`, the common part of the `AsyncRead` impls
+    fn poll_read_slice(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        loop {
+            if !self.unread.is_empty() {
+                let n = buf.len().min(self.unread.len());
+                buf[..n].copy_from_slice(&self.unread[..n]);
+                self.unread.drain(..n);
+                return Poll::Ready(Ok(n));
+            }
+            if self.in_flight {
+                match self.poll_idle(cx) {
+                    Poll::Ready(Ok(())) => {}
+                    other => return other.map(|result| result.map(|()| 0)),
+                }
+            } else {
+                self.send(Request::Read(buf.len().min(MAX_READ)));
+            }
+        }
+    }
+
+    /// Queues `buf` for writing, the common part of the `AsyncWrite` impls
+    fn poll_write_slice(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        match self.poll_idle(cx) {
+            Poll::Ready(Ok(())) => {
+                self.send(Request::Write(buf.to_vec()));
+                Poll::Ready(Ok(buf.len()))
+            }
+            other => other.map(|result| result.map(|()| 0)),
+        }
+    }
+}
+
+/// Polls `device` until it has sent everything written to it
+fn drain<T: Transport>(device: &mut T) -> Result<(), SilabsUsbXpressError> {
+    let started = Instant::now();
+    let mut progress = started;
+    let first = device.pending_output()?;
+    let mut queued = first;
+    while queued > 0 {
+        thread::sleep(DRAIN_POLL);
+        let now = device.pending_output()?;
+        if now < queued {
+            progress = Instant::now();
+        } else if progress.elapsed() >= DRAIN_STALL {
+            return Err(SilabsUsbXpressError::WriteTimeOut {
+                elapsed: started.elapsed(),
+                transferred: first.saturating_sub(now),
+                remaining: now,
+                chunk: 0,
+            });
+        }
+        queued = now;
+    }
+    Ok(())
+}
+
+impl<T: Transport + 'static> Drop for AsyncUsbXpress<T> {
+    fn drop(&mut self) {
+        // Dropping the sender ends the worker, which drops the device once
+        // the transfer in progress is done.
+        self.requests = None;
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: Transport + 'static> tokio::io::AsyncRead for AsyncUsbXpress<T> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll_read_slice(cx, buf.initialize_unfilled()) {
+            Poll::Ready(Ok(n)) => {
+                buf.advance(n);
+                Poll::Ready(Ok(()))
+            }
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<T: Transport + 'static> tokio::io::AsyncWrite for AsyncUsbXpress<T> {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_write_slice(cx, buf)
+    }
+
+    fn poll_flush(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_drain(cx)
+    }
+
+    fn poll_shutdown(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_drain(cx)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T: Transport + 'static> futures_io::AsyncRead for AsyncUsbXpress<T> {
+    fn poll_read(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_read_slice(cx, buf)
+    }
+}
+
+#[cfg(feature = "futures")]
+impl<T: Transport + 'static> futures_io::AsyncWrite for AsyncUsbXpress<T> {
+    fn poll_write(
+        self: std::pin::Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().poll_write_slice(cx, buf)
+    }
+
+    fn poll_flush(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_drain(cx)
+    }
+
+    fn poll_close(self: std::pin::Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_drain(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Loopback;
+    use std::task::Wake;
+
+    struct Unpark(thread::Thread);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<T>(mut poll: impl FnMut(&mut Context<'_>) -> Poll<T>) -> T {
+        let waker = Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match poll(&mut cx) {
+                Poll::Ready(value) => return value,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn transfers_complete_on_the_worker() {
+        let mut device = AsyncUsbXpress::new(Loopback::default());
+        assert_eq!(
+            block_on(|cx| device.poll_write_slice(cx, b"PING")).unwrap(),
+            4
+        );
+        block_on(|cx| device.poll_idle(cx)).unwrap();
+        let mut buf = [0; 2];
+        assert_eq!(
+            block_on(|cx| device.poll_read_slice(cx, &mut buf)).unwrap(),
+            2
+        );
+        assert_eq!(&buf, b"PI");
+        assert_eq!(
+            block_on(|cx| device.poll_read_slice(cx, &mut buf)).unwrap(),
+            2
+        );
+        assert_eq!(&buf, b"NG");
+        assert!(device.into_inner().0.is_empty());
+    }
+
+    /// A device sending `rate` bytes of its queue per status request
+    struct Uart {
+        queued: usize,
+        rate: usize,
+    }
+
+    impl Transport for Uart {
+        fn read(&mut self, _max: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+            Ok(Vec::new())
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+            self.queued += data.len();
+            Ok(data.len())
+        }
+
+        fn pending_output(&mut self) -> Result<usize, SilabsUsbXpressError> {
+            self.queued = self.queued.saturating_sub(self.rate);
+            Ok(self.queued)
+        }
+    }
+
+    #[test]
+    fn flush_waits_for_the_transmit_queue_to_drain() {
+        let mut device = AsyncUsbXpress::new(Uart {
+            queued: 0,
+            rate: 16,
+        });
+        block_on(|cx| device.poll_write_slice(cx, &[0; 100])).unwrap();
+        block_on(|cx| device.poll_drain(cx)).unwrap();
+        assert_eq!(device.into_inner().queued, 0);
+    }
+
+    #[test]
+    fn flush_fails_once_the_transmit_queue_stalls() {
+        let mut device = AsyncUsbXpress::new(Uart { queued: 0, rate: 0 });
+        block_on(|cx| device.poll_write_slice(cx, &[0; 100])).unwrap();
+        let err = block_on(|cx| device.poll_drain(cx)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert_eq!(device.into_inner().queued, 100);
+    }
+}
@@ -0,0 +1,269 @@
+//! What the Rust implementations of the SiUSBXp functions share
+//!
+//! The `rust-backend` and `nusb-backend` features replace the bundled
+//! `SiUSBXp.c` with Rust on top of rusb or nusb. Both keep the C library's
+//! global timeouts and device ID table, and slice their transfers the same
+//! way to honour `SI_CancelIo`; those parts live here.
+#![allow(non_snake_case, clippy::missing_safety_doc)]
+
+use std::{
+    convert::TryFrom,
+    os::raw::{c_char, c_int},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicI32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::ffi::{
+    SI_GLOBAL_DATA_ERROR, SI_INVALID_HANDLE, SI_INVALID_PARAMETER, SI_MAX_DEVICE_IDS,
+    SI_MAX_DEVICE_STRLEN, SI_SUCCESS, SI_USB_PID, SI_USB_VID,
+};
+
+pub(crate) const MAGIC: c_int = 12939485;
+pub(crate) const BUF_SIZE: usize = 4096;
+/// Longest a transfer waits on the device between checks for `SI_CancelIo`
+pub(crate) const CANCEL_POLL: Duration = Duration::from_millis(50);
+pub(crate) const ENDPOINT_IN: u8 = 0x80;
+/// Standard GET_DESCRIPTOR request for a string descriptor
+pub(crate) const GET_DESCRIPTOR: u8 = 0x06;
+pub(crate) const DT_STRING: u8 = 0x03;
+pub(crate) const LANG_US_ENGLISH: u16 = 0x0409;
+/// CP210x vendor request reading the part number, to the interface
+pub(crate) const CP210X_REQTYPE_DEVICE_TO_HOST: u8 = 0xC1;
+pub(crate) const CP210X_VENDOR_SPECIFIC: u8 = 0xFF;
+pub(crate) const CP210X_GET_PARTNUM: u16 = 0x370B;
+/// CP210x vendor request clearing the UART queues
+pub(crate) const CP210X_REQTYPE_HOST_TO_DEVICE: u8 = 0x41;
+pub(crate) const CP210X_PURGE: u8 = 0x12;
+pub(crate) const CP210X_PURGE_TX: u16 = 0x0005;
+pub(crate) const CP210X_PURGE_RX: u16 = 0x000A;
+
+/// Timeouts given to handles opened by `SI_Open`/`SI_OpenInterface`
+pub(crate) static RX_TIMEOUT: AtomicI32 = AtomicI32::new(1000);
+pub(crate) static TX_TIMEOUT: AtomicI32 = AtomicI32::new(1000);
+
+/// The cancellation flags of the open handles, by handle address
+///
+/// `SI_CancelIo` finds the flag here instead of going through the handle,
+/// which the thread blocked in a transfer has borrowed.
+static CANCEL_FLAGS: Mutex<Vec<(usize, Arc<AtomicBool>)>> = Mutex::new(Vec::new());
+
+/// Vendor and product IDs whose strings are read, besides `SI_USB_VID/PID`
+static DEVICE_IDS: Mutex<Vec<(u16, u16)>> = Mutex::new(Vec::new());
+
+pub(crate) fn millis(timeout: c_int) -> Duration {
+    Duration::from_millis(timeout.max(0) as u64)
+}
+
+/// How a transfer run by [`sliced`] ended
+#[derive(Debug, PartialEq)]
+pub(crate) enum Sliced {
+    Done,
+    TimedOut,
+    Cancelled,
+}
+
+/// Calls `transfer` with timeouts of at most `CANCEL_POLL` until it returns
+/// `true` or `timeout` milliseconds have passed, checking `cancelled` before
+/// every call
+///
+/// A `timeout` of zero waits forever, as it does for libusb.
+pub(crate) fn sliced<F: FnMut(c_int) -> bool>(
+    timeout: c_int,
+    cancelled: &AtomicBool,
+    mut transfer: F,
+) -> Sliced {
+    let deadline = if timeout > 0 {
+        Some(Instant::now() + millis(timeout))
+    } else {
+        None
+    };
+    loop {
+        if cancelled.swap(false, Ordering::SeqCst) {
+            return Sliced::Cancelled;
+        }
+        let slice = match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left == Duration::ZERO {
+                    return Sliced::TimedOut;
+                }
+                left.min(CANCEL_POLL)
+            }
+            None => CANCEL_POLL,
+        };
+        if transfer(slice.as_millis().max(1) as c_int) {
+            return Sliced::Done;
+        }
+    }
+}
+
+pub(crate) fn status(status: u32) -> c_int {
+    status as c_int
+}
+
+pub(crate) fn is_si_device(vid: u16, pid: u16) -> bool {
+    (u32::from(vid) == SI_USB_VID && u32::from(pid) == SI_USB_PID)
+        || DEVICE_IDS.lock().unwrap().contains(&(vid, pid))
+}
+
+/// Splits a path into its last two components, the bus and the device
+pub(crate) fn split_path(path: &str) -> Option<(&str, &str)> {
+    let (dir, file) = path.rsplit_once('/')?;
+    if dir.is_empty() {
+        return None;
+    }
+    Some((dir.rsplit('/').next()?, file))
+}
+
+/// Copies `string` to a C string buffer of `SI_MAX_DEVICE_STRLEN` bytes
+pub(crate) unsafe fn copy_string(buffer: *mut c_char, string: &str) {
+    let len = string.len().min(SI_MAX_DEVICE_STRLEN as usize - 1);
+    ptr::copy_nonoverlapping(string.as_ptr(), buffer as *mut u8, len);
+    *buffer.add(len) = 0;
+}
+
+/// Lets `SI_CancelIo` reach the handle at `address` through `cancelled`
+pub(crate) fn register_cancel(address: usize, cancelled: Arc<AtomicBool>) {
+    CANCEL_FLAGS.lock().unwrap().push((address, cancelled));
+}
+
+/// Forgets the handle at `address`, before it is closed
+pub(crate) fn forget_cancel(address: usize) {
+    CANCEL_FLAGS
+        .lock()
+        .unwrap()
+        .retain(|(registered, _)| *registered != address);
+}
+
+/// Cancels the pending read or write of the handle at `address`, or else
+/// its next one
+pub(crate) fn cancel(address: usize) -> c_int {
+    match CANCEL_FLAGS
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(registered, _)| *registered == address)
+    {
+        Some((_, cancelled)) => {
+            cancelled.store(true, Ordering::SeqCst);
+            status(SI_SUCCESS)
+        }
+        None => status(SI_INVALID_HANDLE),
+    }
+}
+
+pub unsafe fn SI_AddDeviceId(vid: c_int, pid: c_int) -> c_int {
+    let (vid, pid) = match (u16::try_from(vid), u16::try_from(pid)) {
+        (Ok(vid), Ok(pid)) => (vid, pid),
+        _ => return status(SI_INVALID_PARAMETER),
+    };
+    if is_si_device(vid, pid) {
+        return status(SI_SUCCESS);
+    }
+    let mut ids = DEVICE_IDS.lock().unwrap();
+    // The table of the C library has room for SI_USB_VID/PID and 31 more
+    if ids.len() + 1 == SI_MAX_DEVICE_IDS as usize {
+        return status(SI_GLOBAL_DATA_ERROR);
+    }
+    ids.push((vid, pid));
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_ClearDeviceIds() -> c_int {
+    DEVICE_IDS.lock().unwrap().clear();
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_SetTimeouts(read_timeout: c_int, write_timeout: c_int) -> c_int {
+    if read_timeout < 0 || write_timeout < 0 {
+        return status(SI_INVALID_PARAMETER);
+    }
+    // Open handles keep theirs, see `SI_SetHandleTimeouts`
+    RX_TIMEOUT.store(read_timeout, Ordering::Relaxed);
+    TX_TIMEOUT.store(write_timeout, Ordering::Relaxed);
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetTimeouts(read_timeout: *mut c_int, write_timeout: *mut c_int) -> c_int {
+    if read_timeout.is_null() || write_timeout.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    *read_timeout = RX_TIMEOUT.load(Ordering::Relaxed);
+    *write_timeout = TX_TIMEOUT.load(Ordering::Relaxed);
+    status(SI_SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_name_the_bus_and_the_device() {
+        assert_eq!(split_path("001/004"), Some(("001", "004")));
+        assert_eq!(split_path("/dev/bus/usb/001/004"), Some(("001", "004")));
+        assert_eq!(split_path("/004"), None);
+        assert_eq!(split_path("004"), None);
+    }
+
+    #[test]
+    fn cancelling_ends_a_blocked_transfer_within_a_slice() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let canceller = {
+            let cancelled = Arc::clone(&cancelled);
+            std::thread::spawn(move || {
+                std::thread::sleep(Duration::from_millis(100));
+                cancelled.store(true, Ordering::SeqCst);
+            })
+        };
+        let started = Instant::now();
+        // A device that never answers
+        let ended = sliced(10_000, &cancelled, |slice| {
+            std::thread::sleep(millis(slice));
+            false
+        });
+        canceller.join().unwrap();
+        assert_eq!(ended, Sliced::Cancelled);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        // The cancellation is used up
+        assert!(!cancelled.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn a_pending_cancellation_ends_the_next_transfer() {
+        let cancelled = AtomicBool::new(true);
+        let mut calls = 0;
+        let ended = sliced(1000, &cancelled, |_| {
+            calls += 1;
+            true
+        });
+        assert_eq!((ended, calls), (Sliced::Cancelled, 0));
+        assert_eq!(sliced(1000, &cancelled, |_| true), Sliced::Done);
+    }
+
+    #[test]
+    fn slices_stop_at_the_timeout() {
+        let cancelled = AtomicBool::new(false);
+        let started = Instant::now();
+        let ended = sliced(120, &cancelled, |slice| {
+            assert!(millis(slice) <= CANCEL_POLL);
+            std::thread::sleep(millis(slice));
+            false
+        });
+        assert_eq!(ended, Sliced::TimedOut);
+        assert!(started.elapsed() >= Duration::from_millis(120));
+    }
+
+    #[test]
+    fn cancellations_reach_registered_handles_only() {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        register_cancel(0x1000, Arc::clone(&cancelled));
+        assert_eq!(cancel(0x1000), status(SI_SUCCESS));
+        assert!(cancelled.load(Ordering::SeqCst));
+        forget_cancel(0x1000);
+        assert_eq!(cancel(0x1000), status(SI_INVALID_HANDLE));
+    }
+}
@@ -0,0 +1,21 @@
+//! Runs device scripts, see the `scripting` module
+//!
+//! ```text
+//! sixpress run script.rhai
+//! ```
+use std::{env, process};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let script = match args.as_slice() {
+        [command, script] if command == "run" => script,
+        _ => {
+            eprintln!("usage: sixpress run <script.rhai>");
+            process::exit(2);
+        }
+    };
+    if let Err(err) = silabs_usb_xpress::scripting::run_file(script) {
+        eprintln!("{}: {}", script, err);
+        process::exit(1);
+    }
+}
@@ -24,11 +24,19 @@ pub const SI_SYSTEM_ERROR_CODE: u32 = 12;
 pub const SI_READ_TIMED_OUT: u32 = 13;
 pub const SI_WRITE_TIMED_OUT: u32 = 14;
 pub const SI_IO_PENDING: u32 = 15;
+pub const SI_DRIVER_DETACH_FAILED: u32 = 16;
+pub const SI_DEVICE_REENUMERATED: u32 = 17;
+pub const SI_IO_CANCELLED: u32 = 18;
+pub const SI_OPEN_DETACH_KERNEL_DRIVER: u32 = 1;
+pub const SI_OPEN_REATTACH_KERNEL_DRIVER: u32 = 2;
 pub const SI_RETURN_SERIAL_NUMBER: u32 = 0;
 pub const SI_RETURN_DESCRIPTION: u32 = 1;
 pub const SI_RETURN_LINK_NAME: u32 = 2;
 pub const SI_RETURN_VID: u32 = 3;
 pub const SI_RETURN_PID: u32 = 4;
+pub const SI_CAP_BULK_IN: u32 = 1;
+pub const SI_CAP_BULK_OUT: u32 = 2;
+pub const SI_CAP_INTERRUPT_IN: u32 = 4;
 pub const SI_RX_NO_OVERRUN: u32 = 0;
 pub const SI_RX_EMPTY: u32 = 0;
 pub const SI_RX_OVERRUN: u32 = 1;
@@ -36,6 +44,7 @@ pub const SI_RX_READY: u32 = 2;
 pub const SI_MAX_DEVICE_STRLEN: u32 = 256;
 pub const SI_MAX_READ_SIZE: u32 = 65536;
 pub const SI_MAX_WRITE_SIZE: u32 = 4096;
+pub const SI_MAX_DEVICE_IDS: u32 = 32;
 #[repr(C)]
 #[derive(Debug, Copy, Clone)]
 pub struct UsbDevHandle {
@@ -51,6 +60,12 @@ pub struct SiPrivate {
     pub ep_in: ::std::os::raw::c_int,
     pub bufsize: ::std::os::raw::c_int,
     pub buffer: [::std::os::raw::c_char; 4096usize],
+    pub reattach: ::std::os::raw::c_int,
+    pub driver: [::std::os::raw::c_char; 256usize],
+    pub rxtimeout: ::std::os::raw::c_int,
+    pub txtimeout: ::std::os::raw::c_int,
+    pub cancelled: ::std::os::raw::c_int,
+    pub fd: ::std::os::raw::c_int,
 }
 
 #[cfg(target_pointer_width = "64")]
@@ -58,7 +73,7 @@ pub struct SiPrivate {
 fn bindgen_test_layout_si_private() {
     assert_eq!(
         ::std::mem::size_of::<SiPrivate>(),
-        4128usize,
+        4408usize,
         concat!("Size of: ", stringify!(SI_Private))
     );
     assert_eq!(
@@ -136,13 +151,73 @@ fn bindgen_test_layout_si_private() {
             stringify!(buffer)
         )
     );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, reattach),
+        4128usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(reattach)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, driver),
+        4132usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(driver)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, rxtimeout),
+        4388usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(rxtimeout)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, txtimeout),
+        4392usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(txtimeout)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, cancelled),
+        4396usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(cancelled)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, fd),
+        4400usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(fd)
+        )
+    );
 }
 #[cfg(target_pointer_width = "32")]
 #[test]
 fn bindgen_test_layout_si_private() {
     assert_eq!(
         ::std::mem::size_of::<SiPrivate>(),
-        4120usize,
+        4396usize,
         concat!("Size of: ", stringify!(SI_Private))
     );
     assert_eq!(
@@ -220,8 +295,77 @@ fn bindgen_test_layout_si_private() {
             stringify!(buffer)
         )
     );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, reattach),
+        4120usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(reattach)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, driver),
+        4124usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(driver)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, rxtimeout),
+        4380usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(rxtimeout)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, txtimeout),
+        4384usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(txtimeout)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, cancelled),
+        4388usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(cancelled)
+        )
+    );
+    assert_eq!(
+        ::std::mem::offset_of!(SiPrivate, fd),
+        4392usize,
+        concat!(
+            "Offset of field: ",
+            stringify!(SI_Private),
+            "::",
+            stringify!(fd)
+        )
+    );
 }
 
+extern "C" {
+    pub fn SI_AddDeviceId(
+        vid: ::std::os::raw::c_int,
+        pid: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_ClearDeviceIds() -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn SI_GetNumDevices(num_devices: *mut ::std::os::raw::c_int) -> ::std::os::raw::c_int;
 }
@@ -232,12 +376,41 @@ extern "C" {
         flags: ::std::os::raw::c_int,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn SI_GetDeviceNumByPath(
+        path: *const ::std::os::raw::c_char,
+        device_num: *mut ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn SI_Open(
         device_num: ::std::os::raw::c_int,
         p_handle: *mut *mut SiPrivate,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn SI_OpenEx(
+        device_num: ::std::os::raw::c_int,
+        interface: ::std::os::raw::c_int,
+        flags: ::std::os::raw::c_int,
+        read_timeout: ::std::os::raw::c_int,
+        write_timeout: ::std::os::raw::c_int,
+        p_handle: *mut *mut SiPrivate,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_OpenInterface(
+        device_num: ::std::os::raw::c_int,
+        interface: ::std::os::raw::c_int,
+        p_handle: *mut *mut SiPrivate,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_GetNumInterfaces(
+        device_num: ::std::os::raw::c_int,
+        num_interfaces: *mut ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn SI_Close(handle: *mut SiPrivate) -> ::std::os::raw::c_int;
 }
@@ -259,9 +432,100 @@ extern "C" {
         o: *mut std::os::raw::c_void,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn SI_ReadWithTimeout(
+        handle: *mut SiPrivate,
+        buffer: *mut ::std::os::raw::c_char,
+        bytes_to_read: ::std::os::raw::c_int,
+        bytes_returned: *mut ::std::os::raw::c_int,
+        timeout: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_WriteWithTimeout(
+        handle: *mut SiPrivate,
+        buffer: *mut ::std::os::raw::c_char,
+        bytes_to_write: ::std::os::raw::c_int,
+        bytes_written: *mut ::std::os::raw::c_int,
+        timeout: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_CancelIo(handle: *mut SiPrivate) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn SI_ResetDevice(handle: *mut SiPrivate) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn SI_GetDeviceStatus(
+        handle: *mut SiPrivate,
+        status: *mut ::std::os::raw::c_int,
+        timeout: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_GetDeviceDetails(
+        device_num: ::std::os::raw::c_int,
+        path: *mut ::std::os::raw::c_char,
+        driver: *mut ::std::os::raw::c_char,
+        capabilities: *mut ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_GetDeviceInfo(
+        handle: *mut SiPrivate,
+        serial: *mut ::std::os::raw::c_char,
+        description: *mut ::std::os::raw::c_char,
+        vid: *mut ::std::os::raw::c_int,
+        pid: *mut ::std::os::raw::c_int,
+        path: *mut ::std::os::raw::c_char,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_GetDeviceProductString(
+        handle: *mut SiPrivate,
+        product: *mut ::std::os::raw::c_void,
+        length: *mut ::std::os::raw::c_uchar,
+        convert_to_ascii: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_GetInterfaceNumber(
+        handle: *mut SiPrivate,
+        interface: *mut ::std::os::raw::c_uchar,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_GetLibusbHandle(
+        handle: *mut SiPrivate,
+        udev: *mut *mut ::std::os::raw::c_void,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_GetDeviceFd(
+        handle: *mut SiPrivate,
+        fd: *mut ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_GetPartNumber(
+        handle: *mut SiPrivate,
+        part_num: *mut ::std::os::raw::c_uchar,
+    ) -> ::std::os::raw::c_int;
+}
+extern "C" {
+    pub fn SI_ControlTransfer(
+        handle: *mut SiPrivate,
+        request_type: ::std::os::raw::c_int,
+        request: ::std::os::raw::c_int,
+        value: ::std::os::raw::c_int,
+        index: ::std::os::raw::c_int,
+        data: *mut ::std::os::raw::c_char,
+        length: ::std::os::raw::c_int,
+        bytes_transferred: *mut ::std::os::raw::c_int,
+        timeout: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn SI_DeviceIOControl(
         handle: *mut SiPrivate,
@@ -285,12 +549,26 @@ extern "C" {
         write_timeout: ::std::os::raw::c_int,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn SI_SetHandleTimeouts(
+        handle: *mut SiPrivate,
+        read_timeout: ::std::os::raw::c_int,
+        write_timeout: ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn SI_GetTimeouts(
         read_timeout: *mut ::std::os::raw::c_int,
         write_timeout: *mut ::std::os::raw::c_int,
     ) -> ::std::os::raw::c_int;
 }
+extern "C" {
+    pub fn SI_FillRXQueue(
+        handle: *mut SiPrivate,
+        timeout: ::std::os::raw::c_int,
+        num_bytes_in_queue: *mut ::std::os::raw::c_int,
+    ) -> ::std::os::raw::c_int;
+}
 extern "C" {
     pub fn SI_CheckRXQueue(
         handle: *mut SiPrivate,
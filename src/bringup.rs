@@ -0,0 +1,248 @@
+//! Bringing up several devices at once
+//!
+//! Opening a device, configuring it and waiting until its firmware answers
+//! can take a good part of a second. [`BringUp`] does this for a whole set
+//! of devices on a few threads at a time:
+//!
+//! ```rust, ignore
+//! let devices: Vec<usize> = (0..silabs_usb_xpress::devices_count()?).collect();
+//! let handles = BringUp::new().max_parallel(4).run(
+//!     &devices,
+//!     |handle, _| {
+//!         handle.set_nominal_baud_rate(115200);
+//!         Ok(())
+//!     },
+//!     |handle, _| handle.transact(b"PING\n", 5, Duration::from_millis(500)).map(drop),
+//! )?;
+//! ```
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
+
+use crate::{OpenOptions, SilabsUsbXpressError, UsbXpress};
+
+/// Opens, configures and probes devices in parallel
+#[derive(Clone, Debug)]
+pub struct BringUp {
+    options: OpenOptions,
+    max_parallel: usize,
+}
+
+impl Default for BringUp {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BringUp {
+    /// Opens devices with the default [`OpenOptions`], four at a time
+    pub fn new() -> Self {
+        BringUp {
+            options: OpenOptions::new(),
+            max_parallel: 4,
+        }
+    }
+
+    /// Opens every device with `options`
+    pub fn open_options(&mut self, options: OpenOptions) -> &mut Self {
+        self.options = options;
+        self
+    }
+
+    /// Brings up at most `max_parallel` devices at the same time, so that a
+    /// hub is not flooded with requests
+    pub fn max_parallel(&mut self, max_parallel: usize) -> &mut Self {
+        self.max_parallel = max_parallel.max(1);
+        self
+    }
+
+    /// Opens the devices at the indices in `devices`, then calls `configure`
+    /// and `probe` on each
+    ///
+    /// `probe` should return once the device is ready, or fail. The handles
+    /// are returned in the order of `devices` once every device is up. On
+    /// the first failure no further devices are started; devices already in
+    /// progress are finished, all opened handles are closed again and the
+    /// failure is returned.
+    ///
+    /// The devices must have been enumerated before, e.g. with
+    /// [`devices_count`](crate::devices_count), like for
+    /// [`UsbXpress::open`].
+    pub fn run<E, C, P>(
+        &self,
+        devices: &[usize],
+        configure: C,
+        probe: P,
+    ) -> Result<Vec<UsbXpress>, BringUpError<E>>
+    where
+        E: Send,
+        C: Fn(&mut UsbXpress, usize) -> Result<(), E> + Sync,
+        P: Fn(&mut UsbXpress, usize) -> Result<(), E> + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new(Vec::with_capacity(devices.len()));
+        let worker = || loop {
+            if failed.load(Ordering::Relaxed) {
+                break;
+            }
+            let pos = next.fetch_add(1, Ordering::Relaxed);
+            let device_ix = match devices.get(pos) {
+                Some(&device_ix) => device_ix,
+                None => break,
+            };
+            let result = self.bring_up(device_ix, &configure, &probe);
+            if result.is_err() {
+                failed.store(true, Ordering::Relaxed);
+            }
+            results.lock().unwrap().push((pos, result));
+        };
+        thread::scope(|scope| {
+            for _ in 0..self.max_parallel.min(devices.len()) {
+                scope.spawn(worker);
+            }
+        });
+
+        let mut results = results.into_inner().unwrap();
+        results.sort_by_key(|(pos, _)| *pos);
+        let mut handles = Vec::with_capacity(results.len());
+        let mut failure = None;
+        for (_, result) in results {
+            match result {
+                Ok(handle) => handles.push(handle),
+                Err(err) if failure.is_none() => failure = Some(err),
+                Err(_) => {}
+            }
+        }
+        match failure {
+            None => Ok(handles),
+            Some(err) => {
+                for handle in handles {
+                    let _ = handle.close();
+                }
+                Err(err)
+            }
+        }
+    }
+
+    fn bring_up<E, C, P>(
+        &self,
+        device_ix: usize,
+        configure: &C,
+        probe: &P,
+    ) -> Result<UsbXpress, BringUpError<E>>
+    where
+        C: Fn(&mut UsbXpress, usize) -> Result<(), E>,
+        P: Fn(&mut UsbXpress, usize) -> Result<(), E>,
+    {
+        let mut handle = self
+            .options
+            .open(device_ix)
+            .map_err(|error| BringUpError::Open { device_ix, error })?;
+        let result = configure(&mut handle, device_ix)
+            .map_err(|error| BringUpError::Configure { device_ix, error })
+            .and_then(|()| {
+                probe(&mut handle, device_ix)
+                    .map_err(|error| BringUpError::Probe { device_ix, error })
+            });
+        match result {
+            Ok(()) => Ok(handle),
+            Err(err) => {
+                let _ = handle.close();
+                Err(err)
+            }
+        }
+    }
+}
+
+/// The device that failed to come up and why
+#[derive(Debug)]
+pub enum BringUpError<E> {
+    Open {
+        device_ix: usize,
+        error: SilabsUsbXpressError,
+    },
+    Configure {
+        device_ix: usize,
+        error: E,
+    },
+    /// The readiness probe failed
+    Probe {
+        device_ix: usize,
+        error: E,
+    },
+}
+
+impl<E> BringUpError<E> {
+    /// Returns the index of the device that failed
+    pub fn device_ix(&self) -> usize {
+        match self {
+            BringUpError::Open { device_ix, .. }
+            | BringUpError::Configure { device_ix, .. }
+            | BringUpError::Probe { device_ix, .. } => *device_ix,
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for BringUpError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format!("{:?}", self))
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for BringUpError<E> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Indices no test machine has devices at
+    const MISSING: [usize; 3] = [997, 998, 999];
+
+    #[test]
+    fn nothing_to_bring_up_is_not_an_error() {
+        let never = |_: &mut UsbXpress, _| -> Result<(), ()> { unreachable!() };
+        let handles = BringUp::new().run(&[], never, never).unwrap();
+        assert!(handles.is_empty());
+    }
+
+    #[test]
+    fn the_first_failure_stops_the_bring_up() {
+        let calls = AtomicUsize::new(0);
+        let count = |_: &mut UsbXpress, _| -> Result<(), ()> {
+            calls.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        };
+        let err = BringUp::new()
+            .max_parallel(1)
+            .run(&MISSING, count, count)
+            .unwrap_err();
+        assert!(matches!(err, BringUpError::Open { device_ix: 997, .. }));
+        assert_eq!(err.device_ix(), 997);
+        assert_eq!(calls.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn at_least_one_device_is_brought_up_at_a_time() {
+        assert_eq!(BringUp::new().max_parallel(0).max_parallel, 1);
+        assert_eq!(BringUp::new().max_parallel(8).max_parallel, 8);
+    }
+
+    #[test]
+    fn errors_name_the_device_that_failed() {
+        let err = BringUpError::Probe {
+            device_ix: 3,
+            error: "no answer",
+        };
+        assert_eq!(err.device_ix(), 3);
+        assert_eq!(
+            err.to_string(),
+            "Probe { device_ix: 3, error: \"no answer\" }"
+        );
+    }
+}
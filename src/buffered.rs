@@ -0,0 +1,324 @@
+//! Buffered access to a device
+//!
+//! [`BufferedUsbXpress`] keeps independent receive and transmit buffers in
+//! front of a [`UsbXpress`] handle, or any other [`Transport`], and
+//! implements [`Read`], [`BufRead`] and [`Write`] on top of them. Unlike wrapping the handle in the generic
+//! `BufReader`/`BufWriter`, both capacities default to the transfer sizes the
+//! USB Xpress driver works with, and callbacks can be attached that fire when
+//! either buffer fills past a high-water mark.
+use std::{
+    fmt,
+    io::{self, BufRead, Read, Write},
+};
+
+use crate::{ffi::SI_MAX_WRITE_SIZE, transport::Transport, SilabsUsbXpressError, UsbXpress};
+
+/// Default receive capacity, the size of the driver's own receive buffer
+pub const DEFAULT_RX_CAPACITY: usize = 4096;
+/// Default transmit capacity, the largest single write the driver accepts
+pub const DEFAULT_TX_CAPACITY: usize = SI_MAX_WRITE_SIZE as usize;
+
+type HighWaterCallback = Box<dyn FnMut(usize) + Send>;
+
+struct HighWater {
+    level: usize,
+    callback: HighWaterCallback,
+}
+
+impl HighWater {
+    fn check(&mut self, buffered: usize) {
+        if buffered >= self.level {
+            (self.callback)(buffered);
+        }
+    }
+}
+
+/// A [`UsbXpress`] handle with receive and transmit buffers
+///
+/// Any [`Transport`] can be wrapped, a [`UsbXpress`] handle by default.
+/// Buffered transmit data is flushed when the buffer runs full, on
+/// [`flush`](Write::flush), and on drop; errors while flushing on drop are
+/// ignored, so call `flush` explicitly when they matter.
+pub struct BufferedUsbXpress<T: Transport = UsbXpress> {
+    inner: Option<T>,
+    rx: Vec<u8>,
+    rx_pos: usize,
+    rx_capacity: usize,
+    tx: Vec<u8>,
+    tx_capacity: usize,
+    rx_high_water: Option<HighWater>,
+    tx_high_water: Option<HighWater>,
+}
+
+impl<T: Transport> BufferedUsbXpress<T> {
+    /// Wraps `inner` with the default receive and transmit capacities
+    pub fn new(inner: T) -> Self {
+        Self::with_capacities(DEFAULT_RX_CAPACITY, DEFAULT_TX_CAPACITY, inner)
+    }
+
+    /// Wraps `inner` with the given receive and transmit capacities
+    ///
+    /// A capacity of zero is rounded up to one byte.
+    pub fn with_capacities(rx_capacity: usize, tx_capacity: usize, inner: T) -> Self {
+        let rx_capacity = rx_capacity.max(1);
+        let tx_capacity = tx_capacity.max(1);
+        BufferedUsbXpress {
+            inner: Some(inner),
+            rx: Vec::with_capacity(rx_capacity),
+            rx_pos: 0,
+            rx_capacity,
+            tx: Vec::with_capacity(tx_capacity),
+            tx_capacity,
+            rx_high_water: None,
+            tx_high_water: None,
+        }
+    }
+
+    /// Calls `callback` whenever a receive fills the buffer to `level` bytes
+    /// or more
+    ///
+    /// The callback gets the number of unread bytes in the buffer, which lets
+    /// a consumer notice it is falling behind the device.
+    pub fn on_rx_high_water<F: FnMut(usize) + Send + 'static>(
+        &mut self,
+        level: usize,
+        callback: F,
+    ) {
+        self.rx_high_water = Some(HighWater {
+            level,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Calls `callback` whenever a write leaves `level` bytes or more
+    /// waiting in the transmit buffer
+    pub fn on_tx_high_water<F: FnMut(usize) + Send + 'static>(
+        &mut self,
+        level: usize,
+        callback: F,
+    ) {
+        self.tx_high_water = Some(HighWater {
+            level,
+            callback: Box::new(callback),
+        });
+    }
+
+    /// Returns the receive buffer capacity
+    pub fn rx_capacity(&self) -> usize {
+        self.rx_capacity
+    }
+
+    /// Returns the transmit buffer capacity
+    pub fn tx_capacity(&self) -> usize {
+        self.tx_capacity
+    }
+
+    /// Returns the received bytes not consumed yet
+    pub fn rx_buffer(&self) -> &[u8] {
+        &self.rx[self.rx_pos..]
+    }
+
+    /// Returns the number of bytes waiting to be transmitted
+    pub fn tx_buffered(&self) -> usize {
+        self.tx.len()
+    }
+
+    /// Gets a reference to the underlying handle
+    pub fn get_ref(&self) -> &T {
+        self.inner.as_ref().expect("handle taken by into_inner")
+    }
+
+    /// Gets a mutable reference to the underlying handle
+    ///
+    /// Reading from or writing to it directly bypasses the buffers.
+    pub fn get_mut(&mut self) -> &mut T {
+        self.inner.as_mut().expect("handle taken by into_inner")
+    }
+
+    /// Flushes the transmit buffer and returns the underlying handle
+    ///
+    /// Received data still in the buffer is lost.
+    pub fn into_inner(mut self) -> io::Result<T> {
+        self.flush_tx()?;
+        Ok(self.inner.take().expect("handle taken by into_inner"))
+    }
+
+    fn flush_tx(&mut self) -> io::Result<()> {
+        while !self.tx.is_empty() {
+            let inner = self.inner.as_mut().expect("handle taken by into_inner");
+            let written = inner.write(&self.tx)?;
+            if written == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    SilabsUsbXpressError::WriteError,
+                ));
+            }
+            self.tx.drain(..written.min(self.tx.len()));
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport> Read for BufferedUsbXpress<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<T: Transport> BufRead for BufferedUsbXpress<T> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.rx_pos >= self.rx.len() {
+            // Receives straight into the buffer, which keeps its allocation
+            self.rx.resize(self.rx_capacity, 0);
+            self.rx_pos = 0;
+            let inner = self.inner.as_mut().expect("handle taken by into_inner");
+            let received = inner.read_into(&mut self.rx);
+            self.rx.truncate(*received.as_ref().unwrap_or(&0));
+            received?;
+            let buffered = self.rx.len();
+            if let Some(high_water) = self.rx_high_water.as_mut() {
+                high_water.check(buffered);
+            }
+        }
+        Ok(&self.rx[self.rx_pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.rx_pos = (self.rx_pos + amt).min(self.rx.len());
+    }
+}
+
+impl<T: Transport> Write for BufferedUsbXpress<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.tx.len() >= self.tx_capacity {
+            self.flush_tx()?;
+        }
+        let n = buf.len().min(self.tx_capacity - self.tx.len());
+        self.tx.extend_from_slice(&buf[..n]);
+        let buffered = self.tx.len();
+        if let Some(high_water) = self.tx_high_water.as_mut() {
+            high_water.check(buffered);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.flush_tx()
+    }
+}
+
+impl<T: Transport> Drop for BufferedUsbXpress<T> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.flush_tx();
+        }
+    }
+}
+
+impl<T: Transport + fmt::Debug> fmt::Debug for BufferedUsbXpress<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BufferedUsbXpress")
+            .field("inner", &self.inner)
+            .field("rx_buffered", &(self.rx.len() - self.rx_pos))
+            .field("rx_capacity", &self.rx_capacity)
+            .field("tx_buffered", &self.tx.len())
+            .field("tx_capacity", &self.tx_capacity)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{assert_transcript, transcript::Transcript};
+
+    #[test]
+    fn reads_are_served_from_the_buffer() {
+        let transcript = Transcript::new().reply(b"HELLO WORLD");
+        assert_transcript!(transcript, |device| {
+            let mut buffered = BufferedUsbXpress::with_capacities(4, 4, device);
+            assert_eq!(buffered.fill_buf().unwrap(), b"HELL");
+            buffered.consume(2);
+            assert_eq!(buffered.rx_buffer(), b"LL");
+            // Consuming past the end empties the buffer and nothing more
+            buffered.consume(10);
+            assert!(buffered.rx_buffer().is_empty());
+            let mut rest = [0; 8];
+            assert_eq!(buffered.read(&mut rest).unwrap(), 4);
+            assert_eq!(&rest[..4], b"O WO");
+            assert_eq!(buffered.fill_buf().unwrap(), b"RLD");
+            buffered.consume(3);
+            let err = buffered.fill_buf().unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        });
+    }
+
+    #[test]
+    fn zero_capacities_are_rounded_up() {
+        assert_transcript!(Transcript::new().reply(b"AB").write(b"xy"), |device| {
+            let mut buffered = BufferedUsbXpress::with_capacities(0, 0, device);
+            assert_eq!((buffered.rx_capacity(), buffered.tx_capacity()), (1, 1));
+            assert_eq!(buffered.fill_buf().unwrap(), b"A");
+            assert_eq!(buffered.write(b"xy").unwrap(), 1);
+            assert_eq!(buffered.write(b"y").unwrap(), 1);
+            buffered.flush().unwrap();
+        });
+    }
+
+    #[test]
+    fn high_water_callbacks_fire_at_their_level() {
+        let transcript = Transcript::new().reply(b"ABCDEF").write(b"PING\n");
+        assert_transcript!(transcript, |device| {
+            let rx_levels = Arc::new(Mutex::new(Vec::new()));
+            let tx_levels = Arc::new(Mutex::new(Vec::new()));
+            let mut buffered = BufferedUsbXpress::with_capacities(4, 8, device);
+            let levels = rx_levels.clone();
+            buffered.on_rx_high_water(3, move |n| levels.lock().unwrap().push(n));
+            let levels = tx_levels.clone();
+            buffered.on_tx_high_water(4, move |n| levels.lock().unwrap().push(n));
+
+            buffered.fill_buf().unwrap();
+            buffered.consume(4);
+            buffered.fill_buf().unwrap();
+            assert_eq!(*rx_levels.lock().unwrap(), [4]);
+
+            buffered.write_all(b"PI").unwrap();
+            buffered.write_all(b"NG\n").unwrap();
+            assert_eq!(*tx_levels.lock().unwrap(), [5]);
+            buffered.flush().unwrap();
+        });
+    }
+
+    #[test]
+    fn flushing_drains_the_transmit_buffer() {
+        assert_transcript!(Transcript::new().write(b"PING\nPONG\n"), |device| {
+            let mut buffered = BufferedUsbXpress::with_capacities(16, 4, device);
+            buffered.write_all(b"PING\n").unwrap();
+            // The full buffer went out to make room for the newline
+            assert_eq!(buffered.tx_buffered(), 1);
+            buffered.write_all(b"PONG\n").unwrap();
+            buffered.flush().unwrap();
+            assert_eq!(buffered.tx_buffered(), 0);
+        });
+
+        let result = Transcript::new().write(b"PING\n").run(|device| {
+            let mut buffered = BufferedUsbXpress::new(device);
+            buffered.write_all(b"PONG\n").unwrap();
+            assert_eq!(buffered.tx_buffered(), 5);
+            assert!(buffered.flush().is_err());
+        });
+        assert!(result.is_err());
+
+        // Dropping flushes too
+        assert_transcript!(Transcript::new().write(b"BYE\n"), |device| {
+            let mut buffered = BufferedUsbXpress::new(device);
+            buffered.write_all(b"BYE\n").unwrap();
+        });
+    }
+}
@@ -0,0 +1,183 @@
+//! Sharing one half-duplex bus between several clients
+//!
+//! On a multi-drop RS-485 network every sensor hangs off the same handle and
+//! only one request/response exchange can be on the wire at a time. A
+//! [`BusMultiplexer`] queues the transactions of several logical clients and
+//! runs them one after the other, taking turns between clients so a chatty
+//! one cannot starve the rest:
+//!
+//! ```rust, ignore
+//! let mut bus = BusMultiplexer::new(handle);
+//! bus.set_gap(Duration::from_millis(4));
+//! let thermo = bus.add_client(Duration::from_millis(100));
+//! let valve = bus.add_client(Duration::from_millis(250));
+//! bus.submit(thermo, b"\x01\x03\x00\x00\x00\x01".to_vec(), 7);
+//! bus.submit(valve, b"\x02\x03\x00\x10\x00\x02".to_vec(), 9);
+//! while let Some(completion) = bus.poll() {
+//!     println!("{:?}: {:?}", completion.client, completion.result);
+//! }
+//! ```
+use std::{
+    collections::VecDeque,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{SilabsUsbXpressError, UsbXpress};
+
+/// Identifies a client of a [`BusMultiplexer`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ClientId(usize);
+
+/// A finished transaction, returned by [`BusMultiplexer::poll`]
+#[derive(Debug)]
+pub struct Completion {
+    pub client: ClientId,
+    /// The request as it was submitted
+    pub request: Vec<u8>,
+    /// The response, see [`UsbXpress::transact`]
+    pub result: Result<Vec<u8>, SilabsUsbXpressError>,
+}
+
+struct Pending {
+    request: Vec<u8>,
+    response_len: usize,
+}
+
+struct Client {
+    timeout: Duration,
+    queue: VecDeque<Pending>,
+}
+
+/// Serializes the transactions of several clients onto one handle
+///
+/// Transactions are run with [`UsbXpress::transact`], one per call to
+/// [`poll`](BusMultiplexer::poll). Clients with queued transactions take
+/// turns in round-robin order; within one client, transactions run in the
+/// order they were submitted.
+pub struct BusMultiplexer {
+    handle: UsbXpress,
+    clients: Vec<Client>,
+    next: usize,
+    gap: Duration,
+    last_transaction: Option<Instant>,
+}
+
+impl BusMultiplexer {
+    pub fn new(handle: UsbXpress) -> Self {
+        BusMultiplexer {
+            handle,
+            clients: Vec::new(),
+            next: 0,
+            gap: Duration::ZERO,
+            last_transaction: None,
+        }
+    }
+
+    /// Keeps the bus idle for at least `gap` between the end of one
+    /// transaction and the start of the next, giving slow nodes time to
+    /// release the line
+    pub fn set_gap(&mut self, gap: Duration) {
+        self.gap = gap;
+    }
+
+    /// Registers a client whose transactions each have `timeout` to complete
+    pub fn add_client(&mut self, timeout: Duration) -> ClientId {
+        self.clients.push(Client {
+            timeout,
+            queue: VecDeque::new(),
+        });
+        ClientId(self.clients.len() - 1)
+    }
+
+    /// Queues `request` on behalf of `client`, expecting a `response_len`
+    /// byte response
+    ///
+    /// # Panics
+    ///
+    /// Panics if `client` was not returned by this multiplexer.
+    pub fn submit(&mut self, client: ClientId, request: Vec<u8>, response_len: usize) {
+        self.clients[client.0].queue.push_back(Pending {
+            request,
+            response_len,
+        });
+    }
+
+    /// Returns the number of transactions waiting to run
+    pub fn pending(&self) -> usize {
+        self.clients.iter().map(|client| client.queue.len()).sum()
+    }
+
+    /// Runs the next queued transaction and returns its outcome, or `None`
+    /// if no client has anything queued
+    pub fn poll(&mut self) -> Option<Completion> {
+        let ix = next_client(&self.clients, self.next)?;
+        self.next = (ix + 1) % self.clients.len();
+        let client = &mut self.clients[ix];
+        let timeout = client.timeout;
+        let pending = client.queue.pop_front()?;
+        if let Some(last) = self.last_transaction {
+            let idle = last.elapsed();
+            if idle < self.gap {
+                thread::sleep(self.gap - idle);
+            }
+        }
+        let result = self
+            .handle
+            .transact(&pending.request, pending.response_len, timeout);
+        self.last_transaction = Some(Instant::now());
+        Some(Completion {
+            client: ClientId(ix),
+            request: pending.request,
+            result,
+        })
+    }
+
+    /// Gives access to the handle, e.g. to reconfigure it between
+    /// transactions
+    pub fn handle_mut(&mut self) -> &mut UsbXpress {
+        &mut self.handle
+    }
+
+    /// Returns the handle, dropping all queued transactions
+    pub fn into_inner(self) -> UsbXpress {
+        self.handle
+    }
+}
+
+/// Returns the first client at or after `start`, wrapping around, that has
+/// a transaction queued
+fn next_client(clients: &[Client], start: usize) -> Option<usize> {
+    (0..clients.len())
+        .map(|offset| (start + offset) % clients.len())
+        .find(|&ix| !clients[ix].queue.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client(queued: usize) -> Client {
+        let mut queue = VecDeque::new();
+        for _ in 0..queued {
+            queue.push_back(Pending {
+                request: Vec::new(),
+                response_len: 0,
+            });
+        }
+        Client {
+            timeout: Duration::ZERO,
+            queue,
+        }
+    }
+
+    #[test]
+    fn next_client_takes_turns_and_skips_idle_clients() {
+        let clients = [client(3), client(0), client(1)];
+        assert_eq!(next_client(&clients, 0), Some(0));
+        assert_eq!(next_client(&clients, 1), Some(2));
+        assert_eq!(next_client(&clients, 3 % clients.len()), Some(0));
+        assert_eq!(next_client(&[client(0), client(0)], 1), None);
+        assert_eq!(next_client(&[], 0), None);
+    }
+}
@@ -0,0 +1,237 @@
+//! Calibration data stored in device flash
+//!
+//! Products often keep per-unit calibration in a flash page of the device,
+//! read and written with vendor requests. A [`CalibrationStore`] names those
+//! requests and the layout version of the data, and
+//! [`UsbXpress::read_calibration`] and [`UsbXpress::write_calibration`] store
+//! any serde type in a record with a version and a CRC:
+//!
+//! ```rust, ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct Calibration { offset: i16, gain: f32 }
+//!
+//! let store = CalibrationStore::new(0x40, 0x41, 2);
+//! handle.write_calibration(&store, &Calibration { offset: -3, gain: 1.02 })?;
+//! let calibration: Calibration = handle.read_calibration(&store)?;
+//! ```
+//!
+//! The firmware answers the read request with `wLength` bytes of the record
+//! starting at the byte offset in `wValue`, and stores the data of the write
+//! request at the offset in `wValue`. Records start with the magic `SXCL`,
+//! then the layout version and the payload length as little endian 16 bit
+//! words, then the payload in [postcard](https://docs.rs/postcard) encoding,
+//! and end with the little endian CRC-32 of everything before it.
+use std::convert::TryInto;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{SilabsUsbXpressError, UsbXpress};
+
+const MAGIC: &[u8; 4] = b"SXCL";
+/// Magic, version and payload length
+const HEADER: usize = 8;
+const CRC: usize = 4;
+
+/// The vendor requests reading and writing calibration records, and the
+/// layout version of the data stored with them
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CalibrationStore {
+    read_request: u8,
+    write_request: u8,
+    version: u16,
+    chunk: usize,
+}
+
+impl CalibrationStore {
+    /// Reads with vendor request `read_request` and writes with
+    /// `write_request`, in 64 byte chunks
+    ///
+    /// `version` is stored with the data and checked when reading it back;
+    /// bump it whenever the layout of the calibration type changes.
+    pub fn new(read_request: u8, write_request: u8, version: u16) -> Self {
+        CalibrationStore {
+            read_request,
+            write_request,
+            version,
+            chunk: 64,
+        }
+    }
+
+    /// Transfers at most `chunk` bytes per request, 64 by default
+    ///
+    /// A chunk of zero is treated as one.
+    pub fn chunk(mut self, chunk: usize) -> Self {
+        self.chunk = chunk.max(1);
+        self
+    }
+}
+
+impl UsbXpress {
+    /// Reads the calibration record from flash and decodes it
+    ///
+    /// Fails with `InvalidCalibration` if the record is damaged or does not
+    /// decode as `T`, and with `CalibrationVersionMismatch` if it was written
+    /// with another version of the store.
+    pub fn read_calibration<T: DeserializeOwned>(
+        &mut self,
+        store: &CalibrationStore,
+    ) -> Result<T, SilabsUsbXpressError> {
+        let mut record = self.read_calibration_chunk(store, 0, HEADER)?;
+        let len = record_len(&record)?;
+        while record.len() < len {
+            let chunk = store.chunk.min(len - record.len());
+            let data = self.read_calibration_chunk(store, record.len(), chunk)?;
+            record.extend_from_slice(&data);
+        }
+        let payload = decode(&record, store.version)?;
+        postcard::from_bytes(payload).map_err(|err| invalid(err.to_string()))
+    }
+
+    /// Encodes `calibration` and writes it to flash
+    ///
+    /// Fails with `InvalidCalibration` if `calibration` cannot be encoded,
+    /// and with `InvalidRequestLength` if the record does not fit in 64 KiB.
+    pub fn write_calibration<T: Serialize>(
+        &mut self,
+        store: &CalibrationStore,
+        calibration: &T,
+    ) -> Result<(), SilabsUsbXpressError> {
+        let payload = postcard::to_stdvec(calibration).map_err(|err| invalid(err.to_string()))?;
+        let record = encode(store.version, &payload)?;
+        for (i, chunk) in record.chunks(store.chunk).enumerate() {
+            self.control_out(store.write_request, w_value(i * store.chunk)?, chunk)?;
+        }
+        Ok(())
+    }
+
+    /// Reads `len` bytes of the record at `offset`, failing if fewer arrive
+    fn read_calibration_chunk(
+        &mut self,
+        store: &CalibrationStore,
+        offset: usize,
+        len: usize,
+    ) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        let data = self.control_in(store.read_request, w_value(offset)?, len)?;
+        if data.len() < len {
+            return Err(invalid(format!(
+                "record ends after {} bytes",
+                offset + data.len()
+            )));
+        }
+        Ok(data)
+    }
+}
+
+fn invalid(reason: String) -> SilabsUsbXpressError {
+    SilabsUsbXpressError::InvalidCalibration(reason)
+}
+
+/// The `wValue` addressing byte `offset` of a record
+fn w_value(offset: usize) -> Result<u16, SilabsUsbXpressError> {
+    offset
+        .try_into()
+        .map_err(|_| SilabsUsbXpressError::InvalidRequestLength)
+}
+
+/// Frames `payload` as a record of layout `version`
+fn encode(version: u16, payload: &[u8]) -> Result<Vec<u8>, SilabsUsbXpressError> {
+    let len: u16 = payload
+        .len()
+        .try_into()
+        .map_err(|_| SilabsUsbXpressError::InvalidRequestLength)?;
+    let mut record = Vec::with_capacity(HEADER + payload.len() + CRC);
+    record.extend_from_slice(MAGIC);
+    record.extend_from_slice(&version.to_le_bytes());
+    record.extend_from_slice(&len.to_le_bytes());
+    record.extend_from_slice(payload);
+    let crc = crc32fast::hash(&record);
+    record.extend_from_slice(&crc.to_le_bytes());
+    // The last chunk must still be addressable by its offset
+    w_value(record.len() - 1)?;
+    Ok(record)
+}
+
+/// The length of the whole record starting with `header`
+fn record_len(header: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+    if header.len() < HEADER || &header[..4] != MAGIC {
+        return Err(invalid("no calibration record".to_owned()));
+    }
+    let len = u16::from_le_bytes([header[6], header[7]]);
+    Ok(HEADER + usize::from(len) + CRC)
+}
+
+/// Checks `record` and returns its payload
+fn decode(record: &[u8], version: u16) -> Result<&[u8], SilabsUsbXpressError> {
+    let len = record_len(record)?;
+    if record.len() != len {
+        return Err(invalid(format!(
+            "record is {} bytes, its header says {}",
+            record.len(),
+            len
+        )));
+    }
+    let (framed, crc) = record.split_at(len - CRC);
+    if crc32fast::hash(framed).to_le_bytes() != crc {
+        return Err(invalid("CRC mismatch".to_owned()));
+    }
+    let found = u16::from_le_bytes([record[4], record[5]]);
+    if found != version {
+        return Err(SilabsUsbXpressError::CalibrationVersionMismatch {
+            found,
+            expected: version,
+        });
+    }
+    Ok(&framed[HEADER..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_round_trip() {
+        let payload = postcard::to_stdvec(&(-3i16, 1.02f32)).unwrap();
+        let record = encode(2, &payload).unwrap();
+        assert_eq!(&record[..4], b"SXCL");
+        assert_eq!(record_len(&record[..HEADER]).unwrap(), record.len());
+        let decoded: (i16, f32) = postcard::from_bytes(decode(&record, 2).unwrap()).unwrap();
+        assert_eq!(decoded, (-3, 1.02));
+    }
+
+    #[test]
+    fn damaged_and_foreign_records_are_rejected() {
+        let record = encode(2, b"calibration").unwrap();
+        assert!(matches!(
+            decode(&record, 3),
+            Err(SilabsUsbXpressError::CalibrationVersionMismatch {
+                found: 2,
+                expected: 3
+            })
+        ));
+
+        let mut flipped = record.clone();
+        flipped[HEADER] ^= 1;
+        assert!(matches!(
+            decode(&flipped, 2),
+            Err(SilabsUsbXpressError::InvalidCalibration(_))
+        ));
+        assert!(matches!(
+            decode(&record[..record.len() - 1], 2),
+            Err(SilabsUsbXpressError::InvalidCalibration(_))
+        ));
+        // Erased flash
+        assert!(matches!(
+            record_len(&[0xff; HEADER]),
+            Err(SilabsUsbXpressError::InvalidCalibration(_))
+        ));
+    }
+
+    #[test]
+    fn oversized_records_are_refused() {
+        assert!(encode(1, &vec![0; usize::from(u16::MAX) - HEADER - CRC]).is_ok());
+        assert!(matches!(
+            encode(1, &vec![0; usize::from(u16::MAX)]),
+            Err(SilabsUsbXpressError::InvalidRequestLength)
+        ));
+    }
+}
@@ -0,0 +1,318 @@
+//! C API over the safe wrapper
+//!
+//! Enabled with the `capi` feature. Build a shared library with
+//!
+//! ```sh
+//! cargo rustc --release --features capi --crate-type cdylib
+//! ```
+//!
+//! and include `include/silabs_usb_xpress.h`. Functions are prefixed `sixp_`
+//! so that they cannot clash with the `SI_` symbols of the bundled shim, and
+//! return the status codes of SiUSBXp.dll, which lets applications written
+//! against the vendor DLL keep their error handling.
+use std::{
+    os::raw::{c_char, c_int},
+    ptr, slice,
+};
+
+use crate::{ffi::*, product_string, ProductStringType, SilabsUsbXpressError, UsbXpress};
+
+/// Opaque device handle handed out to C
+pub struct SixpHandle(UsbXpress);
+
+fn status_of(err: &SilabsUsbXpressError) -> c_int {
+    let status = match err {
+        SilabsUsbXpressError::DeviceNotFound
+        | SilabsUsbXpressError::SerialNotFound(_)
+        | SilabsUsbXpressError::VidPidNotFound { .. } => SI_DEVICE_NOT_FOUND,
+        SilabsUsbXpressError::DuplicateSerial { .. }
+        | SilabsUsbXpressError::InterfaceNotFound(_)
+        | SilabsUsbXpressError::InvalidDeviceIds(_)
+        | SilabsUsbXpressError::InvalidEnvironment(_) => SI_INVALID_PARAMETER,
+        SilabsUsbXpressError::GlobalDataError | SilabsUsbXpressError::VidPidTableFull => {
+            SI_GLOBAL_DATA_ERROR
+        }
+        SilabsUsbXpressError::ReadError
+        | SilabsUsbXpressError::EchoMismatch { .. }
+        | SilabsUsbXpressError::InvalidFirmwareVersion(_)
+        | SilabsUsbXpressError::IncompatibleFirmware { .. }
+        | SilabsUsbXpressError::InvalidCalibration(_)
+        | SilabsUsbXpressError::CalibrationVersionMismatch { .. } => SI_READ_ERROR,
+        SilabsUsbXpressError::ReadTimeOut { .. } | SilabsUsbXpressError::IncompleteRead { .. } => {
+            SI_READ_TIMED_OUT
+        }
+        SilabsUsbXpressError::IoPending => SI_IO_PENDING,
+        SilabsUsbXpressError::InvalidRequestLength => SI_INVALID_REQUEST_LENGTH,
+        SilabsUsbXpressError::DeviceIoFailed
+        | SilabsUsbXpressError::DeviceBusy
+        | SilabsUsbXpressError::KernelDriverConflict { .. } => SI_DEVICE_IO_FAILED,
+        SilabsUsbXpressError::NeedsReopen { .. } => SI_INVALID_HANDLE,
+        SilabsUsbXpressError::WriteError => SI_WRITE_ERROR,
+        SilabsUsbXpressError::WriteTimeOut { .. } => SI_WRITE_TIMED_OUT,
+        SilabsUsbXpressError::UnsupportedBaudRate { .. }
+        | SilabsUsbXpressError::InvalidBaudDivisor(_) => SI_INVALID_BAUDRATE,
+        SilabsUsbXpressError::ConnectionError
+        | SilabsUsbXpressError::SystemErrorCode
+        | SilabsUsbXpressError::OpenTimedOut
+        | SilabsUsbXpressError::DeviceWaitTimedOut
+        | SilabsUsbXpressError::Cancelled => SI_SYSTEM_ERROR_CODE,
+    };
+    status as c_int
+}
+
+/// Stores the number of connected devices in `count`
+///
+/// # Safety
+///
+/// `count` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sixp_devices_count(count: *mut usize) -> c_int {
+    if count.is_null() {
+        return SI_INVALID_PARAMETER as c_int;
+    }
+    match crate::devices_count() {
+        Ok(n) => {
+            *count = n;
+            SI_SUCCESS as c_int
+        }
+        Err(err) => status_of(&err),
+    }
+}
+
+/// Copies a product string of device `index` into `buffer` as a null
+/// terminated string
+///
+/// `flags` takes the `SI_RETURN_*` values of SiUSBXp.dll. Strings longer
+/// than `len - 1` bytes are truncated.
+///
+/// # Safety
+///
+/// `buffer` must be null or valid for writes of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn sixp_product_string(
+    index: usize,
+    flags: c_int,
+    buffer: *mut c_char,
+    len: usize,
+) -> c_int {
+    let kind = match flags as u32 {
+        SI_RETURN_SERIAL_NUMBER => ProductStringType::SerialNumber,
+        SI_RETURN_DESCRIPTION => ProductStringType::Description,
+        SI_RETURN_LINK_NAME => ProductStringType::LinkName,
+        SI_RETURN_VID => ProductStringType::VID,
+        SI_RETURN_PID => ProductStringType::PID,
+        _ => return SI_INVALID_PARAMETER as c_int,
+    };
+    if buffer.is_null() || len == 0 {
+        return SI_INVALID_PARAMETER as c_int;
+    }
+    match product_string(index, kind) {
+        Ok(string) => {
+            copy_truncated(&string, buffer, len);
+            SI_SUCCESS as c_int
+        }
+        Err(err) => status_of(&err),
+    }
+}
+
+/// Copies as much of `string` as fits into `buffer` of `len` bytes, followed
+/// by a null
+unsafe fn copy_truncated(string: &str, buffer: *mut c_char, len: usize) {
+    let n = string.len().min(len - 1);
+    ptr::copy_nonoverlapping(string.as_ptr() as *const c_char, buffer, n);
+    *buffer.add(n) = 0;
+}
+
+/// Opens device `index` and stores its handle in `handle`
+///
+/// # Safety
+///
+/// `handle` must be null or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sixp_open(index: usize, handle: *mut *mut SixpHandle) -> c_int {
+    if handle.is_null() {
+        return SI_INVALID_PARAMETER as c_int;
+    }
+    match UsbXpress::open(index) {
+        Ok(device) => {
+            *handle = Box::into_raw(Box::new(SixpHandle(device)));
+            SI_SUCCESS as c_int
+        }
+        Err(err) => status_of(&err),
+    }
+}
+
+/// Reads up to `len` bytes into `buffer`, storing the count in `read`
+///
+/// # Safety
+///
+/// `handle` must come from [`sixp_open`] and not be closed yet, `buffer`
+/// must be valid for writes of `len` bytes and `read` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sixp_read(
+    handle: *mut SixpHandle,
+    buffer: *mut u8,
+    len: usize,
+    read: *mut usize,
+) -> c_int {
+    if handle.is_null() {
+        return SI_INVALID_HANDLE as c_int;
+    }
+    if (buffer.is_null() && len > 0) || read.is_null() {
+        return SI_INVALID_PARAMETER as c_int;
+    }
+    *read = 0;
+    match (*handle).0.read(len) {
+        Ok(data) => {
+            ptr::copy_nonoverlapping(data.as_ptr(), buffer, data.len());
+            *read = data.len();
+            SI_SUCCESS as c_int
+        }
+        Err(err) => status_of(&err),
+    }
+}
+
+/// Writes `len` bytes from `buffer`, storing the count in `written`
+///
+/// # Safety
+///
+/// `handle` must come from [`sixp_open`] and not be closed yet, `buffer`
+/// must be valid for reads of `len` bytes and `written` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn sixp_write(
+    handle: *mut SixpHandle,
+    buffer: *const u8,
+    len: usize,
+    written: *mut usize,
+) -> c_int {
+    if handle.is_null() {
+        return SI_INVALID_HANDLE as c_int;
+    }
+    if (buffer.is_null() && len > 0) || written.is_null() {
+        return SI_INVALID_PARAMETER as c_int;
+    }
+    *written = 0;
+    let data = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(buffer, len)
+    };
+    match (*handle).0.write(data) {
+        Ok(n) => {
+            *written = n;
+            SI_SUCCESS as c_int
+        }
+        Err(err) => status_of(&err),
+    }
+}
+
+/// Closes `handle`, which must not be used afterwards
+///
+/// # Safety
+///
+/// `handle` must come from [`sixp_open`] and not be closed yet.
+#[no_mangle]
+pub unsafe extern "C" fn sixp_close(handle: *mut SixpHandle) -> c_int {
+    if handle.is_null() {
+        return SI_INVALID_HANDLE as c_int;
+    }
+    match Box::from_raw(handle).0.close() {
+        Ok(()) => SI_SUCCESS as c_int,
+        Err(err) => status_of(&err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A handle that passes the null check but must never be dereferenced
+    fn dangling() -> *mut SixpHandle {
+        ptr::NonNull::dangling().as_ptr()
+    }
+
+    #[test]
+    fn null_out_pointers_are_invalid_parameters() {
+        let invalid = SI_INVALID_PARAMETER as c_int;
+        unsafe {
+            assert_eq!(sixp_devices_count(ptr::null_mut()), invalid);
+            assert_eq!(sixp_open(0, ptr::null_mut()), invalid);
+            let mut n = 0;
+            let mut byte = 0;
+            assert_eq!(sixp_read(dangling(), ptr::null_mut(), 1, &mut n), invalid);
+            assert_eq!(
+                sixp_read(dangling(), &mut byte, 1, ptr::null_mut()),
+                invalid
+            );
+            assert_eq!(sixp_write(dangling(), ptr::null(), 1, &mut n), invalid);
+            assert_eq!(sixp_write(dangling(), &byte, 1, ptr::null_mut()), invalid);
+        }
+    }
+
+    #[test]
+    fn null_handles_are_invalid_handles() {
+        let invalid = SI_INVALID_HANDLE as c_int;
+        let (mut n, mut byte) = (0, 0);
+        unsafe {
+            assert_eq!(sixp_read(ptr::null_mut(), &mut byte, 1, &mut n), invalid);
+            assert_eq!(sixp_write(ptr::null_mut(), &byte, 1, &mut n), invalid);
+            assert_eq!(sixp_close(ptr::null_mut()), invalid);
+        }
+    }
+
+    #[test]
+    fn product_strings_need_a_known_flag_and_room_for_the_null() {
+        let invalid = SI_INVALID_PARAMETER as c_int;
+        let mut buffer = [0 as c_char; 8];
+        unsafe {
+            assert_eq!(
+                sixp_product_string(0, 0x7F, buffer.as_mut_ptr(), 8),
+                invalid
+            );
+            let serial = SI_RETURN_SERIAL_NUMBER as c_int;
+            assert_eq!(sixp_product_string(0, serial, ptr::null_mut(), 8), invalid);
+            assert_eq!(
+                sixp_product_string(0, serial, buffer.as_mut_ptr(), 0),
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn long_strings_are_truncated_and_null_terminated() {
+        let mut buffer = [0x55 as c_char; 6];
+        unsafe { copy_truncated("CP2102N", buffer.as_mut_ptr(), buffer.len()) };
+        assert_eq!(buffer.map(|c| c as u8), *b"CP210\0");
+        let mut buffer = [0x55 as c_char; 6];
+        unsafe { copy_truncated("CP", buffer.as_mut_ptr(), buffer.len()) };
+        assert_eq!(
+            buffer[..3].iter().map(|&c| c as u8).collect::<Vec<_>>(),
+            b"CP\0"
+        );
+        let mut buffer = [0x55 as c_char; 1];
+        unsafe { copy_truncated("CP", buffer.as_mut_ptr(), buffer.len()) };
+        assert_eq!(buffer[0], 0);
+    }
+
+    #[test]
+    fn errors_map_to_the_status_codes_of_the_vendor_dll() {
+        assert_eq!(
+            status_of(&SilabsUsbXpressError::DeviceNotFound),
+            SI_DEVICE_NOT_FOUND as c_int
+        );
+        assert_eq!(
+            status_of(&SilabsUsbXpressError::InvalidEnvironment(vec![
+                "10c4".into()
+            ])),
+            SI_INVALID_PARAMETER as c_int
+        );
+        assert_eq!(
+            status_of(&SilabsUsbXpressError::IoPending),
+            SI_IO_PENDING as c_int
+        );
+        assert_eq!(
+            status_of(&SilabsUsbXpressError::Cancelled),
+            SI_SYSTEM_ERROR_CODE as c_int
+        );
+    }
+}
@@ -0,0 +1,73 @@
+//! Splitting the byte stream of a device into frames
+//!
+//! A [`Decoder`] cuts frames off the front of the received bytes;
+//! [`UsbXpress::frames`] drives one with blocking reads and yields the
+//! frames as an iterator. The decoders themselves live in the `no_std`
+//! capable [`frame`](crate::frame) module and are re-exported here.
+use std::time::Instant;
+
+pub use crate::frame::{Decoder, Delimited};
+use crate::{SilabsUsbXpressError, UsbXpress};
+
+/// Bytes requested from the device per read while waiting for a frame
+const READ_CHUNK: usize = 4096;
+
+/// Iterator over decoded frames, see [`UsbXpress::frames`]
+pub struct Frames<'a, D> {
+    handle: &'a mut UsbXpress,
+    decoder: D,
+    buf: Vec<u8>,
+}
+
+impl<'a, D: Decoder> Frames<'a, D> {
+    pub(crate) fn new(handle: &'a mut UsbXpress, decoder: D) -> Self {
+        Frames {
+            handle,
+            decoder,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Returns the received bytes not decoded yet
+    pub fn buffered(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl<D: Decoder> Iterator for Frames<'_, D> {
+    type Item = Result<D::Frame, SilabsUsbXpressError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let started = Instant::now();
+        let deadline = started + self.handle.read_timeout_for(READ_CHUNK);
+        let mut polled = false;
+        loop {
+            if let Some(frame) = self.decoder.decode(&mut self.buf) {
+                return Some(Ok(frame));
+            }
+            let now = Instant::now();
+            // even with a zero read timeout, look at the device once
+            if polled && now >= deadline {
+                return Some(Err(SilabsUsbXpressError::ReadTimeOut {
+                    elapsed: started.elapsed(),
+                    transferred: self.buf.len(),
+                    remaining: 0,
+                }));
+            }
+            let filled = self.buf.len();
+            self.buf.resize(filled + READ_CHUNK, 0);
+            let result = self.handle.read_once(
+                &mut self.buf[filled..],
+                deadline.saturating_duration_since(now),
+            );
+            polled = true;
+            match result {
+                Ok(n) => self.buf.truncate(filled + n),
+                Err(err) => {
+                    self.buf.truncate(filled);
+                    return Some(Err(err));
+                }
+            }
+        }
+    }
+}
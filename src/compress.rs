@@ -0,0 +1,310 @@
+//! Compressed framing for high-volume streams
+//!
+//! Enabled with the `compression` feature. Full-speed devices top out at
+//! about a megabyte per second, which repetitive sensor data can stretch a
+//! long way when compressed. [`Compressed`] wraps a transport and sends
+//! every write as a frame, compressed with the algorithm agreed with the
+//! firmware, and decompresses the frames it receives.
+//!
+//! A frame is a flags byte, the body length as a little endian 16 bit word
+//! and the body. Bit 0 of the flags is set if the body is compressed; data
+//! that does not shrink is sent as is. A frame carries at most 32 KiB of
+//! data, and frames that decompress to more are rejected.
+//! [`Compressed::negotiate`] sends the byte `0xC0` followed by the IDs of
+//! the algorithms the host offers, most preferred first, and expects the ID
+//! of the one the firmware picked, or `0` if it supports none of them, in
+//! response.
+//!
+//! ```rust, ignore
+//! use silabs_usb_xpress::compress::{Algorithm, Compressed};
+//!
+//! let mut stream = Compressed::negotiate(handle, &[Algorithm::Lz4, Algorithm::Deflate], timeout)?;
+//! let samples = stream.read(65536)?;
+//! ```
+use std::{
+    collections::VecDeque,
+    convert::TryInto,
+    io::{Read, Write},
+    time::{Duration, Instant},
+};
+
+use flate2::{read::DeflateDecoder, write::DeflateEncoder};
+
+use crate::{transport::Transport, SilabsUsbXpressError};
+
+/// First byte of a negotiation request
+const NEGOTIATE: u8 = 0xC0;
+/// Flag of frames with a compressed body
+const COMPRESSED: u8 = 0x01;
+const HEADER_LEN: usize = 3;
+/// Largest amount of data put in one frame
+const MAX_FRAME_DATA: usize = 32768;
+/// Bytes requested from the device per read
+const READ_CHUNK: usize = 4096;
+
+/// A compression algorithm
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Algorithm {
+    /// LZ4 block format, prefixed with the uncompressed size as a little
+    /// endian 32 bit word; fast enough for small microcontrollers
+    Lz4,
+    /// Raw deflate, as in RFC 1951; compresses better
+    Deflate,
+}
+
+impl Algorithm {
+    /// ID of the algorithm in negotiation
+    pub fn id(self) -> u8 {
+        match self {
+            Algorithm::Lz4 => 1,
+            Algorithm::Deflate => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Option<Algorithm> {
+        match id {
+            1 => Some(Algorithm::Lz4),
+            2 => Some(Algorithm::Deflate),
+            _ => None,
+        }
+    }
+
+    fn compress(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Algorithm::Lz4 => lz4_flex::compress_prepend_size(data),
+            Algorithm::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::new(), flate2::Compression::fast());
+                // Writing to a Vec cannot fail.
+                encoder.write_all(data).unwrap();
+                encoder.finish().unwrap()
+            }
+        }
+    }
+
+    /// Decompresses a frame body, `None` if it is corrupt or holds more
+    /// than `MAX_FRAME_DATA` bytes, which no well-behaved firmware sends
+    fn decompress(self, body: &[u8]) -> Option<Vec<u8>> {
+        match self {
+            Algorithm::Lz4 => {
+                let size = u32::from_le_bytes(body.get(..4)?.try_into().ok()?);
+                if size as usize > MAX_FRAME_DATA {
+                    return None;
+                }
+                lz4_flex::decompress_size_prepended(body).ok()
+            }
+            Algorithm::Deflate => {
+                let mut data = Vec::new();
+                DeflateDecoder::new(body)
+                    .take(MAX_FRAME_DATA as u64 + 1)
+                    .read_to_end(&mut data)
+                    .ok()?;
+                (data.len() <= MAX_FRAME_DATA).then_some(data)
+            }
+        }
+    }
+}
+
+/// A transport whose traffic is compressed
+pub struct Compressed<T> {
+    transport: T,
+    algorithm: Option<Algorithm>,
+    received: Vec<u8>,
+    data: VecDeque<u8>,
+}
+
+impl<T: Transport> Compressed<T> {
+    /// Compresses with `algorithm`, which the firmware is known to expect;
+    /// `None` sends every frame uncompressed
+    pub fn new(transport: T, algorithm: Option<Algorithm>) -> Self {
+        Compressed {
+            transport,
+            algorithm,
+            received: Vec::new(),
+            data: VecDeque::new(),
+        }
+    }
+
+    /// Agrees on one of `offered` with the firmware, see the
+    /// [module documentation](self)
+    ///
+    /// Falls back to uncompressed frames if the firmware supports none of
+    /// them. Fails with `ReadTimeOut` if it does not answer within
+    /// `timeout`, and with `ReadError` if it answers with an
+    /// algorithm that was not offered.
+    pub fn negotiate(
+        mut transport: T,
+        offered: &[Algorithm],
+        timeout: Duration,
+    ) -> Result<Self, SilabsUsbXpressError> {
+        let started = Instant::now();
+        let mut request = vec![NEGOTIATE];
+        request.extend(offered.iter().map(|algorithm| algorithm.id()));
+        transport.write(&request)?;
+        let answer = loop {
+            match transport.read(1) {
+                Ok(answer) if !answer.is_empty() => break answer[0],
+                Ok(_) | Err(SilabsUsbXpressError::ReadTimeOut { .. }) => {}
+                Err(err) => return Err(err),
+            }
+            if started.elapsed() >= timeout {
+                return Err(SilabsUsbXpressError::ReadTimeOut {
+                    elapsed: started.elapsed(),
+                    transferred: 0,
+                    remaining: 1,
+                });
+            }
+        };
+        let algorithm = match answer {
+            0 => None,
+            id => match Algorithm::from_id(id).filter(|picked| offered.contains(picked)) {
+                Some(algorithm) => Some(algorithm),
+                None => return Err(SilabsUsbXpressError::ReadError),
+            },
+        };
+        log::debug!("negotiated compression: {:?}", algorithm);
+        Ok(Self::new(transport, algorithm))
+    }
+
+    /// Returns the agreed algorithm, `None` if frames are not compressed
+    pub fn algorithm(&self) -> Option<Algorithm> {
+        self.algorithm
+    }
+
+    /// Ends compression and returns the transport
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+}
+
+/// Appends `data` to `out` as a frame, compressed if that makes it smaller
+fn encode(algorithm: Option<Algorithm>, data: &[u8], out: &mut Vec<u8>) {
+    let compressed = algorithm
+        .map(|algorithm| algorithm.compress(data))
+        .filter(|body| body.len() < data.len() && body.len() <= u16::MAX as usize);
+    let (flags, body) = match &compressed {
+        Some(body) => (COMPRESSED, body.as_slice()),
+        None => (0, data),
+    };
+    out.push(flags);
+    out.extend_from_slice(&(body.len() as u16).to_le_bytes());
+    out.extend_from_slice(body);
+}
+
+impl<T: Transport> Transport for Compressed<T> {
+    /// Returns up to `max` decompressed bytes
+    ///
+    /// If none are pending, reads from the device once, waiting at most its
+    /// read timeout. A frame that fails to decompress is reported as
+    /// `ReadError`.
+    fn read(&mut self, max: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        if self.data.is_empty() {
+            let received = self.transport.read(READ_CHUNK)?;
+            self.received.extend_from_slice(&received);
+            while self.received.len() >= HEADER_LEN {
+                let len = u16::from_le_bytes([self.received[1], self.received[2]]) as usize;
+                if self.received.len() < HEADER_LEN + len {
+                    break;
+                }
+                let frame: Vec<u8> = self.received.drain(..HEADER_LEN + len).collect();
+                let body = &frame[HEADER_LEN..];
+                if frame[0] & COMPRESSED == 0 {
+                    self.data.extend(body);
+                } else {
+                    let data = self
+                        .algorithm
+                        .and_then(|algorithm| algorithm.decompress(body))
+                        .ok_or(SilabsUsbXpressError::ReadError)?;
+                    self.data.extend(data);
+                }
+            }
+        }
+        let n = max.min(self.data.len());
+        Ok(self.data.drain(..n).collect())
+    }
+
+    /// Sends `data` as frames and returns `data.len()` once all are written
+    ///
+    /// Since a partly written frame would corrupt the stream, a short write
+    /// of the frames is reported as `WriteTimeOut`.
+    fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+        let mut frames = Vec::new();
+        for chunk in data.chunks(MAX_FRAME_DATA) {
+            encode(self.algorithm, chunk, &mut frames);
+        }
+        let started = Instant::now();
+        let written = self.transport.write(&frames)?;
+        if written < frames.len() {
+            return Err(SilabsUsbXpressError::WriteTimeOut {
+                elapsed: started.elapsed(),
+                transferred: written,
+                remaining: frames.len() - written,
+                chunk: 0,
+            });
+        }
+        Ok(data.len())
+    }
+
+    /// Counts the compressed frames the device has yet to send, not the
+    /// data they carry
+    fn pending_output(&mut self) -> Result<usize, SilabsUsbXpressError> {
+        self.transport.pending_output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Loopback;
+
+    #[test]
+    fn frames_round_trip_and_shrink() {
+        let samples: Vec<u8> = (0..1000u32).flat_map(|i| (i % 16).to_le_bytes()).collect();
+        for algorithm in [Some(Algorithm::Lz4), Some(Algorithm::Deflate), None] {
+            let mut stream = Compressed::new(Loopback::default(), algorithm);
+            stream.write(&samples).unwrap();
+            stream.write(b"x").unwrap();
+            if algorithm.is_some() {
+                assert!(stream.transport.0.len() < samples.len() / 4);
+            }
+            let mut received = Vec::new();
+            while received.len() < samples.len() + 1 {
+                received.extend(stream.read(1000).unwrap());
+            }
+            assert_eq!(&received[..samples.len()], samples.as_slice());
+            assert_eq!(&received[samples.len()..], b"x");
+        }
+
+        let firmware = Loopback(vec![2]);
+        let stream = Compressed::negotiate(
+            firmware,
+            &[Algorithm::Lz4, Algorithm::Deflate],
+            Duration::ZERO,
+        )
+        .unwrap();
+        assert_eq!(stream.algorithm(), Some(Algorithm::Deflate));
+        assert_eq!(stream.into_inner().0, [NEGOTIATE, 1, 2]);
+    }
+
+    #[test]
+    fn oversized_frames_are_rejected() {
+        let huge = vec![0; MAX_FRAME_DATA + 1];
+        for algorithm in [Algorithm::Lz4, Algorithm::Deflate] {
+            let body = algorithm.compress(&huge);
+            let mut frame = vec![COMPRESSED];
+            frame.extend_from_slice(&(body.len() as u16).to_le_bytes());
+            frame.extend_from_slice(&body);
+            let mut stream = Compressed::new(Loopback(frame), Some(algorithm));
+            assert!(matches!(
+                stream.read(1000),
+                Err(SilabsUsbXpressError::ReadError)
+            ));
+            // The largest frame the host itself sends still decompresses
+            let body = algorithm.compress(&huge[1..]);
+            assert_eq!(algorithm.decompress(&body).unwrap().len(), MAX_FRAME_DATA);
+        }
+        // An LZ4 size prefix of 4 GiB is not believed
+        let mut body = u32::MAX.to_le_bytes().to_vec();
+        body.extend_from_slice(&[0x10, 0]);
+        assert_eq!(Algorithm::Lz4.decompress(&body), None);
+    }
+}
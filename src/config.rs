@@ -0,0 +1,281 @@
+//! Process-wide defaults applied to newly opened devices
+//!
+//! Defaults can be set from code with [`configure`] and overridden at
+//! deployment time through environment variables, which always take
+//! precedence so that operators can retune a binary without rebuilding it:
+//!
+//! | Variable                   | Meaning                                     |
+//! | ----                       | ----                                        |
+//! | `SIUSB_DEFAULT_TIMEOUT_MS` | read and write timeout in milliseconds      |
+//! | `SIUSB_READ_TIMEOUT_MS`    | read timeout in milliseconds                |
+//! | `SIUSB_WRITE_TIMEOUT_MS`   | write timeout in milliseconds               |
+//! | `SIUSB_OPEN_TIMEOUT_MS`    | open timeout in milliseconds                |
+//! | `SIUSB_VID_PID_FILTER`     | devices that may be opened, see below       |
+//! | `SIUSB_LOG_PAYLOADS`       | `1` to log transferred bytes at trace level |
+//! | `SIUSB_LEASE_DIR`          | directory of [device leases](crate::lease)  |
+//! | `SIUSB_DEVICE_IDS`         | more [device IDs](crate::id_table) to match |
+//!
+//! `SIUSB_READ_TIMEOUT_MS` and `SIUSB_WRITE_TIMEOUT_MS` take precedence over
+//! `SIUSB_DEFAULT_TIMEOUT_MS`. `SIUSB_VID_PID_FILTER` is a comma separated
+//! list of hexadecimal `VID:PID` pairs, where `*` matches any product ID,
+//! e.g. `10c4:ea60,10c4:*`. Other devices are left out of enumeration, so
+//! that [`devices_count`](crate::devices_count) and
+//! [`devices`](crate::devices) only count the ones the filter allows and
+//! device indices refer to those alone; opening any other device, e.g. by
+//! path, fails with `DeviceNotFound`. Payloads are logged through the
+//! [`log`] crate.
+//!
+//! Environment variables are read once, the first time a default is needed.
+//! Malformed values and filter entries are ignored with a warning, and
+//! [`check_env`] reports them.
+use std::{
+    env,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use crate::{id_table::parse_vid_pids, SilabsUsbXpressError, Timeout};
+
+/// Timeout used when none has been configured
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(1);
+
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct Defaults {
+    pub(crate) timeouts: Timeout,
+    pub(crate) open_timeout: Option<Duration>,
+}
+
+/// Defaults configured from code, before environment overrides
+static DEFAULTS: Mutex<Defaults> = Mutex::new(Defaults {
+    timeouts: Timeout {
+        read: DEFAULT_TIMEOUT,
+        write: DEFAULT_TIMEOUT,
+    },
+    open_timeout: None,
+});
+
+static ENV_OVERRIDES: OnceLock<EnvOverrides> = OnceLock::new();
+
+#[derive(Debug, Default)]
+struct EnvOverrides {
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    open_timeout: Option<Duration>,
+    /// `SIUSB_VID_PID_FILTER` entries, a `None` product ID matching any
+    vid_pid_filter: Option<Vec<(u16, Option<u16>)>>,
+    log_payloads: bool,
+    /// Malformed values and filter entries, as `NAME=value`
+    invalid: Vec<String>,
+}
+
+impl EnvOverrides {
+    fn from_env() -> Self {
+        let overrides = Self::from_vars(|name| env::var(name).ok());
+        for entry in &overrides.invalid {
+            log::warn!("ignoring {}, not a valid value", entry);
+        }
+        overrides
+    }
+
+    /// Reads the overrides from `var`, which returns the value of a variable
+    fn from_vars<F: Fn(&str) -> Option<String>>(var: F) -> Self {
+        let mut invalid = Vec::new();
+        let mut millis = |name: &str| {
+            let value = var(name)?;
+            match value.trim().parse() {
+                Ok(millis) => Some(Duration::from_millis(millis)),
+                Err(_) => {
+                    invalid.push(format!("{}={}", name, value));
+                    None
+                }
+            }
+        };
+        let default_timeout = millis("SIUSB_DEFAULT_TIMEOUT_MS");
+        let read_timeout = millis("SIUSB_READ_TIMEOUT_MS").or(default_timeout);
+        let write_timeout = millis("SIUSB_WRITE_TIMEOUT_MS").or(default_timeout);
+        let open_timeout = millis("SIUSB_OPEN_TIMEOUT_MS");
+        let vid_pid_filter = var("SIUSB_VID_PID_FILTER").map(|filter| {
+            let (ids, entries) = parse_vid_pids(&filter);
+            invalid.extend(
+                entries
+                    .into_iter()
+                    .map(|entry| format!("SIUSB_VID_PID_FILTER={}", entry)),
+            );
+            ids
+        });
+        EnvOverrides {
+            read_timeout,
+            write_timeout,
+            open_timeout,
+            vid_pid_filter,
+            log_payloads: var("SIUSB_LOG_PAYLOADS")
+                .is_some_and(|value| !matches!(value.trim(), "" | "0" | "false")),
+            invalid,
+        }
+    }
+}
+
+fn env_overrides() -> &'static EnvOverrides {
+    ENV_OVERRIDES.get_or_init(EnvOverrides::from_env)
+}
+
+/// Whether `SIUSB_VID_PID_FILTER` allows opening a device with these IDs
+///
+/// Without a filter every device is allowed.
+pub(crate) fn vid_pid_allowed(vid: u16, pid: u16) -> bool {
+    match &env_overrides().vid_pid_filter {
+        Some(filter) => filter.iter().any(|&(entry_vid, entry_pid)| {
+            entry_vid == vid && entry_pid.is_none_or(|entry_pid| entry_pid == pid)
+        }),
+        None => true,
+    }
+}
+
+/// Whether `SIUSB_VID_PID_FILTER` is set
+pub(crate) fn has_vid_pid_filter() -> bool {
+    env_overrides().vid_pid_filter.is_some()
+}
+
+/// Whether transferred bytes are logged, see `SIUSB_LOG_PAYLOADS`
+pub(crate) fn log_payloads() -> bool {
+    env_overrides().log_payloads
+}
+
+/// Reads the environment variables, once per process
+///
+/// Malformed values are ignored, as if the variable was unset, and
+/// malformed `SIUSB_VID_PID_FILTER` entries are left out of the filter;
+/// calling this tells whether there are any, which fail with
+/// `InvalidEnvironment`.
+pub fn check_env() -> Result<(), SilabsUsbXpressError> {
+    let invalid = &env_overrides().invalid;
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(SilabsUsbXpressError::InvalidEnvironment(invalid.clone()))
+    }
+}
+
+/// Returns the effective defaults, environment overrides included
+pub(crate) fn defaults() -> Defaults {
+    let mut defaults = *DEFAULTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    let overrides = env_overrides();
+    if let Some(read) = overrides.read_timeout {
+        defaults.timeouts.read = read;
+    }
+    if let Some(write) = overrides.write_timeout {
+        defaults.timeouts.write = write;
+    }
+    if let Some(open) = overrides.open_timeout {
+        defaults.open_timeout = Some(open);
+    }
+    defaults
+}
+
+/// Changes the defaults configured from code
+pub(crate) fn update<F: FnOnce(&mut Defaults)>(f: F) {
+    f(&mut DEFAULTS
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()));
+}
+
+/// Starts configuring the process-wide defaults
+///
+/// Only the settings touched on the returned builder are changed when it is
+/// [applied](Configure::apply); everything else keeps its current value.
+///
+/// ```rust, ignore
+/// # use std::time::Duration;
+/// silabs_usb_xpress::configure()
+///     .read_timeout(Duration::from_millis(200))
+///     .open_timeout(Duration::from_secs(3))
+///     .apply();
+/// ```
+pub fn configure() -> Configure {
+    Configure::default()
+}
+
+/// Builder for the process-wide defaults, see [`configure`]
+#[derive(Clone, Debug, Default)]
+pub struct Configure {
+    read_timeout: Option<Duration>,
+    write_timeout: Option<Duration>,
+    open_timeout: Option<Option<Duration>>,
+}
+
+impl Configure {
+    /// Sets the default read timeout
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the default write timeout
+    pub fn write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the default open timeout, `None` to wait indefinitely
+    pub fn open_timeout<T: Into<Option<Duration>>>(mut self, timeout: T) -> Self {
+        self.open_timeout = Some(timeout.into());
+        self
+    }
+
+    /// Makes the configured values the defaults for devices opened from now on
+    pub fn apply(self) {
+        update(|defaults| {
+            if let Some(read) = self.read_timeout {
+                defaults.timeouts.read = read;
+            }
+            if let Some(write) = self.write_timeout {
+                defaults.timeouts.write = write;
+            }
+            if let Some(open) = self.open_timeout {
+                defaults.open_timeout = open;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn overrides(vars: &[(&str, &str)]) -> EnvOverrides {
+        EnvOverrides::from_vars(|name| {
+            vars.iter()
+                .find(|(var, _)| *var == name)
+                .map(|(_, value)| (*value).to_owned())
+        })
+    }
+
+    #[test]
+    fn vid_pid_filter_parses_pairs_and_wildcards() {
+        let overrides = overrides(&[("SIUSB_VID_PID_FILTER", "10c4:ea60, 10C4:*,bogus,1234:zz")]);
+        assert_eq!(
+            overrides.vid_pid_filter,
+            Some(vec![(0x10c4, Some(0xea60)), (0x10c4, None)])
+        );
+        assert_eq!(
+            overrides.invalid,
+            vec!["SIUSB_VID_PID_FILTER=bogus", "SIUSB_VID_PID_FILTER=1234:zz"]
+        );
+    }
+
+    #[test]
+    fn malformed_timeouts_are_reported_and_ignored() {
+        let overrides = overrides(&[
+            ("SIUSB_DEFAULT_TIMEOUT_MS", "250"),
+            ("SIUSB_READ_TIMEOUT_MS", "1s"),
+            ("SIUSB_OPEN_TIMEOUT_MS", " 3000 "),
+        ]);
+        assert_eq!(overrides.read_timeout, Some(Duration::from_millis(250)));
+        assert_eq!(overrides.write_timeout, Some(Duration::from_millis(250)));
+        assert_eq!(overrides.open_timeout, Some(Duration::from_secs(3)));
+        assert_eq!(overrides.invalid, vec!["SIUSB_READ_TIMEOUT_MS=1s"]);
+        assert!(overrides.vid_pid_filter.is_none());
+    }
+}
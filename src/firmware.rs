@@ -0,0 +1,240 @@
+//! Querying and checking the firmware version of a device
+//!
+//! Firmware usually reports its version in response to a fixed command,
+//! either as binary major/minor/build fields or as text. A [`VersionProbe`]
+//! describes which, [`UsbXpress::query_firmware_version`] runs it and
+//! [`UsbXpress::require_firmware`] additionally refuses firmware that is not
+//! compatible with the version the host software was written against:
+//!
+//! ```rust, ignore
+//! let probe = VersionProbe::command(0x56);
+//! handle.require_firmware(&probe, FirmwareVersion::new(2, 1, 0))?;
+//! ```
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{SilabsUsbXpressError, UsbXpress};
+
+/// A `major.minor.build` firmware version
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FirmwareVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub build: u16,
+}
+
+impl FirmwareVersion {
+    pub fn new(major: u16, minor: u16, build: u16) -> Self {
+        FirmwareVersion {
+            major,
+            minor,
+            build,
+        }
+    }
+
+    /// Returns whether host software written against `required` can talk to
+    /// firmware of this version, following semver rules
+    ///
+    /// The major versions must be equal and this version must not be older
+    /// than `required`. Before 1.0, the minor versions must be equal too.
+    pub fn is_compatible_with(&self, required: FirmwareVersion) -> bool {
+        self.major == required.major
+            && (self.major > 0 || self.minor == required.minor)
+            && *self >= required
+    }
+
+    /// Parses `major.minor.build` or `major.minor` from the first run of
+    /// digits and dots in `text`, so `"FW v2.1.7\r\n"` is 2.1.7
+    fn parse(text: &str) -> Option<FirmwareVersion> {
+        let start = text.find(|c: char| c.is_ascii_digit())?;
+        let version = &text[start..];
+        let end = version
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(version.len());
+        let mut parts = version[..end].split('.').map(str::parse::<u16>);
+        let major = parts.next()?.ok()?;
+        let minor = parts.next()?.ok()?;
+        let build = match parts.next() {
+            Some(build) => build.ok()?,
+            None => 0,
+        };
+        Some(FirmwareVersion::new(major, minor, build))
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.build)
+    }
+}
+
+/// How the version fields are encoded in a binary response
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum VersionFormat {
+    /// Three bytes: major, minor, build
+    U8,
+    /// Three little endian 16 bit words: major, minor, build
+    U16Le,
+    /// Three big endian 16 bit words: major, minor, build
+    U16Be,
+    /// ASCII text containing `major.minor.build`, terminated by a newline
+    Text,
+}
+
+impl VersionFormat {
+    /// Length of a binary response, `None` for text
+    fn len(self) -> Option<usize> {
+        match self {
+            VersionFormat::U8 => Some(3),
+            VersionFormat::U16Le | VersionFormat::U16Be => Some(6),
+            VersionFormat::Text => None,
+        }
+    }
+
+    fn decode(self, response: &[u8]) -> Option<FirmwareVersion> {
+        let word = |i: usize| {
+            let bytes = [response[2 * i], response[2 * i + 1]];
+            match self {
+                VersionFormat::U16Le => u16::from_le_bytes(bytes),
+                _ => u16::from_be_bytes(bytes),
+            }
+        };
+        match self {
+            VersionFormat::U8 => Some(FirmwareVersion::new(
+                response[0].into(),
+                response[1].into(),
+                response[2].into(),
+            )),
+            VersionFormat::U16Le | VersionFormat::U16Be => {
+                Some(FirmwareVersion::new(word(0), word(1), word(2)))
+            }
+            VersionFormat::Text => FirmwareVersion::parse(&String::from_utf8_lossy(response)),
+        }
+    }
+}
+
+/// A request that makes the firmware report its version
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VersionProbe {
+    request: Vec<u8>,
+    format: VersionFormat,
+    timeout: Duration,
+}
+
+impl VersionProbe {
+    /// Sends the single byte `command` and expects major, minor and build as
+    /// one byte each
+    pub fn command(command: u8) -> Self {
+        Self::new(vec![command], VersionFormat::U8)
+    }
+
+    /// Sends `request` and expects a response in `format`
+    pub fn new(request: Vec<u8>, format: VersionFormat) -> Self {
+        VersionProbe {
+            request,
+            format,
+            timeout: Duration::from_secs(1),
+        }
+    }
+
+    /// Gives the response `timeout` to arrive, one second by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// Bytes read per attempt while waiting for a text response
+const TEXT_CHUNK: usize = 64;
+
+impl UsbXpress {
+    /// Asks the firmware for its version
+    ///
+    /// Fails with `InvalidFirmwareVersion` if the response cannot be
+    /// decoded.
+    pub fn query_firmware_version(
+        &mut self,
+        probe: &VersionProbe,
+    ) -> Result<FirmwareVersion, SilabsUsbXpressError> {
+        let response = match probe.format.len() {
+            Some(len) => self.transact(&probe.request, len, probe.timeout)?,
+            None => self.transact_line(&probe.request, probe.timeout)?,
+        };
+        probe
+            .format
+            .decode(&response)
+            .ok_or(SilabsUsbXpressError::InvalidFirmwareVersion(response))
+    }
+
+    /// Asks the firmware for its version and fails with
+    /// `IncompatibleFirmware` unless it is compatible with `required`, see
+    /// [`FirmwareVersion::is_compatible_with`]
+    pub fn require_firmware(
+        &mut self,
+        probe: &VersionProbe,
+        required: FirmwareVersion,
+    ) -> Result<FirmwareVersion, SilabsUsbXpressError> {
+        let found = self.query_firmware_version(probe)?;
+        if found.is_compatible_with(required) {
+            Ok(found)
+        } else {
+            Err(SilabsUsbXpressError::IncompatibleFirmware { found, required })
+        }
+    }
+
+    /// Sends `request` and reads a newline terminated response
+    fn transact_line(
+        &mut self,
+        request: &[u8],
+        timeout: Duration,
+    ) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        let started = Instant::now();
+        let deadline = started + timeout;
+        self.flush(false, true)?;
+        self.write_until_deadline(request, deadline)?;
+        let mut response = Vec::new();
+        loop {
+            if let Some(end) = response.iter().position(|&b| b == b'\n') {
+                response.truncate(end);
+                return Ok(response);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(SilabsUsbXpressError::ReadTimeOut {
+                    elapsed: started.elapsed(),
+                    transferred: response.len(),
+                    remaining: 0,
+                });
+            }
+            let filled = response.len();
+            response.resize(filled + TEXT_CHUNK, 0);
+            let received = self.read_once(&mut response[filled..], deadline - now)?;
+            response.truncate(filled + received);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_decode_and_compare() {
+        let v = |major, minor, build| FirmwareVersion::new(major, minor, build);
+        assert_eq!(VersionFormat::U8.decode(&[2, 1, 7]), Some(v(2, 1, 7)));
+        assert_eq!(
+            VersionFormat::U16Le.decode(&[2, 0, 1, 0, 0x2c, 0x01]),
+            Some(v(2, 1, 300))
+        );
+        assert_eq!(VersionFormat::Text.decode(b"FW v2.1.7\r"), Some(v(2, 1, 7)));
+        assert_eq!(VersionFormat::Text.decode(b"v3.4"), Some(v(3, 4, 0)));
+        assert_eq!(VersionFormat::Text.decode(b"unknown"), None);
+
+        assert!(v(2, 3, 0).is_compatible_with(v(2, 1, 5)));
+        assert!(!v(2, 1, 4).is_compatible_with(v(2, 1, 5)));
+        assert!(!v(3, 0, 0).is_compatible_with(v(2, 1, 5)));
+        assert!(!v(0, 3, 0).is_compatible_with(v(0, 2, 0)));
+    }
+}
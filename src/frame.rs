@@ -0,0 +1,127 @@
+//! Frame formats shared with device firmware
+//!
+//! The decoders and encoders of the framings this crate speaks, kept apart
+//! from everything that talks to a device. This module only uses `core`
+//! and `alloc`, never `std`, so that firmware built with `#![no_std]` and an
+//! allocator can compile the very same source and both sides of the link
+//! agree on the frame layout:
+//!
+//! ```rust, ignore
+//! #![no_std]
+//! extern crate alloc;
+//!
+//! #[path = "../silabs_usb_xpress/src/frame.rs"]
+//! mod frame;
+//! ```
+//!
+//! On the host, [`codec`](crate::codec) drives a [`Decoder`] with reads from
+//! a device, and [`mux`](crate::mux) uses the channel frames.
+use alloc::vec::Vec;
+
+/// Length of the header of a channel frame
+pub const MUX_HEADER_LEN: usize = 3;
+
+/// Extracts frames from received bytes
+pub trait Decoder {
+    type Frame;
+
+    /// Removes the first complete frame from the front of `buf` and returns
+    /// it, or returns `None` if `buf` does not hold a complete frame yet
+    ///
+    /// Bytes that can never become part of a valid frame may be dropped from
+    /// `buf` to resynchronize.
+    fn decode(&mut self, buf: &mut Vec<u8>) -> Option<Self::Frame>;
+}
+
+/// Frames terminated by a delimiter byte, such as newline terminated lines
+///
+/// The delimiter is not part of the returned frames.
+#[derive(Copy, Clone, Debug)]
+pub struct Delimited {
+    delimiter: u8,
+}
+
+impl Delimited {
+    pub fn new(delimiter: u8) -> Self {
+        Delimited { delimiter }
+    }
+}
+
+impl Decoder for Delimited {
+    type Frame = Vec<u8>;
+
+    fn decode(&mut self, buf: &mut Vec<u8>) -> Option<Vec<u8>> {
+        let end = buf.iter().position(|&b| b == self.delimiter)?;
+        let mut frame: Vec<u8> = buf.drain(..=end).collect();
+        frame.pop();
+        Some(frame)
+    }
+}
+
+/// Splits received bytes into `(channel, payload)` frames
+///
+/// Every frame starts with a three byte header, the channel number followed
+/// by the payload length as a little endian 16 bit word:
+///
+/// ```text
+/// +---------+------------+------------+---------------+
+/// | channel | length lsb | length msb | payload ...   |
+/// +---------+------------+------------+---------------+
+/// ```
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MuxDecoder;
+
+impl Decoder for MuxDecoder {
+    type Frame = (u8, Vec<u8>);
+
+    fn decode(&mut self, buf: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+        if buf.len() < MUX_HEADER_LEN {
+            return None;
+        }
+        let len = u16::from_le_bytes([buf[1], buf[2]]) as usize;
+        if buf.len() < MUX_HEADER_LEN + len {
+            return None;
+        }
+        let channel = buf[0];
+        let payload = buf[MUX_HEADER_LEN..MUX_HEADER_LEN + len].to_vec();
+        buf.drain(..MUX_HEADER_LEN + len);
+        Some((channel, payload))
+    }
+}
+
+/// Appends `payload` to `out` as frames of `channel`, see [`MuxDecoder`],
+/// splitting it if it exceeds the largest frame
+pub fn encode_mux(channel: u8, payload: &[u8], out: &mut Vec<u8>) {
+    for chunk in payload.chunks(u16::MAX as usize) {
+        out.push(channel);
+        out.extend_from_slice(&(chunk.len() as u16).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delimited_splits_and_keeps_the_remainder() {
+        let mut decoder = Delimited::new(b'\n');
+        let mut buf = b"one\ntwo\nthr".to_vec();
+        assert_eq!(decoder.decode(&mut buf), Some(b"one".to_vec()));
+        assert_eq!(decoder.decode(&mut buf), Some(b"two".to_vec()));
+        assert_eq!(decoder.decode(&mut buf), None);
+        assert_eq!(buf, b"thr");
+    }
+
+    #[test]
+    fn mux_frames_round_trip() {
+        let mut buf = Vec::new();
+        encode_mux(7, b"ok", &mut buf);
+        assert_eq!(buf, [7, 2, 0, b'o', b'k']);
+        let mut partial = buf[..4].to_vec();
+        assert_eq!(MuxDecoder.decode(&mut partial), None);
+        partial.push(b'k');
+        assert_eq!(MuxDecoder.decode(&mut partial), Some((7, b"ok".to_vec())));
+        assert!(partial.is_empty());
+    }
+}
@@ -0,0 +1,391 @@
+//! Notifications of devices being plugged in and removed
+//!
+//! [`watch`] reports the devices with a given vendor and product ID as they
+//! arrive and leave, starting with those already connected:
+//!
+//! ```rust, ignore
+//! use silabs_usb_xpress::hotplug::{self, HotplugEvent};
+//!
+//! let hotplug = hotplug::watch(0x10c4, 0xea60)?;
+//! for event in hotplug.events() {
+//!     match event {
+//!         HotplugEvent::DeviceArrived(info) => println!("+ {}", info.serial),
+//!         HotplugEvent::DeviceLeft(info) => println!("- {}", info.serial),
+//!     }
+//! }
+//! ```
+//!
+//! With the `rust-backend` feature, on platforms where libusb supports it,
+//! the events come from libusb hotplug callbacks. The libusb 0.1 API of the
+//! bundled C library has no such callbacks, so otherwise the device list is
+//! polled every [`POLL_INTERVAL`].
+//!
+//! [`wait_for_device`] waits for one device to show up, for example after
+//! asking an operator to plug it in:
+//!
+//! ```rust, ignore
+//! let info = wait_for_device(&Selector::new().serial("0001A2F3"), Duration::from_secs(30))?;
+//! let handle = UsbXpress::open(info.index)?;
+//! ```
+use std::{
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    devices,
+    inventory::{diff, DeviceSetChange},
+    production::Selector,
+    DeviceInfo, SilabsUsbXpressError,
+};
+
+/// How often the device list is checked without hotplug callbacks
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A device plugged in or removed, see [`watch`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HotplugEvent {
+    DeviceArrived(DeviceInfo),
+    /// A device left; its information is what was known when it arrived
+    DeviceLeft(DeviceInfo),
+}
+
+/// Watches devices with vendor ID `vid` and product ID `pid` being plugged
+/// in and removed, see the [module documentation](self)
+///
+/// The devices connected at the start are reported as arrived first.
+pub fn watch(vid: u16, pid: u16) -> Result<Hotplug, SilabsUsbXpressError> {
+    let (events, rx) = mpsc::channel();
+    let (stop, stopped) = mpsc::channel();
+
+    #[cfg(feature = "rust-backend")]
+    {
+        if rusb::has_hotplug() {
+            let thread = callbacks::spawn(vid, pid, events, stopped)?;
+            return Ok(Hotplug::new(rx, stop, thread));
+        }
+    }
+
+    let known = matching(vid, pid)?;
+    for info in &known {
+        let _ = events.send(HotplugEvent::DeviceArrived(info.clone()));
+    }
+    let list = move || matching(vid, pid);
+    let thread = thread::spawn(move || poll(list, POLL_INTERVAL, known, events, stopped));
+    Ok(Hotplug::new(rx, stop, thread))
+}
+
+/// Waits up to `timeout` for a device matching `selector` and returns it
+///
+/// Returns at once if such a device is connected already; otherwise the
+/// device list is checked every [`POLL_INTERVAL`]. Fails with
+/// `DeviceWaitTimedOut` if no matching device showed up in time.
+pub fn wait_for_device(
+    selector: &Selector,
+    timeout: Duration,
+) -> Result<DeviceInfo, SilabsUsbXpressError> {
+    wait_for(selector, timeout, POLL_INTERVAL, devices)
+}
+
+/// Checks the device list `list` returns every `interval` until it has a
+/// device matching `selector`, see [`wait_for_device`]
+fn wait_for<L>(
+    selector: &Selector,
+    timeout: Duration,
+    interval: Duration,
+    mut list: L,
+) -> Result<DeviceInfo, SilabsUsbXpressError>
+where
+    L: FnMut() -> Result<Vec<DeviceInfo>, SilabsUsbXpressError>,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        let found = match list() {
+            Ok(devices) => devices.into_iter().find(|info| selector.accepts(info)),
+            // A device removed while it was being listed
+            Err(SilabsUsbXpressError::DeviceNotFound) => None,
+            Err(err) => return Err(err),
+        };
+        if let Some(info) = found {
+            return Ok(info);
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(SilabsUsbXpressError::DeviceWaitTimedOut);
+        }
+        thread::sleep(interval.min(deadline - now));
+    }
+}
+
+/// A running [`watch`], which stops when dropped
+#[derive(Debug)]
+pub struct Hotplug {
+    events: mpsc::Receiver<HotplugEvent>,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Hotplug {
+    fn new(
+        events: mpsc::Receiver<HotplugEvent>,
+        stop: mpsc::Sender<()>,
+        thread: thread::JoinHandle<()>,
+    ) -> Self {
+        Hotplug {
+            events,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Returns the channel the events are delivered on
+    ///
+    /// The channel disconnects once watching has stopped.
+    pub fn events(&self) -> &mpsc::Receiver<HotplugEvent> {
+        &self.events
+    }
+
+    /// Stops watching, waiting for the watching thread to finish
+    pub fn stop(mut self) {
+        self.stop_thread();
+    }
+
+    fn stop_thread(&mut self) {
+        // Dropping the sender wakes the thread up.
+        self.stop = None;
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Hotplug {
+    fn drop(&mut self) {
+        self.stop_thread();
+    }
+}
+
+fn matching(vid: u16, pid: u16) -> Result<Vec<DeviceInfo>, SilabsUsbXpressError> {
+    Ok(devices()?
+        .into_iter()
+        .filter(|info| info.vid == vid && info.pid == pid)
+        .collect())
+}
+
+/// Sends the changes between the device lists `list` returns every
+/// `interval`, starting from `known`, until `stop` disconnects
+fn poll<L>(
+    mut list: L,
+    interval: Duration,
+    mut known: Vec<DeviceInfo>,
+    events: mpsc::Sender<HotplugEvent>,
+    stop: mpsc::Receiver<()>,
+) where
+    L: FnMut() -> Result<Vec<DeviceInfo>, SilabsUsbXpressError>,
+{
+    while let Err(RecvTimeoutError::Timeout) = stop.recv_timeout(interval) {
+        let current = match list() {
+            Ok(current) => current,
+            Err(err) => {
+                log::warn!("unable to list devices: {}", err);
+                continue;
+            }
+        };
+        for change in diff(&known, &current) {
+            let event = match change {
+                DeviceSetChange::Added(info) => HotplugEvent::DeviceArrived(info),
+                DeviceSetChange::Removed(info) => HotplugEvent::DeviceLeft(info),
+            };
+            if events.send(event).is_err() {
+                return;
+            }
+        }
+        known = current;
+    }
+}
+
+#[cfg(feature = "rust-backend")]
+mod callbacks {
+    use std::{
+        collections::HashMap,
+        sync::mpsc::{self, TryRecvError},
+        thread,
+        time::Duration,
+    };
+
+    use rusb::{Device, GlobalContext, HotplugBuilder, UsbContext};
+
+    use super::HotplugEvent;
+    use crate::{devices, ffi::device_path, DeviceInfo, SilabsUsbXpressError};
+
+    /// Forwards the bus path of every device that arrived (`true`) or left
+    /// (`false`), since devices may not be opened within the callbacks
+    struct Callback(mpsc::Sender<(bool, String)>);
+
+    impl rusb::Hotplug<GlobalContext> for Callback {
+        fn device_arrived(&mut self, device: Device<GlobalContext>) {
+            let _ = self.0.send((true, device_path(&device)));
+        }
+
+        fn device_left(&mut self, device: Device<GlobalContext>) {
+            let _ = self.0.send((false, device_path(&device)));
+        }
+    }
+
+    pub(super) fn spawn(
+        vid: u16,
+        pid: u16,
+        events: mpsc::Sender<HotplugEvent>,
+        stop: mpsc::Receiver<()>,
+    ) -> Result<thread::JoinHandle<()>, SilabsUsbXpressError> {
+        let (registered, registration_result) = mpsc::channel();
+        let thread = thread::spawn(move || {
+            let (tx, rx) = mpsc::channel();
+            let mut builder = HotplugBuilder::new();
+            builder.vendor_id(vid).product_id(pid).enumerate(true);
+            let _registration =
+                match builder.register(GlobalContext::default(), Box::new(Callback(tx))) {
+                    Ok(registration) => {
+                        let _ = registered.send(Ok(()));
+                        registration
+                    }
+                    Err(err) => {
+                        let _ = registered.send(Err(err));
+                        return;
+                    }
+                };
+
+            let mut known = HashMap::new();
+            while let Err(TryRecvError::Empty) = stop.try_recv() {
+                let handled =
+                    GlobalContext::default().handle_events(Some(Duration::from_millis(100)));
+                if let Err(err) = handled {
+                    log::warn!("unable to handle USB events: {}", err);
+                }
+                for (arrived, bus_path) in rx.try_iter() {
+                    let unknown = DeviceInfo {
+                        vid,
+                        pid,
+                        bus_path: bus_path.clone(),
+                        ..DeviceInfo::default()
+                    };
+                    let event = if arrived {
+                        let info = devices()
+                            .ok()
+                            .and_then(|devices| {
+                                devices.into_iter().find(|info| info.bus_path == bus_path)
+                            })
+                            .unwrap_or(unknown);
+                        known.insert(bus_path, info.clone());
+                        HotplugEvent::DeviceArrived(info)
+                    } else {
+                        HotplugEvent::DeviceLeft(known.remove(&bus_path).unwrap_or(unknown))
+                    };
+                    if events.send(event).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        match registration_result.recv() {
+            Ok(Ok(())) => Ok(thread),
+            Ok(Err(err)) => {
+                log::error!("unable to register hotplug callbacks: {}", err);
+                Err(SilabsUsbXpressError::SystemErrorCode)
+            }
+            Err(_) => Err(SilabsUsbXpressError::SystemErrorCode),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    fn device(serial: &str, bus_path: &str) -> DeviceInfo {
+        DeviceInfo {
+            serial: serial.to_owned(),
+            vid: 0x10c4,
+            pid: 0xea61,
+            bus_path: bus_path.to_owned(),
+            ..DeviceInfo::default()
+        }
+    }
+
+    #[test]
+    fn polling_reports_arrivals_and_removals() {
+        let (a, b) = (device("A", "001/002"), device("B", "001/003"));
+        let mut lists: VecDeque<Result<Vec<DeviceInfo>, SilabsUsbXpressError>> = vec![
+            Ok(vec![a.clone()]),
+            // Listing errors are skipped
+            Err(SilabsUsbXpressError::DeviceNotFound),
+            Ok(vec![a.clone(), b.clone()]),
+        ]
+        .into();
+        let last = vec![b.clone()];
+        let list = move || lists.pop_front().unwrap_or_else(|| Ok(last.clone()));
+
+        let (events, rx) = mpsc::channel();
+        let (stop, stopped) = mpsc::channel();
+        let known = vec![a.clone()];
+        let poller =
+            thread::spawn(move || poll(list, Duration::from_millis(1), known, events, stopped));
+        let timeout = Duration::from_secs(5);
+        assert_eq!(rx.recv_timeout(timeout), Ok(HotplugEvent::DeviceArrived(b)));
+        assert_eq!(rx.recv_timeout(timeout), Ok(HotplugEvent::DeviceLeft(a)));
+
+        drop(stop);
+        poller.join().unwrap();
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn waiting_returns_the_first_matching_device() {
+        let wanted = device("0001A2F3", "001/004");
+        let mut lists: VecDeque<Result<Vec<DeviceInfo>, SilabsUsbXpressError>> = vec![
+            Ok(vec![]),
+            // A device removed while it was being listed
+            Err(SilabsUsbXpressError::DeviceNotFound),
+            Ok(vec![device("0001A2F4", "001/003")]),
+            Ok(vec![device("0001A2F4", "001/003"), wanted.clone()]),
+        ]
+        .into();
+        let selector = Selector::new().serial("0001A2F3");
+        let interval = Duration::from_millis(1);
+        let found = wait_for(&selector, Duration::from_secs(5), interval, || {
+            lists.pop_front().unwrap()
+        });
+        assert_eq!(found.unwrap(), wanted);
+        assert!(lists.is_empty());
+
+        let failed = wait_for(&selector, Duration::from_secs(5), interval, || {
+            Err(SilabsUsbXpressError::SystemErrorCode)
+        });
+        assert!(matches!(failed, Err(SilabsUsbXpressError::SystemErrorCode)));
+    }
+
+    #[test]
+    fn waiting_gives_up_at_the_timeout() {
+        let selector = Selector::new().serial("0001A2F3");
+        let mut polls = 0;
+        let started = Instant::now();
+        let result = wait_for(
+            &selector,
+            Duration::from_millis(20),
+            Duration::from_millis(5),
+            || {
+                polls += 1;
+                Ok(vec![device("0001A2F4", "001/003")])
+            },
+        );
+        assert!(matches!(
+            result,
+            Err(SilabsUsbXpressError::DeviceWaitTimedOut)
+        ));
+        assert!(started.elapsed() >= Duration::from_millis(20));
+        assert!(polls > 1);
+    }
+}
@@ -0,0 +1,199 @@
+//! Vendor and product IDs of USBXpress devices
+//!
+//! Serial numbers and descriptions are only read from devices whose vendor
+//! and product ID are in the match table, which starts out with the
+//! USBXpress default [`DEFAULT_VID_PID`]. Devices re-flashed with IDs of
+//! their own are still counted by [`devices_count`](crate::devices_count)
+//! but show up without strings, so that e.g.
+//! [`open_by_serial`](crate::UsbXpress::open_by_serial) cannot find them
+//! until their IDs are registered:
+//!
+//! ```rust, ignore
+//! silabs_usb_xpress::register_vid_pid(0x10c4, 0x8abc)?;
+//! let handle = UsbXpress::open_by_serial("0001")?;
+//! ```
+//!
+//! Where rebuilding is not an option, the `SIUSB_DEVICE_IDS` environment
+//! variable extends the table with a comma separated list of hexadecimal
+//! `VID:PID` pairs, e.g. `10C4:EA61,10C4:8ABC`, as `SIUSB_VID_PID_FILTER`
+//! in [`config`](crate::config) but without its `*` wildcard, which cannot
+//! be registered. It is read the first time
+//! devices are enumerated or opened; invalid entries are skipped with a
+//! warning, and [`register_from_env`] reports them.
+use std::{
+    env,
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{ffi::*, SilabsUsbXpressError};
+
+/// The vendor and product ID USBXpress devices ship with, always matched
+pub const DEFAULT_VID_PID: (u16, u16) = (SI_USB_VID as u16, SI_USB_PID as u16);
+
+/// Most IDs that can be registered besides [`DEFAULT_VID_PID`]
+pub const MAX_REGISTERED: usize = SI_MAX_DEVICE_IDS as usize - 1;
+
+/// The IDs registered with the backend, in order
+static REGISTERED: Mutex<Vec<(u16, u16)>> = Mutex::new(Vec::new());
+
+/// Entries of `SIUSB_DEVICE_IDS` that are not `VID:PID` pairs
+static ENV_INVALID: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Registers the IDs listed in `SIUSB_DEVICE_IDS`, once per process
+///
+/// Enumerating or opening devices does this on its own; calling it first
+/// tells whether the variable holds entries that are not `VID:PID` pairs,
+/// which fail with `InvalidDeviceIds`. The valid entries are registered
+/// either way.
+pub fn register_from_env() -> Result<(), SilabsUsbXpressError> {
+    let invalid = ENV_INVALID.get_or_init(|| {
+        let value = env::var("SIUSB_DEVICE_IDS").unwrap_or_default();
+        let (ids, mut invalid) = parse_vid_pids(&value);
+        for (vid, pid) in ids {
+            // Only whole pairs can be registered
+            let pid = match pid {
+                Some(pid) => pid,
+                None => {
+                    invalid.push(format!("{:04x}:*", vid));
+                    continue;
+                }
+            };
+            if let Err(err) = register_vid_pid(vid, pid) {
+                log::warn!(
+                    "SIUSB_DEVICE_IDS: {:04x}:{:04x} not registered: {}",
+                    vid,
+                    pid,
+                    err
+                );
+            }
+        }
+        for entry in &invalid {
+            log::warn!("SIUSB_DEVICE_IDS: ignoring {:?}, not a VID:PID pair", entry);
+        }
+        invalid
+    });
+    if invalid.is_empty() {
+        Ok(())
+    } else {
+        Err(SilabsUsbXpressError::InvalidDeviceIds(invalid.clone()))
+    }
+}
+
+/// Registers `SIUSB_DEVICE_IDS` before devices are enumerated or opened
+pub(crate) fn init() {
+    let _ = register_from_env();
+}
+
+/// Splits a comma separated list of hexadecimal `VID:PID` pairs into its
+/// pairs and its invalid entries, for `SIUSB_DEVICE_IDS` and
+/// `SIUSB_VID_PID_FILTER`
+///
+/// A `*` product ID matches any, and comes back as `None`; empty entries are
+/// skipped.
+pub(crate) fn parse_vid_pids(value: &str) -> (Vec<(u16, Option<u16>)>, Vec<String>) {
+    let mut ids = Vec::new();
+    let mut invalid = Vec::new();
+    for entry in value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+    {
+        let id = entry.split_once(':').and_then(|(vid, pid)| {
+            let vid = u16::from_str_radix(vid.trim(), 16).ok()?;
+            match pid.trim() {
+                "*" => Some((vid, None)),
+                pid => Some((vid, Some(u16::from_str_radix(pid, 16).ok()?))),
+            }
+        });
+        match id {
+            Some(id) => ids.push(id),
+            None => invalid.push(entry.to_owned()),
+        }
+    }
+    (ids, invalid)
+}
+
+/// Adds `vid`:`pid` to the match table
+///
+/// Registering IDs that are already matched does nothing. Fails with
+/// `VidPidTableFull` once [`MAX_REGISTERED`] IDs are registered.
+pub fn register_vid_pid(vid: u16, pid: u16) -> Result<(), SilabsUsbXpressError> {
+    let mut registered = REGISTERED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if (vid, pid) == DEFAULT_VID_PID || registered.contains(&(vid, pid)) {
+        return Ok(());
+    }
+    let status = unsafe { SI_AddDeviceId(i32::from(vid), i32::from(pid)) };
+    match status as u32 {
+        SI_SUCCESS => {
+            registered.push((vid, pid));
+            Ok(())
+        }
+        SI_GLOBAL_DATA_ERROR => Err(SilabsUsbXpressError::VidPidTableFull),
+        _ => unreachable!(
+            "Unreachable status code: {}. Please contact the author or submit an issue.",
+            status
+        ),
+    }
+}
+
+/// Removes all registered IDs, leaving [`DEFAULT_VID_PID`]
+///
+/// Those from `SIUSB_DEVICE_IDS` are removed as well and not read again.
+pub fn clear_vid_pids() {
+    let mut registered = REGISTERED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    unsafe { SI_ClearDeviceIds() };
+    registered.clear();
+}
+
+/// Returns the match table, [`DEFAULT_VID_PID`] first
+pub fn vid_pids() -> Vec<(u16, u16)> {
+    init();
+    let registered = REGISTERED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    std::iter::once(DEFAULT_VID_PID)
+        .chain(registered.iter().copied())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_ids_extend_the_table_until_cleared() {
+        register_vid_pid(0x10c4, 0x8abc).unwrap();
+        register_vid_pid(0x10c4, 0x8abc).unwrap();
+        register_vid_pid(DEFAULT_VID_PID.0, DEFAULT_VID_PID.1).unwrap();
+        assert_eq!(vid_pids(), vec![DEFAULT_VID_PID, (0x10c4, 0x8abc)]);
+
+        for pid in 0..MAX_REGISTERED as u16 - 1 {
+            register_vid_pid(0x1234, pid).unwrap();
+        }
+        assert!(matches!(
+            register_vid_pid(0x1234, 0xffff),
+            Err(SilabsUsbXpressError::VidPidTableFull)
+        ));
+
+        clear_vid_pids();
+        assert_eq!(vid_pids(), vec![DEFAULT_VID_PID]);
+    }
+
+    #[test]
+    fn env_ids_parse_pairs_and_report_the_rest() {
+        let (ids, invalid) = parse_vid_pids("10C4:EA61, 10c4:8abc,,bogus,10c4:*,1:2:3");
+        assert_eq!(
+            ids,
+            vec![
+                (0x10c4, Some(0xea61)),
+                (0x10c4, Some(0x8abc)),
+                (0x10c4, None)
+            ]
+        );
+        assert_eq!(invalid, vec!["bogus", "1:2:3"]);
+    }
+}
@@ -0,0 +1,374 @@
+//! Reports of the connected devices for asset tracking
+//!
+//! [`export`] lists every device libusb sees, without opening or claiming
+//! any of them, as JSON or CSV:
+//!
+//! ```rust, ignore
+//! use silabs_usb_xpress::inventory::{export, Format};
+//!
+//! std::fs::write("inventory.csv", export(Format::Csv)?)?;
+//! ```
+//!
+//! [`watch_devices`] keeps track of the connected devices and reports every
+//! device plugged in or removed:
+//!
+//! ```rust, ignore
+//! for change in watch_devices(Duration::from_secs(1)) {
+//!     match change? {
+//!         DeviceSetChange::Added(info) => println!("+ {}", info.serial),
+//!         DeviceSetChange::Removed(info) => println!("- {}", info.serial),
+//!     }
+//! }
+//! ```
+use std::{collections::VecDeque, ffi::CStr, fmt::Write, os::raw::c_char, thread, time::Duration};
+
+use crate::{
+    count_devices, device_ids, device_num, ffi::*, lock_enumeration, product_string, DeviceInfo,
+    ProductStringType, SilabsUsbXpressError,
+};
+
+/// Output format of [`export`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// An array with one object per device
+    Json,
+    /// A header line followed by one line per device
+    Csv,
+}
+
+/// Endpoints of the interface a device is opened on
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    pub bulk_in: bool,
+    pub bulk_out: bool,
+    pub interrupt_in: bool,
+}
+
+/// A connected device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Entry {
+    /// Index to open the device with
+    pub index: usize,
+    /// Serial number string, empty for devices other than USBXpress devices
+    pub serial: String,
+    /// Product description string, empty for devices other than USBXpress
+    /// devices
+    pub description: String,
+    /// Link name string, empty for devices other than USBXpress devices
+    pub link_name: String,
+    pub vid: u16,
+    pub pid: u16,
+    /// Bus and device number, like `001/004`
+    pub bus_path: String,
+    /// Kernel driver bound to the device, `None` where libusb cannot tell
+    /// or no driver is bound
+    pub driver: Option<String>,
+    pub capabilities: Capabilities,
+}
+
+/// Lists the connected devices
+///
+/// All entries come from one enumeration, which other threads cannot
+/// refresh until the list is complete.
+pub fn entries() -> Result<Vec<Entry>, SilabsUsbXpressError> {
+    let _enumeration = lock_enumeration();
+    let mut entries = Vec::new();
+    for index in 0..count_devices()? {
+        let (vid, pid) = device_ids(index)?;
+        let (bus_path, driver, capabilities) = details(index)?;
+        entries.push(Entry {
+            index,
+            serial: product_string(index, ProductStringType::SerialNumber)?,
+            description: product_string(index, ProductStringType::Description)?,
+            link_name: product_string(index, ProductStringType::LinkName)?,
+            vid,
+            pid,
+            bus_path,
+            driver,
+            capabilities,
+        });
+    }
+    Ok(entries)
+}
+
+/// Lists the connected devices in `format`
+pub fn export(format: Format) -> Result<String, SilabsUsbXpressError> {
+    let entries = entries()?;
+    Ok(match format {
+        Format::Json => to_json(&entries),
+        Format::Csv => to_csv(&entries),
+    })
+}
+
+/// A device plugged in or removed, see [`watch_devices`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSetChange {
+    Added(DeviceInfo),
+    Removed(DeviceInfo),
+}
+
+/// Watches for devices being plugged in and removed, checking every
+/// `interval`
+///
+/// The returned iterator blocks until the set of connected devices changes
+/// and yields one change at a time; the devices connected at the start are
+/// reported as added first. libusb 0.1 has no hotplug notifications, so the
+/// device list is polled on every platform. An enumeration error is yielded
+/// as is and watching goes on with the next poll.
+pub fn watch_devices(interval: Duration) -> Watch {
+    Watch {
+        interval,
+        known: Vec::new(),
+        changes: VecDeque::new(),
+        first: true,
+    }
+}
+
+/// Iterator over device set changes, see [`watch_devices`]
+#[derive(Debug)]
+pub struct Watch {
+    interval: Duration,
+    known: Vec<DeviceInfo>,
+    changes: VecDeque<DeviceSetChange>,
+    first: bool,
+}
+
+impl Watch {
+    /// Returns the devices connected as of the last poll
+    pub fn devices(&self) -> &[DeviceInfo] {
+        &self.known
+    }
+}
+
+impl Iterator for Watch {
+    type Item = Result<DeviceSetChange, SilabsUsbXpressError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(change) = self.changes.pop_front() {
+                return Some(Ok(change));
+            }
+            if !self.first {
+                thread::sleep(self.interval);
+            }
+            self.first = false;
+            let current = match crate::devices() {
+                Ok(devices) => devices,
+                Err(err) => return Some(Err(err)),
+            };
+            self.changes.extend(diff(&self.known, &current));
+            self.known = current;
+        }
+    }
+}
+
+/// Lists the devices in `new` but not in `old` as added, then those in
+/// `old` but not in `new` as removed
+///
+/// Indices shift as other devices come and go, so they are not compared.
+pub(crate) fn diff(old: &[DeviceInfo], new: &[DeviceInfo]) -> Vec<DeviceSetChange> {
+    let same = |a: &DeviceInfo, b: &DeviceInfo| {
+        DeviceInfo {
+            index: 0,
+            ..a.clone()
+        } == DeviceInfo {
+            index: 0,
+            ..b.clone()
+        }
+    };
+    let added = new
+        .iter()
+        .filter(|info| !old.iter().any(|known| same(known, info)))
+        .map(|info| DeviceSetChange::Added(info.clone()));
+    let removed = old
+        .iter()
+        .filter(|info| !new.iter().any(|current| same(current, info)))
+        .map(|info| DeviceSetChange::Removed(info.clone()));
+    added.chain(removed).collect()
+}
+
+fn details(index: usize) -> Result<(String, Option<String>, Capabilities), SilabsUsbXpressError> {
+    let device_num = device_num(index)?;
+    let mut path = [0 as c_char; SI_MAX_DEVICE_STRLEN as usize];
+    let mut driver = [0 as c_char; SI_MAX_DEVICE_STRLEN as usize];
+    let mut caps = 0;
+    let status = unsafe {
+        SI_GetDeviceDetails(
+            device_num,
+            path.as_mut_ptr(),
+            driver.as_mut_ptr(),
+            &mut caps,
+        )
+    };
+    match status as u32 {
+        SI_SUCCESS => {
+            let string = |buffer: &[c_char]| {
+                unsafe { CStr::from_ptr(buffer.as_ptr()) }
+                    .to_string_lossy()
+                    .into_owned()
+            };
+            let driver = string(&driver);
+            let caps = caps as u32;
+            Ok((
+                string(&path),
+                if driver.is_empty() {
+                    None
+                } else {
+                    Some(driver)
+                },
+                Capabilities {
+                    bulk_in: caps & SI_CAP_BULK_IN != 0,
+                    bulk_out: caps & SI_CAP_BULK_OUT != 0,
+                    interrupt_in: caps & SI_CAP_INTERRUPT_IN != 0,
+                },
+            ))
+        }
+        SI_DEVICE_NOT_FOUND => Err(SilabsUsbXpressError::DeviceNotFound),
+        _ => unreachable!(
+            "Unreachable status code: {}. Please contact the author or submit an issue.",
+            status
+        ),
+    }
+}
+
+fn to_json(entries: &[Entry]) -> String {
+    let mut json = String::from("[");
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        let _ = write!(
+            json,
+            "\n  {{\"index\": {}, \"serial\": {}, \"description\": {}, \"vid\": \"{:04x}\", \
+             \"pid\": \"{:04x}\", \"bus_path\": {}, \"driver\": {}, \"capabilities\": \
+             {{\"bulk_in\": {}, \"bulk_out\": {}, \"interrupt_in\": {}}}}}",
+            entry.index,
+            json_string(&entry.serial),
+            json_string(&entry.description),
+            entry.vid,
+            entry.pid,
+            json_string(&entry.bus_path),
+            entry
+                .driver
+                .as_deref()
+                .map_or_else(|| "null".to_owned(), json_string),
+            entry.capabilities.bulk_in,
+            entry.capabilities.bulk_out,
+            entry.capabilities.interrupt_in,
+        );
+    }
+    if !entries.is_empty() {
+        json.push('\n');
+    }
+    json.push_str("]\n");
+    json
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(json, "\\u{:04x}", c as u32);
+            }
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+fn to_csv(entries: &[Entry]) -> String {
+    let mut csv = String::from(
+        "index,serial,description,vid,pid,bus_path,driver,bulk_in,bulk_out,interrupt_in\n",
+    );
+    for entry in entries {
+        let _ = writeln!(
+            csv,
+            "{},{},{},{:04x},{:04x},{},{},{},{},{}",
+            entry.index,
+            csv_field(&entry.serial),
+            csv_field(&entry.description),
+            entry.vid,
+            entry.pid,
+            csv_field(&entry.bus_path),
+            csv_field(entry.driver.as_deref().unwrap_or("")),
+            entry.capabilities.bulk_in,
+            entry.capabilities.bulk_out,
+            entry.capabilities.interrupt_in,
+        );
+    }
+    csv
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            index: 0,
+            serial: "0001".to_owned(),
+            description: "Sensor, \"v2\"".to_owned(),
+            link_name: String::new(),
+            vid: 0x10c4,
+            pid: 0x8149,
+            bus_path: "001/004".to_owned(),
+            driver: None,
+            capabilities: Capabilities {
+                bulk_in: true,
+                bulk_out: true,
+                interrupt_in: false,
+            },
+        }
+    }
+
+    #[test]
+    fn exports_escape_strings() {
+        assert_eq!(
+            to_csv(&[entry()]),
+            "index,serial,description,vid,pid,bus_path,driver,bulk_in,bulk_out,interrupt_in\n\
+             0,0001,\"Sensor, \"\"v2\"\"\",10c4,8149,001/004,,true,true,false\n"
+        );
+        assert_eq!(
+            to_json(&[entry()]),
+            "[\n  {\"index\": 0, \"serial\": \"0001\", \"description\": \"Sensor, \\\"v2\\\"\", \
+             \"vid\": \"10c4\", \"pid\": \"8149\", \"bus_path\": \"001/004\", \"driver\": null, \
+             \"capabilities\": {\"bulk_in\": true, \"bulk_out\": true, \"interrupt_in\": false}}\n]\n"
+        );
+        assert_eq!(to_json(&[]), "[]\n");
+    }
+
+    #[test]
+    fn watch_reports_added_and_removed_devices() {
+        let info = |serial: &str, bus_path: &str| DeviceInfo {
+            serial: serial.to_owned(),
+            bus_path: bus_path.to_owned(),
+            ..DeviceInfo::default()
+        };
+        let old = [info("0001", "001/004"), info("0002", "001/005")];
+        let mut new = [info("0002", "001/005"), info("0001", "001/007")];
+        new[0].index = 1;
+        assert_eq!(
+            diff(&old, &new),
+            [
+                DeviceSetChange::Added(info("0001", "001/007")),
+                DeviceSetChange::Removed(info("0001", "001/004")),
+            ]
+        );
+        assert!(diff(&new, &new).is_empty());
+    }
+}
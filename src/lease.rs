@@ -0,0 +1,381 @@
+//! Reserving shared devices between processes
+//!
+//! CI jobs sharing a rack of devices reserve the ones they use with
+//! [`reserve`]. A reservation is a small lease file, named after the device
+//! and holding its owner and expiry time, in a directory all jobs share. A
+//! job that dies without releasing its devices leaves its leases behind, but
+//! they expire after their time to live and are then taken over by the next
+//! job asking for the device.
+//!
+//! ```rust, ignore
+//! let lease = lease::reserve("0001A2F3", "ci-job-4711", Duration::from_secs(600))?;
+//! // ... use the device ...
+//! lease.release()?;
+//! ```
+//!
+//! Leases are kept in `silabs_usb_xpress-leases` in the system's temporary
+//! directory unless the `SIUSB_LEASE_DIR` environment variable names
+//! another one; [`LeaseStore`] uses a directory given from code.
+//!
+//! Every change to a lease is made while holding an advisory lock on a
+//! `.lock` file next to it, so that two jobs finding the same lease expired
+//! cannot both take it over. Lease files are replaced by renaming a new one
+//! into place and never seen partly written.
+use std::{
+    env, fmt,
+    fs::{self, File, OpenOptions},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// A directory of lease files
+#[derive(Clone, Debug)]
+pub struct LeaseStore {
+    dir: PathBuf,
+}
+
+impl Default for LeaseStore {
+    fn default() -> Self {
+        let dir = env::var_os("SIUSB_LEASE_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| env::temp_dir().join("silabs_usb_xpress-leases"));
+        LeaseStore { dir }
+    }
+}
+
+impl LeaseStore {
+    /// Keeps leases in `dir`, which is created when needed
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        LeaseStore { dir: dir.into() }
+    }
+
+    /// Reserves the device identified by `device_id`, usually its serial
+    /// number, for `owner` during `ttl`
+    ///
+    /// Fails with [`LeaseError::Held`] if another owner holds an unexpired
+    /// lease on the device, and with [`LeaseError::Unreadable`] if its lease
+    /// file cannot be parsed.
+    pub fn reserve(
+        &self,
+        device_id: &str,
+        owner: &str,
+        ttl: Duration,
+    ) -> Result<Lease, LeaseError> {
+        fs::create_dir_all(&self.dir).map_err(LeaseError::Io)?;
+        let path = self.dir.join(file_name(device_id));
+        let _lock = lock(&path).map_err(LeaseError::Io)?;
+        match Record::read(&path).map_err(LeaseError::Io)? {
+            Stored::Missing => {}
+            Stored::Unreadable => return Err(LeaseError::Unreadable(path)),
+            Stored::Valid(held) if held.expires > SystemTime::now() => {
+                return Err(LeaseError::Held {
+                    owner: held.owner,
+                    expires: held.expires,
+                })
+            }
+            // Expired, taken over below
+            Stored::Valid(_) => {}
+        }
+        let record = Record {
+            owner: owner.to_owned(),
+            expires: SystemTime::now() + ttl,
+            token: token(),
+        };
+        record.store(&path).map_err(LeaseError::Io)?;
+        Ok(Lease {
+            path,
+            record,
+            released: false,
+        })
+    }
+}
+
+/// Takes the advisory lock guarding the lease at `path`, held until the
+/// returned file is dropped
+///
+/// The lock files stay in place, removing them would let two processes lock
+/// different files for the same lease.
+fn lock(path: &Path) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.with_extension("lock"))?;
+    file.lock()?;
+    Ok(file)
+}
+
+/// Reserves a device in the default lease directory, see
+/// [`LeaseStore::reserve`]
+pub fn reserve(device_id: &str, owner: &str, ttl: Duration) -> Result<Lease, LeaseError> {
+    LeaseStore::default().reserve(device_id, owner, ttl)
+}
+
+/// A held reservation, released when dropped
+#[derive(Debug)]
+pub struct Lease {
+    path: PathBuf,
+    record: Record,
+    released: bool,
+}
+
+impl Lease {
+    pub fn owner(&self) -> &str {
+        &self.record.owner
+    }
+
+    pub fn expires(&self) -> SystemTime {
+        self.record.expires
+    }
+
+    /// Extends the lease to `ttl` from now
+    ///
+    /// Fails with [`LeaseError::Lost`] if the lease expired and was taken
+    /// over in the meantime.
+    pub fn renew(&mut self, ttl: Duration) -> Result<(), LeaseError> {
+        let _lock = lock(&self.path).map_err(LeaseError::Io)?;
+        self.check_held()?;
+        let mut record = self.record.clone();
+        record.expires = SystemTime::now() + ttl;
+        record.store(&self.path).map_err(LeaseError::Io)?;
+        self.record = record;
+        Ok(())
+    }
+
+    /// Gives the device back
+    ///
+    /// Fails with [`LeaseError::Lost`] if the lease expired and was taken
+    /// over in the meantime, in which case the new owner's lease is kept.
+    pub fn release(mut self) -> Result<(), LeaseError> {
+        self.released = true;
+        let _lock = lock(&self.path).map_err(LeaseError::Io)?;
+        self.check_held()?;
+        remove(&self.path).map_err(LeaseError::Io)
+    }
+
+    /// Tells whether the lease file is still this lease, with its lock held
+    fn check_held(&self) -> Result<(), LeaseError> {
+        match Record::read(&self.path).map_err(LeaseError::Io)? {
+            Stored::Valid(record) if record.token == self.record.token => Ok(()),
+            _ => Err(LeaseError::Lost),
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if self.released {
+            return;
+        }
+        if let Ok(_lock) = lock(&self.path) {
+            if self.check_held().is_ok() {
+                let _ = remove(&self.path);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LeaseError {
+    Io(io::Error),
+    /// Another owner holds the device until `expires`
+    Held {
+        owner: String,
+        expires: SystemTime,
+    },
+    /// The lease expired and was taken over by another owner
+    Lost,
+    /// The lease file at the path cannot be parsed
+    ///
+    /// It is treated as held, as its owner and expiry are unknown; remove
+    /// the file by hand to free the device.
+    Unreadable(PathBuf),
+}
+
+impl fmt::Display for LeaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format!("{:?}", self))
+    }
+}
+
+impl std::error::Error for LeaseError {}
+
+/// Contents of a lease file, one `key=value` per line
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Record {
+    owner: String,
+    expires: SystemTime,
+    /// Tells this lease apart from a later one of the same owner
+    token: String,
+}
+
+/// What a lease file holds
+enum Stored {
+    Missing,
+    Unreadable,
+    Valid(Record),
+}
+
+impl Record {
+    /// Reads the lease at `path`
+    fn read(path: &Path) -> io::Result<Stored> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Record::parse(&contents).map_or(Stored::Unreadable, Stored::Valid)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Stored::Missing),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Replaces the lease at `path` with this record
+    ///
+    /// The record is written to a file of its own first and then renamed
+    /// into place, so that readers see either the old or the new lease.
+    fn store(&self, path: &Path) -> io::Result<()> {
+        let staged = path.with_file_name(format!("{}.tmp", self.token));
+        fs::write(&staged, self.to_string())?;
+        fs::rename(&staged, path).inspect_err(|_| {
+            let _ = fs::remove_file(&staged);
+        })
+    }
+
+    fn parse(contents: &str) -> Option<Record> {
+        let (mut owner, mut expires, mut token) = (None, None, None);
+        for line in contents.lines() {
+            match line.split_once('=')? {
+                ("owner", value) => owner = Some(value.to_owned()),
+                ("expires", value) => {
+                    expires = Some(UNIX_EPOCH + Duration::from_millis(value.parse().ok()?))
+                }
+                ("token", value) => token = Some(value.to_owned()),
+                _ => {}
+            }
+        }
+        Some(Record {
+            owner: owner?,
+            expires: expires?,
+            token: token?,
+        })
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expires = self
+            .expires
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        writeln!(f, "owner={}", self.owner.replace('\n', " "))?;
+        writeln!(f, "expires={}", expires)?;
+        writeln!(f, "token={}", self.token)
+    }
+}
+
+fn token() -> String {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let count = COUNT.fetch_add(1, Ordering::Relaxed);
+    format!("{}-{}-{}", process::id(), nanos, count)
+}
+
+/// Maps a device ID to a file name, replacing anything but letters, digits,
+/// `-`, `_` and `.`
+fn file_name(device_id: &str) -> String {
+    let name: String = device_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}.lease", name)
+}
+
+fn remove(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leases_exclude_others_until_released_or_expired() {
+        let dir = env::temp_dir().join(format!("silabs_usb_xpress-lease-test-{}", token()));
+        let store = LeaseStore::new(&dir);
+        let ttl = Duration::from_secs(60);
+
+        let lease = store.reserve("0001A2F3", "job-1", ttl).unwrap();
+        match store.reserve("0001A2F3", "job-2", ttl) {
+            Err(LeaseError::Held { owner, .. }) => assert_eq!(owner, "job-1"),
+            other => panic!("unexpected {:?}", other),
+        }
+        lease.release().unwrap();
+        let stale = store.reserve("0001A2F3", "job-2", Duration::ZERO).unwrap();
+        let lease = store.reserve("0001A2F3", "job-3", ttl).unwrap();
+        assert!(matches!(stale.release(), Err(LeaseError::Lost)));
+        assert_eq!(lease.owner(), "job-3");
+        drop(lease);
+        assert!(!dir.join("0001A2F3.lease").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn one_of_two_stores_racing_for_an_expired_lease_wins() {
+        use std::sync::{Arc, Barrier};
+
+        let dir = env::temp_dir().join(format!("silabs_usb_xpress-lease-test-{}", token()));
+        for round in 0..20 {
+            let device = format!("DEV{}", round);
+            let stale = LeaseStore::new(&dir)
+                .reserve(&device, "crashed-job", Duration::ZERO)
+                .unwrap();
+            std::mem::forget(stale);
+
+            let barrier = Arc::new(Barrier::new(2));
+            let racers: Vec<_> = ["job-a", "job-b"]
+                .iter()
+                .map(|owner| {
+                    let (dir, device, barrier) = (dir.clone(), device.clone(), barrier.clone());
+                    std::thread::spawn(move || {
+                        let store = LeaseStore::new(dir);
+                        barrier.wait();
+                        store.reserve(&device, owner, Duration::from_secs(60))
+                    })
+                })
+                .collect();
+            let results: Vec<_> = racers.into_iter().map(|r| r.join().unwrap()).collect();
+            let held: Vec<_> = results.iter().filter_map(|r| r.as_ref().ok()).collect();
+            assert_eq!(held.len(), 1, "round {}: {:?}", round, results);
+            assert!(results.iter().any(
+                |r| matches!(r, Err(LeaseError::Held { owner, .. }) if owner == held[0].owner())
+            ));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn unparseable_leases_are_held() {
+        let dir = env::temp_dir().join(format!("silabs_usb_xpress-lease-test-{}", token()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("0001A2F3.lease"), "owner=job-1\nexpi").unwrap();
+        let store = LeaseStore::new(&dir);
+        assert!(matches!(
+            store.reserve("0001A2F3", "job-2", Duration::from_secs(60)),
+            Err(LeaseError::Unreadable(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -29,8 +29,116 @@
 //! By default, `libusb` is linked dynamically, and set environment variable `VCPKGRS_DYNAMIC=0`
 //! if you want to link statically.
 //!
+//! Set `LIBUSB_STATIC=1` to link `libusb` statically; this is the default for
+//! musl targets. When cross-compiling, point `LIBUSB_DIR` at a `libusb`
+//! (`libusb-compat` on top of `libusb-1.0`) built for the target, with
+//! `include` and `lib` directories, and `pkg-config` is not consulted at all.
+//! A static `libusb-1.0` for embedded hosts is best configured with
+//! `--disable-udev` so that it has no further dependencies.
+//!
+//! On macOS, install `libusb-compat` with Homebrew (`brew install libusb-compat`)
+//! or MacPorts. If `pkg-config` cannot find it, the build script looks in the
+//! default Homebrew and MacPorts prefixes. The CP210x serial drivers shipped by
+//! Apple and SiLabs claim the device, in which case opening it fails with
+//! `DeviceBusy`.
+//!
+//! On FreeBSD the `libusb` in base is used. On OpenBSD and NetBSD, install
+//! `libusb-compat` from ports or pkgsrc. The `uslcom` driver has to be kept off
+//! the device and the user needs access to its `/dev/ugen*` nodes.
+//!
+//! For the `windows-gnu` targets, natively in MSYS2 or cross-compiled from Linux,
+//! `libusb-compat` is found through `pkg-config` (set `PKG_CONFIG_ALLOW_CROSS=1`
+//! when cross-compiling). Alternatively, point `LIBUSB_DIR` at an unpacked
+//! `libusb-win32` with its `lusb0_usb.h` in `include` and the MinGW `libusb.a`
+//! in `lib`.
+//!
 //! To pack a available driver in Windows, use [libusbk' inf wizard](https://osdn.net/projects/sfnet_libusb-win32/downloads/libusb-win32-releases/libusbK-inf-wizard.exe/).
 //!
+//! The `rust-backend` feature replaces the bundled C library with an
+//! implementation on top of [rusb](https://crates.io/crates/rusb), so that
+//! `libusb-compat` is not needed. `rusb` links an installed `libusb-1.0` and
+//! only falls back to compiling it from source if there is none. Device paths
+//! then take the form `001/004`, and the kernel driver of a device is not
+//! reported.
+//!
+//! The `nusb-backend` feature does the same on top of
+//! [nusb](https://crates.io/crates/nusb), which talks to the operating
+//! system directly and needs no libusb at all. Device paths take the same
+//! form, the kernel driver is reported on Linux, and a reset always
+//! re-enumerates the device. With both features, `rust-backend` is used.
+//!
+//! The `raw-handle` feature adds `UsbXpress::raw_libusb_handle`, which hands
+//! out the libusb handle underneath for vendor requests the crate does not
+//! model yet: a `libusb_device_handle` with the `rust-backend` feature, a
+//! libusb 0.1 `usb_dev_handle` otherwise.
+//!
+//! Enumeration and product strings sit behind the default `enumeration`
+//! feature. With `default-features = false`, only the transfer API is built,
+//! and devices are opened by their device node with `UsbXpress::open_path`, or
+//! on Linux with a Rust backend from a file descriptor with
+//! `UsbXpress::from_fd`.
+//!
+//! The `capi` feature exports a C API, declared in
+//! `include/silabs_usb_xpress.h`, for use as a drop-in replacement of
+//! SiUSBXp.dll from C, C++ or LabVIEW. Build it with
+//! `cargo rustc --release --features capi --crate-type cdylib`.
+//!
+//! The `python` feature turns the crate into a `silabs_usb_xpress` Python
+//! extension module, exposing `devices()`, `set_timeouts()` and an `SiHandle`
+//! class. Build it with [maturin](https://github.com/PyO3/maturin) using
+//! `maturin build --features python`.
+//!
+//! The `profiles` feature loads named device profiles from a TOML file with
+//! `profiles::load`, keyed by serial number, and opens devices by profile name.
+//!
+//! The `calibration` feature stores serde types in device flash with
+//! `UsbXpress::write_calibration` and reads them back with
+//! `UsbXpress::read_calibration`, framed with a version and a CRC.
+//!
+//! Friendly device names can be installed with `aliases::set_store`, from any
+//! map of serial numbers to names or from an `aliases.toml` file read with
+//! `aliases::load` (`profiles` feature); logs, `Debug` output and production
+//! reports show the alias instead of the serial number.
+//!
+//! The `server` feature serves the host's devices over a small REST API, see
+//! the `server` module.
+//!
+//! Devices of other backends, like proxies or simulators, can be plugged in
+//! as transports and opened by URIs such as `remote://host/serial`, see the
+//! `transport` module.
+//!
+//! The `secure` feature runs a Noise handshake with the device firmware and
+//! encrypts the traffic that follows, see the `secure` module.
+//!
+//! The `compression` feature compresses the traffic to firmware that supports
+//! it with LZ4 or deflate, see the `compress` module.
+//!
+//! The `scripting` feature drives devices from rhai scripts, which the
+//! `sixpress` tool runs with `sixpress run script.rhai`, see the `scripting`
+//! module.
+//!
+//! The `tokio` feature adds `AsyncUsbXpress`, which implements tokio's
+//! `AsyncRead` and `AsyncWrite` and runs the transfers on a worker thread,
+//! see the `async_io` module. The `futures` feature implements the
+//! `futures-io` traits instead, for async-std, smol and other runtimes.
+//!
+//! `hotplug::watch` reports devices with a given vendor and product ID as they
+//! are plugged in and removed, from libusb hotplug callbacks with the
+//! `rust-backend` feature and by polling the device list otherwise.
+//! `hotplug::wait_for_device` waits for a device with a given serial number or
+//! IDs to show up.
+//!
+//! `overlapped::Overlapped` submits reads and writes to a worker thread and
+//! returns at once with a transfer to poll or wait on.
+//!
+//! `reconnect::ReconnectingHandle` reopens a device by its serial number
+//! after it was unplugged and plugged back in, and repeats the transfer that
+//! failed, retrying according to a `RetryPolicy`.
+//!
+//! Handles count their transfers, errors and timeouts per device, see the
+//! `metrics` module. The `prometheus` feature serves these counters to a
+//! Prometheus server.
+//!
 //! # Example
 //! ```rust, ignore
 //! # use silabs_usb_xpress::{UsbXpress, product_string, devices_count,
@@ -41,6 +149,9 @@
 //! // get device count
 //! let num = devices_count();
 //!
+//! // list the devices with their serial numbers, IDs and paths
+//! let list = devices().unwrap();
+//!
 //! // print serial number for selected devices
 //! let if_sn = product_string(0, ProductStringType::SerialNumber);
 //!
@@ -48,6 +159,9 @@
 //! let pst = ProductStringType::VID;
 //! let if_vid = product_string(0, pst);
 //!
+//! // or as a number
+//! let vid = vendor_id(0).unwrap();
+//!
 //! // get timeouts
 //! let t = timeouts().unwrap();
 //!
@@ -57,13 +171,32 @@
 //! // open handle
 //! let mut handle = UsbXpress::open(0).unwrap();
 //!
+//! // or by serial number, which unlike the index does not change
+//! let mut handle = UsbXpress::open_by_serial("0001A2F3").unwrap();
+//!
+//! // with its own timeouts, leaving other handles alone
+//! handle.set_timeouts(Duration::from_secs(2), None);
+//!
 //! // write to device handle
 //! let v = vec![0x55, 0x80, 0x00, 0x01, 0x01, 0xAA];
 //! handle.write(&v);
 //!
+//! // or keep writing until all of it went out
+//! handle.write_all(&v).unwrap();
+//!
 //! // read 7 bytes from device handle
 //! let read_res = handle.read(7);
 //!
+//! // or give a slow command longer, for this call only
+//! let read_res = handle.read_timeout(7, Duration::from_secs(5));
+//!
+//! // or insist on all 7 bytes, since a read may return fewer
+//! let read_res = handle.read_exact(7);
+//!
+//! // or into a buffer reused from read to read
+//! let mut buf = [0u8; 64];
+//! let n = handle.read_into(&mut buf).unwrap();
+//!
 //! // close device
 //! handle.close();
 //! # }
@@ -71,15 +204,120 @@
 //!
 //! # License
 //! [![License: GPL v3](https://img.shields.io/badge/License-GPLv3-blue.svg)](https://www.gnu.org/licenses/gpl-3.0)
-use std::{error::Error, fmt, fmt::Formatter, mem::MaybeUninit, os::raw::c_char, time::Duration};
+extern crate alloc;
+
+#[cfg(target_os = "linux")]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(all(
+    target_os = "linux",
+    any(feature = "rust-backend", feature = "nusb-backend")
+))]
+use std::os::unix::io::{IntoRawFd, OwnedFd};
+use std::{
+    error::Error,
+    ffi::{CStr, CString},
+    fmt,
+    fmt::Formatter,
+    io::{self, IoSlice},
+    ops::RangeInclusive,
+    os::raw::{c_char, c_int, c_void},
+    path::Path,
+    sync::{mpsc, Arc, Mutex, MutexGuard},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
 
+pub use buffered::BufferedUsbXpress;
+use codec::{Decoder, Frames};
+pub use config::{configure, Configure};
 use ffi::*;
+pub use id_table::{clear_vid_pids, register_vid_pid, vid_pids};
+pub use pump::pump;
+pub use version::library_version;
 
+pub mod aliases;
+#[cfg(any(feature = "tokio", feature = "futures"))]
+pub mod async_io;
+#[cfg(any(feature = "rust-backend", feature = "nusb-backend"))]
+#[allow(dead_code)]
+mod backend;
+pub mod bringup;
+pub mod buffered;
+pub mod bus;
+#[cfg(feature = "calibration")]
+pub mod calibration;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod codec;
+#[cfg(feature = "compression")]
+pub mod compress;
+pub mod config;
+pub mod firmware;
+pub mod frame;
+#[cfg(feature = "enumeration")]
+pub mod hotplug;
+pub mod id_table;
+#[cfg(feature = "enumeration")]
+pub mod inventory;
+pub mod lease;
+pub mod metrics;
+pub mod mux;
+pub mod overlapped;
+#[cfg(feature = "enumeration")]
+pub mod production;
+#[cfg(feature = "profiles")]
+pub mod profiles;
+pub mod pump;
+#[cfg(feature = "python")]
+mod python;
+#[cfg(feature = "enumeration")]
+pub mod reconnect;
+#[cfg(feature = "scripting")]
+pub mod scripting;
+#[cfg(feature = "secure")]
+pub mod secure;
+pub mod selftest;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod transcript;
+pub mod transport;
+pub mod version;
+
+#[cfg(not(any(feature = "rust-backend", feature = "nusb-backend")))]
 #[allow(dead_code)]
 mod ffi {
     include!("bindings.rs");
 }
 
+#[cfg(feature = "rust-backend")]
+#[allow(dead_code)]
+#[path = "rusb_backend.rs"]
+mod ffi;
+
+#[cfg(all(feature = "nusb-backend", not(feature = "rust-backend")))]
+#[allow(dead_code)]
+#[path = "nusb_backend.rs"]
+mod ffi;
+
+#[cfg(all(
+    feature = "raw-handle",
+    feature = "nusb-backend",
+    not(feature = "rust-backend")
+))]
+compile_error!(
+    "the `raw-handle` feature needs libusb, which the `nusb-backend` feature does without"
+);
+
+/// The libusb device handle returned by
+/// [`UsbXpress::raw_libusb_handle`], libusb-1.0's `libusb_device_handle`
+#[cfg(all(feature = "raw-handle", feature = "rust-backend"))]
+pub type LibusbDeviceHandle = rusb::ffi::libusb_device_handle;
+
+/// The libusb device handle returned by
+/// [`UsbXpress::raw_libusb_handle`], libusb 0.1's opaque `usb_dev_handle`
+#[cfg(all(feature = "raw-handle", not(feature = "rust-backend")))]
+pub type LibusbDeviceHandle = ffi::UsbDevHandle;
+
 /// Returns the number of devices connected
 ///
 /// This function returns the number of devices connected to the host.
@@ -89,14 +327,31 @@ mod ffi {
 /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
 /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
 /// CP2101/2/3/4/5/8/9/
+#[cfg(feature = "enumeration")]
 pub fn devices_count() -> Result<usize, SilabsUsbXpressError> {
-    let (status, num) = unsafe {
-        let mut num = MaybeUninit::uninit();
-        let status = SI_GetNumDevices(num.as_mut_ptr());
-        (status, num.assume_init())
-    };
+    let _enumeration = lock_enumeration();
+    count_devices()
+}
+
+/// Held while the device list is refreshed, and by [`devices`] while it
+/// reads the whole list, so that no other thread refreshes it part way
+/// through
+static ENUMERATION: Mutex<()> = Mutex::new(());
+
+fn lock_enumeration() -> MutexGuard<'static, ()> {
+    ENUMERATION
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Refreshes the device list and counts the devices, with the enumeration
+/// lock held by the caller
+fn count_devices() -> Result<usize, SilabsUsbXpressError> {
+    id_table::init();
+    let mut num = 0;
+    let status = unsafe { SI_GetNumDevices(&mut num) };
     match status as u32 {
-        SI_SUCCESS => Ok(num as usize),
+        SI_SUCCESS => Ok(filter_devices(num)),
         SI_DEVICE_NOT_FOUND => Err(SilabsUsbXpressError::DeviceNotFound),
         _ => unreachable!(
             "Unreachable status code: {}. Please contact the author or submit an issue.",
@@ -105,6 +360,109 @@ pub fn devices_count() -> Result<usize, SilabsUsbXpressError> {
     }
 }
 
+/// Device numbers of the devices `SIUSB_VID_PID_FILTER` allows, in order,
+/// as of the last refresh of the device list
+///
+/// With a filter set, device indices count these devices only, see
+/// [`config`].
+static ALLOWED: Mutex<Option<Vec<c_int>>> = Mutex::new(None);
+
+/// Picks the devices the filter allows out of the `num` devices of the
+/// refreshed list, returning how many there are
+fn filter_devices(num: c_int) -> usize {
+    if !config::has_vid_pid_filter() {
+        return num as usize;
+    }
+    let allowed: Vec<c_int> = (0..num)
+        .filter(|&device_num| {
+            ids_of(device_num).is_ok_and(|(vid, pid)| config::vid_pid_allowed(vid, pid))
+        })
+        .collect();
+    let count = allowed.len();
+    *ALLOWED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(allowed);
+    count
+}
+
+/// Returns the number the backend knows the device at `device_ix` by
+///
+/// The two only differ with `SIUSB_VID_PID_FILTER` set, when the device list
+/// is refreshed first if it has not been yet.
+fn device_num(device_ix: usize) -> Result<c_int, SilabsUsbXpressError> {
+    if !config::has_vid_pid_filter() {
+        return Ok(device_ix as c_int);
+    }
+    let listed = ALLOWED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .is_some();
+    if !listed {
+        let _enumeration = lock_enumeration();
+        count_devices()?;
+    }
+    ALLOWED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()
+        .and_then(|allowed| allowed.get(device_ix).copied())
+        .ok_or(SilabsUsbXpressError::DeviceNotFound)
+}
+
+/// Returns the index of the device at `path`, refreshing the device list
+///
+/// Only the last two components of `path`, the bus and the device, are
+/// looked at.
+fn path_index(path: &Path) -> Result<usize, SilabsUsbXpressError> {
+    let path = CString::new(path.to_string_lossy().as_bytes())
+        .map_err(|_| SilabsUsbXpressError::DeviceNotFound)?;
+    let mut device_num = 0;
+    let status = {
+        let _enumeration = lock_enumeration();
+        unsafe { SI_GetDeviceNumByPath(path.as_ptr(), &mut device_num) }
+    };
+    match status as u32 {
+        SI_SUCCESS => device_index(device_num),
+        SI_DEVICE_NOT_FOUND | SI_INVALID_PARAMETER => Err(SilabsUsbXpressError::DeviceNotFound),
+        _ => unreachable!(
+            "Unreachable status code: {}. Please contact the author or submit an issue.",
+            status
+        ),
+    }
+}
+
+/// Returns the index of the device opened as `handle`, `usize::MAX` if
+/// enumeration does not list it
+#[cfg(all(
+    target_os = "linux",
+    any(feature = "rust-backend", feature = "nusb-backend")
+))]
+fn handle_index(handle: *mut SiPrivate) -> usize {
+    let info = DeviceInfo::query(usize::MAX, handle);
+    path_index(Path::new(&info.bus_path)).unwrap_or(usize::MAX)
+}
+
+/// Returns the index of the device the backend knows by `device_num`,
+/// refreshing the device list
+fn device_index(device_num: c_int) -> Result<usize, SilabsUsbXpressError> {
+    if !config::has_vid_pid_filter() {
+        return Ok(device_num as usize);
+    }
+    // Only allowed devices have an index
+    let (vid, pid) = ids_of(device_num)?;
+    if !config::vid_pid_allowed(vid, pid) {
+        return Err(SilabsUsbXpressError::DeviceNotFound);
+    }
+    let _enumeration = lock_enumeration();
+    count_devices()?;
+    ALLOWED
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+        .as_ref()
+        .and_then(|allowed| allowed.iter().position(|&allowed| allowed == device_num))
+        .ok_or(SilabsUsbXpressError::DeviceNotFound)
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum ProductStringType {
     SerialNumber = 0,
@@ -126,18 +484,16 @@ pub enum ProductStringType {
 /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
 /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
 /// CP2101/2/3/4/5/8/9
+#[cfg(feature = "enumeration")]
 pub fn product_string(
     device_ix: usize,
     product_string_type: ProductStringType,
 ) -> Result<String, SilabsUsbXpressError> {
+    id_table::init();
+    let device_num = device_num(device_ix)?;
     let mut buffer: [c_char; 256] = [0; 256];
-    let status = unsafe {
-        SI_GetProductString(
-            device_ix as i32,
-            buffer.as_mut_ptr(),
-            product_string_type as i32,
-        )
-    };
+    let status =
+        unsafe { SI_GetProductString(device_num, buffer.as_mut_ptr(), product_string_type as i32) };
     match status as u32 {
         SI_SUCCESS => {
             let mut string = String::from_utf8(buffer.iter().map(|&c| c as u8).collect())
@@ -164,9 +520,597 @@ pub fn product_string(
     }
 }
 
+/// Decodes what `SI_GetDeviceProductString` left in `buffer`
+///
+/// The string is not null terminated: `length` counts its bytes, UTF-16LE
+/// unless it was converted to ASCII. A length past the end of the buffer is
+/// cut short.
+fn decode_product_string(buffer: &[u8], length: u8, ascii: bool) -> String {
+    let string = &buffer[..usize::from(length).min(buffer.len())];
+    if ascii {
+        String::from_utf8_lossy(string).into_owned()
+    } else {
+        let units: Vec<u16> = string
+            .chunks_exact(2)
+            .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    }
+}
+
+/// Returns the divisor for [`UsbXpress::set_baud_divisor`] closest to
+/// `baud_rate`
+///
+/// Rates too high or too low for any divisor get the fastest or slowest
+/// one.
+pub fn baud_divisor(baud_rate: u32) -> u16 {
+    let divisor = (CP2101_BAUD_CLOCK + baud_rate / 2) / baud_rate.max(1);
+    divisor.clamp(1, u32::from(u16::MAX)) as u16
+}
+
+/// Returns the `wValue` of a CP210x `SET_MHS` request setting the lines
+/// given, leaving those that are `None` alone
+fn modem_handshake(dtr: Option<bool>, rts: Option<bool>) -> u16 {
+    let mut value = 0;
+    if let Some(high) = dtr {
+        value |= CP210X_MHS_DTR_MASK | if high { CP210X_MHS_DTR } else { 0 };
+    }
+    if let Some(high) = rts {
+        value |= CP210X_MHS_RTS_MASK | if high { CP210X_MHS_RTS } else { 0 };
+    }
+    value
+}
+
+/// Fails if `part` cannot run its UART at `baud_rate`
+fn check_baud_rate(part: PartNumber, baud_rate: u32) -> Result<(), SilabsUsbXpressError> {
+    let supported = match part.baud_rates() {
+        Some(range) => range.contains(&baud_rate),
+        None => baud_rate > 0,
+    };
+    if supported {
+        Ok(())
+    } else {
+        Err(SilabsUsbXpressError::UnsupportedBaudRate { baud_rate, part })
+    }
+}
+
+/// Reads the number of the interface an open handle claimed
+fn interface_number(handle: *mut SiPrivate) -> Option<u8> {
+    let mut interface = 0;
+    let status = unsafe { SI_GetInterfaceNumber(handle, &mut interface) };
+    (status as u32 == SI_SUCCESS).then_some(interface)
+}
+
+/// Returns the kernel driver bound to the device at `device_ix`, `None`
+/// where libusb cannot tell
+fn kernel_driver(device_ix: usize) -> Option<String> {
+    const LEN: usize = SI_MAX_DEVICE_STRLEN as usize;
+    let device_num = device_num(device_ix).ok()?;
+    let mut path = [0 as c_char; LEN];
+    let mut driver = [0 as c_char; LEN];
+    let mut caps = 0;
+    let status = unsafe {
+        SI_GetDeviceDetails(
+            device_num,
+            path.as_mut_ptr(),
+            driver.as_mut_ptr(),
+            &mut caps,
+        )
+    };
+    if status as u32 != SI_SUCCESS {
+        return None;
+    }
+    let driver = unsafe { CStr::from_ptr(driver.as_ptr()) }.to_string_lossy();
+    if driver.is_empty() {
+        None
+    } else {
+        Some(driver.into_owned())
+    }
+}
+
+/// Returns the number of interfaces of the device at `device_ix`
+///
+/// Most devices have one. The CP2105 has two, the enhanced and the standard
+/// UART, and the CP2108 four, which are opened separately with
+/// [`UsbXpress::open_interface`].
+#[cfg(feature = "enumeration")]
+pub fn interfaces_count(device_ix: usize) -> Result<usize, SilabsUsbXpressError> {
+    let device_num = device_num(device_ix)?;
+    let mut num = 0;
+    let status = unsafe { SI_GetNumInterfaces(device_num, &mut num) };
+    match status as u32 {
+        SI_SUCCESS => Ok(num as usize),
+        SI_DEVICE_NOT_FOUND => Err(SilabsUsbXpressError::DeviceNotFound),
+        _ => unreachable!(
+            "Unreachable status code: {}. Please contact the author or submit an issue.",
+            status
+        ),
+    }
+}
+
+/// Returns the vendor ID of the device at `device_ix`
+///
+/// The same as [`product_string`] with [`ProductStringType::VID`], as a
+/// number rather than a hexadecimal string.
+#[cfg(feature = "enumeration")]
+pub fn vendor_id(device_ix: usize) -> Result<u16, SilabsUsbXpressError> {
+    Ok(device_ids(device_ix)?.0)
+}
+
+/// Returns the product ID of the device at `device_ix`
+///
+/// The same as [`product_string`] with [`ProductStringType::PID`], as a
+/// number rather than a hexadecimal string.
+#[cfg(feature = "enumeration")]
+pub fn product_id(device_ix: usize) -> Result<u16, SilabsUsbXpressError> {
+    Ok(device_ids(device_ix)?.1)
+}
+
+/// Reads the vendor and product ID of the device at `device_ix`
+#[cfg(feature = "enumeration")]
+fn device_ids(device_ix: usize) -> Result<(u16, u16), SilabsUsbXpressError> {
+    ids_of(device_num(device_ix)?)
+}
+
+/// Reads the vendor and product ID of the device the backend knows by
+/// `device_num`
+fn ids_of(device_num: c_int) -> Result<(u16, u16), SilabsUsbXpressError> {
+    let read_id = |flags: u32| {
+        let mut buffer = [0 as c_char; SI_MAX_DEVICE_STRLEN as usize];
+        let status =
+            unsafe { SI_GetProductString(device_num, buffer.as_mut_ptr(), flags as c_int) };
+        match status as u32 {
+            SI_SUCCESS => {
+                let id = unsafe { CStr::from_ptr(buffer.as_ptr()) };
+                u16::from_str_radix(&id.to_string_lossy(), 16)
+                    .map_err(|_| SilabsUsbXpressError::DeviceNotFound)
+            }
+            SI_DEVICE_NOT_FOUND => Err(SilabsUsbXpressError::DeviceNotFound),
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
+    };
+    Ok((read_id(SI_RETURN_VID)?, read_id(SI_RETURN_PID)?))
+}
+
+/// Returns the index of the only device with serial number `serial`
+#[cfg(feature = "enumeration")]
+fn find_serial(serial: &str) -> Result<usize, SilabsUsbXpressError> {
+    let mut indices = Vec::new();
+    for device_ix in 0..devices_count()? {
+        if product_string(device_ix, ProductStringType::SerialNumber)? == serial {
+            indices.push(device_ix);
+        }
+    }
+    match indices.as_slice() {
+        [device_ix] => Ok(*device_ix),
+        [] => Err(SilabsUsbXpressError::SerialNotFound(serial.to_owned())),
+        _ => Err(SilabsUsbXpressError::DuplicateSerial {
+            serial: serial.to_owned(),
+            indices,
+        }),
+    }
+}
+
+/// Returns the index of the `nth` device with vendor ID `vid` and product ID
+/// `pid`
+#[cfg(feature = "enumeration")]
+fn find_vid_pid(vid: u16, pid: u16, nth: usize) -> Result<usize, SilabsUsbXpressError> {
+    let mut indices = Vec::new();
+    for device_ix in 0..devices_count()? {
+        if device_ids(device_ix)? == (vid, pid) {
+            indices.push(device_ix);
+        }
+    }
+    indices
+        .get(nth)
+        .copied()
+        .ok_or(SilabsUsbXpressError::VidPidNotFound {
+            vid,
+            pid,
+            nth,
+            matches: indices.len(),
+        })
+}
+
+/// Options and flags which can be used to configure how a device is opened
+///
+/// This builder exposes the ability to configure how a [`UsbXpress`] is
+/// opened; [`UsbXpress::open`] is equivalent to
+/// `OpenOptions::new().open(device_ix)`.
+///
+/// ```rust, ignore
+/// # use silabs_usb_xpress::OpenOptions;
+/// # use std::time::Duration;
+/// let handle = OpenOptions::new()
+///     .open_timeout(Duration::from_secs(2))
+///     .open(0)
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct OpenOptions {
+    open_timeout: Option<Duration>,
+    flush_rx_on_open: bool,
+    flush_tx_on_open: bool,
+    discard_until_quiet: Option<Duration>,
+    detach_kernel_driver: bool,
+    reattach_kernel_driver: bool,
+}
+
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OpenOptions {
+    /// Creates a set of options from the process-wide defaults, which behaves
+    /// like [`UsbXpress::open`]
+    pub fn new() -> Self {
+        OpenOptions {
+            open_timeout: config::defaults().open_timeout,
+            flush_rx_on_open: false,
+            flush_tx_on_open: false,
+            discard_until_quiet: None,
+            detach_kernel_driver: false,
+            reattach_kernel_driver: true,
+        }
+    }
+
+    /// Bounds how long the whole open/claim sequence may take
+    ///
+    /// Misbehaving hardware can keep `SI_Open` busy for many seconds. With a
+    /// timeout set, the open sequence runs on a helper thread and
+    /// [`open`](OpenOptions::open) gives up with `OpenTimedOut` once the
+    /// timeout expires. Should the abandoned open eventually succeed, the
+    /// helper thread closes the device again, so it is never left claimed.
+    ///
+    /// `None` waits for as long as `SI_Open` takes, which is the default
+    /// unless one was [configured](configure).
+    pub fn open_timeout<T: Into<Option<Duration>>>(&mut self, timeout: T) -> &mut Self {
+        self.open_timeout = timeout.into();
+        self
+    }
+
+    /// Flushes the receive buffers right after opening
+    ///
+    /// Discards whatever the device sent before the application started,
+    /// both on the host and in the UART's receive queue.
+    pub fn flush_rx_on_open(&mut self, flush: bool) -> &mut Self {
+        self.flush_rx_on_open = flush;
+        self
+    }
+
+    /// Purges the UART's transmit queue right after opening
+    ///
+    /// Drops data an earlier program wrote but the CP210x has not sent yet.
+    /// Opening fails with `DeviceIoFailed` if the device does not take the
+    /// CP210x `PURGE` request.
+    pub fn flush_tx_on_open(&mut self, flush: bool) -> &mut Self {
+        self.flush_tx_on_open = flush;
+        self
+    }
+
+    /// Reads and throws away incoming data until the device has been silent
+    /// for `quiet`
+    ///
+    /// Useful for devices that keep streaming stale data for a while after
+    /// being opened. A device that never goes quiet keeps
+    /// [`open`](OpenOptions::open) discarding; set an
+    /// [`open_timeout`](OpenOptions::open_timeout) to bound the wait, which
+    /// then fails with `OpenTimedOut`.
+    pub fn discard_until_quiet<T: Into<Option<Duration>>>(&mut self, quiet: T) -> &mut Self {
+        self.discard_until_quiet = quiet.into();
+        self
+    }
+
+    /// Detaches the kernel driver bound to the interface before claiming it
+    ///
+    /// On Linux the `cp210x` serial driver binds to CP210x bridges as soon
+    /// as they are plugged in, which makes opening them fail with
+    /// `DeviceBusy`. With this option set the driver is detached first;
+    /// should that fail, e.g. for lack of permissions, opening fails with
+    /// `KernelDriverConflict` naming the driver. Off by default, as it
+    /// takes the device away from whoever uses its serial port. Where
+    /// libusb cannot detach drivers this has no effect.
+    pub fn detach_kernel_driver(&mut self, detach: bool) -> &mut Self {
+        self.detach_kernel_driver = detach;
+        self
+    }
+
+    /// Reattaches the kernel driver detached at open once the handle is
+    /// closed or dropped
+    ///
+    /// The device then shows up as a serial port again, e.g. `/dev/ttyUSB0`,
+    /// for other tools once the program is done with it. On by default; only
+    /// has an effect together with
+    /// [`detach_kernel_driver`](OpenOptions::detach_kernel_driver).
+    ///
+    /// libusb 0.1 has no call to reattach drivers, so the bundled backend
+    /// binds the driver through sysfs on Linux. Where that is not permitted,
+    /// which is without root, it resets the device so that the kernel probes
+    /// it again, and logs an error if that fails too.
+    pub fn reattach_kernel_driver(&mut self, reattach: bool) -> &mut Self {
+        self.reattach_kernel_driver = reattach;
+        self
+    }
+
+    fn open_flags(&self) -> c_int {
+        let mut flags = 0;
+        if self.detach_kernel_driver {
+            flags |= SI_OPEN_DETACH_KERNEL_DRIVER;
+        }
+        if self.reattach_kernel_driver {
+            flags |= SI_OPEN_REATTACH_KERNEL_DRIVER;
+        }
+        flags as c_int
+    }
+
+    /// Opens the device at `device_ix` with the options specified by `self`
+    ///
+    /// With `SIUSB_VID_PID_FILTER` set, `device_ix` counts only the devices
+    /// the filter allows, see [`config`].
+    pub fn open(&self, device_ix: usize) -> Result<UsbXpress, SilabsUsbXpressError> {
+        self.open_interface(device_ix, 0)
+    }
+
+    /// Opens interface `interface` of the device at `device_ix` with the
+    /// options specified by `self`
+    ///
+    /// The CP2105 has a UART on each of its two interfaces and the CP2108 on
+    /// each of its four; every one is opened as a handle of its own, which
+    /// is configured independently of the others. Interfaces count from
+    /// zero, see [`interfaces_count`], and [`open`](OpenOptions::open) opens
+    /// the first one. Fails with `InterfaceNotFound` if the device has no
+    /// such interface.
+    pub fn open_interface(
+        &self,
+        device_ix: usize,
+        interface: usize,
+    ) -> Result<UsbXpress, SilabsUsbXpressError> {
+        id_table::init();
+        let started = Instant::now();
+        let mut handle = self.open_device(device_ix, interface)?;
+        if let Err(err) = self.prepare(&mut handle, started) {
+            let _ = handle.close();
+            return Err(err);
+        }
+        Ok(handle)
+    }
+
+    /// Opens the device with serial number `serial` with the options
+    /// specified by `self`
+    ///
+    /// Unlike indices, serial numbers do not change as other devices come
+    /// and go. Fails with `SerialNotFound` if no device has this serial
+    /// number, and with `DuplicateSerial` if several do.
+    #[cfg(feature = "enumeration")]
+    pub fn open_by_serial(&self, serial: &str) -> Result<UsbXpress, SilabsUsbXpressError> {
+        self.open(find_serial(serial)?)
+    }
+
+    /// Opens the `nth` device, counting from zero, with vendor ID `vid` and
+    /// product ID `pid` with the options specified by `self`
+    ///
+    /// Fails with `VidPidNotFound`, which tells how many devices matched, if
+    /// there are not more than `nth`.
+    #[cfg(feature = "enumeration")]
+    pub fn open_by_vid_pid(
+        &self,
+        vid: u16,
+        pid: u16,
+        nth: usize,
+    ) -> Result<UsbXpress, SilabsUsbXpressError> {
+        self.open(find_vid_pid(vid, pid, nth)?)
+    }
+
+    /// Opens the device at `path` with the options specified by `self`
+    ///
+    /// `path` names the device node, like `/dev/bus/usb/001/004` on Linux;
+    /// only its last two components, the bus and the device, are looked at.
+    /// This works without the `enumeration` feature, for deployments where
+    /// the device node is handed to the process. A descriptor already open
+    /// on the node is opened with [`open_fd`](OpenOptions::open_fd) instead.
+    pub fn open_path<P: AsRef<Path>>(&self, path: P) -> Result<UsbXpress, SilabsUsbXpressError> {
+        self.open(path_index(path.as_ref())?)
+    }
+
+    /// Opens the device whose usbfs node `fd` is open on, with the options
+    /// specified by `self`
+    ///
+    /// For deployments where devices are passed in as file descriptors, e.g.
+    /// from Android's `UsbDeviceConnection` or a privileged helper, and the
+    /// process may not open the device node itself. The handle takes `fd`
+    /// over and closes it together with the device, or right away if
+    /// opening fails. The first interface is opened; the
+    /// [`open_timeout`](OpenOptions::open_timeout) does not apply, since
+    /// nothing is enumerated or waited for.
+    ///
+    /// [`UsbXpress::device_index`] is the index of the device if enumeration
+    /// lists it, and `usize::MAX` otherwise. Only available on Linux with
+    /// the `rust-backend` or `nusb-backend` feature: libusb 0.1 cannot take
+    /// over a descriptor, and the nusb backend still reads the details of
+    /// the device from sysfs, failing with `DeviceNotFound` if it is not
+    /// listed there.
+    #[cfg(all(
+        target_os = "linux",
+        any(feature = "rust-backend", feature = "nusb-backend")
+    ))]
+    pub fn open_fd(&self, fd: OwnedFd) -> Result<UsbXpress, SilabsUsbXpressError> {
+        id_table::init();
+        let started = Instant::now();
+        let timeouts = config::defaults().timeouts;
+        let mut handle = std::ptr::null_mut();
+        let status = unsafe {
+            SI_OpenFd(
+                fd.into_raw_fd(),
+                0,
+                self.open_flags(),
+                timeout_millis(timeouts.read),
+                timeout_millis(timeouts.write),
+                &mut handle,
+            )
+        };
+        let device_ix = match status as u32 {
+            SI_SUCCESS => handle_index(handle),
+            SI_DEVICE_NOT_FOUND => return Err(SilabsUsbXpressError::DeviceNotFound),
+            _ => usize::MAX,
+        };
+        let mut handle = UsbXpress::from_open_status(device_ix, 0, timeouts, status, handle)?;
+        if let Err(err) = self.prepare(&mut handle, started) {
+            let _ = handle.close();
+            return Err(err);
+        }
+        Ok(handle)
+    }
+
+    fn open_device(
+        &self,
+        device_ix: usize,
+        interface: usize,
+    ) -> Result<UsbXpress, SilabsUsbXpressError> {
+        let timeout = match self.open_timeout {
+            Some(timeout) => timeout,
+            None => return UsbXpress::open_blocking(device_ix, interface, self.open_flags()),
+        };
+        let flags = self.open_flags();
+        let timeouts = config::defaults().timeouts;
+        let device_num = device_num(device_ix)?;
+
+        struct OpenedHandle(*mut SiPrivate);
+        unsafe impl Send for OpenedHandle {}
+
+        // A rendezvous channel, so that the helper thread either hands the
+        // handle over or learns that nobody is waiting for it any more.
+        let (tx, rx) = mpsc::sync_channel(0);
+        thread::spawn(move || {
+            let mut handle = std::ptr::null_mut();
+            let status = unsafe {
+                SI_OpenEx(
+                    device_num,
+                    interface as i32,
+                    flags,
+                    timeout_millis(timeouts.read),
+                    timeout_millis(timeouts.write),
+                    &mut handle,
+                )
+            };
+            if let Err(mpsc::SendError((status, handle))) = tx.send((status, OpenedHandle(handle)))
+            {
+                if status as u32 == SI_SUCCESS {
+                    unsafe { SI_Close(handle.0) };
+                }
+            }
+        });
+        match rx.recv_timeout(timeout) {
+            Ok((status, handle)) => {
+                UsbXpress::from_open_status(device_ix, interface, timeouts, status, handle.0)
+            }
+            Err(_) => Err(SilabsUsbXpressError::OpenTimedOut),
+        }
+    }
+
+    /// Establishes the initial buffer state asked for
+    fn prepare(
+        &self,
+        handle: &mut UsbXpress,
+        started: Instant,
+    ) -> Result<(), SilabsUsbXpressError> {
+        if self.flush_rx_on_open || self.flush_tx_on_open {
+            handle.flush(self.flush_tx_on_open, self.flush_rx_on_open)?;
+        }
+        if let Some(quiet) = self.discard_until_quiet {
+            let deadline = self.open_timeout.map(|timeout| started + timeout);
+            let mut scratch = [0u8; 4096];
+            loop {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    return Err(SilabsUsbXpressError::OpenTimedOut);
+                }
+                match handle.read_until_deadline(&mut scratch, Instant::now() + quiet) {
+                    Ok(_) => continue,
+                    Err(SilabsUsbXpressError::ReadTimeOut { .. }) => break,
+                    Err(err) => return Err(err),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct UsbXpress {
     inner: *mut SiPrivate,
     device_ix: usize,
+    interface: u8,
+    timeouts: Timeout,
+    nominal_baud_rate: Option<u32>,
+    adaptive_timeout: Option<AdaptiveTimeout>,
+    pacer: Option<Pacer>,
+    keepalive: Option<Keepalive>,
+    last_activity: Instant,
+    coalesce_window: Option<Duration>,
+    echo_suppression: bool,
+    rs485: Option<Rs485>,
+    info: DeviceInfo,
+    kernel_driver: Option<String>,
+    opened_at: SystemTime,
+    opened: Instant,
+    scratch: Vec<u8>,
+    cancel: Arc<Mutex<CancelTarget>>,
+    metrics: Arc<metrics::DeviceMetrics>,
+}
+
+// The C shim keeps all per-device state behind the handle, and libusb allows
+// transfers on different device handles from different threads. Handles are
+// not `Sync`: every method takes `&mut self`.
+unsafe impl Send for UsbXpress {}
+
+/// Vendor request to an interface, host to device
+const CP210X_REQTYPE_HOST_TO_DEVICE: u8 = 0x41;
+/// Vendor request to an interface, device to host
+const CP210X_REQTYPE_DEVICE_TO_HOST: u8 = 0xC1;
+/// CP210x request setting the baud rate divisor, the only way on the CP2101
+const CP210X_SET_BAUDDIV: u8 = 0x01;
+/// CP210x request setting the baud rate, with the rate as its payload
+const CP210X_SET_BAUDRATE: u8 = 0x1E;
+/// Clock the CP2101 divides to derive its baud rate
+const CP2101_BAUD_CLOCK: u32 = 3_686_400;
+/// CP210x request setting the data bits, parity and stop bits
+const CP210X_SET_LINE_CTL: u8 = 0x03;
+/// CP210x request setting the flow control, with a 16 byte payload
+const CP210X_SET_FLOW: u8 = 0x13;
+const CP210X_CTS_HANDSHAKE: u32 = 0x08;
+const CP210X_DSR_HANDSHAKE: u32 = 0x10;
+/// Receive buffer levels at which XON and XOFF are sent, unused without
+/// software flow control
+const CP210X_XON_LIMIT: u32 = 128;
+const CP210X_XOFF_LIMIT: u32 = 128;
+/// CP210x request starting or ending a break condition
+const CP210X_SET_BREAK: u8 = 0x05;
+/// CP210x request setting the modem handshake lines
+const CP210X_SET_MHS: u8 = 0x07;
+/// CP210x request reading the modem status
+const CP210X_GET_MDMSTS: u8 = 0x08;
+/// CP210x request reading the error flags and queue levels, 19 bytes
+const CP210X_GET_COMM_STATUS: u8 = 0x10;
+const CP210X_COMM_STATUS_LEN: usize = 19;
+/// CP210x request multiplexing vendor specific commands through `wValue`
+const CP210X_VENDOR_SPECIFIC: u8 = 0xFF;
+const CP210X_READ_LATCH: u16 = 0x00C2;
+const CP210X_WRITE_LATCH: u16 = 0x37E1;
+const CP210X_MHS_DTR: u16 = 0x0001;
+const CP210X_MHS_RTS: u16 = 0x0002;
+const CP210X_MHS_DTR_MASK: u16 = 0x0100;
+const CP210X_MHS_RTS_MASK: u16 = 0x0200;
+
+/// Bytes that paced writes may send back to back before being throttled
+const PACING_BURST: usize = 64;
+
+/// Token bucket state for paced writes, measured in bytes
+struct Pacer {
+    credit: f64,
+    last: Instant,
 }
 
 impl UsbXpress {
@@ -181,16 +1125,105 @@ impl UsbXpress {
     /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
     /// CP2101/2/3/4/5/8/9
     pub fn open(device_ix: usize) -> Result<Self, SilabsUsbXpressError> {
-        let mut handle: MaybeUninit<*mut SiPrivate> = MaybeUninit::uninit();
-        let (status, handle) = unsafe {
-            let status = SI_Open(device_ix as i32, handle.as_mut_ptr());
-            (status, handle.assume_init())
+        OpenOptions::new().open(device_ix)
+    }
+
+    /// Opens the device with serial number `serial`, see
+    /// [`OpenOptions::open_by_serial`]
+    #[cfg(feature = "enumeration")]
+    pub fn open_by_serial(serial: &str) -> Result<Self, SilabsUsbXpressError> {
+        OpenOptions::new().open_by_serial(serial)
+    }
+
+    /// Opens the `nth` device with vendor ID `vid` and product ID `pid`, see
+    /// [`OpenOptions::open_by_vid_pid`]
+    #[cfg(feature = "enumeration")]
+    pub fn open_by_vid_pid(vid: u16, pid: u16, nth: usize) -> Result<Self, SilabsUsbXpressError> {
+        OpenOptions::new().open_by_vid_pid(vid, pid, nth)
+    }
+
+    /// Opens the device at `path`, see [`OpenOptions::open_path`]
+    pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Self, SilabsUsbXpressError> {
+        OpenOptions::new().open_path(path)
+    }
+
+    /// Opens the device whose usbfs node `fd` is open on, see
+    /// [`OpenOptions::open_fd`]
+    #[cfg(all(
+        target_os = "linux",
+        any(feature = "rust-backend", feature = "nusb-backend")
+    ))]
+    pub fn from_fd(fd: OwnedFd) -> Result<Self, SilabsUsbXpressError> {
+        OpenOptions::new().open_fd(fd)
+    }
+
+    /// Opens interface `interface` of the device at `device_ix`, see
+    /// [`OpenOptions::open_interface`]
+    pub fn open_interface(
+        device_ix: usize,
+        interface: usize,
+    ) -> Result<Self, SilabsUsbXpressError> {
+        OpenOptions::new().open_interface(device_ix, interface)
+    }
+
+    fn open_blocking(
+        device_ix: usize,
+        interface: usize,
+        flags: c_int,
+    ) -> Result<Self, SilabsUsbXpressError> {
+        let timeouts = config::defaults().timeouts;
+        let device_num = device_num(device_ix)?;
+        let mut handle = std::ptr::null_mut();
+        let status = unsafe {
+            SI_OpenEx(
+                device_num,
+                interface as i32,
+                flags,
+                timeout_millis(timeouts.read),
+                timeout_millis(timeouts.write),
+                &mut handle,
+            )
         };
+        Self::from_open_status(device_ix, interface, timeouts, status, handle)
+    }
+
+    fn from_open_status(
+        device_ix: usize,
+        interface: usize,
+        timeouts: Timeout,
+        status: c_int,
+        handle: *mut SiPrivate,
+    ) -> Result<Self, SilabsUsbXpressError> {
         match status as u32 {
-            SI_SUCCESS => Ok(UsbXpress {
-                inner: handle,
-                device_ix: device_ix,
+            SI_SUCCESS => {
+                let info = DeviceInfo::query(device_ix, handle);
+                Ok(UsbXpress {
+                    metrics: metrics::register(&info.serial, device_ix),
+                    info,
+                    kernel_driver: kernel_driver(device_ix),
+                    opened_at: SystemTime::now(),
+                    opened: Instant::now(),
+                    scratch: Vec::new(),
+                    cancel: Arc::new(Mutex::new(CancelTarget(handle))),
+                    inner: handle,
+                    device_ix,
+                    interface: interface_number(handle).unwrap_or(interface as u8),
+                    timeouts,
+                    nominal_baud_rate: None,
+                    adaptive_timeout: None,
+                    pacer: None,
+                    keepalive: None,
+                    last_activity: Instant::now(),
+                    coalesce_window: None,
+                    echo_suppression: false,
+                    rs485: None,
+                })
+            }
+            SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceBusy),
+            SI_DRIVER_DETACH_FAILED => Err(SilabsUsbXpressError::KernelDriverConflict {
+                driver: kernel_driver(device_ix),
             }),
+            SI_INVALID_PARAMETER => Err(SilabsUsbXpressError::InterfaceNotFound(interface)),
             SI_SYSTEM_ERROR_CODE => Err(SilabsUsbXpressError::SystemErrorCode),
             SI_GLOBAL_DATA_ERROR => Err(SilabsUsbXpressError::GlobalDataError),
             _ => unreachable!(
@@ -200,19 +1233,157 @@ impl UsbXpress {
         }
     }
 
-    /// Cancels pending IO and closes a device
+    /// Returns what identifies the device, as read when it was opened
+    pub fn device_info(&self) -> &DeviceInfo {
+        &self.info
+    }
+
+    /// Returns the backend and the kernel driver the device is used through
+    pub fn driver_info(&self) -> version::DriverInfo {
+        version::DriverInfo {
+            backend: version::Backend::current(),
+            kernel_driver: self.kernel_driver.clone(),
+        }
+    }
+
+    /// Returns the index the device was opened with
     ///
-    /// Closes an open device using the handle provided by SI_Open and sets the
-    /// handle to INVALID_HANDLE_VALUE.
+    /// Devices opened from a file descriptor that enumeration does not list
+    /// have none and report `usize::MAX`.
+    pub fn device_index(&self) -> usize {
+        self.device_ix
+    }
+
+    /// Returns the number of the USB interface the handle is bound to
     ///
-    /// - Supported Devices
+    /// This is the interface's `bInterfaceNumber`, which tells the ports of
+    /// a composite device apart, like the enhanced (0) and the standard (1)
+    /// UART of a CP2105.
+    pub fn interface_number(&self) -> u8 {
+        self.interface
+    }
+
+    /// Returns the libusb handle the device is driven through
     ///
-    /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
-    /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
-    /// CP2101/2/3/4/5/8/9
-    pub fn close(self) -> Result<(), SilabsUsbXpressError> {
-        let status = unsafe { SI_Close(self.inner) };
-        match status as u32 {
+    /// Meant for vendor control transfers this crate does not model, issued
+    /// with `libusb_control_transfer` (or `usb_control_msg` with the bundled
+    /// libusb 0.1 backend), see [`LibusbDeviceHandle`].
+    ///
+    /// # Safety
+    ///
+    /// The handle is owned by `self` and dangles once it is closed or
+    /// dropped. Neither release the claimed interface nor close the handle
+    /// through it, and keep in mind that data read from the bulk endpoint
+    /// behind the crate's back is lost to [`read`](UsbXpress::read).
+    #[cfg(feature = "raw-handle")]
+    pub unsafe fn raw_libusb_handle(&self) -> *mut LibusbDeviceHandle {
+        let mut udev = std::ptr::null_mut();
+        let status = SI_GetLibusbHandle(self.inner, &mut udev);
+        match status as u32 {
+            SI_SUCCESS => udev.cast(),
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
+    }
+
+    /// Returns the device's serial number, empty if it has none
+    pub fn serial(&self) -> &str {
+        &self.info.serial
+    }
+
+    /// Returns a descriptor of the open device
+    ///
+    /// Unlike [`product_string`], this does not go through the device's
+    /// enumeration index, which may point at another device once devices
+    /// are plugged or unplugged. The description is read from the device
+    /// with `SI_GetDeviceProductString`; the other strings are those read
+    /// when it was opened, see [`device_info`](UsbXpress::device_info).
+    ///
+    /// - Supported Devices
+    ///
+    /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
+    /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
+    /// CP2101/2/3/4/5/8/9
+    pub fn product_string(
+        &mut self,
+        product_string_type: ProductStringType,
+    ) -> Result<String, SilabsUsbXpressError> {
+        match product_string_type {
+            ProductStringType::SerialNumber => Ok(self.info.serial.clone()),
+            ProductStringType::Description => self.device_product_string(),
+            ProductStringType::LinkName => Ok(self.info.link_name.clone()),
+            ProductStringType::VID => Ok(format!("{:04X}", self.info.vid)),
+            ProductStringType::PID => Ok(format!("{:04X}", self.info.pid)),
+        }
+    }
+
+    /// Reads the product description with `SI_GetDeviceProductString`
+    fn device_product_string(&mut self) -> Result<String, SilabsUsbXpressError> {
+        let mut buffer = [0u8; SI_MAX_DEVICE_STRLEN as usize];
+        let mut length = 0;
+        let status = unsafe {
+            SI_GetDeviceProductString(
+                self.inner,
+                buffer.as_mut_ptr() as *mut c_void,
+                &mut length,
+                0,
+            )
+        };
+        match status as u32 {
+            SI_SUCCESS => Ok(decode_product_string(&buffer, length, false)),
+            SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
+    }
+
+    /// Returns the device's alias from the installed [alias store](aliases)
+    pub fn alias(&self) -> Option<String> {
+        aliases::alias(&self.info.serial)
+    }
+
+    /// Returns the wall clock time the device was opened at
+    pub fn opened_at(&self) -> SystemTime {
+        self.opened_at
+    }
+
+    /// Returns how long the device has been open
+    ///
+    /// Measured with a monotonic clock, so unlike computing it from
+    /// [`opened_at`](UsbXpress::opened_at) it is not thrown off by changes
+    /// to the system time.
+    pub fn uptime(&self) -> Duration {
+        self.opened.elapsed()
+    }
+
+    /// Returns the device's transfer counters, see [`metrics`]
+    pub fn metrics(&self) -> &metrics::DeviceMetrics {
+        &self.metrics
+    }
+
+    /// Cancels pending IO and closes a device
+    ///
+    /// Closes an open device using the handle provided by SI_Open and sets the
+    /// handle to INVALID_HANDLE_VALUE.
+    ///
+    /// Dropping a handle closes the device as well, but any error is lost;
+    /// closing explicitly reports it.
+    ///
+    /// - Supported Devices
+    ///
+    /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
+    /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
+    /// CP2101/2/3/4/5/8/9
+    pub fn close(mut self) -> Result<(), SilabsUsbXpressError> {
+        self.retire_cancel_target();
+        let status = unsafe { SI_Close(self.inner) };
+        // Keeps `drop` from closing the device a second time.
+        self.inner = std::ptr::null_mut();
+        match status as u32 {
             SI_SUCCESS => Ok(()),
             SI_SYSTEM_ERROR_CODE => Err(SilabsUsbXpressError::SystemErrorCode),
             SI_GLOBAL_DATA_ERROR => Err(SilabsUsbXpressError::GlobalDataError),
@@ -242,30 +1413,166 @@ impl UsbXpress {
     /// determine if all requested data was returned. To make sure that
     /// SI_Read returns the requested number of bytes use SI_CheckRxQueue().
     ///
+    /// A zero read timeout turns this into a poll: only bytes already sitting
+    /// in the host-side receive buffer are returned, possibly none at all,
+    /// and the call never waits on the device.
+    ///
     /// - Supported Devices
     ///
     /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
     /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
     /// CP2101/2/3/4/5/8/9
     pub fn read(&mut self, bytes_to_read: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
-        let mut buffer = Vec::with_capacity(bytes_to_read);
-        // let mut buffer: [i8;256] = [0;256];
+        let timeout = self.read_timeout_for(bytes_to_read);
+        self.read_timeout(bytes_to_read, timeout)
+    }
+
+    /// Reads like [`read`](UsbXpress::read), but waits up to `timeout`
+    /// instead of the handle's read timeout
+    ///
+    /// Lets a single slow command wait longer without changing the timeouts
+    /// of the handle for everything else.
+    pub fn read_timeout(
+        &mut self,
+        bytes_to_read: usize,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        let mut data = vec![0; bytes_to_read];
+        let received = self.read_into_timeout(&mut data, timeout)?;
+        data.truncate(received);
+        Ok(data)
+    }
+
+    /// Reads up to `buf.len()` bytes into `buf` and returns how many arrived
+    ///
+    /// Behaves like [`read`](UsbXpress::read), which is built on it, but
+    /// fills a buffer owned by the caller, so a read loop can reuse one
+    /// buffer instead of allocating for every call.
+    pub fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, SilabsUsbXpressError> {
+        let timeout = self.read_timeout_for(buf.len());
+        self.read_into_timeout(buf, timeout)
+    }
+
+    fn read_into_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, SilabsUsbXpressError> {
+        if timeout == Duration::ZERO {
+            return self.read_buffered(buf);
+        }
+        let started = Instant::now();
+        let mut bytes_returned = 0;
         let status = unsafe {
-            let mut bytes_returned = MaybeUninit::uninit();
-            let status = SI_Read(
+            SI_ReadWithTimeout(
                 self.inner,
-                buffer.as_mut_slice().as_mut_ptr(),
-                bytes_to_read as i32,
-                bytes_returned.as_mut_ptr(),
-                MaybeUninit::uninit().as_mut_ptr(),
-            );
-            buffer.set_len(bytes_returned.assume_init() as usize);
-            status
+                buf.as_mut_ptr() as *mut c_char,
+                request_len(buf.len()),
+                &mut bytes_returned,
+                timeout_millis(timeout),
+            )
+        };
+        self.metrics.record_status(status);
+        let mut received = read_result(status, bytes_returned, buf.len(), started)?;
+        self.last_activity = Instant::now();
+        if let Some(window) = self.coalesce_window {
+            received = self.coalesce(buf, received, window);
+        }
+        self.record_transfer("read", &buf[..received]);
+        Ok(received)
+    }
+
+    /// Gathers bursts of small packets into a single read
+    ///
+    /// Once a `read` has received some data, it keeps waiting for more as
+    /// long as the next packet arrives within `window` of the previous one
+    /// and the requested size has not been reached. A telemetry stream of
+    /// many tiny samples then comes back in a few large chunks instead of one
+    /// call per USB packet. `None` disables coalescing.
+    pub fn set_coalesce_window<T: Into<Option<Duration>>>(&mut self, window: T) {
+        self.coalesce_window = window.into();
+    }
+
+    /// Reads data arriving within `window` of each other into `buf` after
+    /// the `filled` bytes already there and returns the new fill level
+    ///
+    /// Errors end the burst without being reported, the bytes gathered so
+    /// far are still returned and the error surfaces on the next read.
+    fn coalesce(&mut self, buf: &mut [u8], mut filled: usize, window: Duration) -> usize {
+        while filled < buf.len() {
+            let mut bytes_returned = 0;
+            let status = unsafe {
+                SI_ReadWithTimeout(
+                    self.inner,
+                    buf[filled..].as_mut_ptr() as *mut c_char,
+                    request_len(buf.len() - filled),
+                    &mut bytes_returned,
+                    timeout_millis(window),
+                )
+            };
+            let received = received_len(bytes_returned, buf.len() - filled);
+            if status as u32 == SI_IO_CANCELLED {
+                // Ends the burst, but the next read still sees the
+                // cancellation
+                unsafe { SI_CancelIo(self.inner) };
+            }
+            if status as u32 != SI_SUCCESS || received == 0 {
+                break;
+            }
+            filled += received;
+        }
+        filled
+    }
+
+    /// Reads up to `max` bytes into a buffer owned by the handle
+    ///
+    /// Behaves like [`read`](UsbXpress::read), except that the data is
+    /// returned as a slice into an internal buffer that is reused from call
+    /// to call, so a read loop does not allocate once the buffer has grown
+    /// to `max`. The slice is valid until the next call on the handle. The
+    /// [coalescing window](UsbXpress::set_coalesce_window) does not apply.
+    pub fn read_borrowed(&mut self, max: usize) -> Result<&[u8], SilabsUsbXpressError> {
+        let timeout = self.read_timeout_for(max);
+        let polling = timeout == Duration::ZERO;
+        let (len, timeout) = if polling {
+            let (queued, _) = self.check_rx_queue()?;
+            (queued.min(max), 1)
+        } else {
+            (max, timeout_millis(timeout))
+        };
+        if len == 0 {
+            return Ok(&[]);
+        }
+        if self.scratch.len() < len {
+            self.scratch.resize(len, 0);
+        }
+        let started = Instant::now();
+        let mut bytes_returned = 0;
+        let status = unsafe {
+            SI_ReadWithTimeout(
+                self.inner,
+                self.scratch.as_mut_ptr() as *mut c_char,
+                request_len(len),
+                &mut bytes_returned,
+                timeout,
+            )
         };
+        self.metrics.record_status(status);
         match status as u32 {
-            SI_SUCCESS => Ok(buffer.iter().map(|&c| c as u8).collect()),
+            SI_SUCCESS => {
+                self.last_activity = Instant::now();
+                let data = &self.scratch[..received_len(bytes_returned, len)];
+                self.record_transfer("read", data);
+                Ok(data)
+            }
+            SI_READ_TIMED_OUT if polling => Ok(&[]),
+            SI_READ_TIMED_OUT => Err(read_timed_out(
+                started,
+                received_len(bytes_returned, len),
+                max,
+            )),
+            SI_IO_CANCELLED => Err(SilabsUsbXpressError::Cancelled),
             SI_READ_ERROR => Err(SilabsUsbXpressError::ReadError),
-            SI_READ_TIMED_OUT => Err(SilabsUsbXpressError::ReadTimeOut),
             SI_IO_PENDING => Err(SilabsUsbXpressError::IoPending),
             SI_SYSTEM_ERROR_CODE => Err(SilabsUsbXpressError::SystemErrorCode),
             SI_INVALID_REQUEST_LENGTH => Err(SilabsUsbXpressError::InvalidRequestLength),
@@ -277,47 +1584,125 @@ impl UsbXpress {
         }
     }
 
-    /// Writes a block of data to a device
+    /// Copies data received from the device into `writer`
     ///
-    /// On USB MCU devices, this function flushes both the receive buffer in the
-    /// USBXpress device driver and the transmit buffer in the device.
+    /// Copies until `limit` bytes have been transferred or, without a limit,
+    /// until a read times out, which is how the end of a stream shows up.
+    /// `progress` is called with the running total after every chunk.
+    /// Returns the number of bytes copied.
+    pub fn copy_to_writer<W: io::Write, F: FnMut(u64)>(
+        &mut self,
+        mut writer: W,
+        limit: Option<u64>,
+        mut progress: F,
+    ) -> io::Result<u64> {
+        let mut copied = 0u64;
+        let mut buf = vec![0u8; SI_MAX_READ_SIZE as usize];
+        while limit.is_none_or(|limit| copied < limit) {
+            let want = match limit {
+                Some(limit) => buf.len().min((limit - copied) as usize),
+                None => buf.len(),
+            };
+            let started = Instant::now();
+            let received = self.read_once(&mut buf[..want], self.read_timeout_for(want))?;
+            if received == 0 {
+                match limit {
+                    None => break,
+                    Some(limit) => {
+                        return Err(SilabsUsbXpressError::ReadTimeOut {
+                            elapsed: started.elapsed(),
+                            transferred: copied as usize,
+                            remaining: (limit - copied) as usize,
+                        }
+                        .into())
+                    }
+                }
+            }
+            writer.write_all(&buf[..received])?;
+            copied += received as u64;
+            progress(copied);
+        }
+        Ok(copied)
+    }
+
+    /// Sends everything `reader` yields to the device
     ///
-    /// **Note**: Parameter 2 and 3 of `SI_Write` have no effect and any
-    /// value can be passed when used with USB MCU devices.
+    /// Data is read and written in chunks of at most `SI_MAX_WRITE_SIZE`
+    /// bytes, each of which has to reach the device within the write
+    /// timeout. `progress` is called with the running total after every
+    /// chunk. Returns the number of bytes copied.
+    pub fn copy_from_reader<R: io::Read, F: FnMut(u64)>(
+        &mut self,
+        mut reader: R,
+        mut progress: F,
+    ) -> io::Result<u64> {
+        let mut copied = 0u64;
+        let mut buf = vec![0u8; SI_MAX_WRITE_SIZE as usize];
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+            let deadline = Instant::now() + self.write_timeout_for(n);
+            self.write_until_deadline(&buf[..n], deadline)?;
+            copied += n as u64;
+            progress(copied);
+        }
+        Ok(copied)
+    }
+
+    /// Returns an iterator over the frames `decoder` finds in the received
+    /// data
     ///
-    /// On CP210x devices, this function operates in accordance with parameters
-    /// 2 and 3. If parameter 2 (FlushTransmit) is non-zero, the CP210x
-    /// device’s UART transmit buffer is flushed. If parameter 3
-    /// (FlushReceive) is non-zero, the CP210x device’s UART receive buffer
-    /// is flushed. If parameters 2 and 3 are both non-zero, then both the
-    /// CP210x device UART transmit buffer and UART receive buffer are
-    /// flushed.
+    /// Every call to `next` blocks until the next frame is complete or the
+    /// read timeout expires, in which case `ReadTimeOut` is yielded and
+    /// iteration may simply continue. Received bytes that do not form a
+    /// complete frame yet are lost when the iterator is dropped.
     ///
-    /// - Supported Devices
+    /// ```rust, ignore
+    /// use silabs_usb_xpress::codec::Delimited;
     ///
-    /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
-    /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
-    /// CP2101/2/3/4/5/8/9
-    pub fn write(&mut self, to_write: &Vec<u8>) -> Result<usize, SilabsUsbXpressError> {
-        let mut buffer: Vec<c_char> = to_write.iter().map(|&c| c as c_char).collect();
-        let (status, bytes_written) = unsafe {
-            let mut bytes_written = MaybeUninit::uninit();
-            let status = SI_Write(
+    /// for line in handle.frames(Delimited::new(b'\n')) {
+    ///     println!("{:?}", line?);
+    /// }
+    /// ```
+    pub fn frames<D: Decoder>(&mut self, decoder: D) -> Frames<'_, D> {
+        Frames::new(self, decoder)
+    }
+
+    /// Issues a single read of up to `buf.len()` bytes, returning `Ok(0)`
+    /// if nothing arrived within `timeout`
+    fn read_once(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize, SilabsUsbXpressError> {
+        let mut bytes_returned = 0;
+        let status = unsafe {
+            SI_ReadWithTimeout(
                 self.inner,
-                buffer.as_mut_ptr(),
-                to_write.len() as i32,
-                bytes_written.as_mut_ptr(),
-                MaybeUninit::uninit().as_mut_ptr(),
-            );
-            (status, bytes_written.assume_init())
+                buf.as_mut_ptr() as *mut c_char,
+                request_len(buf.len()),
+                &mut bytes_returned,
+                timeout_millis(timeout),
+            )
         };
+        self.metrics.record_status(status);
         match status as u32 {
-            SI_SUCCESS => Ok(bytes_written as usize),
-            SI_WRITE_ERROR => Err(SilabsUsbXpressError::WriteError),
-            SI_INVALID_REQUEST_LENGTH => Err(SilabsUsbXpressError::InvalidRequestLength),
-            SI_WRITE_TIMED_OUT => Err(SilabsUsbXpressError::WriteTimeOut),
+            SI_SUCCESS => {
+                self.last_activity = Instant::now();
+                let received = &buf[..received_len(bytes_returned, buf.len())];
+                self.record_transfer("read", received);
+                Ok(received.len())
+            }
+            SI_READ_TIMED_OUT => Ok(0),
+            SI_IO_CANCELLED => Err(SilabsUsbXpressError::Cancelled),
+            SI_READ_ERROR => Err(SilabsUsbXpressError::ReadError),
             SI_IO_PENDING => Err(SilabsUsbXpressError::IoPending),
             SI_SYSTEM_ERROR_CODE => Err(SilabsUsbXpressError::SystemErrorCode),
+            SI_INVALID_REQUEST_LENGTH => Err(SilabsUsbXpressError::InvalidRequestLength),
             SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
             _ => unreachable!(
                 "Unreachable status code: {}. Please contact the author or submit an issue.",
@@ -326,30 +1711,89 @@ impl UsbXpress {
         }
     }
 
-    /// Allows sending low-level commands to the device driver
+    /// Reads everything the device has ready right now, without waiting
     ///
-    /// **UNIMPLEMENTED!!!**
-    ///
-    /// Interface for any miscellaneous device control functions. A separate
-    /// call to SI_DeviceIOControl is required for each input or output
-    /// operation. A single call cannot be used to perform both an input and
-    /// output operation simultaneously. Refer to DeviceIOControl function
-    /// definition on MSDN Help for more details.
-    ///
-    /// - Supported Devices
+    /// Pulls the data the device has already sent into the host-side buffer
+    /// and returns all of it, possibly nothing. Unlike a read with a short
+    /// timeout, this never waits for data still on its way, which makes it
+    /// the building block for loops polling many devices in turn.
+    pub fn read_available(&mut self) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        let queued = self.fill_rx_queue(Duration::from_millis(1))?;
+        let mut buffer = vec![0; queued];
+        if queued > 0 {
+            let received = self.read_once(&mut buffer, Duration::from_millis(1))?;
+            buffer.truncate(received);
+        }
+        Ok(buffer)
+    }
+
+    /// Moves data the device has ready into the host-side buffer, waiting at
+    /// most `timeout`, and returns the number of bytes buffered
+    fn fill_rx_queue(&mut self, timeout: Duration) -> Result<usize, SilabsUsbXpressError> {
+        let mut queued = 0;
+        let status = unsafe { SI_FillRXQueue(self.inner, timeout_millis(timeout), &mut queued) };
+        match status as u32 {
+            SI_SUCCESS => {
+                self.metrics.set_queue_depth(queued as usize);
+                Ok(queued as usize)
+            }
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
+    }
+
+    /// Reads up to `buf.len()` bytes that are already buffered on the host
     ///
-    /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
-    /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3
-    pub fn device_io_control(&mut self) {
-        unimplemented!()
+    /// Asks for no more than what `SI_CheckRXQueue` reports, so `SI_Read`
+    /// is served from the buffer and never goes out to the device.
+    fn read_buffered(&mut self, buf: &mut [u8]) -> Result<usize, SilabsUsbXpressError> {
+        let (queued, _) = self.check_rx_queue()?;
+        let len = queued.min(buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
+        let mut bytes_returned = 0;
+        let status = unsafe {
+            SI_ReadWithTimeout(
+                self.inner,
+                buf.as_mut_ptr() as *mut c_char,
+                request_len(len),
+                &mut bytes_returned,
+                1,
+            )
+        };
+        self.metrics.record_status(status);
+        match status as u32 {
+            SI_SUCCESS => {
+                let received = received_len(bytes_returned, len);
+                self.record_transfer("read", &buf[..received]);
+                Ok(received)
+            }
+            SI_READ_TIMED_OUT => Ok(0),
+            SI_IO_CANCELLED => Err(SilabsUsbXpressError::Cancelled),
+            SI_READ_ERROR => Err(SilabsUsbXpressError::ReadError),
+            SI_IO_PENDING => Err(SilabsUsbXpressError::IoPending),
+            SI_SYSTEM_ERROR_CODE => Err(SilabsUsbXpressError::SystemErrorCode),
+            SI_INVALID_REQUEST_LENGTH => Err(SilabsUsbXpressError::InvalidRequestLength),
+            SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
     }
 
-    /// Flushes the TX and RX buffers for a device
+    /// Writes a block of data to a device
+    ///
+    /// Takes the data as anything that derefs to bytes, such as a slice, an
+    /// array or a `Vec`, and hands it to the driver without copying it.
     ///
     /// On USB MCU devices, this function flushes both the receive buffer in the
     /// USBXpress device driver and the transmit buffer in the device.
     ///
-    /// **Note**: Parameter 2 and 3 of `SI_FlushBuffers` have no effect and any
+    /// **Note**: Parameter 2 and 3 of `SI_Write` have no effect and any
     /// value can be passed when used with USB MCU devices.
     ///
     /// On CP210x devices, this function operates in accordance with parameters
@@ -365,11 +1809,118 @@ impl UsbXpress {
     /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
     /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
     /// CP2101/2/3/4/5/8/9
-    pub fn flush_buffers(&mut self) -> Result<(), SilabsUsbXpressError> {
-        let status = unsafe { SI_FlushBuffers(self.inner, 1 as c_char, 1 as c_char) };
+    pub fn write<D: AsRef<[u8]>>(&mut self, to_write: D) -> Result<usize, SilabsUsbXpressError> {
+        self.write_with(to_write.as_ref(), None)
+    }
+
+    /// Writes like [`write`](UsbXpress::write), but waits up to `timeout`
+    /// instead of the handle's write timeout
+    ///
+    /// Lets a single slow command wait longer without changing the timeouts
+    /// of the handle for everything else. When writes are paced, the timeout
+    /// applies to each burst.
+    pub fn write_timeout(
+        &mut self,
+        to_write: &[u8],
+        timeout: Duration,
+    ) -> Result<usize, SilabsUsbXpressError> {
+        self.write_with(to_write, Some(timeout))
+    }
+
+    fn write_with(
+        &mut self,
+        to_write: &[u8],
+        timeout: Option<Duration>,
+    ) -> Result<usize, SilabsUsbXpressError> {
+        if !self.is_pacing() {
+            return self
+                .write_chunk(to_write, timeout, 0)
+                .or_else(|err| short_write(0, err));
+        }
+        let mut written = 0;
+        for (ix, chunk) in to_write.chunks(PACING_BURST).enumerate() {
+            self.pace(chunk.len());
+            match self.write_chunk(chunk, timeout, ix) {
+                Ok(n) if n == chunk.len() => written += n,
+                Ok(n) => return Ok(written + n),
+                Err(err) => return short_write(written, err),
+            }
+        }
+        Ok(written)
+    }
+
+    /// Writes `data` in chunks of `chunk_size` bytes, waiting
+    /// `inter_chunk_delay` between them
+    ///
+    /// Some slow firmwares drop data when the host sends full-speed packets
+    /// back to back; spacing the chunks out gives them time to drain their
+    /// endpoint buffer. A `chunk_size` of zero is treated as one byte.
+    ///
+    /// Like [`write`](UsbXpress::write), an error is returned only if nothing
+    /// was written. Once some data has gone out, a failing chunk ends the
+    /// transfer and the number of bytes written so far is returned.
+    pub fn write_chunked(
+        &mut self,
+        data: &[u8],
+        chunk_size: usize,
+        inter_chunk_delay: Duration,
+    ) -> Result<usize, SilabsUsbXpressError> {
+        let mut written = 0;
+        for (ix, chunk) in data.chunks(chunk_size.max(1)).enumerate() {
+            if ix > 0 && inter_chunk_delay > Duration::ZERO {
+                thread::sleep(inter_chunk_delay);
+            }
+            self.pace(chunk.len());
+            match self.write_chunk(chunk, None, ix) {
+                Ok(n) if n == chunk.len() => written += n,
+                Ok(n) => return Ok(written + n),
+                Err(err) => return short_write(written, err),
+            }
+        }
+        Ok(written)
+    }
+
+    /// Returns the size of the pieces longer writes are split into
+    fn write_chunk_size(&self) -> usize {
+        if self.is_pacing() {
+            PACING_BURST
+        } else {
+            SI_MAX_WRITE_SIZE as usize
+        }
+    }
+
+    /// Hands chunk number `chunk` of a write to the device in a single
+    /// `SI_Write`, waiting up to `timeout` or else the handle's write timeout
+    fn write_chunk(
+        &mut self,
+        to_write: &[u8],
+        timeout: Option<Duration>,
+        chunk: usize,
+    ) -> Result<usize, SilabsUsbXpressError> {
+        let started = Instant::now();
+        let timeout = timeout.unwrap_or_else(|| self.write_timeout_for(to_write.len()));
+        let (status, bytes_written) = self.transmit(to_write, timeout)?;
         match status as u32 {
-            SI_SUCCESS => Ok(()),
+            SI_SUCCESS => {
+                self.last_activity = Instant::now();
+                self.discard_echo(&to_write[..bytes_written])?;
+                Ok(bytes_written)
+            }
+            SI_WRITE_ERROR => Err(SilabsUsbXpressError::WriteError),
+            SI_INVALID_REQUEST_LENGTH => Err(SilabsUsbXpressError::InvalidRequestLength),
+            SI_WRITE_TIMED_OUT => {
+                self.discard_echo(&to_write[..bytes_written])?;
+                Err(SilabsUsbXpressError::WriteTimeOut {
+                    elapsed: started.elapsed(),
+                    transferred: bytes_written,
+                    remaining: to_write.len() - bytes_written,
+                    chunk,
+                })
+            }
+            SI_IO_CANCELLED => Err(SilabsUsbXpressError::Cancelled),
+            SI_IO_PENDING => Err(SilabsUsbXpressError::IoPending),
             SI_SYSTEM_ERROR_CODE => Err(SilabsUsbXpressError::SystemErrorCode),
+            SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
             _ => unreachable!(
                 "Unreachable status code: {}. Please contact the author or submit an issue.",
                 status
@@ -377,30 +1928,178 @@ impl UsbXpress {
         }
     }
 
-    /// Returns the number of bytes in a device's RX queue
+    /// Counts transferred bytes in the device's [metrics] and logs them at
+    /// trace level if `SIUSB_LOG_PAYLOADS` is set
+    fn record_transfer(&self, direction: &str, data: &[u8]) {
+        if direction == "read" {
+            self.metrics.add_read(data.len());
+        } else {
+            self.metrics.add_written(data.len());
+        }
+        if config::log_payloads() && !data.is_empty() {
+            let device = match self.alias() {
+                Some(alias) => alias,
+                None => self.device_ix.to_string(),
+            };
+            log::trace!(
+                "device {} interface {}: {} {} bytes: {:02x?}",
+                device,
+                self.interface,
+                direction,
+                data.len(),
+                data
+            );
+        }
+    }
+
+    /// Discards the echo of every write on half-duplex links
     ///
-    /// Returns the number of bytes in the receive queue and a status value that
-    /// indicates if an overrun (SI_QUEUE_OVERRUN) has occurred and if the RX
-    /// queue is ready (SI_QUEUE_READY) for reading. Upon indication of an
-    /// Overrun condition it is recommended that data transfer be stopped
-    /// and all buffers be flushed using the SI_FlushBuffers command.
-    pub fn check_rx_queue(&mut self) -> Result<(usize, usize), SilabsUsbXpressError> {
-        let (status, num_bytes_in_queue, queue_status) = unsafe {
-            let mut num_bytes_in_queue = MaybeUninit::uninit();
-            let mut queue_status = MaybeUninit::uninit();
-            let status = SI_CheckRXQueue(
+    /// On RS-485 and single-wire links everything the host transmits is
+    /// received back. With echo suppression enabled, each write waits for its
+    /// own echo and drops it, so subsequent reads only see what the other end
+    /// sent. The echo is verified byte for byte; if it does not match, or
+    /// does not arrive within the write timeout, the write fails with
+    /// `EchoMismatch` carrying what was received instead.
+    pub fn set_echo_suppression(&mut self, enable: bool) {
+        self.echo_suppression = enable;
+    }
+
+    /// Reads back and checks the echo of `sent` if echo suppression is on
+    fn discard_echo(&mut self, sent: &[u8]) -> Result<(), SilabsUsbXpressError> {
+        if !self.echo_suppression || sent.is_empty() {
+            return Ok(());
+        }
+        let mut echo = vec![0; sent.len()];
+        let deadline = Instant::now() + self.write_timeout_for(sent.len());
+        let received = match self.read_until_deadline(&mut echo, deadline) {
+            Ok(n) => n,
+            Err(SilabsUsbXpressError::ReadTimeOut { .. }) => 0,
+            Err(err) => return Err(err),
+        };
+        echo.truncate(received);
+        if echo != sent {
+            return Err(SilabsUsbXpressError::EchoMismatch {
+                expected: sent.to_vec(),
+                received: echo,
+            });
+        }
+        Ok(())
+    }
+
+    /// Passes `data` to `SI_WriteWithTimeout`, driving the RS-485 direction
+    /// line around it if enabled
+    ///
+    /// Returns the raw status and the number of bytes written, which a
+    /// timed out write reports as well; only failing to switch the direction
+    /// line is reported as an error here.
+    fn transmit(
+        &mut self,
+        data: &[u8],
+        timeout: Duration,
+    ) -> Result<(c_int, usize), SilabsUsbXpressError> {
+        let rs485 = self.rs485;
+        if let Some(rs485) = rs485 {
+            self.set_tx_enable(rs485.enable, rs485.active_high)?;
+            if rs485.pre_delay > Duration::ZERO {
+                thread::sleep(rs485.pre_delay);
+            }
+        }
+        let mut bytes_written = 0;
+        let status = unsafe {
+            SI_WriteWithTimeout(
                 self.inner,
-                num_bytes_in_queue.as_mut_ptr(),
-                queue_status.as_mut_ptr(),
-            );
-            (
-                status,
-                num_bytes_in_queue.assume_init(),
-                queue_status.assume_init(),
+                data.as_ptr() as *mut c_char,
+                request_len(data.len()),
+                &mut bytes_written,
+                timeout_millis(timeout),
             )
         };
+        self.metrics.record_status(status);
+        let bytes_written = received_len(bytes_written, data.len());
+        self.record_transfer("write", &data[..bytes_written]);
+        if let Some(rs485) = rs485 {
+            if rs485.post_delay > Duration::ZERO {
+                thread::sleep(rs485.post_delay);
+            }
+            self.set_tx_enable(rs485.enable, !rs485.active_high)?;
+        }
+        Ok((status, bytes_written))
+    }
+
+    /// Drives a transceiver's direction pin from a modem line or a GPIO pin
+    /// around writes
+    ///
+    /// With RS-485 mode enabled, the configured line is asserted before
+    /// every write and released once it completes, switching the transceiver
+    /// between transmit and receive. The line is released right away, so the
+    /// receiver starts out enabled. Only CP210x devices have modem lines, and
+    /// only those with a [GPIO latch](UsbXpress::write_gpio_latch) GPIO
+    /// pins.
+    pub fn rs485(&mut self, enable: bool) -> Result<(), SilabsUsbXpressError> {
+        if enable {
+            let config = self.rs485.unwrap_or_default();
+            self.set_rs485_config(config)
+        } else {
+            self.rs485 = None;
+            Ok(())
+        }
+    }
+
+    /// Enables RS-485 mode with the given direction line and delays
+    pub fn set_rs485_config(&mut self, config: Rs485) -> Result<(), SilabsUsbXpressError> {
+        self.set_tx_enable(config.enable, !config.active_high)?;
+        self.rs485 = Some(config);
+        Ok(())
+    }
+
+    /// Drives an RS-485 transmit enable line
+    fn set_tx_enable(&mut self, enable: TxEnable, high: bool) -> Result<(), SilabsUsbXpressError> {
+        match enable {
+            TxEnable::Modem(line) => self.set_modem_line(line, high),
+            TxEnable::Gpio(pin) => {
+                let mask = 1 << pin;
+                self.write_gpio_latch(mask, if high { mask } else { 0 })
+            }
+        }
+    }
+
+    /// Sets the GPIO pins in `mask` to the levels of the corresponding bits
+    /// in `state`, leaving the other pins alone
+    ///
+    /// Uses the GPIO latch of the CP2103, CP2104 and CP2102N. Pins only
+    /// follow the latch when they are configured as GPIOs in the device's
+    /// one-time programmable configuration.
+    pub fn write_gpio_latch(&mut self, mask: u8, state: u8) -> Result<(), SilabsUsbXpressError> {
+        let index = u16::from(state) << 8 | u16::from(mask);
+        self.control(
+            CP210X_REQTYPE_HOST_TO_DEVICE,
+            CP210X_VENDOR_SPECIFIC,
+            CP210X_WRITE_LATCH,
+            Some(index),
+            &mut [],
+        )
+        .map(|_| ())
+    }
+
+    /// Reads the levels of the GPIO pins, one bit per pin
+    pub fn read_gpio_latch(&mut self) -> Result<u8, SilabsUsbXpressError> {
+        let latch = self.control_in(CP210X_VENDOR_SPECIFIC, CP210X_READ_LATCH, 1)?;
+        latch
+            .first()
+            .copied()
+            .ok_or(SilabsUsbXpressError::DeviceIoFailed)
+    }
+
+    /// Returns the device's part number
+    ///
+    /// - Supported Devices
+    ///
+    /// CP2101/2/3/4/5/8/9, CP2102N
+    pub fn part_number(&mut self) -> Result<PartNumber, SilabsUsbXpressError> {
+        let mut part_num = 0;
+        let status = unsafe { SI_GetPartNumber(self.inner, &mut part_num) };
         match status as u32 {
-            SI_SUCCESS => Ok((num_bytes_in_queue as usize, queue_status as usize)),
+            SI_SUCCESS => Ok(PartNumber::from(part_num)),
             SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
             _ => unreachable!(
                 "Unreachable status code: {}. Please contact the author or submit an issue.",
@@ -408,22 +2107,897 @@ impl UsbXpress {
             ),
         }
     }
-}
 
-impl fmt::Debug for UsbXpress {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("UsbXpress")
-            .field("device_ix", &self.device_ix)
-            .finish()
+    /// Starts or ends a break condition on a CP210x device's UART
+    ///
+    /// While a break is set the transmit line is held low, which e.g. wakes
+    /// up a LIN bus or makes some bootloaders take over.
+    ///
+    /// - Supported Devices
+    ///
+    /// CP2101/2/3/4/5/8/9, CP2102N
+    pub fn set_break(&mut self, enable: bool) -> Result<(), SilabsUsbXpressError> {
+        self.control_out(CP210X_SET_BREAK, u16::from(enable), &[])
+    }
+
+    /// Holds a break condition for `duration`, see
+    /// [`set_break`](UsbXpress::set_break)
+    pub fn send_break(&mut self, duration: Duration) -> Result<(), SilabsUsbXpressError> {
+        self.set_break(true)?;
+        thread::sleep(duration);
+        self.set_break(false)
+    }
+
+    /// Reads the modem lines with the CP210x `GET_MDMSTS` request
+    ///
+    /// Tells whether the remote side drives CTS, DSR, RI and DCD, e.g. to
+    /// check that it is powered and ready before streaming data to it, and
+    /// the levels of the DTR and RTS outputs.
+    ///
+    /// - Supported Devices
+    ///
+    /// CP2101/2/3/4/5/8/9, CP2102N
+    pub fn modem_status(&mut self) -> Result<ModemStatus, SilabsUsbXpressError> {
+        let status = self.control_in(CP210X_GET_MDMSTS, 0, 1)?;
+        status
+            .first()
+            .map(|&bits| ModemStatus::from_bits(bits))
+            .ok_or(SilabsUsbXpressError::DeviceIoFailed)
+    }
+
+    /// Returns how many written bytes a CP210x device has yet to send on its
+    /// UART, with the CP210x `GET_COMM_STATUS` request
+    ///
+    /// A write completes once the device has accepted the data, not once it
+    /// went out on the wire. This tells how much is still queued, e.g. to
+    /// wait until a command has been sent before changing the baud rate or
+    /// turning a half duplex line around.
+    ///
+    /// - Supported Devices
+    ///
+    /// CP2101/2/3/4/5/8/9, CP2102N
+    pub fn tx_queue_len(&mut self) -> Result<usize, SilabsUsbXpressError> {
+        let status = self.control_in(CP210X_GET_COMM_STATUS, 0, CP210X_COMM_STATUS_LEN)?;
+        comm_status_tx_queue(&status).ok_or(SilabsUsbXpressError::DeviceIoFailed)
+    }
+
+    /// Sets the DTR output of a CP210x device, leaving RTS alone
+    ///
+    /// Boards reset into their bootloader through DTR and RTS, Arduino
+    /// style, can be driven this way. A line used for
+    /// [flow control](UsbXpress::set_flow_control) is taken over until flow
+    /// control is set again.
+    ///
+    /// - Supported Devices
+    ///
+    /// CP2101/2/3/4/5/8/9, CP2102N
+    pub fn set_dtr(&mut self, active: bool) -> Result<(), SilabsUsbXpressError> {
+        self.set_modem_line(ModemLine::Dtr, active)
+    }
+
+    /// Sets the RTS output of a CP210x device, leaving DTR alone, see
+    /// [`set_dtr`](UsbXpress::set_dtr)
+    pub fn set_rts(&mut self, active: bool) -> Result<(), SilabsUsbXpressError> {
+        self.set_modem_line(ModemLine::Rts, active)
+    }
+
+    /// Sets both the DTR and the RTS output of a CP210x device in a single
+    /// request, so that they change at the same time
+    pub fn set_modem_lines(&mut self, dtr: bool, rts: bool) -> Result<(), SilabsUsbXpressError> {
+        let value = modem_handshake(Some(dtr), Some(rts));
+        self.control_out(CP210X_SET_MHS, value, &[])
+    }
+
+    /// Sets a modem control line with the CP210x `SET_MHS` request
+    fn set_modem_line(&mut self, line: ModemLine, high: bool) -> Result<(), SilabsUsbXpressError> {
+        let value = match line {
+            ModemLine::Dtr => modem_handshake(Some(high), None),
+            ModemLine::Rts => modem_handshake(None, Some(high)),
+        };
+        self.control_out(CP210X_SET_MHS, value, &[])
+    }
+
+    /// Sends a vendor request addressed to the interface, without data stage
+    /// or with `data` as its payload
+    fn control_out(
+        &mut self,
+        request: u8,
+        value: u16,
+        data: &[u8],
+    ) -> Result<(), SilabsUsbXpressError> {
+        let mut data = data.to_vec();
+        self.control(
+            CP210X_REQTYPE_HOST_TO_DEVICE,
+            request,
+            value,
+            None,
+            &mut data,
+        )
+        .map(|_| ())
+    }
+
+    /// Reads up to `len` bytes with a vendor request addressed to the
+    /// interface
+    fn control_in(
+        &mut self,
+        request: u8,
+        value: u16,
+        len: usize,
+    ) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        let mut data = vec![0; len];
+        let received = self.control(
+            CP210X_REQTYPE_DEVICE_TO_HOST,
+            request,
+            value,
+            None,
+            &mut data,
+        )?;
+        data.truncate(received);
+        Ok(data)
+    }
+
+    /// Issues a control transfer
+    ///
+    /// `index` defaults to the claimed interface. Returns how many bytes of
+    /// `data` were transferred.
+    fn control(
+        &mut self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: Option<u16>,
+        data: &mut [u8],
+    ) -> Result<usize, SilabsUsbXpressError> {
+        let mut transferred = 0;
+        let status = unsafe {
+            SI_ControlTransfer(
+                self.inner,
+                c_int::from(request_type),
+                c_int::from(request),
+                c_int::from(value),
+                index.map_or(-1, c_int::from),
+                data.as_mut_ptr() as *mut c_char,
+                request_len(data.len()),
+                &mut transferred,
+                timeout_millis(self.timeouts.write),
+            )
+        };
+        match status as u32 {
+            SI_SUCCESS => Ok(transferred as usize),
+            SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
+    }
+
+    /// Allows sending low-level commands to the device driver
+    ///
+    /// **UNIMPLEMENTED!!!**
+    ///
+    /// Interface for any miscellaneous device control functions. A separate
+    /// call to SI_DeviceIOControl is required for each input or output
+    /// operation. A single call cannot be used to perform both an input and
+    /// output operation simultaneously. Refer to DeviceIOControl function
+    /// definition on MSDN Help for more details.
+    ///
+    /// - Supported Devices
+    ///
+    /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
+    /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3
+    pub fn device_io_control(&mut self) {
+        unimplemented!()
+    }
+
+    /// Flushes the TX and RX buffers for a device
+    ///
+    /// On USB MCU devices, this function flushes both the receive buffer in the
+    /// USBXpress device driver and the transmit buffer in the device.
+    ///
+    /// **Note**: Parameter 2 and 3 of `SI_FlushBuffers` have no effect and any
+    /// value can be passed when used with USB MCU devices.
+    ///
+    /// On CP210x devices, this function operates in accordance with parameters
+    /// 2 and 3. If parameter 2 (FlushTransmit) is non-zero, the CP210x
+    /// device’s UART transmit buffer is flushed. If parameter 3
+    /// (FlushReceive) is non-zero, the CP210x device’s UART receive buffer
+    /// is flushed. If parameters 2 and 3 are both non-zero, then both the
+    /// CP210x device UART transmit buffer and UART receive buffer are
+    /// flushed.
+    ///
+    /// - Supported Devices
+    ///
+    /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
+    /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
+    /// CP2101/2/3/4/5/8/9
+    pub fn flush_buffers(&mut self) -> Result<(), SilabsUsbXpressError> {
+        self.flush(true, true)
+    }
+
+    /// Resets the device with a USB port reset
+    ///
+    /// Recovers a bridge that stopped answering, which otherwise takes
+    /// unplugging it. The UART settings go back to the device's defaults
+    /// and have to be applied again; the nominal baud rate is forgotten.
+    ///
+    /// If the device re-enumerates, the handle stops working and this fails
+    /// with `NeedsReopen`, carrying the serial number to
+    /// [`open_by_serial`](UsbXpress::open_by_serial) it with, as its index
+    /// may have changed. libusb 0.1 and the `nusb-backend` feature always
+    /// re-enumerate; with the `rust-backend` feature the handle usually
+    /// stays valid.
+    pub fn reset(&mut self) -> Result<(), SilabsUsbXpressError> {
+        let status = unsafe { SI_ResetDevice(self.inner) };
+        self.nominal_baud_rate = None;
+        match status as u32 {
+            SI_SUCCESS => Ok(()),
+            SI_DEVICE_REENUMERATED => Err(SilabsUsbXpressError::NeedsReopen {
+                serial: self.info.serial.clone(),
+            }),
+            SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
+    }
+
+    fn flush(&mut self, transmit: bool, receive: bool) -> Result<(), SilabsUsbXpressError> {
+        let status = unsafe { SI_FlushBuffers(self.inner, transmit as c_char, receive as c_char) };
+        match status as u32 {
+            SI_SUCCESS => Ok(()),
+            SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
+            SI_SYSTEM_ERROR_CODE => Err(SilabsUsbXpressError::SystemErrorCode),
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
+    }
+
+    /// Returns the number of bytes in a device's RX queue
+    ///
+    /// Returns the number of bytes in the receive queue and a status value that
+    /// indicates if an overrun (SI_QUEUE_OVERRUN) has occurred and if the RX
+    /// queue is ready (SI_QUEUE_READY) for reading. Upon indication of an
+    /// Overrun condition it is recommended that data transfer be stopped
+    /// and all buffers be flushed using the SI_FlushBuffers command.
+    pub fn check_rx_queue(&mut self) -> Result<(usize, usize), SilabsUsbXpressError> {
+        let mut num_bytes_in_queue = 0;
+        let mut queue_status = 0;
+        let status =
+            unsafe { SI_CheckRXQueue(self.inner, &mut num_bytes_in_queue, &mut queue_status) };
+        match status as u32 {
+            SI_SUCCESS => {
+                self.metrics.set_queue_depth(num_bytes_in_queue as usize);
+                Ok((num_bytes_in_queue as usize, queue_status as usize))
+            }
+            SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
+    }
+
+    /// Sets the baud rate of a CP210x device's UART
+    ///
+    /// The rate is checked against the range the device's
+    /// [part](UsbXpress::part_number) supports, failing with
+    /// [`UnsupportedBaudRate`](SilabsUsbXpressError::UnsupportedBaudRate)
+    /// outside of it; parts this crate does not know only reject zero. The
+    /// device picks the closest rate it can generate. On success the rate
+    /// is also recorded as the
+    /// [nominal baud rate](UsbXpress::set_nominal_baud_rate).
+    ///
+    /// - Supported Devices
+    ///
+    /// CP2101/2/3/4/5/8/9, CP2102N
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), SilabsUsbXpressError> {
+        let part = self.part_number()?;
+        check_baud_rate(part, baud_rate)?;
+        if part == PartNumber::Cp2101 {
+            return self.set_baud_divisor(baud_divisor(baud_rate));
+        }
+        self.control_out(CP210X_SET_BAUDRATE, 0, &baud_rate.to_le_bytes())?;
+        self.nominal_baud_rate = Some(baud_rate);
+        Ok(())
+    }
+
+    /// Sets the baud rate divisor of a CP210x device's UART
+    ///
+    /// The UART runs at 3.6864 MHz divided by `divisor`, see
+    /// [`baud_divisor`]. This is how the baud rate of the CP2101 is set,
+    /// which does not accept a literal rate; later parts still understand
+    /// it. The resulting rate is recorded as the
+    /// [nominal baud rate](UsbXpress::set_nominal_baud_rate).
+    ///
+    /// - Supported Devices
+    ///
+    /// CP2101/2/3/4/5/8/9
+    pub fn set_baud_divisor(&mut self, divisor: u16) -> Result<(), SilabsUsbXpressError> {
+        if divisor == 0 {
+            return Err(SilabsUsbXpressError::InvalidBaudDivisor(divisor));
+        }
+        self.control_out(CP210X_SET_BAUDDIV, divisor, &[])?;
+        self.nominal_baud_rate = Some(CP2101_BAUD_CLOCK / u32::from(divisor));
+        Ok(())
+    }
+
+    /// Sets the framing of a CP210x device's UART, like 8N1 or 7E1
+    ///
+    /// ```rust, ignore
+    /// # use silabs_usb_xpress::{DataBits, LineControl, Parity};
+    /// handle.set_line_control(
+    ///     LineControl::new()
+    ///         .data_bits(DataBits::Seven)
+    ///         .parity(Parity::Even),
+    /// )?;
+    /// ```
+    ///
+    /// - Supported Devices
+    ///
+    /// CP2101/2/3/4/5/8/9, CP2102N
+    pub fn set_line_control(
+        &mut self,
+        line_control: LineControl,
+    ) -> Result<(), SilabsUsbXpressError> {
+        self.control_out(CP210X_SET_LINE_CTL, line_control.value(), &[])
+    }
+
+    /// Sets the hardware flow control of a CP210x device's UART
+    ///
+    /// Configures RTS/CTS and DTR/DSR handshaking, or holds RTS and DTR at
+    /// fixed levels when they are not used for handshaking.
+    ///
+    /// ```rust, ignore
+    /// # use silabs_usb_xpress::FlowControl;
+    /// handle.set_flow_control(FlowControl::rts_cts())?;
+    /// ```
+    ///
+    /// - Supported Devices
+    ///
+    /// CP2101/2/3/4/5/8/9, CP2102N
+    pub fn set_flow_control(
+        &mut self,
+        flow_control: FlowControl,
+    ) -> Result<(), SilabsUsbXpressError> {
+        self.control_out(CP210X_SET_FLOW, 0, &flow_control.payload())
+    }
+
+    /// Tells the handle which baud rate the device's UART runs at
+    ///
+    /// This does not reconfigure the device; it only records the rate so that
+    /// timing-related features such as [`AdaptiveTimeout`] can reason about
+    /// how long a transfer should take on the wire.
+    pub fn set_nominal_baud_rate(&mut self, baud_rate: u32) {
+        self.nominal_baud_rate = Some(baud_rate);
+    }
+
+    /// Returns the baud rate recorded with
+    /// [`set_nominal_baud_rate`](UsbXpress::set_nominal_baud_rate), if any
+    pub fn nominal_baud_rate(&self) -> Option<u32> {
+        self.nominal_baud_rate
+    }
+
+    /// Throttles writes to the rate the device's UART can drain
+    ///
+    /// Writing to a CP210x faster than its UART transmits makes data back up
+    /// in the device until it is eventually dropped. With pacing enabled,
+    /// writes are split into small bursts and the host waits between them so
+    /// that, on average, bytes are handed over no faster than the
+    /// [nominal baud rate](UsbXpress::set_nominal_baud_rate) allows; without
+    /// a known baud rate pacing has no effect.
+    pub fn pace_writes(&mut self, enable: bool) {
+        self.pacer = if enable {
+            Some(Pacer {
+                credit: PACING_BURST as f64,
+                last: Instant::now(),
+            })
+        } else {
+            None
+        };
+    }
+
+    fn is_pacing(&self) -> bool {
+        self.pacer.is_some() && self.nominal_baud_rate.is_some()
+    }
+
+    /// Blocks until `bytes` more bytes may be handed to the device
+    fn pace(&mut self, bytes: usize) {
+        let (pacer, baud_rate) = match (self.pacer.as_mut(), self.nominal_baud_rate) {
+            (Some(pacer), Some(baud_rate)) => (pacer, baud_rate),
+            _ => return,
+        };
+        // ten bit times per byte: start bit, eight data bits, stop bit
+        let bytes_per_sec = f64::from(baud_rate.max(1)) / 10.0;
+        let now = Instant::now();
+        pacer.credit = (pacer.credit + (now - pacer.last).as_secs_f64() * bytes_per_sec)
+            .min(PACING_BURST as f64);
+        pacer.last = now;
+        let shortfall = bytes as f64 - pacer.credit;
+        if shortfall > 0.0 {
+            thread::sleep(Duration::from_secs_f64(shortfall / bytes_per_sec));
+            pacer.credit = 0.0;
+            pacer.last = Instant::now();
+        } else {
+            pacer.credit -= bytes as f64;
+        }
+    }
+
+    /// Returns a token that aborts blocking transfers on this handle from
+    /// another thread
+    ///
+    /// The library waits on the device in slices of at most 50ms and checks
+    /// for `SI_CancelIo` in between, so a cancelled read or write returns
+    /// `Cancelled` within about that time instead of running into its full
+    /// timeout. All tokens of a handle are shared, and they do nothing once
+    /// the handle is closed.
+    pub fn cancel_token(&mut self) -> CancelToken {
+        CancelToken(Arc::clone(&self.cancel))
+    }
+
+    /// Keeps tokens from reaching the handle once it is closed
+    fn retire_cancel_target(&self) {
+        lock_cancel_target(&self.cancel).0 = std::ptr::null_mut();
+    }
+
+    /// Sets the read and write timeouts of this handle
+    ///
+    /// Unlike [`set_timeouts`], this only affects this handle, so threads
+    /// talking to different devices can use different timeouts. A timeout
+    /// given as `None` goes back to the one newly opened devices get. The
+    /// library also uses them for the control requests it sends on this
+    /// handle, like the one reading the part number.
+    pub fn set_timeouts<R: Into<Option<Duration>>, W: Into<Option<Duration>>>(
+        &mut self,
+        read: R,
+        write: W,
+    ) {
+        let defaults = config::defaults().timeouts;
+        self.timeouts = Timeout {
+            read: read.into().unwrap_or(defaults.read),
+            write: write.into().unwrap_or(defaults.write),
+        };
+        // Only fails for an invalid handle or a negative timeout, neither of
+        // which can get here
+        unsafe {
+            SI_SetHandleTimeouts(
+                self.inner,
+                timeout_millis(self.timeouts.read),
+                timeout_millis(self.timeouts.write),
+            )
+        };
+    }
+
+    /// Returns the read and write timeouts of this handle
+    pub fn timeouts(&self) -> Timeout {
+        self.timeouts
+    }
+
+    /// Derives read and write timeouts from the transfer size
+    ///
+    /// While an [`AdaptiveTimeout`] is set and a nominal baud rate is known,
+    /// every `read` and `write` computes its timeout from the number of bytes
+    /// requested instead of using the fixed timeouts. Pass `None` to go back
+    /// to the fixed timeouts.
+    pub fn set_adaptive_timeout<T: Into<Option<AdaptiveTimeout>>>(&mut self, adaptive: T) {
+        self.adaptive_timeout = adaptive.into();
+    }
+
+    fn read_timeout_for(&self, bytes: usize) -> Duration {
+        match (self.adaptive_timeout, self.nominal_baud_rate) {
+            (Some(adaptive), Some(baud_rate)) => adaptive.timeout_for(bytes, baud_rate),
+            _ => self.timeouts.read,
+        }
+    }
+
+    fn write_timeout_for(&self, bytes: usize) -> Duration {
+        match (self.adaptive_timeout, self.nominal_baud_rate) {
+            (Some(adaptive), Some(baud_rate)) => adaptive.timeout_for(bytes, baud_rate),
+            _ => self.timeouts.write,
+        }
+    }
+
+    /// Probes the device whenever the link has been idle for a while
+    ///
+    /// The keepalive itself is driven by
+    /// [`poll_keepalive`](UsbXpress::poll_keepalive), which an application
+    /// calls from its event loop or a timer. Pass `None` to disable it.
+    pub fn set_keepalive<K: Into<Option<Keepalive>>>(&mut self, keepalive: K) {
+        self.keepalive = keepalive.into();
+    }
+
+    /// Runs the keepalive probe if the link has been idle long enough
+    ///
+    /// Returns `Ok(true)` when a probe was sent and the device answered,
+    /// `Ok(false)` when no keepalive is configured or the link saw traffic
+    /// within the keepalive interval. A device that has stopped responding is
+    /// reported through the probe's error, typically `DeviceIoFailed`, well
+    /// before the next real transfer would have noticed.
+    pub fn poll_keepalive(&mut self) -> Result<bool, SilabsUsbXpressError> {
+        let keepalive = match &self.keepalive {
+            Some(keepalive) if self.last_activity.elapsed() >= keepalive.interval => {
+                keepalive.clone()
+            }
+            _ => return Ok(false),
+        };
+        match keepalive.probe {
+            KeepaliveProbe::Status => {
+                self.probe_status()?;
+                self.last_activity = Instant::now();
+            }
+            KeepaliveProbe::Frame(frame) => {
+                self.write(&frame)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Sends a standard USB `GET_STATUS` request, which fails with
+    /// `DeviceIoFailed` if the device is gone
+    pub(crate) fn probe_status(&mut self) -> Result<(), SilabsUsbXpressError> {
+        let mut device_status = 0;
+        let status = unsafe {
+            SI_GetDeviceStatus(
+                self.inner,
+                &mut device_status,
+                timeout_millis(self.timeouts.write),
+            )
+        };
+        match status as u32 {
+            SI_SUCCESS => Ok(()),
+            SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
+    }
+
+    /// Reads into `buf` until it is full or `deadline` passes
+    ///
+    /// The time left until `deadline` is recomputed before every underlying
+    /// `SI_Read`, so the whole call never blocks much past the deadline no
+    /// matter how many partial transfers it takes to fill `buf`. Returns the
+    /// number of bytes read, which is less than `buf.len()` only when the
+    /// deadline expired first. If nothing at all arrived before the deadline,
+    /// `ReadTimeOut` is returned.
+    pub fn read_until_deadline(
+        &mut self,
+        buf: &mut [u8],
+        deadline: Instant,
+    ) -> Result<usize, SilabsUsbXpressError> {
+        let started = Instant::now();
+        let mut filled = 0;
+        while filled < buf.len() {
+            if Instant::now() >= deadline {
+                break;
+            }
+            let mut bytes_returned = 0;
+            let status = unsafe {
+                SI_ReadWithTimeout(
+                    self.inner,
+                    buf[filled..].as_mut_ptr() as *mut c_char,
+                    request_len(buf.len() - filled),
+                    &mut bytes_returned,
+                    timeout_millis(deadline.saturating_duration_since(Instant::now())),
+                )
+            };
+            self.metrics.record_status(status);
+            match status as u32 {
+                SI_SUCCESS => {
+                    self.last_activity = Instant::now();
+                    let received = received_len(bytes_returned, buf.len() - filled);
+                    self.record_transfer("read", &buf[filled..filled + received]);
+                    filled += received
+                }
+                SI_READ_TIMED_OUT => {}
+                SI_IO_CANCELLED => return Err(SilabsUsbXpressError::Cancelled),
+                SI_READ_ERROR => return Err(SilabsUsbXpressError::ReadError),
+                SI_IO_PENDING => return Err(SilabsUsbXpressError::IoPending),
+                SI_SYSTEM_ERROR_CODE => return Err(SilabsUsbXpressError::SystemErrorCode),
+                SI_INVALID_REQUEST_LENGTH => {
+                    return Err(SilabsUsbXpressError::InvalidRequestLength)
+                }
+                SI_DEVICE_IO_FAILED => return Err(SilabsUsbXpressError::DeviceIoFailed),
+                _ => unreachable!(
+                    "Unreachable status code: {}. Please contact the author or submit an issue.",
+                    status
+                ),
+            }
+        }
+        if filled == 0 && !buf.is_empty() {
+            return Err(read_timed_out(started, filled, buf.len()));
+        }
+        Ok(filled)
+    }
+
+    /// Reads exactly `n` bytes, waiting up to the read timeout for all of
+    /// them
+    ///
+    /// Keeps reading until `n` bytes have arrived, so callers do not need
+    /// their own loop around [`read`](UsbXpress::read), which may return
+    /// fewer. If the timeout expires first, `IncompleteRead` holds the bytes
+    /// that did arrive.
+    pub fn read_exact(&mut self, n: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        let started = Instant::now();
+        let deadline = started + self.read_timeout_for(n);
+        let mut data = vec![0; n];
+        let received = match self.read_until_deadline(&mut data, deadline) {
+            Ok(received) => received,
+            Err(SilabsUsbXpressError::ReadTimeOut { .. }) => 0,
+            Err(err) => return Err(err),
+        };
+        if received < n {
+            data.truncate(received);
+            return Err(SilabsUsbXpressError::IncompleteRead {
+                data,
+                elapsed: started.elapsed(),
+                remaining: n - received,
+            });
+        }
+        Ok(data)
+    }
+
+    /// Sends `request` and waits for a `response_len` byte response
+    ///
+    /// Performs the usual command/response exchange as one operation: stale
+    /// received data is flushed, the request is written and the response is
+    /// read, all within a single `timeout`. Since it borrows the handle
+    /// mutably for the whole exchange, no other transfer can slip in between
+    /// the request and its response. If the response is incomplete when the
+    /// timeout expires, `ReadTimeOut` reports how much of it arrived.
+    pub fn transact(
+        &mut self,
+        request: &[u8],
+        response_len: usize,
+        timeout: Duration,
+    ) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        let started = Instant::now();
+        let deadline = started + timeout;
+        self.flush(false, true)?;
+        self.write_until_deadline(request, deadline)?;
+        let mut response = vec![0; response_len];
+        let received = self.read_until_deadline(&mut response, deadline)?;
+        if received < response_len {
+            return Err(read_timed_out(started, received, response_len));
+        }
+        Ok(response)
+    }
+
+    /// Writes all of `data`, waiting up to the write timeout for it to go out
+    ///
+    /// Unlike [`write`](UsbXpress::write), a short write is not returned to
+    /// the caller: the rest of `data` is sent again until everything went
+    /// out. If the timeout expires first, `WriteTimeOut` reports how many
+    /// bytes were transmitted.
+    pub fn write_all(&mut self, data: &[u8]) -> Result<(), SilabsUsbXpressError> {
+        let deadline = Instant::now() + self.write_timeout_for(data.len());
+        self.write_until_deadline(data, deadline)?;
+        Ok(())
+    }
+
+    /// Writes the slices of `bufs` one after the other, all within one
+    /// write timeout
+    ///
+    /// Suits frames built from separate buffers, such as a header, a payload
+    /// and a checksum, which then need not be copied into one buffer first.
+    /// The timeout is derived from the total length and covers all slices.
+    /// Returns the number of bytes written, which is always the total. If the
+    /// timeout expires part way through, `WriteTimeOut` counts the bytes and
+    /// chunks across all slices, as if they were one buffer.
+    pub fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize, SilabsUsbXpressError> {
+        let bufs: Vec<&[u8]> = bufs.iter().map(|buf| &**buf).collect();
+        let total = bufs.iter().map(|buf| buf.len()).sum();
+        let deadline = Instant::now() + self.write_timeout_for(total);
+        self.write_slices(&bufs, deadline)
+    }
+
+    /// Writes all of `buf` unless `deadline` passes first
+    ///
+    /// `buf` is sent in chunks of at most `SI_MAX_WRITE_SIZE` bytes (smaller
+    /// when [pacing](UsbXpress::pace_writes) is enabled), each
+    /// chunk getting whatever time is left until `deadline` as its write
+    /// timeout. Returns the number of bytes written, which is always
+    /// `buf.len()`. If the deadline expires part way through, `WriteTimeOut`
+    /// reports how many bytes reached the device and in which chunk the
+    /// transfer stalled, so the caller can decide whether to resume or resync.
+    pub fn write_until_deadline(
+        &mut self,
+        buf: &[u8],
+        deadline: Instant,
+    ) -> Result<usize, SilabsUsbXpressError> {
+        self.write_slices(&[buf], deadline)
+    }
+
+    /// Writes `bufs` as one stream, see
+    /// [`write_until_deadline`](UsbXpress::write_until_deadline)
+    ///
+    /// Chunk boundaries are counted from the start of the stream, so a chunk
+    /// spanning two slices goes out in two transfers and a chunk the device
+    /// took only part of is finished before the next one starts. The chunk
+    /// a stall happened in is then simply the one holding the first byte
+    /// that was not written.
+    fn write_slices(
+        &mut self,
+        bufs: &[&[u8]],
+        deadline: Instant,
+    ) -> Result<usize, SilabsUsbXpressError> {
+        let started = Instant::now();
+        let total: usize = bufs.iter().map(|buf| buf.len()).sum();
+        let chunk_size = self.write_chunk_size();
+        let mut written = 0;
+        for buf in bufs {
+            let mut sent = 0;
+            while sent < buf.len() {
+                let now = Instant::now();
+                if now >= deadline {
+                    return Err(write_timed_out(started, written, total, chunk_size));
+                }
+                let piece = &buf[sent..piece_end(buf.len(), sent, written, chunk_size)];
+                self.pace(piece.len());
+                let (status, bytes_written) = self.transmit(piece, deadline - now)?;
+                match status as u32 {
+                    SI_SUCCESS | SI_WRITE_TIMED_OUT => {
+                        if bytes_written > 0 {
+                            self.last_activity = Instant::now();
+                        }
+                        self.discard_echo(&piece[..bytes_written])?;
+                        sent += bytes_written;
+                        written += bytes_written;
+                    }
+                    SI_IO_CANCELLED => return Err(SilabsUsbXpressError::Cancelled),
+                    SI_WRITE_ERROR => return Err(SilabsUsbXpressError::WriteError),
+                    SI_INVALID_REQUEST_LENGTH => {
+                        return Err(SilabsUsbXpressError::InvalidRequestLength)
+                    }
+                    SI_IO_PENDING => return Err(SilabsUsbXpressError::IoPending),
+                    SI_SYSTEM_ERROR_CODE => return Err(SilabsUsbXpressError::SystemErrorCode),
+                    SI_DEVICE_IO_FAILED => return Err(SilabsUsbXpressError::DeviceIoFailed),
+                    _ => unreachable!(
+                        "Unreachable status code: {}. Please contact the author or submit an issue.",
+                        status
+                    ),
+                }
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl Drop for UsbXpress {
+    fn drop(&mut self) {
+        if !self.inner.is_null() {
+            self.retire_cancel_target();
+            let status = unsafe { SI_Close(self.inner) };
+            if status as u32 != SI_SUCCESS {
+                log::warn!(
+                    "device {}: closing on drop failed with status {}",
+                    self.device_ix,
+                    status
+                );
+            }
+        }
+    }
+}
+
+/// The descriptor of the device node the backend drives the device through
+///
+/// Lets the device take part in `poll` based event loops and sandboxing,
+/// e.g. keeping it reachable once seccomp or Landlock rules are in place.
+/// usbfs reports it writable when transfers complete, not when data
+/// arrives, so wait for data with [`select_readable`] or
+/// [`read_available`](UsbXpress::read_available). The descriptor belongs to
+/// the backend: never read from, write to or close it. It is -1 once the
+/// handle is closed, or if the backend could not tell which descriptor is
+/// its own.
+///
+/// Only implemented on Linux; elsewhere libusb has no way to hand out the
+/// descriptor, or on Windows the handle, it uses.
+#[cfg(target_os = "linux")]
+impl AsRawFd for UsbXpress {
+    fn as_raw_fd(&self) -> RawFd {
+        let mut fd = -1;
+        let status = unsafe { SI_GetDeviceFd(self.inner, &mut fd) };
+        match status as u32 {
+            SI_SUCCESS => fd,
+            SI_INVALID_HANDLE => -1,
+            _ => unreachable!(
+                "Unreachable status code: {}. Please contact the author or submit an issue.",
+                status
+            ),
+        }
     }
 }
 
+impl fmt::Debug for UsbXpress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UsbXpress")
+            .field("device_ix", &self.device_ix)
+            .field("interface", &self.interface)
+            .field("alias", &self.alias())
+            .field("timeouts", &self.timeouts)
+            .finish()
+    }
+}
+
+/// Returns the transmit queue level of a CP210x `GET_COMM_STATUS` response
+///
+/// The response holds the error and hold flags, then the receive and the
+/// transmit queue levels, all little endian 32 bit words.
+fn comm_status_tx_queue(status: &[u8]) -> Option<usize> {
+    let level = status.get(12..16)?;
+    Some(u32::from_le_bytes([level[0], level[1], level[2], level[3]]) as usize)
+}
+
+/// How long [`select_readable`] waits on each device per round
+const SELECT_SLICE: Duration = Duration::from_millis(1);
+
+/// Waits until at least one of `handles` has received data
+///
+/// Returns the indices into `handles` of every device with data buffered on
+/// the host, so the following [`UsbXpress::read_available`] calls on them
+/// return right away. Returns an empty list if none had data within
+/// `timeout`.
+///
+/// This is a polling loop, not an event wait. Each round tries one transfer
+/// per device with the shortest timeout libusb allows, 1 ms, so with `n`
+/// idle devices a round takes about `n` ms. Data is noticed up to one round
+/// after it arrived, the call may return up to one round after `timeout`,
+/// and the thread wakes about once per millisecond while it waits, although
+/// it sleeps in the kernel in between. For a handful of devices that is
+/// fine; with many, or where latency matters, read each device on its own
+/// thread instead. The descriptor a handle hands out through `AsRawFd` on
+/// Linux cannot replace the loop: usbfs only reports it ready when a
+/// submitted transfer completes, and the transfers of this library are
+/// synchronous, so none is pending while it waits.
+pub fn select_readable(
+    handles: &mut [UsbXpress],
+    timeout: Duration,
+) -> Result<Vec<usize>, SilabsUsbXpressError> {
+    let deadline = Instant::now() + timeout;
+    let mut ready = Vec::new();
+    for (ix, handle) in handles.iter_mut().enumerate() {
+        if handle.check_rx_queue()?.0 > 0 {
+            ready.push(ix);
+        }
+    }
+    while ready.is_empty() && !handles.is_empty() {
+        for (ix, handle) in handles.iter_mut().enumerate() {
+            if handle.fill_rx_queue(SELECT_SLICE)? > 0 {
+                ready.push(ix);
+            }
+        }
+        if Instant::now() >= deadline {
+            break;
+        }
+    }
+    Ok(ready)
+}
+
 /// Sets read and write block timeouts
 ///
 /// Sets the read and write timeouts. Timeouts are used for SI_Read and SI_Write
 /// when called synchronously (OVERLAPPED* o is set to NULL). The default value
 /// for timeouts is 1000ms.
 ///
+/// Every [`UsbXpress`] takes a copy of them when it is opened, hands it to
+/// the library for the control requests on that handle and passes it down
+/// with each transfer. Changing them therefore only affects devices opened
+/// afterwards, and handles used from different threads never see each
+/// other's settings change underneath them. Environment overrides described
+/// in the [`config`] module take precedence.
+/// [`UsbXpress::set_timeouts`] changes the timeouts of a single open handle.
+///
+/// The library's own defaults are updated as well, so handles opened through
+/// the plain `SI_Open` C entry point use the same timeouts.
+///
+/// A zero read timeout makes [`UsbXpress::read`] a non-blocking poll of the
+/// data already received by the host. Zero is never passed down to libusb,
+/// where it would mean "wait forever".
+///
 /// - Supported Devices
 ///
 /// C8051F320/1/6/7, C8051F340/1/2/3/4/5/6/7/8/9/A/B/C/D,
@@ -433,15 +3007,56 @@ pub fn set_timeouts<R: Into<Option<Duration>>, W: Into<Option<Duration>>>(
     read: R,
     write: W,
 ) -> Result<(), SilabsUsbXpressError> {
-    let status = unsafe {
-        SI_SetTimeouts(
-            read.into().unwrap_or(Duration::from_secs(1)).as_millis() as i32,
-            write.into().unwrap_or(Duration::from_secs(1)).as_millis() as i32,
-        )
-    };
+    let read = read.into().unwrap_or(config::DEFAULT_TIMEOUT);
+    let write = write.into().unwrap_or(config::DEFAULT_TIMEOUT);
+    let status = unsafe { SI_SetTimeouts(timeout_millis(read), timeout_millis(write)) };
+
+    match status as u32 {
+        SI_SUCCESS => {
+            config::update(|defaults| {
+                defaults.timeouts.read = read;
+                defaults.timeouts.write = write;
+            });
+            Ok(())
+        }
+        SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
+        _ => unreachable!(
+            "Unreachable status code: {}. Please contact the author or submit an issue.",
+            status
+        ),
+    }
+}
 
+/// Builds the `ReadTimeOut` of a read started at `started` that got
+/// `transferred` of the `requested` bytes
+fn read_timed_out(started: Instant, transferred: usize, requested: usize) -> SilabsUsbXpressError {
+    SilabsUsbXpressError::ReadTimeOut {
+        elapsed: started.elapsed(),
+        transferred,
+        remaining: requested.saturating_sub(transferred),
+    }
+}
+
+/// Turns the status and count of a read of `requested` bytes, started at
+/// `started`, into the number of bytes received
+fn read_result(
+    status: c_int,
+    bytes_returned: c_int,
+    requested: usize,
+    started: Instant,
+) -> Result<usize, SilabsUsbXpressError> {
     match status as u32 {
-        SI_SUCCESS => Ok(()),
+        SI_SUCCESS => Ok(received_len(bytes_returned, requested)),
+        SI_READ_ERROR => Err(SilabsUsbXpressError::ReadError),
+        SI_READ_TIMED_OUT => Err(read_timed_out(
+            started,
+            received_len(bytes_returned, requested),
+            requested,
+        )),
+        SI_IO_CANCELLED => Err(SilabsUsbXpressError::Cancelled),
+        SI_IO_PENDING => Err(SilabsUsbXpressError::IoPending),
+        SI_SYSTEM_ERROR_CODE => Err(SilabsUsbXpressError::SystemErrorCode),
+        SI_INVALID_REQUEST_LENGTH => Err(SilabsUsbXpressError::InvalidRequestLength),
         SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
         _ => unreachable!(
             "Unreachable status code: {}. Please contact the author or submit an issue.",
@@ -450,7 +3065,77 @@ pub fn set_timeouts<R: Into<Option<Duration>>, W: Into<Option<Duration>>>(
     }
 }
 
-#[derive(Debug)]
+/// Builds the `WriteTimeOut` of a write of `total` bytes in chunks of
+/// `chunk_size` that stalled after `transferred` bytes
+fn write_timed_out(
+    started: Instant,
+    transferred: usize,
+    total: usize,
+    chunk_size: usize,
+) -> SilabsUsbXpressError {
+    SilabsUsbXpressError::WriteTimeOut {
+        elapsed: started.elapsed(),
+        transferred,
+        remaining: total - transferred,
+        chunk: transferred / chunk_size,
+    }
+}
+
+/// Returns where in a slice of `len` bytes the next transfer of a chunked
+/// write ends, when `sent` bytes of the slice and `written` bytes of the
+/// whole stream have gone out
+///
+/// Transfers never cross a chunk boundary of the stream.
+fn piece_end(len: usize, sent: usize, written: usize, chunk_size: usize) -> usize {
+    let chunk_end = (written / chunk_size + 1) * chunk_size;
+    len.min(sent + chunk_end - written)
+}
+
+/// Ends a write split into chunks at a chunk that failed after `written`
+/// bytes had gone out
+///
+/// Bytes the failing chunk still got out before timing out count as
+/// written. The error is reported only if nothing was written at all.
+fn short_write(written: usize, err: SilabsUsbXpressError) -> Result<usize, SilabsUsbXpressError> {
+    match err {
+        SilabsUsbXpressError::WriteTimeOut { transferred, .. } if written + transferred > 0 => {
+            Ok(written + transferred)
+        }
+        _ if written > 0 => Ok(written),
+        err => Err(err),
+    }
+}
+
+/// Returns how many of the `requested` bytes a read reported as received
+///
+/// The count comes from the C shim and is never trusted to lie within the
+/// buffer handed to it.
+fn received_len(bytes_returned: c_int, requested: usize) -> usize {
+    if bytes_returned < 0 {
+        0
+    } else {
+        (bytes_returned as usize).min(requested)
+    }
+}
+
+/// Converts a buffer length into the byte count of a transfer
+///
+/// Buffers beyond what a `c_int` holds are only used up to `c_int::MAX`
+/// bytes instead of wrapping around to a negative count.
+fn request_len(len: usize) -> c_int {
+    len.min(c_int::MAX as usize) as c_int
+}
+
+/// Converts a timeout into the whole milliseconds expected by the C shim
+///
+/// Rounds up and never returns 0, since libusb treats a zero timeout as "wait
+/// forever".
+fn timeout_millis(timeout: Duration) -> c_int {
+    let millis = timeout.as_nanos().div_ceil(1_000_000);
+    millis.max(1).min(c_int::MAX as u128) as c_int
+}
+
+#[derive(Copy, Clone, Debug)]
 pub struct Timeout {
     read: Duration,
     write: Duration,
@@ -465,11 +3150,590 @@ impl Timeout {
     }
 }
 
+/// Timeout computed from the baud rate and the size of each transfer
+///
+/// The timeout for a transfer of `bytes` bytes is
+/// `base + bytes * bit_time(baud_rate) * 10 * margin`, counting ten bit times
+/// (start bit, eight data bits, stop bit) per byte. A 1200 baud link thus gets
+/// enough time to actually move the data, while a 3 MBaud link gives up on a
+/// silent device almost immediately.
+#[derive(Copy, Clone, Debug)]
+pub struct AdaptiveTimeout {
+    base: Duration,
+    margin: f64,
+}
+
+impl AdaptiveTimeout {
+    /// Creates an adaptive timeout
+    ///
+    /// `base` covers the fixed USB and firmware latency, `margin` scales the
+    /// on-the-wire time to tolerate slow or bursty devices.
+    pub fn new(base: Duration, margin: f64) -> Self {
+        AdaptiveTimeout { base, margin }
+    }
+
+    /// Returns the timeout for transferring `bytes` bytes at `baud_rate`
+    pub fn timeout_for(&self, bytes: usize, baud_rate: u32) -> Duration {
+        let wire_time = bytes as f64 * 10.0 / f64::from(baud_rate.max(1));
+        self.base + Duration::from_secs_f64(wire_time * self.margin.max(0.0))
+    }
+}
+
+impl Default for AdaptiveTimeout {
+    /// 100ms of base latency and a margin of 2
+    fn default() -> Self {
+        AdaptiveTimeout::new(Duration::from_millis(100), 2.0)
+    }
+}
+
+/// The handle [`CancelToken`]s cancel, null once it is closed
+#[derive(Debug)]
+struct CancelTarget(*mut SiPrivate);
+
+// Only ever passed to `SI_CancelIo`, which may be called from any thread,
+// and only while the lock is held, so the handle cannot be closed meanwhile.
+unsafe impl Send for CancelTarget {}
+
+fn lock_cancel_target(target: &Mutex<CancelTarget>) -> MutexGuard<'_, CancelTarget> {
+    target
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Aborts blocking transfers of a handle from another thread, see
+/// [`UsbXpress::cancel_token`]
+#[derive(Clone, Debug)]
+pub struct CancelToken(Arc<Mutex<CancelTarget>>);
+
+impl CancelToken {
+    /// Makes the pending read or write of the handle fail with `Cancelled`
+    ///
+    /// If no transfer is pending, the next one that has to wait on the
+    /// device is cancelled instead, so a shutdown path never misses a read
+    /// that was just about to start. Does nothing once the handle is closed.
+    pub fn cancel_io(&self) {
+        let target = lock_cancel_target(&self.0);
+        if !target.0.is_null() {
+            unsafe { SI_CancelIo(target.0) };
+        }
+    }
+}
+
+/// Keepalive settings, see [`UsbXpress::set_keepalive`]
+#[derive(Clone, Debug)]
+pub struct Keepalive {
+    interval: Duration,
+    probe: KeepaliveProbe,
+}
+
+impl Keepalive {
+    /// Probes the device after `interval` without any traffic
+    pub fn new(interval: Duration, probe: KeepaliveProbe) -> Self {
+        Keepalive { interval, probe }
+    }
+}
+
+/// What a keepalive sends to an idle device
+#[derive(Clone, Debug)]
+pub enum KeepaliveProbe {
+    /// A standard USB `GET_STATUS` request, harmless to any firmware
+    Status,
+    /// A frame the device firmware is known to tolerate, written as is
+    Frame(Vec<u8>),
+}
+
+/// Identification of a device, see [`devices`] and
+/// [`UsbXpress::device_info`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// Index the device was enumerated at
+    pub index: usize,
+    /// Serial number string, empty if the device has none
+    pub serial: String,
+    /// Product description string, empty if the device has none
+    pub description: String,
+    /// Link name string, empty if the device has none or, without the
+    /// `enumeration` feature, for open devices
+    pub link_name: String,
+    /// USB vendor ID
+    pub vid: u16,
+    /// USB product ID
+    pub pid: u16,
+    /// Bus and device number, like `001/004`, which
+    /// [`UsbXpress::open_path`] accepts
+    pub bus_path: String,
+}
+
+/// Lists the connected devices
+///
+/// Unlike calling [`product_string`] for every index, all devices are read
+/// in one pass over a single enumeration that no other thread refreshes
+/// meanwhile, so the information of one device never mixes with that of
+/// another. The strings are still read from each device in turn, and a
+/// device unplugged part way through shows up with empty ones.
+#[cfg(feature = "enumeration")]
+pub fn devices() -> Result<Vec<DeviceInfo>, SilabsUsbXpressError> {
+    Ok(inventory::entries()?
+        .into_iter()
+        .map(DeviceInfo::from)
+        .collect())
+}
+
+#[cfg(feature = "enumeration")]
+impl From<inventory::Entry> for DeviceInfo {
+    fn from(entry: inventory::Entry) -> Self {
+        DeviceInfo {
+            index: entry.index,
+            serial: entry.serial,
+            description: entry.description,
+            link_name: entry.link_name,
+            vid: entry.vid,
+            pid: entry.pid,
+            bus_path: entry.bus_path,
+        }
+    }
+}
+
+impl DeviceInfo {
+    /// Reads the information from an open handle, leaving it empty but for
+    /// the index if that fails
+    fn query(device_ix: usize, handle: *mut SiPrivate) -> Self {
+        const LEN: usize = SI_MAX_DEVICE_STRLEN as usize;
+        let mut serial = [0 as c_char; LEN];
+        let mut description = [0 as c_char; LEN];
+        let mut path = [0 as c_char; LEN];
+        let (mut vid, mut pid) = (0, 0);
+        let status = unsafe {
+            SI_GetDeviceInfo(
+                handle,
+                serial.as_mut_ptr(),
+                description.as_mut_ptr(),
+                &mut vid,
+                &mut pid,
+                path.as_mut_ptr(),
+            )
+        };
+        if status as u32 != SI_SUCCESS {
+            return DeviceInfo {
+                index: device_ix,
+                ..DeviceInfo::default()
+            };
+        }
+        let string = |buffer: &[c_char; LEN]| {
+            unsafe { CStr::from_ptr(buffer.as_ptr()) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        #[cfg(feature = "enumeration")]
+        let link_name = product_string(device_ix, ProductStringType::LinkName).unwrap_or_default();
+        #[cfg(not(feature = "enumeration"))]
+        let link_name = String::new();
+        DeviceInfo {
+            index: device_ix,
+            serial: string(&serial),
+            description: string(&description),
+            link_name,
+            vid: vid as u16,
+            pid: pid as u16,
+            bus_path: string(&path),
+        }
+    }
+}
+
+/// RS-485 direction control settings, see [`UsbXpress::set_rs485_config`]
+#[derive(Copy, Clone, Debug)]
+pub struct Rs485 {
+    enable: TxEnable,
+    active_high: bool,
+    pre_delay: Duration,
+    post_delay: Duration,
+}
+
+impl Rs485 {
+    /// Drives the transceiver's transmit enable from `line`, high while
+    /// transmitting
+    pub fn new(line: ModemLine) -> Self {
+        Rs485::with_enable(TxEnable::Modem(line))
+    }
+
+    /// Drives the transceiver's transmit enable from GPIO `pin` of the
+    /// [GPIO latch](UsbXpress::write_gpio_latch), high while transmitting
+    ///
+    /// # Panics
+    ///
+    /// Panics if `pin` is not one of the latch's pins 0 to 7.
+    pub fn gpio(pin: u8) -> Self {
+        assert!(pin < 8, "GPIO pin {} is not on the latch", pin);
+        Rs485::with_enable(TxEnable::Gpio(pin))
+    }
+
+    fn with_enable(enable: TxEnable) -> Self {
+        Rs485 {
+            enable,
+            active_high: true,
+            pre_delay: Duration::ZERO,
+            post_delay: Duration::ZERO,
+        }
+    }
+
+    /// Drives the line low while transmitting instead
+    pub fn active_low(mut self) -> Self {
+        self.active_high = false;
+        self
+    }
+
+    /// Waits `delay` after enabling the transmitter before sending data
+    pub fn pre_delay(mut self, delay: Duration) -> Self {
+        self.pre_delay = delay;
+        self
+    }
+
+    /// Waits `delay` after a write before switching back to receive
+    ///
+    /// A write completes once the device has accepted the data, not once its
+    /// UART has shifted it out, so this should cover the time the last bytes
+    /// need on the wire at the configured baud rate.
+    pub fn post_delay(mut self, delay: Duration) -> Self {
+        self.post_delay = delay;
+        self
+    }
+}
+
+impl Default for Rs485 {
+    /// RTS, high while transmitting, without delays
+    fn default() -> Self {
+        Rs485::new(ModemLine::Rts)
+    }
+}
+
+/// UART framing, see [`UsbXpress::set_line_control`]
+///
+/// Defaults to 8N1: eight data bits, no parity and one stop bit.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LineControl {
+    pub data_bits: DataBits,
+    pub parity: Parity,
+    pub stop_bits: StopBits,
+}
+
+impl LineControl {
+    /// 8N1 framing
+    pub fn new() -> Self {
+        LineControl::default()
+    }
+
+    /// Sets the number of data bits
+    pub fn data_bits(mut self, data_bits: DataBits) -> Self {
+        self.data_bits = data_bits;
+        self
+    }
+
+    /// Sets the parity bit
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Sets the number of stop bits
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+
+    /// The `wValue` of the CP210x `SET_LINE_CTL` request
+    fn value(&self) -> u16 {
+        (self.data_bits as u16) << 8 | (self.parity as u16) << 4 | self.stop_bits as u16
+    }
+}
+
+/// Number of data bits in a UART frame
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DataBits {
+    Five = 5,
+    Six = 6,
+    Seven = 7,
+    #[default]
+    Eight = 8,
+}
+
+/// Parity bit of a UART frame
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Parity {
+    #[default]
+    None = 0,
+    Odd = 1,
+    Even = 2,
+    /// Always set
+    Mark = 3,
+    /// Always clear
+    Space = 4,
+}
+
+/// Number of stop bits of a UART frame
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum StopBits {
+    #[default]
+    One = 0,
+    /// Only with five data bits
+    OneAndAHalf = 1,
+    Two = 2,
+}
+
+/// Levels of the CP210x modem lines, see [`UsbXpress::modem_status`]
+///
+/// A set of the flags below, which are active when contained:
+///
+/// ```rust, ignore
+/// # use silabs_usb_xpress::ModemStatus;
+/// let status = handle.modem_status()?;
+/// if status.contains(ModemStatus::CTS | ModemStatus::DSR) {
+///     // the remote side is ready
+/// }
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ModemStatus(u8);
+
+impl ModemStatus {
+    /// Data Terminal Ready, an output
+    pub const DTR: ModemStatus = ModemStatus(0x01);
+    /// Request To Send, an output
+    pub const RTS: ModemStatus = ModemStatus(0x02);
+    /// Clear To Send
+    pub const CTS: ModemStatus = ModemStatus(0x10);
+    /// Data Set Ready
+    pub const DSR: ModemStatus = ModemStatus(0x20);
+    /// Ring Indicator
+    pub const RI: ModemStatus = ModemStatus(0x40);
+    /// Data Carrier Detect
+    pub const DCD: ModemStatus = ModemStatus(0x80);
+
+    /// The status from the CP210x modem status byte
+    pub fn from_bits(bits: u8) -> Self {
+        ModemStatus(bits)
+    }
+
+    /// The CP210x modem status byte
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Whether all lines of `other` are active
+    pub fn contains(self, other: ModemStatus) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Whether CTS is active
+    pub fn cts(self) -> bool {
+        self.contains(ModemStatus::CTS)
+    }
+
+    /// Whether DSR is active
+    pub fn dsr(self) -> bool {
+        self.contains(ModemStatus::DSR)
+    }
+
+    /// Whether RI is active
+    pub fn ri(self) -> bool {
+        self.contains(ModemStatus::RI)
+    }
+
+    /// Whether DCD is active
+    pub fn dcd(self) -> bool {
+        self.contains(ModemStatus::DCD)
+    }
+}
+
+impl std::ops::BitOr for ModemStatus {
+    type Output = ModemStatus;
+
+    fn bitor(self, other: ModemStatus) -> ModemStatus {
+        ModemStatus(self.0 | other.0)
+    }
+}
+
+/// Hardware flow control settings, see [`UsbXpress::set_flow_control`]
+///
+/// Defaults to no flow control, with RTS and DTR held active.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FlowControl {
+    pub rts: OutputLine,
+    pub dtr: OutputLine,
+    /// Transmits only while CTS is active
+    pub cts_handshake: bool,
+    /// Transmits only while DSR is active
+    pub dsr_handshake: bool,
+}
+
+impl FlowControl {
+    /// No flow control, with RTS and DTR held active
+    pub fn none() -> Self {
+        FlowControl {
+            rts: OutputLine::Active,
+            dtr: OutputLine::Active,
+            cts_handshake: false,
+            dsr_handshake: false,
+        }
+    }
+
+    /// RTS/CTS handshaking, with DTR held active
+    pub fn rts_cts() -> Self {
+        FlowControl {
+            rts: OutputLine::Handshake,
+            cts_handshake: true,
+            ..FlowControl::none()
+        }
+    }
+
+    /// DTR/DSR handshaking, with RTS held active
+    pub fn dtr_dsr() -> Self {
+        FlowControl {
+            dtr: OutputLine::Handshake,
+            dsr_handshake: true,
+            ..FlowControl::none()
+        }
+    }
+
+    /// Sets how RTS is driven
+    pub fn rts(mut self, rts: OutputLine) -> Self {
+        self.rts = rts;
+        self
+    }
+
+    /// Sets how DTR is driven
+    pub fn dtr(mut self, dtr: OutputLine) -> Self {
+        self.dtr = dtr;
+        self
+    }
+
+    /// The payload of the CP210x `SET_FLOW` request
+    fn payload(&self) -> [u8; 16] {
+        let mut control_handshake = self.dtr as u32;
+        if self.cts_handshake {
+            control_handshake |= CP210X_CTS_HANDSHAKE;
+        }
+        if self.dsr_handshake {
+            control_handshake |= CP210X_DSR_HANDSHAKE;
+        }
+        let flow_replace = (self.rts as u32) << 6;
+        let mut payload = [0; 16];
+        payload[..4].copy_from_slice(&control_handshake.to_le_bytes());
+        payload[4..8].copy_from_slice(&flow_replace.to_le_bytes());
+        payload[8..12].copy_from_slice(&CP210X_XON_LIMIT.to_le_bytes());
+        payload[12..].copy_from_slice(&CP210X_XOFF_LIMIT.to_le_bytes());
+        payload
+    }
+}
+
+impl Default for FlowControl {
+    fn default() -> Self {
+        FlowControl::none()
+    }
+}
+
+/// How a CP210x drives RTS or DTR, see [`FlowControl`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutputLine {
+    /// Held inactive
+    Inactive = 0,
+    /// Held active
+    Active = 1,
+    /// Driven by the device for handshaking, active while it can receive
+    Handshake = 2,
+}
+
+/// The line an RS-485 transceiver's transmit enable is wired to
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TxEnable {
+    Modem(ModemLine),
+    /// A pin of the GPIO latch, 0 to 7
+    Gpio(u8),
+}
+
+/// A CP210x modem control output
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ModemLine {
+    /// Request To Send
+    Rts,
+    /// Data Terminal Ready
+    Dtr,
+}
+
+/// The part a device reports with `SI_GetPartNumber`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PartNumber {
+    Cp2101,
+    Cp2102,
+    Cp2103,
+    Cp2104,
+    Cp2105,
+    Cp2108,
+    Cp2109,
+    /// CP2102N in a 28-pin QFN package
+    Cp2102NQfn28,
+    /// CP2102N in a 24-pin QFN package
+    Cp2102NQfn24,
+    /// CP2102N in a 20-pin QFN package
+    Cp2102NQfn20,
+    /// A part number this crate does not know, as reported by the device
+    Unknown(u8),
+}
+
+impl From<u8> for PartNumber {
+    fn from(part_num: u8) -> Self {
+        match part_num {
+            0x01 => PartNumber::Cp2101,
+            0x02 => PartNumber::Cp2102,
+            0x03 => PartNumber::Cp2103,
+            0x04 => PartNumber::Cp2104,
+            0x05 => PartNumber::Cp2105,
+            0x08 => PartNumber::Cp2108,
+            0x09 => PartNumber::Cp2109,
+            0x20 => PartNumber::Cp2102NQfn28,
+            0x21 => PartNumber::Cp2102NQfn24,
+            0x22 => PartNumber::Cp2102NQfn20,
+            other => PartNumber::Unknown(other),
+        }
+    }
+}
+
+impl PartNumber {
+    /// Baud rates the part's UART supports, `None` for unknown parts
+    pub fn baud_rates(&self) -> Option<RangeInclusive<u32>> {
+        match self {
+            PartNumber::Cp2101 => Some(300..=921_600),
+            PartNumber::Cp2102 | PartNumber::Cp2103 | PartNumber::Cp2109 => Some(300..=1_000_000),
+            PartNumber::Cp2104 | PartNumber::Cp2105 | PartNumber::Cp2108 => Some(300..=2_000_000),
+            PartNumber::Cp2102NQfn28 | PartNumber::Cp2102NQfn24 | PartNumber::Cp2102NQfn20 => {
+                Some(300..=3_000_000)
+            }
+            PartNumber::Unknown(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for PartNumber {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PartNumber::Cp2101 => write!(f, "CP2101"),
+            PartNumber::Cp2102 => write!(f, "CP2102"),
+            PartNumber::Cp2103 => write!(f, "CP2103"),
+            PartNumber::Cp2104 => write!(f, "CP2104"),
+            PartNumber::Cp2105 => write!(f, "CP2105"),
+            PartNumber::Cp2108 => write!(f, "CP2108"),
+            PartNumber::Cp2109 => write!(f, "CP2109"),
+            PartNumber::Cp2102NQfn28 => write!(f, "CP2102N (QFN28)"),
+            PartNumber::Cp2102NQfn24 => write!(f, "CP2102N (QFN24)"),
+            PartNumber::Cp2102NQfn20 => write!(f, "CP2102N (QFN20)"),
+            PartNumber::Unknown(part_num) => write!(f, "unknown part 0x{:02X}", part_num),
+        }
+    }
+}
+
 /// Gets read and write block timeouts
 ///
-/// Returns the current read and write timeouts. If a timeout value is None in
-/// Rust, it has been set to wait 1000ms; otherwise the timeouts are specified
-/// in milliseconds.
+/// Returns the current read and write timeouts that newly opened devices will
+/// use. If a timeout value is None in Rust, it has been set to wait 1000ms;
+/// otherwise the timeouts are specified in milliseconds.
 ///
 /// - Supported Devices
 ///
@@ -477,39 +3741,136 @@ impl Timeout {
 /// C8051F380/1/2/3/4/5/6/7, C8051T320/1/2/3/6/7, C8051T620/1/2/3,
 /// CP2101/2/3/4/5/8/9
 pub fn timeouts() -> Result<Timeout, SilabsUsbXpressError> {
-    let (status, read, write) = unsafe {
-        let mut read = MaybeUninit::uninit();
-        let mut write = MaybeUninit::uninit();
-        let status = SI_GetTimeouts(read.as_mut_ptr(), write.as_mut_ptr());
-        (status, read.assume_init(), write.assume_init())
-    };
-
-    match status as u32 {
-        SI_SUCCESS => Ok(Timeout {
-            read: Duration::from_millis(read as u64),
-            write: Duration::from_millis(write as u64),
-        }),
-        SI_DEVICE_IO_FAILED => Err(SilabsUsbXpressError::DeviceIoFailed),
-        _ => unreachable!(
-            "Unreachable status code: {}. Please contact the author or submit an issue.",
-            status
-        ),
-    }
+    Ok(config::defaults().timeouts)
 }
 
 #[derive(Debug)]
 pub enum SilabsUsbXpressError {
     ConnectionError,
     DeviceNotFound,
+    /// No device has the serial number asked for
+    SerialNotFound(String),
+    /// Several devices have the serial number asked for; `indices` are
+    /// theirs
+    DuplicateSerial {
+        serial: String,
+        indices: Vec<usize>,
+    },
+    /// There is no `nth` device with the vendor and product ID asked for,
+    /// only `matches` of them
+    VidPidNotFound {
+        vid: u16,
+        pid: u16,
+        nth: usize,
+        matches: usize,
+    },
     SystemErrorCode,
     GlobalDataError,
     ReadError,
-    ReadTimeOut,
+    /// A read gave up before the requested data arrived
+    ///
+    /// `transferred` bytes arrived within `elapsed` and `remaining` of the
+    /// requested bytes never did. A silent device shows up with nothing
+    /// transferred, a slow one with some progress.
+    ReadTimeOut {
+        elapsed: Duration,
+        transferred: usize,
+        remaining: usize,
+    },
+    /// [`read_exact`](UsbXpress::read_exact) timed out before all bytes
+    /// arrived
+    ///
+    /// `data` holds the bytes received within `elapsed`; `remaining` more
+    /// were expected.
+    IncompleteRead {
+        data: Vec<u8>,
+        elapsed: Duration,
+        remaining: usize,
+    },
     IoPending,
     InvalidRequestLength,
     DeviceIoFailed,
     WriteError,
-    WriteTimeOut,
+    /// A write gave up before all data reached the device
+    ///
+    /// `transferred` bytes were written within `elapsed` and `remaining`
+    /// bytes were never sent. For writes split into several USB transfers,
+    /// `chunk` is the zero-based index of the chunk that timed out.
+    WriteTimeOut {
+        elapsed: Duration,
+        transferred: usize,
+        remaining: usize,
+        chunk: usize,
+    },
+    OpenTimedOut,
+    /// No matching device was connected in time, see
+    /// [`hotplug::wait_for_device`]
+    DeviceWaitTimedOut,
+    /// A read or write was aborted through a [`CancelToken`]
+    Cancelled,
+    /// The device was found but another driver holds its interface
+    ///
+    /// Typically a kernel serial driver: `cp210x` on Linux, `uslcom` on the
+    /// BSDs, or Apple's or the SiLabs VCP driver on macOS. See
+    /// [`OpenOptions::detach_kernel_driver`].
+    DeviceBusy,
+    /// The kernel driver bound to the interface could not be detached
+    ///
+    /// `driver` names it where libusb can tell.
+    KernelDriverConflict {
+        driver: Option<String>,
+    },
+    /// The device re-enumerated after a [reset](UsbXpress::reset)
+    ///
+    /// The handle no longer works; the device has to be opened again by its
+    /// `serial`.
+    NeedsReopen {
+        serial: String,
+    },
+    /// The echo read back after a write differs from what was written
+    ///
+    /// Only raised with [echo suppression](UsbXpress::set_echo_suppression)
+    /// enabled. `received` holds the bytes that came back in place of the
+    /// echo, which is shorter than `expected` if the echo stopped early.
+    EchoMismatch {
+        expected: Vec<u8>,
+        received: Vec<u8>,
+    },
+    /// The response to a [firmware version probe](firmware::VersionProbe)
+    /// could not be decoded; holds the response
+    InvalidFirmwareVersion(Vec<u8>),
+    /// The firmware's version is not compatible with the required one
+    IncompatibleFirmware {
+        found: firmware::FirmwareVersion,
+        required: firmware::FirmwareVersion,
+    },
+    /// A calibration record is damaged or does not decode, or calibration
+    /// data cannot be encoded; holds the reason, see [`calibration`]
+    InvalidCalibration(String),
+    /// The calibration record was written with layout version `found`
+    CalibrationVersionMismatch {
+        found: u16,
+        expected: u16,
+    },
+    /// The device's part cannot run its UART at `baud_rate`, see
+    /// [`PartNumber::baud_rates`]
+    UnsupportedBaudRate {
+        baud_rate: u32,
+        part: PartNumber,
+    },
+    /// A baud rate divisor of zero, see [`UsbXpress::set_baud_divisor`]
+    InvalidBaudDivisor(u16),
+    /// The device has no interface with this index, see
+    /// [`OpenOptions::open_interface`]
+    InterfaceNotFound(usize),
+    /// No more vendor and product IDs can be registered, see [`id_table`]
+    VidPidTableFull,
+    /// Entries of `SIUSB_DEVICE_IDS` that are not `VID:PID` pairs, see
+    /// [`id_table::register_from_env`]
+    InvalidDeviceIds(Vec<String>),
+    /// Environment variables, or `SIUSB_VID_PID_FILTER` entries, that could
+    /// not be parsed, as `NAME=value`, see [`config::check_env`]
+    InvalidEnvironment(Vec<String>),
 }
 
 impl fmt::Display for SilabsUsbXpressError {
@@ -519,3 +3880,287 @@ impl fmt::Display for SilabsUsbXpressError {
 }
 
 impl Error for SilabsUsbXpressError {}
+
+impl From<SilabsUsbXpressError> for io::Error {
+    fn from(err: SilabsUsbXpressError) -> Self {
+        let kind = match err {
+            SilabsUsbXpressError::ReadTimeOut { .. }
+            | SilabsUsbXpressError::IncompleteRead { .. }
+            | SilabsUsbXpressError::WriteTimeOut { .. }
+            | SilabsUsbXpressError::OpenTimedOut
+            | SilabsUsbXpressError::DeviceWaitTimedOut => io::ErrorKind::TimedOut,
+            SilabsUsbXpressError::DeviceNotFound
+            | SilabsUsbXpressError::SerialNotFound(_)
+            | SilabsUsbXpressError::VidPidNotFound { .. }
+            | SilabsUsbXpressError::InterfaceNotFound(_) => io::ErrorKind::NotFound,
+            SilabsUsbXpressError::InvalidRequestLength
+            | SilabsUsbXpressError::UnsupportedBaudRate { .. }
+            | SilabsUsbXpressError::InvalidBaudDivisor(_)
+            | SilabsUsbXpressError::InvalidDeviceIds(_)
+            | SilabsUsbXpressError::InvalidEnvironment(_) => io::ErrorKind::InvalidInput,
+            SilabsUsbXpressError::EchoMismatch { .. }
+            | SilabsUsbXpressError::InvalidFirmwareVersion(_)
+            | SilabsUsbXpressError::IncompatibleFirmware { .. }
+            | SilabsUsbXpressError::InvalidCalibration(_)
+            | SilabsUsbXpressError::CalibrationVersionMismatch { .. } => io::ErrorKind::InvalidData,
+            _ => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adaptive_timeout_scales_with_baud_rate() {
+        let adaptive = AdaptiveTimeout::new(Duration::from_millis(100), 2.0);
+        assert_eq!(
+            adaptive.timeout_for(120, 1200),
+            Duration::from_millis(100) + Duration::from_secs(2)
+        );
+        assert_eq!(adaptive.timeout_for(0, 1200), Duration::from_millis(100));
+        assert!(adaptive.timeout_for(120, 3_000_000) < Duration::from_millis(101));
+    }
+
+    #[test]
+    fn short_reads_keep_the_reported_length() {
+        assert_eq!(received_len(3, 7), 3);
+        assert_eq!(received_len(0, 7), 0);
+        assert_eq!(received_len(7, 7), 7);
+    }
+
+    #[test]
+    fn read_timeouts_report_what_arrived() {
+        let started = Instant::now();
+        assert!(matches!(
+            read_timed_out(started, 3, 8),
+            SilabsUsbXpressError::ReadTimeOut {
+                transferred: 3,
+                remaining: 5,
+                ..
+            }
+        ));
+        assert!(matches!(
+            read_timed_out(started, 0, 8),
+            SilabsUsbXpressError::ReadTimeOut {
+                transferred: 0,
+                remaining: 8,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn partial_writes_count_the_bytes_of_the_stalled_chunk() {
+        let timed_out = |transferred| SilabsUsbXpressError::WriteTimeOut {
+            elapsed: Duration::ZERO,
+            transferred,
+            remaining: 64 - transferred,
+            chunk: 2,
+        };
+        assert!(matches!(short_write(128, timed_out(5)), Ok(133)));
+        assert!(matches!(short_write(0, timed_out(5)), Ok(5)));
+        assert!(matches!(
+            short_write(0, timed_out(0)),
+            Err(SilabsUsbXpressError::WriteTimeOut { chunk: 2, .. })
+        ));
+        assert!(matches!(
+            short_write(64, SilabsUsbXpressError::WriteError),
+            Ok(64)
+        ));
+        assert!(matches!(
+            short_write(0, SilabsUsbXpressError::WriteError),
+            Err(SilabsUsbXpressError::WriteError)
+        ));
+    }
+
+    #[test]
+    fn write_timeouts_name_the_chunk_that_stalled() {
+        assert!(matches!(
+            write_timed_out(Instant::now(), 100, 300, 64),
+            SilabsUsbXpressError::WriteTimeOut {
+                transferred: 100,
+                remaining: 200,
+                chunk: 1,
+                ..
+            }
+        ));
+        assert!(matches!(
+            write_timed_out(Instant::now(), 0, 300, 64),
+            SilabsUsbXpressError::WriteTimeOut { chunk: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn chunked_writes_finish_a_partial_chunk_first() {
+        assert_eq!(piece_end(300, 0, 0, 64), 64);
+        // 36 bytes of the second chunk went out before a timeout
+        assert_eq!(piece_end(300, 100, 100, 64), 128);
+        // A second slice starting 100 bytes into the stream
+        assert_eq!(piece_end(50, 0, 100, 64), 28);
+        assert_eq!(piece_end(50, 28, 128, 64), 50);
+    }
+
+    #[test]
+    fn comm_status_reports_the_transmit_queue() {
+        let mut status = [0; CP210X_COMM_STATUS_LEN];
+        status[8] = 7;
+        status[12..16].copy_from_slice(&300u32.to_le_bytes());
+        assert_eq!(comm_status_tx_queue(&status), Some(300));
+        assert_eq!(comm_status_tx_queue(&status[..14]), None);
+    }
+
+    #[test]
+    fn bogus_read_lengths_stay_within_the_buffer() {
+        assert_eq!(received_len(64, 7), 7);
+        assert_eq!(received_len(-1, 7), 0);
+    }
+
+    #[test]
+    fn short_reads_return_what_the_backend_reported() {
+        let started = Instant::now();
+        let success = SI_SUCCESS as c_int;
+        assert_eq!(read_result(success, 3, 8, started).unwrap(), 3);
+        assert_eq!(read_result(success, 0, 8, started).unwrap(), 0);
+        // A backend reporting more than was asked for, or a negative count
+        assert_eq!(read_result(success, 64, 8, started).unwrap(), 8);
+        assert_eq!(read_result(success, -1, 8, started).unwrap(), 0);
+        assert!(matches!(
+            read_result(SI_READ_TIMED_OUT as c_int, 2, 8, started),
+            Err(SilabsUsbXpressError::ReadTimeOut {
+                transferred: 2,
+                remaining: 6,
+                ..
+            })
+        ));
+        assert!(matches!(
+            read_result(SI_IO_CANCELLED as c_int, 0, 8, started),
+            Err(SilabsUsbXpressError::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn huge_buffers_never_ask_for_a_negative_count() {
+        assert_eq!(request_len(7), 7);
+        assert_eq!(request_len(c_int::MAX as usize), c_int::MAX);
+        assert_eq!(request_len(c_int::MAX as usize + 1), c_int::MAX);
+        assert_eq!(request_len(usize::MAX), c_int::MAX);
+    }
+
+    #[test]
+    fn part_numbers_map_to_parts() {
+        assert_eq!(PartNumber::from(0x02), PartNumber::Cp2102);
+        assert_eq!(PartNumber::from(0x04), PartNumber::Cp2104);
+        assert_eq!(PartNumber::from(0x08), PartNumber::Cp2108);
+        assert_eq!(PartNumber::from(0x7F), PartNumber::Unknown(0x7F));
+    }
+
+    #[test]
+    fn ascii_product_strings_end_at_their_length() {
+        let buffer = b"CP2102 USB to UARTgarbage";
+        assert_eq!(
+            decode_product_string(buffer, 18, true),
+            "CP2102 USB to UART"
+        );
+        assert_eq!(decode_product_string(buffer, 0, true), "");
+    }
+
+    #[test]
+    fn unicode_product_strings_end_at_their_length() {
+        let mut buffer: Vec<u8> = "CP2108 Quad"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        buffer.extend_from_slice(&[0x41, 0x00, 0x42, 0x00]);
+        assert_eq!(decode_product_string(&buffer, 22, false), "CP2108 Quad");
+        assert_eq!(decode_product_string(&buffer, 255, false), "CP2108 QuadAB");
+    }
+
+    #[test]
+    fn baud_rates_are_checked_against_the_part() {
+        assert!(check_baud_rate(PartNumber::Cp2102, 115_200).is_ok());
+        assert!(check_baud_rate(PartNumber::Cp2102NQfn28, 3_000_000).is_ok());
+        assert!(matches!(
+            check_baud_rate(PartNumber::Cp2102, 3_000_000),
+            Err(SilabsUsbXpressError::UnsupportedBaudRate {
+                baud_rate: 3_000_000,
+                part: PartNumber::Cp2102,
+            })
+        ));
+        assert!(check_baud_rate(PartNumber::Cp2104, 110).is_err());
+        assert!(check_baud_rate(PartNumber::Unknown(0x7F), 12_000_000).is_ok());
+        assert!(check_baud_rate(PartNumber::Unknown(0x7F), 0).is_err());
+    }
+
+    #[test]
+    fn baud_divisors_round_to_the_closest_rate() {
+        assert_eq!(baud_divisor(115_200), 32);
+        assert_eq!(baud_divisor(9600), 384);
+        assert_eq!(baud_divisor(921_600), 4);
+        // 3686400 / 57 = 64673 and / 58 = 63558 baud
+        assert_eq!(baud_divisor(64_000), 58);
+        assert_eq!(baud_divisor(10_000_000), 1);
+        assert_eq!(baud_divisor(1), u16::MAX);
+        assert_eq!(baud_divisor(0), u16::MAX);
+    }
+
+    #[test]
+    fn line_control_encodes_set_line_ctl() {
+        assert_eq!(LineControl::new().value(), 0x0800);
+        let seven_e_one = LineControl::new()
+            .data_bits(DataBits::Seven)
+            .parity(Parity::Even);
+        assert_eq!(seven_e_one.value(), 0x0720);
+        let five_n_one_half = LineControl::new()
+            .data_bits(DataBits::Five)
+            .stop_bits(StopBits::OneAndAHalf);
+        assert_eq!(five_n_one_half.value(), 0x0501);
+    }
+
+    #[test]
+    fn flow_control_encodes_set_flow() {
+        let payload = FlowControl::rts_cts().payload();
+        assert_eq!(payload[..4], [0x09, 0, 0, 0]);
+        assert_eq!(payload[4..8], [0x80, 0, 0, 0]);
+        assert_eq!(payload[8..], [128, 0, 0, 0, 128, 0, 0, 0]);
+        let forced = FlowControl::none()
+            .rts(OutputLine::Inactive)
+            .dtr(OutputLine::Inactive)
+            .payload();
+        assert_eq!(forced[..8], [0; 8]);
+        assert_eq!(
+            FlowControl::dtr_dsr().payload()[..8],
+            [0x12, 0, 0, 0, 0x40, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn modem_status_reports_input_lines() {
+        let status = ModemStatus::from_bits(0x31);
+        assert!(status.cts() && status.dsr());
+        assert!(!status.ri() && !status.dcd());
+        assert!(status.contains(ModemStatus::CTS | ModemStatus::DSR | ModemStatus::DTR));
+        assert!(!status.contains(ModemStatus::CTS | ModemStatus::DCD));
+    }
+
+    #[test]
+    fn modem_handshake_only_touches_the_lines_given() {
+        assert_eq!(modem_handshake(Some(true), None), 0x0101);
+        assert_eq!(modem_handshake(None, Some(false)), 0x0200);
+        assert_eq!(modem_handshake(Some(false), Some(true)), 0x0302);
+    }
+
+    #[test]
+    fn rs485_drives_latch_pins() {
+        assert_eq!(Rs485::gpio(3).enable, TxEnable::Gpio(3));
+        assert_eq!(Rs485::default().enable, TxEnable::Modem(ModemLine::Rts));
+    }
+
+    #[test]
+    #[should_panic]
+    fn rs485_rejects_pins_off_the_latch() {
+        Rs485::gpio(8);
+    }
+}
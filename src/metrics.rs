@@ -0,0 +1,263 @@
+//! Per-device transfer metrics
+//!
+//! Every open handle counts the bytes it transfers, its failed and timed out
+//! transfers and how many bytes were last seen waiting in its receive queue.
+//! Counters are kept per device, identified by serial number, and survive
+//! the handle: opening a device that was open before counts as a reconnect
+//! and continues its counters. [`render`] formats all of them in the
+//! Prometheus text format.
+//!
+//! With the `prometheus` feature, [`serve`] answers scrapes of `/metrics`
+//! from a background thread:
+//!
+//! ```rust, ignore
+//! silabs_usb_xpress::metrics::serve("0.0.0.0:9464")?;
+//! ```
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    os::raw::c_int,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::{aliases, ffi::*};
+
+/// Counters of one device
+#[derive(Debug, Default)]
+pub struct DeviceMetrics {
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    errors: AtomicU64,
+    timeouts: AtomicU64,
+    reconnects: AtomicU64,
+    queue_depth: AtomicU64,
+}
+
+impl DeviceMetrics {
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.load(Ordering::Relaxed)
+    }
+
+    /// Number of transfers that failed other than by timing out
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Number of transfers that timed out
+    pub fn timeouts(&self) -> u64 {
+        self.timeouts.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the device was opened again after its first open
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+
+    /// Bytes in the receive queue when it was last checked
+    pub fn queue_depth(&self) -> u64 {
+        self.queue_depth.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_read(&self, bytes: usize) {
+        self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_written(&self, bytes: usize) {
+        self.bytes_written
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Counts the outcome of an `SI_Read` or `SI_Write` call
+    pub(crate) fn record_status(&self, status: c_int) {
+        match status as u32 {
+            SI_SUCCESS => {}
+            SI_READ_TIMED_OUT | SI_WRITE_TIMED_OUT => {
+                self.timeouts.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn set_queue_depth(&self, bytes: usize) {
+        self.queue_depth.store(bytes as u64, Ordering::Relaxed);
+    }
+}
+
+static REGISTRY: Mutex<BTreeMap<String, Arc<DeviceMetrics>>> = Mutex::new(BTreeMap::new());
+
+/// Returns the counters of the device with serial number `serial`, or of
+/// the device at `device_ix` if it has none, counting a reconnect if the
+/// device was open before
+pub(crate) fn register(serial: &str, device_ix: usize) -> Arc<DeviceMetrics> {
+    let mut registry = REGISTRY.lock().unwrap();
+    let metrics = registry.entry(key(serial, device_ix)).or_insert_with(|| {
+        // Offsets the increment below, so the first open is no reconnect.
+        Arc::new(DeviceMetrics {
+            reconnects: AtomicU64::new(u64::MAX),
+            ..DeviceMetrics::default()
+        })
+    });
+    metrics.reconnects.fetch_add(1, Ordering::Relaxed);
+    metrics.clone()
+}
+
+fn key(serial: &str, device_ix: usize) -> String {
+    if serial.is_empty() {
+        format!("index:{}", device_ix)
+    } else {
+        serial.to_owned()
+    }
+}
+
+/// Returns the counters of the device with serial number `serial`, if it
+/// was ever opened
+pub fn device(serial: &str) -> Option<Arc<DeviceMetrics>> {
+    REGISTRY.lock().unwrap().get(serial).cloned()
+}
+
+/// Formats the counters of every device ever opened in the Prometheus text
+/// exposition format
+pub fn render() -> String {
+    let registry = REGISTRY.lock().unwrap();
+    let devices: Vec<_> = registry
+        .iter()
+        .map(|(key, metrics)| (labels(key), metrics.as_ref()))
+        .collect();
+    format_metrics(&devices)
+}
+
+/// Label set of a device, with its alias if it has one
+fn labels(key: &str) -> String {
+    match aliases::alias(key) {
+        Some(alias) => format!("device=\"{}\",alias=\"{}\"", escape(key), escape(&alias)),
+        None => format!("device=\"{}\"", escape(key)),
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+type Family = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&DeviceMetrics) -> u64,
+);
+
+const FAMILIES: [Family; 6] = [
+    (
+        "bytes_read_total",
+        "counter",
+        "Bytes received from the device",
+        DeviceMetrics::bytes_read,
+    ),
+    (
+        "bytes_written_total",
+        "counter",
+        "Bytes sent to the device",
+        DeviceMetrics::bytes_written,
+    ),
+    (
+        "errors_total",
+        "counter",
+        "Transfers that failed",
+        DeviceMetrics::errors,
+    ),
+    (
+        "timeouts_total",
+        "counter",
+        "Transfers that timed out",
+        DeviceMetrics::timeouts,
+    ),
+    (
+        "reconnects_total",
+        "counter",
+        "Times the device was opened again",
+        DeviceMetrics::reconnects,
+    ),
+    (
+        "rx_queue_bytes",
+        "gauge",
+        "Bytes in the receive queue when last checked",
+        DeviceMetrics::queue_depth,
+    ),
+];
+
+fn format_metrics(devices: &[(String, &DeviceMetrics)]) -> String {
+    let mut text = String::new();
+    for (name, kind, help, value) in FAMILIES.iter() {
+        let _ = writeln!(text, "# HELP silabs_usb_xpress_{} {}", name, help);
+        let _ = writeln!(text, "# TYPE silabs_usb_xpress_{} {}", name, kind);
+        for (labels, metrics) in devices {
+            let _ = writeln!(
+                text,
+                "silabs_usb_xpress_{}{{{}}} {}",
+                name,
+                labels,
+                value(metrics)
+            );
+        }
+    }
+    text
+}
+
+/// Serves [`render`] at `/metrics` on `addr` from a background thread
+#[cfg(feature = "prometheus")]
+pub fn serve<A: std::net::ToSocketAddrs>(addr: A) -> std::io::Result<std::thread::JoinHandle<()>> {
+    use tiny_http::{Header, Response};
+
+    let server = tiny_http::Server::http(addr).map_err(std::io::Error::other)?;
+    Ok(std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = if request.url() == "/metrics" {
+                let content_type =
+                    Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..])
+                        .unwrap();
+                Response::from_string(render()).with_header(content_type)
+            } else {
+                Response::from_string("not found").with_status_code(404)
+            };
+            if let Err(err) = request.respond(response) {
+                log::warn!("metrics: {}", err);
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn metrics_render_in_text_format() {
+        let metrics = DeviceMetrics::default();
+        metrics.add_read(12);
+        metrics.record_status(SI_READ_TIMED_OUT as c_int);
+        metrics.record_status(SI_DEVICE_IO_FAILED as c_int);
+        metrics.set_queue_depth(3);
+        let text = format_metrics(&[(labels("0001\"A"), &metrics)]);
+        assert!(text.contains("# TYPE silabs_usb_xpress_bytes_read_total counter\n"));
+        assert!(text.contains("silabs_usb_xpress_bytes_read_total{device=\"0001\\\"A\"} 12\n"));
+        assert!(text.contains("silabs_usb_xpress_timeouts_total{device=\"0001\\\"A\"} 1\n"));
+        assert!(text.contains("silabs_usb_xpress_errors_total{device=\"0001\\\"A\"} 1\n"));
+        assert!(text.contains("silabs_usb_xpress_rx_queue_bytes{device=\"0001\\\"A\"} 3\n"));
+
+        let first = register("metrics-test", 0);
+        let again = register("metrics-test", 0);
+        assert!(Arc::ptr_eq(&first, &again));
+        assert_eq!(first.reconnects(), 1);
+    }
+}
@@ -0,0 +1,160 @@
+//! Independent logical channels over one device
+//!
+//! Firmware that serves, say, a console and a data stream over a single
+//! pair of endpoints can tag every frame with a channel number. A [`Mux`]
+//! owns the device and hands out one [`Channel`] per number; each channel
+//! reads only the frames sent to it and writes frames tagged with its
+//! number, so the streams never mix.
+//!
+//! Every frame starts with a three byte header, the channel number followed
+//! by the payload length, see [`MuxDecoder`]. The frame format is defined in
+//! the `no_std` capable [`frame`](crate::frame) module, so that firmware can
+//! share it.
+//!
+//! ```rust, ignore
+//! use silabs_usb_xpress::{mux::Mux, transport::Transport};
+//!
+//! let mux = Mux::new(handle);
+//! let (mut console, mut data) = (mux.channel(0), mux.channel(1));
+//! console.write(b"status\n")?;
+//! let samples = data.read(4096)?;
+//! ```
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+pub use crate::frame::MuxDecoder;
+use crate::{
+    frame::{encode_mux, Decoder, MUX_HEADER_LEN},
+    transport::Transport,
+    SilabsUsbXpressError,
+};
+
+/// Bytes requested from the device per read
+const READ_CHUNK: usize = 4096;
+
+struct Shared<T> {
+    device: T,
+    received: Vec<u8>,
+    queues: BTreeMap<u8, VecDeque<u8>>,
+}
+
+/// A device carrying several logical channels
+pub struct Mux<T> {
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T: Transport> Mux<T> {
+    pub fn new(device: T) -> Self {
+        Mux {
+            shared: Arc::new(Mutex::new(Shared {
+                device,
+                received: Vec::new(),
+                queues: BTreeMap::new(),
+            })),
+        }
+    }
+
+    /// Returns a handle to channel `number`
+    ///
+    /// Any number of handles to the same channel may exist; they share the
+    /// data received on it.
+    pub fn channel(&self, number: u8) -> Channel<T> {
+        Channel {
+            number,
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+/// One logical channel of a [`Mux`]
+///
+/// Channels are transports themselves, and can be used from different
+/// threads; transfers on channels of the same device are serialized.
+pub struct Channel<T> {
+    number: u8,
+    shared: Arc<Mutex<Shared<T>>>,
+}
+
+impl<T> Channel<T> {
+    pub fn number(&self) -> u8 {
+        self.number
+    }
+}
+
+impl<T: Transport> Transport for Channel<T> {
+    /// Returns up to `max` bytes received on this channel
+    ///
+    /// If none are queued, reads from the device once, waiting at most its
+    /// read timeout, and queues what arrives for the channels it is for.
+    /// Returns no data if nothing for this channel arrived meanwhile.
+    fn read(&mut self, max: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        let mut shared = self.shared.lock().unwrap();
+        let shared = &mut *shared;
+        if shared
+            .queues
+            .get(&self.number)
+            .is_none_or(VecDeque::is_empty)
+        {
+            let data = shared.device.read(READ_CHUNK)?;
+            shared.received.extend_from_slice(&data);
+            while let Some((channel, payload)) = MuxDecoder.decode(&mut shared.received) {
+                shared.queues.entry(channel).or_default().extend(payload);
+            }
+        }
+        Ok(match shared.queues.get_mut(&self.number) {
+            Some(queue) => {
+                let n = max.min(queue.len());
+                queue.drain(..n).collect()
+            }
+            None => Vec::new(),
+        })
+    }
+
+    /// Writes `data` as frames of this channel
+    ///
+    /// Returns `data.len()` once all frames are written. Since a partly
+    /// written frame would corrupt every channel, a short write of the
+    /// frames is reported as `WriteTimeOut`.
+    fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+        let mut frames = Vec::with_capacity(data.len() + MUX_HEADER_LEN);
+        encode_mux(self.number, data, &mut frames);
+        let mut shared = self.shared.lock().unwrap();
+        let started = std::time::Instant::now();
+        let written = shared.device.write(&frames)?;
+        if written < frames.len() {
+            return Err(SilabsUsbXpressError::WriteTimeOut {
+                elapsed: started.elapsed(),
+                transferred: written,
+                remaining: frames.len() - written,
+                chunk: 0,
+            });
+        }
+        Ok(data.len())
+    }
+
+    /// Counts the frames of every channel the device has yet to send
+    fn pending_output(&mut self) -> Result<usize, SilabsUsbXpressError> {
+        self.shared.lock().unwrap().device.pending_output()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Loopback;
+
+    #[test]
+    fn channels_only_see_their_own_frames() {
+        let mux = Mux::new(Loopback::default());
+        let (mut console, mut data) = (mux.channel(0), mux.channel(1));
+        console.write(b"status").unwrap();
+        data.write(&[1, 2, 3]).unwrap();
+        console.write(b"?").unwrap();
+        assert_eq!(data.read(16).unwrap(), [1, 2, 3]);
+        assert_eq!(console.read(4).unwrap(), b"stat");
+        assert_eq!(console.read(16).unwrap(), b"us?");
+        assert!(data.read(16).unwrap().is_empty());
+    }
+}
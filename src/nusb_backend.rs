@@ -0,0 +1,1226 @@
+//! The SiUSBXp functions implemented on top of nusb
+//!
+//! Built with the `nusb-backend` feature in place of the bundled
+//! `SiUSBXp.c`. nusb talks to usbfs, IOKit or WinUSB directly, so neither
+//! libusb-compat nor libusb-1.0 has to be installed. The functions keep the
+//! signatures, status codes and receive buffering of the C library, like
+//! the `rust-backend` ones.
+//!
+//! Device numbers count every USB device, in the order nusb lists them, and
+//! paths are `bus/address` as in `001/004`. The kernel driver is read from
+//! sysfs on Linux and not reported elsewhere. nusb cannot reset a device in
+//! place, so `SI_ResetDevice` always reports it re-enumerated.
+#![allow(non_snake_case, clippy::missing_safety_doc)]
+
+use std::{
+    convert::TryFrom,
+    ffi::CStr,
+    os::raw::{c_char, c_int, c_void},
+    ptr, slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll, Wake, Waker},
+    thread::{self, Thread},
+    time::{Duration, Instant},
+};
+#[cfg(target_os = "linux")]
+use std::{
+    fs::File,
+    os::unix::{
+        fs::MetadataExt,
+        io::{AsRawFd, FromRawFd},
+    },
+};
+
+use nusb::{
+    transfer::{
+        Control, ControlType, EndpointType, Queue, Recipient, RequestBuffer, ResponseBuffer,
+        TransferError,
+    },
+    Device, DeviceInfo, Interface,
+};
+
+use crate::backend::{
+    cancel, copy_string, forget_cancel, is_si_device, millis, register_cancel, sliced, split_path,
+    status, Sliced, BUF_SIZE, CP210X_GET_PARTNUM, CP210X_PURGE, CP210X_PURGE_RX, CP210X_PURGE_TX,
+    CP210X_REQTYPE_DEVICE_TO_HOST, CP210X_REQTYPE_HOST_TO_DEVICE, CP210X_VENDOR_SPECIFIC,
+    DT_STRING, ENDPOINT_IN, GET_DESCRIPTOR, LANG_US_ENGLISH, MAGIC, RX_TIMEOUT, TX_TIMEOUT,
+};
+pub use crate::backend::{SI_AddDeviceId, SI_ClearDeviceIds, SI_SetTimeouts};
+
+mod c {
+    include!("bindings.rs");
+}
+
+// The constants of the C library; its functions and handle type are
+// shadowed by the ones below and the shared ones of `backend`.
+pub use c::*;
+
+/// The devices found by the last enumeration, by device number
+static DEVICES: Mutex<Vec<DeviceInfo>> = Mutex::new(Vec::new());
+
+/// Standard descriptor type of the device descriptor
+const DT_DEVICE: u8 = 0x01;
+/// Offset of the product string index in the device descriptor
+const DEVICE_IPRODUCT: usize = 15;
+
+pub struct SiPrivate {
+    magic: c_int,
+    info: DeviceInfo,
+    interface: Interface,
+    /// The usbfs descriptor `interface` was opened on, -1 if unknown
+    fd: c_int,
+    number: u8,
+    ep_out: u8,
+    /// Reads are whole packets of this size, anything shorter would overflow
+    packet_size: usize,
+    /// Whether a kernel driver was detached at open and is reattached at close
+    reattach: bool,
+    /// Used by `SI_Read`/`SI_Write` and the control requests of this handle
+    rx_timeout: c_int,
+    tx_timeout: c_int,
+    /// Set by `SI_CancelIo`, possibly from another thread
+    cancelled: Arc<AtomicBool>,
+    /// Data received but not read yet, at most `BUF_SIZE` bytes
+    buffer: Vec<u8>,
+    /// The bulk IN transfer, kept pending across reads that time out
+    rx: Queue<RequestBuffer>,
+    tx: Queue<Vec<u8>>,
+}
+
+impl SiPrivate {
+    /// Receives into the buffer, returning `None` if nothing completed
+    /// within `timeout` and otherwise the status and the number of bytes
+    /// received
+    fn fill_buffer(&mut self, timeout: c_int) -> Option<(Result<(), TransferError>, usize)> {
+        if self.rx.pending() == 0 {
+            let len = (BUF_SIZE - self.buffer.len()) / self.packet_size * self.packet_size;
+            if len == 0 {
+                return Some((Ok(()), 0));
+            }
+            self.rx.submit(RequestBuffer::new(len));
+        }
+        let rx = &mut self.rx;
+        let completion = wait(|cx| rx.poll_next(cx), timeout)?;
+        self.buffer.extend_from_slice(&completion.data);
+        Some((completion.status, completion.data.len()))
+    }
+
+    fn take_buffer(&mut self, out: &mut [u8]) -> usize {
+        let n = out.len().min(self.buffer.len());
+        out[..n].copy_from_slice(&self.buffer[..n]);
+        self.buffer.drain(..n);
+        n
+    }
+
+    fn control_in(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &mut [u8],
+        timeout: c_int,
+    ) -> Result<usize, TransferError> {
+        let control = control(request_type, request, value, index).ok_or(TransferError::Fault)?;
+        self.interface
+            .control_in_blocking(control, data, millis(timeout))
+    }
+
+    fn control_out(
+        &self,
+        request_type: u8,
+        request: u8,
+        value: u16,
+        index: u16,
+        data: &[u8],
+        timeout: c_int,
+    ) -> Result<usize, TransferError> {
+        let control = control(request_type, request, value, index).ok_or(TransferError::Fault)?;
+        self.interface
+            .control_out_blocking(control, data, millis(timeout))
+    }
+
+    /// Reads the device descriptor, for the indexes of its strings
+    fn device_descriptor(&self) -> Option<[u8; 18]> {
+        let mut descriptor = [0; 18];
+        let read = self
+            .control_in(
+                ENDPOINT_IN,
+                GET_DESCRIPTOR,
+                u16::from(DT_DEVICE) << 8,
+                0,
+                &mut descriptor,
+                self.tx_timeout,
+            )
+            .ok()?;
+        (read == descriptor.len() && descriptor[1] == DT_DEVICE).then_some(descriptor)
+    }
+}
+
+/// Wakes the thread blocked in [`wait`]
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Polls `poll` until it is ready or `timeout` milliseconds have passed,
+/// forever if `timeout` is zero as for libusb
+fn wait<T, F: FnMut(&mut Context) -> Poll<T>>(mut poll: F, timeout: c_int) -> Option<T> {
+    let waker = Waker::from(Arc::new(Unpark(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    let deadline = if timeout > 0 {
+        Some(Instant::now() + millis(timeout))
+    } else {
+        None
+    };
+    loop {
+        if let Poll::Ready(value) = poll(&mut cx) {
+            return Some(value);
+        }
+        match deadline {
+            Some(deadline) => {
+                let left = deadline.saturating_duration_since(Instant::now());
+                if left == Duration::ZERO {
+                    return None;
+                }
+                thread::park_timeout(left);
+            }
+            None => thread::park(),
+        }
+    }
+}
+
+/// Splits a libusb style `bmRequestType` into nusb's setup fields, `None`
+/// for the reserved type and recipients
+fn control(request_type: u8, request: u8, value: u16, index: u16) -> Option<Control> {
+    let control_type = match request_type >> 5 & 0x03 {
+        0 => ControlType::Standard,
+        1 => ControlType::Class,
+        2 => ControlType::Vendor,
+        _ => return None,
+    };
+    let recipient = match request_type & 0x1F {
+        0 => Recipient::Device,
+        1 => Recipient::Interface,
+        2 => Recipient::Endpoint,
+        3 => Recipient::Other,
+        _ => return None,
+    };
+    Some(Control {
+        control_type,
+        recipient,
+        request,
+        value,
+        index,
+    })
+}
+
+fn enumerate() -> Vec<DeviceInfo> {
+    match nusb::list_devices() {
+        Ok(devices) => devices.collect(),
+        Err(err) => {
+            log::error!("unable to list USB devices: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+fn device(device_num: c_int) -> Option<DeviceInfo> {
+    let devices = DEVICES.lock().unwrap();
+    usize::try_from(device_num)
+        .ok()
+        .and_then(|ix| devices.get(ix).cloned())
+}
+
+pub(crate) fn device_path(info: &DeviceInfo) -> String {
+    format!("{:03}/{:03}", info.bus_number(), info.device_address())
+}
+
+/// The kernel driver bound to the `interface` of a device, from sysfs
+#[cfg(target_os = "linux")]
+fn driver(info: &DeviceInfo, interface: u8) -> Option<String> {
+    let name = info.sysfs_path().file_name()?.to_str()?;
+    let link = info
+        .sysfs_path()
+        .join(format!("{}:1.{}", name, interface))
+        .join("driver");
+    let target = std::fs::read_link(link).ok()?;
+    Some(target.file_name()?.to_str()?.to_owned())
+}
+
+/// Other platforms have no sysfs to ask, and no driver to detach either
+#[cfg(not(target_os = "linux"))]
+fn driver(_info: &DeviceInfo, _interface: u8) -> Option<String> {
+    None
+}
+
+/// Address, type and packet size of the endpoints of an interface
+type Endpoints = Vec<(u8, EndpointType, usize)>;
+
+/// Number and endpoints of the `nth` interface of the active configuration
+fn nth_interface(device: &Device, nth: usize) -> Option<(u8, Endpoints)> {
+    let config = device.active_configuration().ok()?;
+    let interface = config.interfaces().nth(nth)?;
+    let setting = interface.alt_settings().next()?;
+    let endpoints = setting
+        .endpoints()
+        .map(|endpoint| {
+            (
+                endpoint.address(),
+                endpoint.transfer_type(),
+                endpoint.max_packet_size(),
+            )
+        })
+        .collect();
+    Some((setting.interface_number(), endpoints))
+}
+
+unsafe fn valid<'a>(handle: *mut SiPrivate) -> Option<&'a mut SiPrivate> {
+    handle.as_mut().filter(|handle| handle.magic == MAGIC)
+}
+
+pub unsafe fn SI_GetNumDevices(num_devices: *mut c_int) -> c_int {
+    if num_devices.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let mut devices = DEVICES.lock().unwrap();
+    *devices = enumerate();
+    *num_devices = devices.len() as c_int;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetProductString(
+    device_num: c_int,
+    device_string: *mut c_char,
+    flags: c_int,
+) -> c_int {
+    if device_string.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    copy_string(device_string, "");
+    let info = match device(device_num) {
+        Some(info) => info,
+        None => return status(SI_DEVICE_NOT_FOUND),
+    };
+    let (vid, pid) = (info.vendor_id(), info.product_id());
+    // nusb reads the strings along with the device list, without opening it
+    let string = match flags as u32 {
+        SI_RETURN_SERIAL_NUMBER => info.serial_number(),
+        SI_RETURN_DESCRIPTION => info.product_string(),
+        SI_RETURN_LINK_NAME => info.manufacturer_string(),
+        SI_RETURN_VID => {
+            copy_string(device_string, &format!("{:x}", vid));
+            return status(SI_SUCCESS);
+        }
+        SI_RETURN_PID => {
+            copy_string(device_string, &format!("{:x}", pid));
+            return status(SI_SUCCESS);
+        }
+        _ => return status(SI_INVALID_PARAMETER),
+    };
+    if is_si_device(vid, pid) {
+        copy_string(device_string, string.unwrap_or_default());
+    }
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetDeviceNumByPath(path: *const c_char, device_num: *mut c_int) -> c_int {
+    if path.is_null() || device_num.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let path = CStr::from_ptr(path).to_string_lossy();
+    let (bus, address) = match split_path(&path) {
+        Some(components) => components,
+        None => return status(SI_INVALID_PARAMETER),
+    };
+    let (bus, address) = match (bus.parse::<u8>(), address.parse::<u8>()) {
+        (Ok(bus), Ok(address)) => (bus, address),
+        _ => return status(SI_DEVICE_NOT_FOUND),
+    };
+    let mut devices = DEVICES.lock().unwrap();
+    *devices = enumerate();
+    match devices
+        .iter()
+        .position(|info| info.bus_number() == bus && info.device_address() == address)
+    {
+        Some(ix) => {
+            *device_num = ix as c_int;
+            status(SI_SUCCESS)
+        }
+        None => status(SI_DEVICE_NOT_FOUND),
+    }
+}
+
+/// Opens the device of `info`, on Linux through its usbfs node so that the
+/// descriptor nusb uses is known
+#[cfg(target_os = "linux")]
+fn open_node(info: &DeviceInfo) -> Result<(Device, c_int), nusb::Error> {
+    let node = File::options().read(true).write(true).open(format!(
+        "/dev/bus/usb/{:03}/{:03}",
+        info.bus_number(),
+        info.device_address()
+    ))?;
+    let fd = node.as_raw_fd();
+    Ok((Device::from_fd(node.into())?, fd))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_node(info: &DeviceInfo) -> Result<(Device, c_int), nusb::Error> {
+    Ok((info.open()?, -1))
+}
+
+fn open(
+    info: &DeviceInfo,
+    nth: usize,
+    flags: u32,
+    timeouts: (c_int, c_int),
+) -> Result<SiPrivate, u32> {
+    let (device, fd) = open_node(info).map_err(|err| {
+        log::error!("unable to open USB device: {}", err);
+        SI_SYSTEM_ERROR_CODE
+    })?;
+    claim(info, device, fd, nth, flags, timeouts)
+}
+
+/// Claims the `nth` interface of `device`, opened on the descriptor `fd`,
+/// and enables its UART
+fn claim(
+    info: &DeviceInfo,
+    device: Device,
+    fd: c_int,
+    nth: usize,
+    flags: u32,
+    (rx_timeout, tx_timeout): (c_int, c_int),
+) -> Result<SiPrivate, u32> {
+    let (number, endpoints) = nth_interface(&device, nth).ok_or(SI_INVALID_PARAMETER)?;
+    let bulk = |direction: u8| {
+        endpoints.iter().find(|(address, kind, _)| {
+            *kind == EndpointType::Bulk && address & ENDPOINT_IN == direction
+        })
+    };
+    let (ep_in, ep_out, packet_size) = match (bulk(ENDPOINT_IN), bulk(0)) {
+        (Some(&(ep_in, _, packet_size)), Some(&(ep_out, _, _))) if packet_size > 0 => {
+            (ep_in, ep_out, packet_size)
+        }
+        _ => {
+            log::error!("unable to identify bulk in/out endpoints");
+            return Err(SI_SYSTEM_ERROR_CODE);
+        }
+    };
+    // Detach a kernel driver bound to the interface, like cp210x on Linux;
+    // platforms without sysfs have none to detach
+    let mut detached = false;
+    if flags & SI_OPEN_DETACH_KERNEL_DRIVER != 0 && driver(info, number).is_some() {
+        if let Err(err) = device.detach_kernel_driver(number) {
+            log::error!("unable to detach kernel driver: {}", err);
+            return Err(SI_DRIVER_DETACH_FAILED);
+        }
+        detached = true;
+    }
+    let reattach = detached && flags & SI_OPEN_REATTACH_KERNEL_DRIVER != 0;
+    let interface = device.claim_interface(number).map_err(|err| {
+        log::error!(
+            "unable to claim interface: {}. Ensure the device is not claimed by a kernel driver \
+             and that you may access it.",
+            err
+        );
+        if reattach {
+            let _ = device.attach_kernel_driver(number);
+        }
+        SI_DEVICE_IO_FAILED
+    })?;
+
+    // Enable the UART and clear both endpoints before any transfer is queued
+    let timeout = millis(tx_timeout);
+    let enable = |value: u16, request: u8| {
+        let control = Control {
+            control_type: ControlType::Vendor,
+            recipient: Recipient::Device,
+            request,
+            value,
+            index: u16::from(number),
+        };
+        let _ = interface.control_out_blocking(control, &[], timeout);
+    };
+    enable(0xFFFF, 0x00);
+    let _ = interface.clear_halt(ep_in);
+    let _ = interface.clear_halt(ep_out);
+    enable(0x0002, 0x02);
+
+    let mut handle = SiPrivate {
+        magic: MAGIC,
+        info: info.clone(),
+        rx: interface.bulk_in_queue(ep_in),
+        tx: interface.bulk_out_queue(ep_out),
+        interface,
+        fd,
+        number,
+        ep_out,
+        packet_size,
+        reattach,
+        rx_timeout,
+        tx_timeout,
+        cancelled: Arc::new(AtomicBool::new(false)),
+        buffer: Vec::with_capacity(BUF_SIZE),
+    };
+    handle.fill_buffer(100);
+    Ok(handle)
+}
+
+pub unsafe fn SI_Open(device_num: c_int, p_handle: *mut *mut SiPrivate) -> c_int {
+    SI_OpenInterface(device_num, 0, p_handle)
+}
+
+pub unsafe fn SI_OpenInterface(
+    device_num: c_int,
+    interface: c_int,
+    p_handle: *mut *mut SiPrivate,
+) -> c_int {
+    SI_OpenEx(
+        device_num,
+        interface,
+        0,
+        RX_TIMEOUT.load(Ordering::Relaxed),
+        TX_TIMEOUT.load(Ordering::Relaxed),
+        p_handle,
+    )
+}
+
+pub unsafe fn SI_OpenEx(
+    device_num: c_int,
+    interface: c_int,
+    flags: c_int,
+    read_timeout: c_int,
+    write_timeout: c_int,
+    p_handle: *mut *mut SiPrivate,
+) -> c_int {
+    let nth = match usize::try_from(interface) {
+        Ok(nth) if !p_handle.is_null() && read_timeout >= 0 && write_timeout >= 0 => nth,
+        _ => return status(SI_INVALID_PARAMETER),
+    };
+    let opened = device(device_num)
+        .ok_or(SI_SYSTEM_ERROR_CODE)
+        .and_then(|info| open(&info, nth, flags as u32, (read_timeout, write_timeout)));
+    hand_out(opened, p_handle)
+}
+
+/// Opens interface `interface` of the device whose usbfs node is open as
+/// `fd`, which the handle takes over
+///
+/// `fd` is closed again if opening fails. The device still has to be listed
+/// in sysfs, where its strings and kernel driver are read from.
+#[cfg(target_os = "linux")]
+pub unsafe fn SI_OpenFd(
+    fd: c_int,
+    interface: c_int,
+    flags: c_int,
+    read_timeout: c_int,
+    write_timeout: c_int,
+    p_handle: *mut *mut SiPrivate,
+) -> c_int {
+    if fd < 0 {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let node = File::from_raw_fd(fd);
+    let nth = match usize::try_from(interface) {
+        Ok(nth) if !p_handle.is_null() && read_timeout >= 0 && write_timeout >= 0 => nth,
+        _ => return status(SI_INVALID_PARAMETER),
+    };
+    let info = match node_info(&node) {
+        Some(info) => info,
+        None => return status(SI_DEVICE_NOT_FOUND),
+    };
+    let device = match Device::from_fd(node.into()) {
+        Ok(device) => device,
+        Err(err) => {
+            log::error!("unable to open USB device: {}", err);
+            return status(SI_SYSTEM_ERROR_CODE);
+        }
+    };
+    let timeouts = (read_timeout, write_timeout);
+    hand_out(
+        claim(&info, device, fd, nth, flags as u32, timeouts),
+        p_handle,
+    )
+}
+
+/// Finds the device whose usbfs node `node` is open on
+#[cfg(target_os = "linux")]
+fn node_info(node: &File) -> Option<DeviceInfo> {
+    /// Major number of the usbfs device nodes
+    const USB_DEVICE_MAJOR: u64 = 189;
+    let rdev = node.metadata().ok()?.rdev();
+    let major = ((rdev >> 32) & 0xffff_f000) | ((rdev >> 8) & 0xfff);
+    let minor = ((rdev >> 12) & 0x000f_ff00) | (rdev & 0xff);
+    if major != USB_DEVICE_MAJOR {
+        return None;
+    }
+    // Address `a` on bus `b` is minor `(b - 1) * 128 + a - 1`
+    let (bus, address) = (minor / 128 + 1, minor % 128 + 1);
+    nusb::list_devices().ok()?.find(|info| {
+        u64::from(info.bus_number()) == bus && u64::from(info.device_address()) == address
+    })
+}
+
+/// Stores the handle `opened` in `p_handle`, or returns why opening failed
+unsafe fn hand_out(opened: Result<SiPrivate, u32>, p_handle: *mut *mut SiPrivate) -> c_int {
+    match opened {
+        Ok(handle) => {
+            let cancelled = Arc::clone(&handle.cancelled);
+            *p_handle = Box::into_raw(Box::new(handle));
+            register_cancel(*p_handle as usize, cancelled);
+            status(SI_SUCCESS)
+        }
+        Err(code) => status(code),
+    }
+}
+
+pub unsafe fn SI_GetNumInterfaces(device_num: c_int, num_interfaces: *mut c_int) -> c_int {
+    if num_interfaces.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let info = match device(device_num) {
+        Some(info) => info,
+        None => return status(SI_DEVICE_NOT_FOUND),
+    };
+    *num_interfaces = info.interfaces().count() as c_int;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_Close(handle: *mut SiPrivate) -> c_int {
+    if valid(handle).is_none() {
+        return status(SI_INVALID_HANDLE);
+    }
+    forget_cancel(handle as usize);
+    let mut handle = Box::from_raw(handle);
+    let index = u16::from(handle.number);
+    let _ = handle.control_out(0x40, 0x02, 0x0004, index, &[], handle.tx_timeout);
+    handle.magic = 0;
+    let SiPrivate {
+        info,
+        interface,
+        number,
+        reattach,
+        rx,
+        tx,
+        ..
+    } = *handle;
+    // Dropping the queues cancels their transfers, dropping the last of the
+    // interface releases it
+    drop((rx, tx));
+    if reattach {
+        match info.open() {
+            Ok(device) => {
+                drop(interface);
+                if let Err(err) = device.attach_kernel_driver(number) {
+                    log::warn!("unable to reattach kernel driver: {}", err);
+                }
+            }
+            Err(err) => log::warn!("unable to reattach kernel driver: {}", err),
+        }
+    }
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_Read(
+    handle: *mut SiPrivate,
+    buffer: *mut c_char,
+    bytes_to_read: c_int,
+    bytes_returned: *mut c_int,
+    _o: *mut c_void,
+) -> c_int {
+    let timeout = match valid(handle) {
+        Some(handle) => handle.rx_timeout,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    SI_ReadWithTimeout(handle, buffer, bytes_to_read, bytes_returned, timeout)
+}
+
+pub unsafe fn SI_Write(
+    handle: *mut SiPrivate,
+    buffer: *mut c_char,
+    bytes_to_write: c_int,
+    bytes_written: *mut c_int,
+    _o: *mut c_void,
+) -> c_int {
+    let timeout = match valid(handle) {
+        Some(handle) => handle.tx_timeout,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    SI_WriteWithTimeout(handle, buffer, bytes_to_write, bytes_written, timeout)
+}
+
+pub unsafe fn SI_ReadWithTimeout(
+    handle: *mut SiPrivate,
+    buffer: *mut c_char,
+    bytes_to_read: c_int,
+    bytes_returned: *mut c_int,
+    timeout: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if buffer.is_null() || bytes_returned.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let buffer = slice::from_raw_parts_mut(buffer as *mut u8, bytes_to_read.max(0) as usize);
+    if handle.buffer.len() < buffer.len() {
+        // The transfer stays pending after a timeout or cancellation, and
+        // what it receives is buffered for the next read
+        let cancelled = Arc::clone(&handle.cancelled);
+        let ended = sliced(timeout, &cancelled, |slice| {
+            handle.fill_buffer(slice).is_some()
+        });
+        if ended == Sliced::Cancelled {
+            *bytes_returned = 0;
+            return status(SI_IO_CANCELLED);
+        }
+    }
+    let read = handle.take_buffer(buffer);
+    *bytes_returned = read as c_int;
+    if read > 0 {
+        status(SI_SUCCESS)
+    } else {
+        status(SI_READ_TIMED_OUT)
+    }
+}
+
+pub unsafe fn SI_WriteWithTimeout(
+    handle: *mut SiPrivate,
+    buffer: *mut c_char,
+    bytes_to_write: c_int,
+    bytes_written: *mut c_int,
+    timeout: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if buffer.is_null() || bytes_written.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let buffer = slice::from_raw_parts(buffer as *const u8, bytes_to_write.max(0) as usize);
+
+    // Never let the opportunistic receive eat more than the caller's budget
+    let fill_timeout = if timeout > 0 && timeout < 100 {
+        timeout
+    } else {
+        100
+    };
+    handle.fill_buffer(fill_timeout);
+    let (mut written, mut failed) = (0, false);
+    let ended = if buffer.is_empty() {
+        Sliced::Done
+    } else {
+        let cancelled = Arc::clone(&handle.cancelled);
+        let tx = &mut handle.tx;
+        let mut sent = |completion: nusb::transfer::Completion<ResponseBuffer>| {
+            written = completion.data.actual_length();
+            failed = completion.status.is_err();
+        };
+        tx.submit(buffer.to_vec());
+        let ended = sliced(timeout, &cancelled, |slice| {
+            wait(|cx| tx.poll_next(cx), slice).map(&mut sent).is_some()
+        });
+        if ended != Sliced::Done {
+            // A cancelled transfer completes at once, with what it sent
+            tx.cancel_all();
+            if let Some(completion) = wait(|cx| tx.poll_next(cx), 0) {
+                sent(completion);
+                failed = false;
+            }
+        }
+        ended
+    };
+    handle.fill_buffer(fill_timeout);
+
+    *bytes_written = written as c_int;
+    match ended {
+        _ if failed => status(SI_WRITE_ERROR),
+        Sliced::Done => status(SI_SUCCESS),
+        Sliced::TimedOut => status(SI_WRITE_TIMED_OUT),
+        Sliced::Cancelled => status(SI_IO_CANCELLED),
+    }
+}
+
+pub unsafe fn SI_CancelIo(handle: *mut SiPrivate) -> c_int {
+    // Picked up by the pending read or write, or else the next one
+    cancel(handle as usize)
+}
+
+pub unsafe fn SI_ResetDevice(handle: *mut SiPrivate) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    // nusb has the device re-enumerate, it has to be opened anew
+    let device = match handle.info.open() {
+        Ok(device) => device,
+        Err(err) => {
+            log::error!("unable to reset USB device: {}", err);
+            return status(SI_DEVICE_IO_FAILED);
+        }
+    };
+    match device.reset() {
+        Ok(()) => {
+            handle.buffer.clear();
+            status(SI_DEVICE_REENUMERATED)
+        }
+        Err(err) => {
+            log::error!("unable to reset USB device: {}", err);
+            status(SI_DEVICE_IO_FAILED)
+        }
+    }
+}
+
+pub unsafe fn SI_GetDeviceStatus(
+    handle: *mut SiPrivate,
+    device_status: *mut c_int,
+    timeout: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if device_status.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    // Standard GET_STATUS request, answered by every USB device
+    let mut data = [0; 2];
+    match handle.control_in(ENDPOINT_IN, 0x00, 0, 0, &mut data, timeout) {
+        Ok(_) => {
+            *device_status = c_int::from(u16::from_le_bytes(data));
+            status(SI_SUCCESS)
+        }
+        Err(_) => status(SI_DEVICE_IO_FAILED),
+    }
+}
+
+pub unsafe fn SI_GetDeviceDetails(
+    device_num: c_int,
+    path: *mut c_char,
+    driver: *mut c_char,
+    capabilities: *mut c_int,
+) -> c_int {
+    if path.is_null() || driver.is_null() || capabilities.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let info = match device(device_num) {
+        Some(info) => info,
+        None => return status(SI_DEVICE_NOT_FOUND),
+    };
+    copy_string(path, &device_path(&info));
+    let bound = self::driver(&info, 0);
+    copy_string(driver, bound.as_deref().unwrap_or_default());
+
+    // The endpoints are only described by an open device
+    *capabilities = 0;
+    let endpoints = info
+        .open()
+        .ok()
+        .and_then(|device| nth_interface(&device, 0))
+        .map(|(_, endpoints)| endpoints)
+        .unwrap_or_default();
+    for (address, kind, _) in endpoints {
+        let input = address & ENDPOINT_IN != 0;
+        *capabilities |= match kind {
+            EndpointType::Bulk if input => SI_CAP_BULK_IN,
+            EndpointType::Bulk => SI_CAP_BULK_OUT,
+            EndpointType::Interrupt if input => SI_CAP_INTERRUPT_IN,
+            _ => 0,
+        } as c_int;
+    }
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetDeviceInfo(
+    handle: *mut SiPrivate,
+    serial: *mut c_char,
+    description: *mut c_char,
+    vid: *mut c_int,
+    pid: *mut c_int,
+    path: *mut c_char,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if serial.is_null() || description.is_null() || vid.is_null() || pid.is_null() || path.is_null()
+    {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let info = &handle.info;
+    *vid = c_int::from(info.vendor_id());
+    *pid = c_int::from(info.product_id());
+    // Strings are optional, a device without them gets empty ones
+    copy_string(serial, info.serial_number().unwrap_or_default());
+    copy_string(description, info.product_string().unwrap_or_default());
+    copy_string(path, &device_path(info));
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetDeviceProductString(
+    handle: *mut SiPrivate,
+    product: *mut c_void,
+    length: *mut u8,
+    convert_to_ascii: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if product.is_null() || length.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    // A device without a product string gets an empty one
+    *length = 0;
+    let index = match handle.device_descriptor() {
+        Some(descriptor) if descriptor[DEVICE_IPRODUCT] != 0 => descriptor[DEVICE_IPRODUCT],
+        Some(_) => return status(SI_SUCCESS),
+        None => return status(SI_DEVICE_IO_FAILED),
+    };
+    // The raw string descriptor in US English, UTF-16LE after a 2 byte header
+    let mut desc = [0; SI_MAX_DEVICE_STRLEN as usize];
+    let read = match handle.control_in(
+        ENDPOINT_IN,
+        GET_DESCRIPTOR,
+        u16::from(DT_STRING) << 8 | u16::from(index),
+        LANG_US_ENGLISH,
+        &mut desc,
+        handle.tx_timeout,
+    ) {
+        Ok(read) if read >= 2 && desc[1] == DT_STRING => read,
+        _ => return status(SI_DEVICE_IO_FAILED),
+    };
+    let string = &desc[2..usize::from(desc[0]).clamp(2, read)];
+
+    // Neither form is null terminated
+    let out = product as *mut u8;
+    if convert_to_ascii != 0 {
+        for (i, unit) in string.chunks_exact(2).enumerate() {
+            *out.add(i) = if unit[1] != 0 { b'?' } else { unit[0] };
+        }
+        *length = (string.len() / 2) as u8;
+    } else {
+        ptr::copy_nonoverlapping(string.as_ptr(), out, string.len());
+        *length = string.len() as u8;
+    }
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetInterfaceNumber(handle: *mut SiPrivate, interface: *mut u8) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if interface.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    *interface = handle.number;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetLibusbHandle(handle: *mut SiPrivate, udev: *mut *mut c_void) -> c_int {
+    match valid(handle) {
+        // There is no libusb underneath
+        Some(_) if !udev.is_null() => {
+            *udev = ptr::null_mut();
+            status(SI_FUNCTION_NOT_SUPPORTED)
+        }
+        Some(_) => status(SI_INVALID_PARAMETER),
+        None => status(SI_INVALID_HANDLE),
+    }
+}
+
+pub unsafe fn SI_GetDeviceFd(handle: *mut SiPrivate, fd: *mut c_int) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if fd.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    *fd = handle.fd;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetPartNumber(handle: *mut SiPrivate, part_num: *mut u8) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if part_num.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let mut data = [0; 1];
+    match handle.control_in(
+        CP210X_REQTYPE_DEVICE_TO_HOST,
+        CP210X_VENDOR_SPECIFIC,
+        CP210X_GET_PARTNUM,
+        u16::from(handle.number),
+        &mut data,
+        handle.tx_timeout,
+    ) {
+        Ok(1) => {
+            *part_num = data[0];
+            status(SI_SUCCESS)
+        }
+        _ => status(SI_DEVICE_IO_FAILED),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn SI_ControlTransfer(
+    handle: *mut SiPrivate,
+    request_type: c_int,
+    request: c_int,
+    value: c_int,
+    index: c_int,
+    data: *mut c_char,
+    length: c_int,
+    bytes_transferred: *mut c_int,
+    timeout: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if bytes_transferred.is_null() || (length > 0 && data.is_null()) {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let (request_type, request, value) = (request_type as u8, request as u8, value as u16);
+    if control(request_type, request, value, 0).is_none() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    // A negative index addresses the claimed interface
+    let index = if index < 0 {
+        u16::from(handle.number)
+    } else {
+        index as u16
+    };
+    let data: &mut [u8] = if length > 0 {
+        slice::from_raw_parts_mut(data as *mut u8, length as usize)
+    } else {
+        &mut []
+    };
+    let transferred = if request_type & ENDPOINT_IN != 0 {
+        handle.control_in(request_type, request, value, index, data, timeout)
+    } else {
+        handle.control_out(request_type, request, value, index, data, timeout)
+    };
+    match transferred {
+        Ok(transferred) => {
+            *bytes_transferred = transferred as c_int;
+            status(SI_SUCCESS)
+        }
+        Err(_) => {
+            *bytes_transferred = 0;
+            status(SI_DEVICE_IO_FAILED)
+        }
+    }
+}
+
+pub unsafe fn SI_DeviceIOControl(
+    handle: *mut SiPrivate,
+    _io_control_code: c_int,
+    _in_buffer: *mut c_char,
+    _bytes_to_read: c_int,
+    _out_buffer: *mut c_char,
+    _bytes_to_write: c_int,
+) -> c_int {
+    match valid(handle) {
+        Some(_) => status(SI_SUCCESS),
+        None => status(SI_INVALID_HANDLE),
+    }
+}
+
+pub unsafe fn SI_FlushBuffers(
+    handle: *mut SiPrivate,
+    flush_transmit: c_char,
+    flush_receive: c_char,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    let mut purge = 0;
+    if flush_receive != 0 {
+        handle.buffer.clear();
+        purge |= CP210X_PURGE_RX;
+    }
+    if flush_transmit != 0 {
+        purge |= CP210X_PURGE_TX;
+    }
+    if purge == 0 {
+        return status(SI_SUCCESS);
+    }
+    // Clear the queues of the device's UART as well
+    match handle.control_out(
+        CP210X_REQTYPE_HOST_TO_DEVICE,
+        CP210X_PURGE,
+        purge,
+        u16::from(handle.number),
+        &[],
+        handle.tx_timeout,
+    ) {
+        Ok(_) => status(SI_SUCCESS),
+        Err(_) => status(SI_DEVICE_IO_FAILED),
+    }
+}
+
+pub unsafe fn SI_SetHandleTimeouts(
+    handle: *mut SiPrivate,
+    read_timeout: c_int,
+    write_timeout: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if read_timeout < 0 || write_timeout < 0 {
+        return status(SI_INVALID_PARAMETER);
+    }
+    handle.rx_timeout = read_timeout;
+    handle.tx_timeout = write_timeout;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_FillRXQueue(
+    handle: *mut SiPrivate,
+    timeout: c_int,
+    num_bytes_in_queue: *mut c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if num_bytes_in_queue.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    // Pull whatever the device has ready into the buffer, waiting at most
+    // `timeout`
+    handle.fill_buffer(timeout);
+    *num_bytes_in_queue = handle.buffer.len() as c_int;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_CheckRXQueue(
+    handle: *mut SiPrivate,
+    num_bytes_in_queue: *mut c_int,
+    queue_status: *mut c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if num_bytes_in_queue.is_null() || queue_status.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    *num_bytes_in_queue = handle.buffer.len() as c_int;
+    *queue_status = (SI_RX_NO_OVERRUN
+        | if handle.buffer.is_empty() {
+            SI_RX_EMPTY
+        } else {
+            SI_RX_READY
+        }) as c_int;
+    status(SI_SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_types_split_into_setup_fields() {
+        let vendor = control(CP210X_REQTYPE_DEVICE_TO_HOST, 0xFF, 0x370B, 0).unwrap();
+        assert_eq!(vendor.control_type, ControlType::Vendor);
+        assert_eq!(vendor.recipient, Recipient::Interface);
+        let status = control(ENDPOINT_IN, 0x00, 0, 0).unwrap();
+        assert_eq!(status.control_type, ControlType::Standard);
+        assert_eq!(status.recipient, Recipient::Device);
+        // Reserved type and recipient
+        assert!(control(0x60, 0, 0, 0).is_none());
+        assert!(control(0x04, 0, 0, 0).is_none());
+    }
+
+    #[test]
+    fn waits_end_at_the_timeout_or_when_woken() {
+        let started = Instant::now();
+        assert_eq!(wait(|_| Poll::<()>::Pending, 50), None);
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        // A completion on another thread wakes the waiting one
+        let done = Arc::new(AtomicBool::new(false));
+        let mut waker = None;
+        let started = Instant::now();
+        let woken = wait(
+            |cx| {
+                if done.load(Ordering::SeqCst) {
+                    return Poll::Ready(());
+                }
+                if waker.is_none() {
+                    let (done, cx_waker) = (Arc::clone(&done), cx.waker().clone());
+                    waker = Some(thread::spawn(move || {
+                        thread::sleep(Duration::from_millis(20));
+                        done.store(true, Ordering::SeqCst);
+                        cx_waker.wake();
+                    }));
+                }
+                Poll::Pending
+            },
+            0,
+        );
+        assert_eq!(woken, Some(()));
+        assert!(started.elapsed() < Duration::from_secs(1));
+        waker.unwrap().join().unwrap();
+    }
+
+    #[test]
+    fn invalid_handles_and_parameters_are_rejected() {
+        let mut n = 0;
+        let mut buffer = [0 as c_char; 4];
+        unsafe {
+            assert_eq!(
+                SI_ReadWithTimeout(ptr::null_mut(), buffer.as_mut_ptr(), 4, &mut n, 0),
+                status(SI_INVALID_HANDLE)
+            );
+            assert_eq!(SI_Close(ptr::null_mut()), status(SI_INVALID_HANDLE));
+            assert_eq!(SI_CancelIo(ptr::null_mut()), status(SI_INVALID_HANDLE));
+            assert_eq!(SI_ResetDevice(ptr::null_mut()), status(SI_INVALID_HANDLE));
+            assert_eq!(
+                SI_GetDeviceFd(ptr::null_mut(), &mut n),
+                status(SI_INVALID_HANDLE)
+            );
+            assert_eq!(SI_Open(0, ptr::null_mut()), status(SI_INVALID_PARAMETER));
+            #[cfg(target_os = "linux")]
+            assert_eq!(
+                SI_OpenFd(-1, 0, 0, 1000, 1000, &mut ptr::null_mut()),
+                status(SI_INVALID_PARAMETER)
+            );
+            assert_eq!(
+                SI_GetDeviceNumByPath(b"004\0".as_ptr() as *const c_char, &mut n),
+                status(SI_INVALID_PARAMETER)
+            );
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn only_usbfs_nodes_are_opened_from_descriptors() {
+        use std::os::unix::io::IntoRawFd;
+
+        let null = File::open("/dev/null").unwrap();
+        assert!(node_info(&null).is_none());
+        let mut handle = ptr::null_mut();
+        let status = unsafe { SI_OpenFd(null.into_raw_fd(), 0, 0, 1000, 1000, &mut handle) };
+        assert_eq!(status, super::status(SI_DEVICE_NOT_FOUND));
+        assert!(handle.is_null());
+    }
+}
@@ -0,0 +1,154 @@
+//! Submitting transfers without waiting for them
+//!
+//! An [`Overlapped`] device takes transfers with
+//! [`submit_read`](Overlapped::submit_read) and
+//! [`submit_write`](Overlapped::submit_write) and returns at once with a
+//! [`Transfer`] to poll or wait on, much like an `OVERLAPPED` read or write of
+//! the Windows driver:
+//!
+//! ```rust, ignore
+//! let device = Overlapped::new(UsbXpress::open(0)?);
+//! let request = device.submit_write(b"PING\n".to_vec());
+//! let mut response = device.submit_read(64);
+//! while response.poll().is_none() {
+//!     // ... do other work ...
+//! }
+//! ```
+//!
+//! The transfers run one after the other, in the order they were submitted,
+//! on a worker thread owning the device.
+use std::{
+    sync::mpsc::{self, RecvTimeoutError, TryRecvError},
+    thread,
+    time::Duration,
+};
+
+use crate::{transport::Transport, SilabsUsbXpressError, UsbXpress};
+
+type Job<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+/// A device whose transfers are submitted to a worker thread, see the
+/// [module documentation](self)
+///
+/// Any [`Transport`] can be wrapped, a [`UsbXpress`] handle by default.
+pub struct Overlapped<T: Transport + 'static = UsbXpress> {
+    jobs: Option<mpsc::Sender<Job<T>>>,
+    worker: Option<thread::JoinHandle<T>>,
+}
+
+impl<T: Transport + 'static> Overlapped<T> {
+    pub fn new(device: T) -> Self {
+        let (jobs, rx) = mpsc::channel::<Job<T>>();
+        let worker = thread::spawn(move || {
+            let mut device = device;
+            for job in rx {
+                job(&mut device);
+            }
+            device
+        });
+        Overlapped {
+            jobs: Some(jobs),
+            worker: Some(worker),
+        }
+    }
+
+    /// Submits a read of up to `max` bytes, see [`UsbXpress::read`]
+    pub fn submit_read(&self, max: usize) -> Transfer<Vec<u8>> {
+        self.submit(move |device| device.read(max))
+    }
+
+    /// Submits a write of `data`, see [`UsbXpress::write`]
+    pub fn submit_write(&self, data: Vec<u8>) -> Transfer<usize> {
+        self.submit(move |device| device.write(&data))
+    }
+
+    fn submit<R, F>(&self, transfer: F) -> Transfer<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut T) -> Result<R, SilabsUsbXpressError> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        if let Some(jobs) = &self.jobs {
+            // The worker only stops once `jobs` is dropped.
+            let _ = jobs.send(Box::new(move |device: &mut T| {
+                let _ = tx.send(transfer(device));
+            }));
+        }
+        Transfer { outcome: rx }
+    }
+
+    /// Waits for all submitted transfers to finish and returns the device
+    pub fn into_inner(mut self) -> T {
+        self.jobs = None;
+        let worker = self.worker.take().unwrap();
+        worker.join().expect("device worker panicked")
+    }
+}
+
+impl<T: Transport + 'static> Drop for Overlapped<T> {
+    fn drop(&mut self) {
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// A submitted transfer, see [`Overlapped`]
+///
+/// Dropping it does not cancel the transfer; its outcome is discarded.
+#[derive(Debug)]
+pub struct Transfer<R> {
+    outcome: mpsc::Receiver<Result<R, SilabsUsbXpressError>>,
+}
+
+impl<R> Transfer<R> {
+    /// Returns the outcome if the transfer has completed, without waiting
+    ///
+    /// The outcome is returned only once; later calls return `None`.
+    pub fn poll(&mut self) -> Option<Result<R, SilabsUsbXpressError>> {
+        match self.outcome.try_recv() {
+            Ok(outcome) => Some(outcome),
+            Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => None,
+        }
+    }
+
+    /// Waits up to `timeout` for the transfer to complete, see
+    /// [`poll`](Transfer::poll)
+    pub fn wait_timeout(&mut self, timeout: Duration) -> Option<Result<R, SilabsUsbXpressError>> {
+        match self.outcome.recv_timeout(timeout) {
+            Ok(outcome) => Some(outcome),
+            Err(RecvTimeoutError::Timeout) | Err(RecvTimeoutError::Disconnected) => None,
+        }
+    }
+
+    /// Waits for the transfer to complete and returns its outcome
+    ///
+    /// Fails with `ConnectionError` if the worker went away without
+    /// running the transfer.
+    pub fn wait(self) -> Result<R, SilabsUsbXpressError> {
+        self.outcome
+            .recv()
+            .unwrap_or(Err(SilabsUsbXpressError::ConnectionError))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::Loopback;
+
+    #[test]
+    fn transfers_complete_in_submission_order() {
+        let device = Overlapped::new(Loopback::default());
+        let write = device.submit_write(b"PING".to_vec());
+        let mut read = device.submit_read(16);
+        assert_eq!(
+            read.wait_timeout(Duration::from_secs(5)).unwrap().unwrap(),
+            b"PING"
+        );
+        assert!(read.poll().is_none());
+        assert_eq!(write.wait().unwrap(), 4);
+        assert!(device.into_inner().0.is_empty());
+    }
+}
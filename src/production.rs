@@ -0,0 +1,311 @@
+//! Provisioning many devices in one go
+//!
+//! [`program_batch`] runs a provisioning step, such as writing a serial
+//! number and verifying it, on every connected device that matches a
+//! [`Selector`], and collects the outcome of each into a [`BatchReport`]:
+//!
+//! ```rust, ignore
+//! use silabs_usb_xpress::production::{program_batch, Selector};
+//!
+//! let report = program_batch(&Selector::new().vid(0x10c4), |device, idx| {
+//!     device.transact(&provisioning_request(idx), 2, Duration::from_secs(1))
+//! })?;
+//! println!("{}", report);
+//! if report.failed() > 0 {
+//!     std::process::exit(1);
+//! }
+//! ```
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    device_ids, devices_count, product_string, DeviceInfo, ProductStringType, SilabsUsbXpressError,
+    UsbXpress,
+};
+
+/// Chooses which connected devices a batch applies to, or which device
+/// [`wait_for_device`](crate::hotplug::wait_for_device) waits for
+///
+/// A new selector matches every device; each criterion narrows it down.
+#[derive(Clone, Debug, Default)]
+pub struct Selector {
+    vid: Option<u16>,
+    pid: Option<u16>,
+    serial: Option<String>,
+    serial_prefix: Option<String>,
+}
+
+impl Selector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only matches devices with vendor ID `vid`
+    pub fn vid(mut self, vid: u16) -> Self {
+        self.vid = Some(vid);
+        self
+    }
+
+    /// Only matches devices with product ID `pid`
+    pub fn pid(mut self, pid: u16) -> Self {
+        self.pid = Some(pid);
+        self
+    }
+
+    /// Only matches the device with serial number `serial`
+    pub fn serial<S: Into<String>>(mut self, serial: S) -> Self {
+        self.serial = Some(serial.into());
+        self
+    }
+
+    /// Only matches devices whose serial number starts with `prefix`
+    pub fn serial_prefix<S: Into<String>>(mut self, prefix: S) -> Self {
+        self.serial_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Returns the indices of the connected devices this selector matches
+    pub fn matching(&self) -> Result<Vec<usize>, SilabsUsbXpressError> {
+        let mut matching = Vec::new();
+        for device_ix in 0..devices_count()? {
+            if self.matches(device_ix)? {
+                matching.push(device_ix);
+            }
+        }
+        Ok(matching)
+    }
+
+    fn matches(&self, device_ix: usize) -> Result<bool, SilabsUsbXpressError> {
+        if self.vid.is_some() || self.pid.is_some() {
+            let (vid, pid) = device_ids(device_ix)?;
+            if self.vid.is_some_and(|want| want != vid) || self.pid.is_some_and(|want| want != pid)
+            {
+                return Ok(false);
+            }
+        }
+        if self.serial.is_some() || self.serial_prefix.is_some() {
+            let serial = product_string(device_ix, ProductStringType::SerialNumber)?;
+            if !self.serial_matches(&serial) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Whether this selector matches the device described by `info`
+    pub(crate) fn accepts(&self, info: &DeviceInfo) -> bool {
+        self.vid.is_none_or(|vid| vid == info.vid)
+            && self.pid.is_none_or(|pid| pid == info.pid)
+            && self.serial_matches(&info.serial)
+    }
+
+    fn serial_matches(&self, serial: &str) -> bool {
+        self.serial.as_deref().is_none_or(|want| want == serial)
+            && self
+                .serial_prefix
+                .as_deref()
+                .is_none_or(|prefix| serial.starts_with(prefix))
+    }
+}
+
+/// Why provisioning a device failed
+#[derive(Debug)]
+pub enum BatchError<E> {
+    /// The device could not be opened or closed
+    Device(SilabsUsbXpressError),
+    /// The provisioning closure returned an error
+    Provision(E),
+}
+
+impl<E: fmt::Debug> fmt::Display for BatchError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format!("{:?}", self))
+    }
+}
+
+impl<E: fmt::Debug> std::error::Error for BatchError<E> {}
+
+/// Outcome of provisioning one device
+#[derive(Debug)]
+pub struct DeviceOutcome<T, E> {
+    /// Position of the device within the batch
+    pub idx: usize,
+    /// Device index the device was opened with
+    pub device_ix: usize,
+    /// Serial number, empty if the device could not be opened
+    pub serial: String,
+    pub elapsed: Duration,
+    pub result: Result<T, BatchError<E>>,
+}
+
+/// Outcomes of a whole batch, in the order the devices were provisioned
+///
+/// Its `Display` implementation prints a summary with one line per device.
+#[derive(Debug)]
+pub struct BatchReport<T, E> {
+    pub outcomes: Vec<DeviceOutcome<T, E>>,
+    pub elapsed: Duration,
+}
+
+impl<T, E> BatchReport<T, E> {
+    pub fn succeeded(&self) -> usize {
+        self.outcomes.iter().filter(|o| o.result.is_ok()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.outcomes.len() - self.succeeded()
+    }
+}
+
+impl<T, E: fmt::Debug> fmt::Display for BatchReport<T, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} devices, {} succeeded, {} failed in {:.1}s",
+            self.outcomes.len(),
+            self.succeeded(),
+            self.failed(),
+            self.elapsed.as_secs_f64()
+        )?;
+        for outcome in &self.outcomes {
+            write!(
+                f,
+                "  #{} device {} serial {:?}",
+                outcome.idx, outcome.device_ix, outcome.serial
+            )?;
+            if let Some(alias) = crate::aliases::alias(&outcome.serial) {
+                write!(f, " ({})", alias)?;
+            }
+            f.write_str(": ")?;
+            match &outcome.result {
+                Ok(_) => writeln!(f, "ok")?,
+                Err(err) => writeln!(f, "FAILED: {}", err)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs `provision` on every device matching `selector`
+///
+/// Devices are opened one at a time, handed to `provision` together with
+/// their position in the batch, and closed again before the next one is
+/// opened. A failure on one device is recorded in the report and does not
+/// stop the batch. An error returned here means the devices could not be
+/// enumerated at all.
+pub fn program_batch<T, E, F>(
+    selector: &Selector,
+    mut provision: F,
+) -> Result<BatchReport<T, E>, SilabsUsbXpressError>
+where
+    E: fmt::Debug,
+    F: FnMut(&mut UsbXpress, usize) -> Result<T, E>,
+{
+    let started = Instant::now();
+    let mut outcomes = Vec::new();
+    for (idx, device_ix) in selector.matching()?.into_iter().enumerate() {
+        let device_started = Instant::now();
+        let mut serial = String::new();
+        let result = UsbXpress::open(device_ix)
+            .map_err(BatchError::Device)
+            .and_then(|mut device| {
+                serial = device.serial().to_owned();
+                let result = provision(&mut device, idx).map_err(BatchError::Provision);
+                let closed = device.close().map_err(BatchError::Device);
+                result.and_then(|value| closed.map(|()| value))
+            });
+        let device = crate::aliases::alias(&serial).unwrap_or_else(|| format!("{:?}", serial));
+        match &result {
+            Ok(_) => log::info!("device {} ({}) provisioned", device_ix, device),
+            Err(err) => log::warn!("device {} ({}) failed: {}", device_ix, device, err),
+        }
+        outcomes.push(DeviceOutcome {
+            idx,
+            device_ix,
+            serial,
+            elapsed: device_started.elapsed(),
+            result,
+        });
+    }
+    let report = BatchReport {
+        outcomes,
+        elapsed: started.elapsed(),
+    };
+    log::info!(
+        "batch finished: {} succeeded, {} failed",
+        report.succeeded(),
+        report.failed()
+    );
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(
+        idx: usize,
+        result: Result<u8, BatchError<&'static str>>,
+    ) -> DeviceOutcome<u8, &'static str> {
+        DeviceOutcome {
+            idx,
+            device_ix: idx + 2,
+            serial: format!("SN{}", idx),
+            elapsed: Duration::ZERO,
+            result,
+        }
+    }
+
+    #[test]
+    fn reports_count_and_list_every_device() {
+        let report = BatchReport {
+            outcomes: vec![
+                outcome(0, Ok(1)),
+                outcome(1, Err(BatchError::Provision("verify failed"))),
+                outcome(2, Err(BatchError::Device(SilabsUsbXpressError::DeviceBusy))),
+                outcome(3, Ok(2)),
+            ],
+            elapsed: Duration::from_millis(1500),
+        };
+        assert_eq!((report.succeeded(), report.failed()), (2, 2));
+        assert_eq!(
+            report.to_string(),
+            "4 devices, 2 succeeded, 2 failed in 1.5s\n\
+             \x20 #0 device 2 serial \"SN0\": ok\n\
+             \x20 #1 device 3 serial \"SN1\": FAILED: Provision(\"verify failed\")\n\
+             \x20 #2 device 4 serial \"SN2\": FAILED: Device(DeviceBusy)\n\
+             \x20 #3 device 5 serial \"SN3\": ok\n"
+        );
+    }
+
+    #[test]
+    fn empty_batches_neither_succeed_nor_fail() {
+        let report = BatchReport::<u8, &str> {
+            outcomes: Vec::new(),
+            elapsed: Duration::ZERO,
+        };
+        assert_eq!((report.succeeded(), report.failed()), (0, 0));
+    }
+
+    #[test]
+    fn selectors_narrow_down_with_every_criterion() {
+        let info = DeviceInfo {
+            serial: "FT-0042".to_owned(),
+            vid: 0x10c4,
+            pid: 0xea61,
+            ..DeviceInfo::default()
+        };
+        assert!(Selector::new().accepts(&info));
+        assert!(Selector::new().vid(0x10c4).pid(0xea61).accepts(&info));
+        assert!(!Selector::new().vid(0x10c4).pid(0xea60).accepts(&info));
+        assert!(Selector::new().serial_prefix("FT-").accepts(&info));
+        assert!(!Selector::new().serial_prefix("FT-1").accepts(&info));
+        assert!(Selector::new().serial("FT-0042").accepts(&info));
+        assert!(!Selector::new()
+            .serial("FT-0042")
+            .serial_prefix("X")
+            .accepts(&info));
+    }
+}
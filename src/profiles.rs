@@ -0,0 +1,183 @@
+//! Named device profiles loaded from TOML
+//!
+//! Enabled with the `profiles` feature. A profile file maps names to devices,
+//! identified by serial number, together with the settings to open them
+//! with:
+//!
+//! ```toml
+//! [flow-sensor-3]
+//! serial = "0001A2F3"
+//! friendly_name = "Flow sensor, line 3"
+//! baud_rate = 115200
+//! read_timeout_ms = 200
+//! write_timeout_ms = 200
+//! open_timeout_ms = 2000
+//! pace_writes = true
+//! ```
+//!
+//! ```rust, ignore
+//! let profiles = silabs_usb_xpress::profiles::load("devices.toml")?;
+//! let mut handle = profiles.open_profile("flow-sensor-3")?;
+//! ```
+use std::{collections::BTreeMap, fmt, fs, io, path::Path, str::FromStr, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{product_string, OpenOptions, ProductStringType, SilabsUsbXpressError, UsbXpress};
+
+/// Settings of a single device, see the [module documentation](self)
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    /// Serial number the device is found by
+    pub serial: String,
+    /// Human readable name for logs and user interfaces
+    pub friendly_name: Option<String>,
+    /// Baud rate the device's UART runs at, see
+    /// [`UsbXpress::set_nominal_baud_rate`]
+    pub baud_rate: Option<u32>,
+    pub read_timeout_ms: Option<u64>,
+    pub write_timeout_ms: Option<u64>,
+    pub open_timeout_ms: Option<u64>,
+    /// See [`UsbXpress::pace_writes`]
+    #[serde(default)]
+    pub pace_writes: bool,
+    /// See [`UsbXpress::set_echo_suppression`]
+    #[serde(default)]
+    pub echo_suppression: bool,
+}
+
+impl Profile {
+    /// Opens the device with this profile's serial number and applies its
+    /// settings
+    pub fn open(&self) -> Result<UsbXpress, ProfileError> {
+        let device_ix = find_serial(&self.serial)?
+            .ok_or_else(|| ProfileError::DeviceNotFound(self.serial.clone()))?;
+        let mut options = OpenOptions::new();
+        if let Some(open_timeout) = self.open_timeout_ms {
+            options.open_timeout(Duration::from_millis(open_timeout));
+        }
+        let mut handle = options.open(device_ix)?;
+        if let Some(read_timeout) = self.read_timeout_ms {
+            handle.timeouts.read = Duration::from_millis(read_timeout);
+        }
+        if let Some(write_timeout) = self.write_timeout_ms {
+            handle.timeouts.write = Duration::from_millis(write_timeout);
+        }
+        if let Some(baud_rate) = self.baud_rate {
+            handle.set_nominal_baud_rate(baud_rate);
+        }
+        handle.pace_writes(self.pace_writes);
+        handle.set_echo_suppression(self.echo_suppression);
+        Ok(handle)
+    }
+}
+
+/// Returns the index of the device with serial number `serial`
+fn find_serial(serial: &str) -> Result<Option<usize>, SilabsUsbXpressError> {
+    for device_ix in 0..crate::devices_count()? {
+        if product_string(device_ix, ProductStringType::SerialNumber)? == serial {
+            return Ok(Some(device_ix));
+        }
+    }
+    Ok(None)
+}
+
+/// A set of named profiles
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(transparent)]
+pub struct Profiles {
+    profiles: BTreeMap<String, Profile>,
+}
+
+impl Profiles {
+    /// Returns the profile called `name`
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// Iterates over the profile names in alphabetical order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Opens the device of the profile called `name`
+    pub fn open_profile(&self, name: &str) -> Result<UsbXpress, ProfileError> {
+        self.get(name)
+            .ok_or_else(|| ProfileError::UnknownProfile(name.to_owned()))?
+            .open()
+    }
+}
+
+impl FromStr for Profiles {
+    type Err = ProfileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s).map_err(ProfileError::Parse)
+    }
+}
+
+/// Loads profiles from the TOML file at `path`
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Profiles, ProfileError> {
+    fs::read_to_string(path).map_err(ProfileError::Io)?.parse()
+}
+
+#[derive(Debug)]
+pub enum ProfileError {
+    /// The profile file could not be read
+    Io(io::Error),
+    /// The profile file is not valid
+    Parse(toml::de::Error),
+    /// No profile has the requested name
+    UnknownProfile(String),
+    /// No connected device has the profile's serial number
+    DeviceNotFound(String),
+    /// The device was found but opening it failed
+    Device(SilabsUsbXpressError),
+}
+
+impl From<SilabsUsbXpressError> for ProfileError {
+    fn from(err: SilabsUsbXpressError) -> Self {
+        ProfileError::Device(err)
+    }
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format!("{:?}", self))
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn profiles_parse_from_toml() {
+        let profiles: Profiles = r#"
+            [flow-sensor-3]
+            serial = "0001A2F3"
+            friendly_name = "Flow sensor, line 3"
+            baud_rate = 115200
+            read_timeout_ms = 200
+
+            [scale]
+            serial = "0042"
+        "#
+        .parse()
+        .unwrap();
+        assert_eq!(
+            profiles.names().collect::<Vec<_>>(),
+            ["flow-sensor-3", "scale"]
+        );
+        let profile = profiles.get("flow-sensor-3").unwrap();
+        assert_eq!(profile.serial, "0001A2F3");
+        assert_eq!(profile.baud_rate, Some(115200));
+        assert!(!profile.pace_writes);
+        assert!("[x]\nserial = \"1\"\nbogus = 1"
+            .parse::<Profiles>()
+            .is_err());
+    }
+}
@@ -0,0 +1,216 @@
+//! Forwarding data between two devices
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{SilabsUsbXpressError, UsbXpress};
+
+/// Options for [`pump`]
+#[derive(Clone, Debug)]
+pub struct PumpOptions {
+    stop: Option<Arc<AtomicBool>>,
+    idle_sleep: Duration,
+    run_for: Option<Duration>,
+}
+
+impl Default for PumpOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PumpOptions {
+    /// Pumps until an error occurs, sleeping 1ms whenever both sides are idle
+    pub fn new() -> Self {
+        PumpOptions {
+            stop: None,
+            idle_sleep: Duration::from_millis(1),
+            run_for: None,
+        }
+    }
+
+    /// Stops pumping once `stop` is set, typically from another thread
+    pub fn stop_signal(&mut self, stop: Arc<AtomicBool>) -> &mut Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Sleeps `sleep` whenever neither device had data, trading latency for
+    /// CPU time
+    pub fn idle_sleep(&mut self, sleep: Duration) -> &mut Self {
+        self.idle_sleep = sleep;
+        self
+    }
+
+    /// Stops pumping after `duration`
+    pub fn run_for<T: Into<Option<Duration>>>(&mut self, duration: T) -> &mut Self {
+        self.run_for = duration.into();
+        self
+    }
+}
+
+/// Traffic forwarded by [`pump`] in one direction
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct DirectionStats {
+    pub bytes: u64,
+    /// Number of chunks forwarded, one per read that returned data
+    pub chunks: u64,
+}
+
+/// What [`pump`] forwarded before it stopped
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct PumpStats {
+    pub a_to_b: DirectionStats,
+    pub b_to_a: DirectionStats,
+    pub elapsed: Duration,
+}
+
+/// Forwards everything received on `a` to `b` and vice versa
+///
+/// Both devices are polled in turn on the calling thread, so the two
+/// directions never block each other. Pumping continues until the stop
+/// signal is set or the configured run time is over, and returns the
+/// traffic statistics. An error on either device stops pumping right away;
+/// data already read from one side but not yet written to the other is
+/// lost in that case.
+pub fn pump(
+    a: &mut UsbXpress,
+    b: &mut UsbXpress,
+    opts: &PumpOptions,
+) -> Result<PumpStats, SilabsUsbXpressError> {
+    run(opts, |stats| {
+        Ok(forward(a, b, &mut stats.a_to_b)? | forward(b, a, &mut stats.b_to_a)?)
+    })
+}
+
+/// Calls `step` until `opts` says to stop, sleeping whenever it forwarded
+/// nothing
+fn run<F>(opts: &PumpOptions, mut step: F) -> Result<PumpStats, SilabsUsbXpressError>
+where
+    F: FnMut(&mut PumpStats) -> Result<bool, SilabsUsbXpressError>,
+{
+    let started = Instant::now();
+    let mut stats = PumpStats::default();
+    loop {
+        if opts
+            .stop
+            .as_ref()
+            .is_some_and(|stop| stop.load(Ordering::Relaxed))
+            || opts
+                .run_for
+                .is_some_and(|run_for| started.elapsed() >= run_for)
+        {
+            break;
+        }
+        if !step(&mut stats)? && opts.idle_sleep > Duration::ZERO {
+            thread::sleep(opts.idle_sleep);
+        }
+    }
+    stats.elapsed = started.elapsed();
+    Ok(stats)
+}
+
+/// Moves the data `from` has ready to `to`, returning whether there was any
+fn forward(
+    from: &mut UsbXpress,
+    to: &mut UsbXpress,
+    stats: &mut DirectionStats,
+) -> Result<bool, SilabsUsbXpressError> {
+    let data = from.read_available()?;
+    if data.is_empty() {
+        return Ok(false);
+    }
+    let deadline = Instant::now() + to.write_timeout_for(data.len());
+    to.write_until_deadline(&data, deadline)?;
+    stats.bytes += data.len() as u64;
+    stats.chunks += 1;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts a forwarded chunk of `len` bytes, like `forward` does
+    fn chunk(stats: &mut DirectionStats, len: usize) -> bool {
+        stats.bytes += len as u64;
+        stats.chunks += 1;
+        true
+    }
+
+    #[test]
+    fn a_set_stop_signal_ends_pumping_before_the_first_step() {
+        let stop = Arc::new(AtomicBool::new(true));
+        let mut steps = 0;
+        let stats = run(PumpOptions::new().stop_signal(stop), |_| {
+            steps += 1;
+            Ok(false)
+        })
+        .unwrap();
+        assert_eq!(steps, 0);
+        assert_eq!(stats.a_to_b, DirectionStats::default());
+    }
+
+    #[test]
+    fn the_stop_signal_is_checked_between_steps() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut opts = PumpOptions::new();
+        opts.stop_signal(Arc::clone(&stop))
+            .idle_sleep(Duration::ZERO);
+        let mut steps = 0;
+        let stats = run(&opts, |stats| {
+            steps += 1;
+            if steps == 3 {
+                stop.store(true, Ordering::Relaxed);
+            }
+            Ok(chunk(&mut stats.a_to_b, 4) | chunk(&mut stats.b_to_a, 1))
+        })
+        .unwrap();
+        assert_eq!(steps, 3);
+        assert_eq!(
+            stats.a_to_b,
+            DirectionStats {
+                bytes: 12,
+                chunks: 3
+            }
+        );
+        assert_eq!(
+            stats.b_to_a,
+            DirectionStats {
+                bytes: 3,
+                chunks: 3
+            }
+        );
+    }
+
+    #[test]
+    fn pumping_ends_once_the_run_time_is_over() {
+        let mut opts = PumpOptions::new();
+        opts.run_for(Duration::from_millis(50))
+            .idle_sleep(Duration::from_millis(5));
+        let stats = run(&opts, |_| Ok(false)).unwrap();
+        assert!(stats.elapsed >= Duration::from_millis(50));
+        assert!(stats.elapsed < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn errors_stop_pumping_right_away() {
+        let mut steps = 0;
+        let err = run(&PumpOptions::new(), |_| {
+            steps += 1;
+            if steps == 2 {
+                Err(SilabsUsbXpressError::DeviceIoFailed)
+            } else {
+                Ok(true)
+            }
+        })
+        .unwrap_err();
+        assert!(matches!(err, SilabsUsbXpressError::DeviceIoFailed));
+        assert_eq!(steps, 2);
+    }
+}
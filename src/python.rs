@@ -0,0 +1,287 @@
+//! Python bindings
+//!
+//! Enabled with the `python` feature, which builds a `silabs_usb_xpress`
+//! Python extension module when compiled as a cdylib, e.g. with maturin:
+//!
+//! ```python
+//! import silabs_usb_xpress as xp
+//!
+//! for device in xp.devices():
+//!     print(device["index"], device["serial"])
+//!
+//! xp.set_timeouts(read=0.2)
+//! handle = xp.SiHandle(0)
+//! handle.write(b"\x55\x80\x00\x01\x01\xaa")
+//! print(handle.read(7))
+//! handle.close()
+//! ```
+use std::time::Duration;
+
+use pyo3::{
+    exceptions::{PyIOError, PyTimeoutError, PyValueError},
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+
+use crate::{OpenOptions, SilabsUsbXpressError, UsbXpress};
+
+impl From<SilabsUsbXpressError> for PyErr {
+    fn from(err: SilabsUsbXpressError) -> Self {
+        match err {
+            SilabsUsbXpressError::ReadTimeOut { .. }
+            | SilabsUsbXpressError::IncompleteRead { .. }
+            | SilabsUsbXpressError::WriteTimeOut { .. }
+            | SilabsUsbXpressError::OpenTimedOut
+            | SilabsUsbXpressError::DeviceWaitTimedOut => PyTimeoutError::new_err(err.to_string()),
+            _ => PyIOError::new_err(err.to_string()),
+        }
+    }
+}
+
+/// Lists connected devices as dictionaries
+///
+/// Every entry has the device `index` to open it with, its `vid` and `pid`
+/// as integers, and for USBXpress devices its `serial` and `description`.
+#[pyfunction]
+fn devices(py: Python<'_>) -> PyResult<Vec<Bound<'_, PyDict>>> {
+    let mut devices = Vec::new();
+    for info in crate::devices()? {
+        let device = PyDict::new(py);
+        device.set_item("index", info.index)?;
+        device.set_item("vid", info.vid)?;
+        device.set_item("pid", info.pid)?;
+        device.set_item("serial", info.serial)?;
+        device.set_item("description", info.description)?;
+        devices.push(device);
+    }
+    Ok(devices)
+}
+
+/// Sets the read and write timeouts of devices opened from now on, in
+/// seconds; a timeout left out goes back to its default
+#[pyfunction]
+#[pyo3(signature = (read = None, write = None))]
+fn set_timeouts(read: Option<f64>, write: Option<f64>) -> PyResult<()> {
+    let read = read.map(seconds).transpose()?;
+    let write = write.map(seconds).transpose()?;
+    Ok(crate::set_timeouts(read, write)?)
+}
+
+/// An open device
+#[pyclass(name = "SiHandle", unsendable)]
+struct SiHandle {
+    inner: Option<UsbXpress>,
+}
+
+impl SiHandle {
+    fn handle(&mut self) -> PyResult<&mut UsbXpress> {
+        self.inner
+            .as_mut()
+            .ok_or_else(|| PyValueError::new_err("handle is closed"))
+    }
+}
+
+#[pymethods]
+impl SiHandle {
+    /// Opens the device with the given index, on its first interface unless
+    /// told otherwise; `detach_kernel_driver` takes the device away from a
+    /// kernel driver holding it
+    #[new]
+    #[pyo3(signature = (index, interface = 0, detach_kernel_driver = false))]
+    fn new(index: usize, interface: usize, detach_kernel_driver: bool) -> PyResult<Self> {
+        let handle = OpenOptions::new()
+            .detach_kernel_driver(detach_kernel_driver)
+            .open_interface(index, interface)?;
+        Ok(SiHandle {
+            inner: Some(handle),
+        })
+    }
+
+    /// Reads up to `size` bytes
+    fn read<'py>(&mut self, py: Python<'py>, size: usize) -> PyResult<Bound<'py, PyBytes>> {
+        let handle = self.handle()?;
+        let data = released(py, || handle.read(size))?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// Writes `data` and returns the number of bytes written
+    fn write(&mut self, py: Python<'_>, data: Vec<u8>) -> PyResult<usize> {
+        let handle = self.handle()?;
+        released(py, || handle.write(&data))
+    }
+
+    /// Sends `request` and reads a `response_len` byte response within
+    /// `timeout` seconds
+    fn transact<'py>(
+        &mut self,
+        py: Python<'py>,
+        request: Vec<u8>,
+        response_len: usize,
+        timeout: f64,
+    ) -> PyResult<Bound<'py, PyBytes>> {
+        let timeout = seconds(timeout)?;
+        let handle = self.handle()?;
+        let data = released(py, || handle.transact(&request, response_len, timeout))?;
+        Ok(PyBytes::new(py, &data))
+    }
+
+    /// Sets the read and write timeouts of this handle, in seconds; a
+    /// timeout left out goes back to the one set with `set_timeouts()`
+    #[pyo3(signature = (read = None, write = None))]
+    fn set_timeouts(&mut self, read: Option<f64>, write: Option<f64>) -> PyResult<()> {
+        let read = read.map(seconds).transpose()?;
+        let write = write.map(seconds).transpose()?;
+        self.handle()?.set_timeouts(read, write);
+        Ok(())
+    }
+
+    /// Returns the read and write timeouts of this handle, in seconds
+    fn timeouts(&mut self) -> PyResult<(f64, f64)> {
+        let timeouts = self.handle()?.timeouts();
+        Ok((
+            timeouts.read_timeout().as_secs_f64(),
+            timeouts.write_timeout().as_secs_f64(),
+        ))
+    }
+
+    /// Returns the device's part number, e.g. `"CP2102"`
+    fn part_number(&mut self) -> PyResult<String> {
+        Ok(self.handle()?.part_number()?.to_string())
+    }
+
+    /// Returns the number of the USB interface the handle is bound to
+    fn interface_number(&mut self) -> PyResult<u8> {
+        Ok(self.handle()?.interface_number())
+    }
+
+    /// Flushes the receive and transmit buffers
+    fn flush(&mut self) -> PyResult<()> {
+        Ok(self.handle()?.flush_buffers()?)
+    }
+
+    /// Sets the baud rate of a CP210x device's UART
+    fn set_baud_rate(&mut self, baud_rate: u32) -> PyResult<()> {
+        Ok(self.handle()?.set_baud_rate(baud_rate)?)
+    }
+
+    /// Holds a break condition for `duration` seconds
+    fn send_break(&mut self, py: Python<'_>, duration: f64) -> PyResult<()> {
+        let duration = seconds(duration)?;
+        let handle = self.handle()?;
+        released(py, || handle.send_break(duration))
+    }
+
+    /// Resets the device; should it re-enumerate, the handle is closed and
+    /// has to be opened again
+    fn reset(&mut self) -> PyResult<()> {
+        let result = self.handle()?.reset();
+        if let Err(SilabsUsbXpressError::NeedsReopen { .. }) = result {
+            self.inner = None;
+        }
+        Ok(result?)
+    }
+
+    /// Sets the DTR and RTS outputs; a line left out stays as it is
+    #[pyo3(signature = (dtr = None, rts = None))]
+    fn set_modem_lines(&mut self, dtr: Option<bool>, rts: Option<bool>) -> PyResult<()> {
+        let handle = self.handle()?;
+        match (dtr, rts) {
+            (Some(dtr), Some(rts)) => handle.set_modem_lines(dtr, rts)?,
+            (Some(dtr), None) => handle.set_dtr(dtr)?,
+            (None, Some(rts)) => handle.set_rts(rts)?,
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    /// Records the baud rate of the device's UART
+    fn set_nominal_baud_rate(&mut self, baud_rate: u32) -> PyResult<()> {
+        self.handle()?.set_nominal_baud_rate(baud_rate);
+        Ok(())
+    }
+
+    /// Throttles writes to the nominal baud rate
+    fn pace_writes(&mut self, enable: bool) -> PyResult<()> {
+        self.handle()?.pace_writes(enable);
+        Ok(())
+    }
+
+    /// Closes the device
+    fn close(&mut self) -> PyResult<()> {
+        match self.inner.take() {
+            Some(handle) => Ok(handle.close()?),
+            None => Ok(()),
+        }
+    }
+
+    fn __enter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __exit__(
+        &mut self,
+        _exc_type: Py<PyAny>,
+        _exc_value: Py<PyAny>,
+        _traceback: Py<PyAny>,
+    ) -> PyResult<bool> {
+        self.close()?;
+        Ok(false)
+    }
+}
+
+/// Runs a transfer that may wait on the device with the GIL released, so
+/// that other Python threads keep running meanwhile
+fn released<T, F>(py: Python<'_>, transfer: F) -> PyResult<T>
+where
+    T: Send,
+    F: FnOnce() -> Result<T, SilabsUsbXpressError> + Send,
+{
+    Ok(py.detach(transfer)?)
+}
+
+fn seconds(secs: f64) -> PyResult<Duration> {
+    Duration::try_from_secs_f64(secs).map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+#[pymodule]
+fn silabs_usb_xpress(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(devices, m)?)?;
+    m.add_function(wrap_pyfunction!(set_timeouts, m)?)?;
+    m.add_class::<SiHandle>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use super::*;
+
+    #[test]
+    fn transfers_release_the_gil() {
+        Python::initialize();
+        let (tx, rx) = mpsc::channel();
+        let other = thread::spawn(move || Python::attach(|_| tx.send(()).unwrap()));
+        // Stands in for a read waiting on the device: it only ends once a
+        // thread that needs the GIL got to run
+        let ran = Python::attach(|py| {
+            released(py, move || {
+                Ok(rx.recv_timeout(Duration::from_secs(5)).is_ok())
+            })
+            .unwrap()
+        });
+        other.join().unwrap();
+        assert!(ran);
+    }
+
+    #[test]
+    fn timeouts_raise_timeout_error() {
+        Python::initialize();
+        Python::attach(|py| {
+            let err = PyErr::from(SilabsUsbXpressError::OpenTimedOut);
+            assert!(err.is_instance_of::<PyTimeoutError>(py));
+            let err = PyErr::from(SilabsUsbXpressError::ReadError);
+            assert!(err.is_instance_of::<PyIOError>(py));
+        });
+    }
+}
@@ -0,0 +1,300 @@
+//! Handles that survive the device being unplugged and plugged back in
+//!
+//! A [`ReconnectingHandle`] remembers the serial number of its device. When a
+//! transfer fails because the device went away, it finds the device again by
+//! its serial number, reopens it and repeats the transfer, retrying according
+//! to its [`RetryPolicy`]:
+//!
+//! ```rust, ignore
+//! let mut handle = ReconnectingHandle::open("0001A2F3")?;
+//! handle.set_retry_policy(RetryPolicy::new().attempts(20).delay(Duration::from_millis(250)));
+//! handle.set_event_callback(|event| println!("{:?}", event));
+//! handle.write(b"\x01\x02")?;
+//! let reply = handle.read(64)?;
+//! ```
+//!
+//! Data in flight when the device went away is lost; a transfer is repeated
+//! as a whole on the reopened device.
+use std::{fmt, thread, time::Duration};
+
+use crate::{transport::Transport, OpenOptions, SilabsUsbXpressError, UsbXpress};
+
+/// How often and how fast a [`ReconnectingHandle`] tries to reopen its device
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    attempts: Option<usize>,
+    delay: Duration,
+    backoff: f64,
+    max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: Some(10),
+            delay: Duration::from_millis(500),
+            backoff: 1.0,
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Tries 10 times, half a second apart
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gives up after `attempts` failed attempts to reopen the device
+    pub fn attempts(mut self, attempts: usize) -> Self {
+        self.attempts = Some(attempts);
+        self
+    }
+
+    /// Keeps trying to reopen the device until it is back
+    pub fn forever(mut self) -> Self {
+        self.attempts = None;
+        self
+    }
+
+    /// Waits `delay` before the first attempt
+    pub fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Multiplies the delay by `factor` after every failed attempt, up to
+    /// `max_delay`
+    pub fn backoff(mut self, factor: f64, max_delay: Duration) -> Self {
+        self.backoff = factor.max(1.0);
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Returns the delay before the zero-based `attempt`
+    pub fn delay_before(&self, attempt: usize) -> Duration {
+        let factor = self.backoff.powi(attempt.min(i32::MAX as usize) as i32);
+        let delay = self.delay.as_secs_f64() * factor;
+        if delay >= self.max_delay.as_secs_f64() {
+            self.max_delay.max(self.delay)
+        } else {
+            Duration::from_secs_f64(delay)
+        }
+    }
+
+    fn allows(&self, attempt: usize) -> bool {
+        self.attempts.is_none_or(|attempts| attempt < attempts)
+    }
+}
+
+/// What a [`ReconnectingHandle`] reports to its event callback
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReconnectEvent {
+    /// The device went away
+    Disconnected,
+    /// The device is being reopened, `attempt` counting from 1
+    Retrying { attempt: usize },
+    /// The device was reopened after `attempts` attempts
+    Reconnected { attempts: usize },
+    /// The retry policy ran out after `attempts` attempts
+    GaveUp { attempts: usize },
+}
+
+/// A handle that reopens its device by serial number after it was unplugged,
+/// see the [module documentation](self)
+pub struct ReconnectingHandle {
+    serial: String,
+    options: OpenOptions,
+    policy: RetryPolicy,
+    handle: Option<UsbXpress>,
+    callback: Option<Box<dyn FnMut(ReconnectEvent) + Send>>,
+}
+
+impl fmt::Debug for ReconnectingHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReconnectingHandle")
+            .field("serial", &self.serial)
+            .field("options", &self.options)
+            .field("policy", &self.policy)
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl ReconnectingHandle {
+    /// Opens the device with serial number `serial`
+    pub fn open(serial: &str) -> Result<Self, SilabsUsbXpressError> {
+        Self::with_options(serial, OpenOptions::new())
+    }
+
+    /// Opens the device with serial number `serial` using `options`, which
+    /// are used again whenever the device is reopened
+    pub fn with_options(serial: &str, options: OpenOptions) -> Result<Self, SilabsUsbXpressError> {
+        let handle = options.open_by_serial(serial)?;
+        Ok(ReconnectingHandle {
+            serial: serial.to_owned(),
+            options,
+            policy: RetryPolicy::default(),
+            handle: Some(handle),
+            callback: None,
+        })
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.policy = policy;
+    }
+
+    /// Calls `callback` whenever the device goes away or is reopened
+    pub fn set_event_callback<F>(&mut self, callback: F)
+    where
+        F: FnMut(ReconnectEvent) + Send + 'static,
+    {
+        self.callback = Some(Box::new(callback));
+    }
+
+    pub fn serial(&self) -> &str {
+        &self.serial
+    }
+
+    /// Returns whether the device is open, which it is not after the retry
+    /// policy ran out
+    pub fn is_connected(&self) -> bool {
+        self.handle.is_some()
+    }
+
+    /// Returns the open device, reopening it first if it was lost
+    pub fn handle(&mut self) -> Result<&mut UsbXpress, SilabsUsbXpressError> {
+        if self.handle.is_none() {
+            self.reconnect()?;
+        }
+        Ok(self.handle.as_mut().expect("reconnected"))
+    }
+
+    /// Runs `f` on the device; if it fails because the device went away,
+    /// reopens the device and runs `f` once more
+    pub fn run<T, F>(&mut self, mut f: F) -> Result<T, SilabsUsbXpressError>
+    where
+        F: FnMut(&mut UsbXpress) -> Result<T, SilabsUsbXpressError>,
+    {
+        let handle = self.handle()?;
+        match f(handle) {
+            Err(err) if is_disconnect(handle, &err) => {
+                self.emit(ReconnectEvent::Disconnected);
+                self.reconnect()?;
+                f(self.handle.as_mut().expect("reconnected"))
+            }
+            result => result,
+        }
+    }
+
+    /// Reads up to `max` bytes, see [`UsbXpress::read`]
+    pub fn read(&mut self, max: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        self.run(|handle| handle.read(max))
+    }
+
+    /// Writes `data`, see [`UsbXpress::write`]
+    pub fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+        self.run(|handle| handle.write(data))
+    }
+
+    /// Closes the lost device and reopens it according to the retry policy
+    pub fn reconnect(&mut self) -> Result<(), SilabsUsbXpressError> {
+        if let Some(handle) = self.handle.take() {
+            // The device is gone, so closing it is expected to fail.
+            let _ = handle.close();
+        }
+        let mut attempt = 0;
+        loop {
+            if !self.policy.allows(attempt) {
+                self.emit(ReconnectEvent::GaveUp { attempts: attempt });
+                return Err(SilabsUsbXpressError::SerialNotFound(self.serial.clone()));
+            }
+            thread::sleep(self.policy.delay_before(attempt));
+            attempt += 1;
+            self.emit(ReconnectEvent::Retrying { attempt });
+            match self.options.open_by_serial(&self.serial) {
+                Ok(handle) => {
+                    self.handle = Some(handle);
+                    self.emit(ReconnectEvent::Reconnected { attempts: attempt });
+                    return Ok(());
+                }
+                Err(err) => log::debug!("unable to reopen {}: {}", self.serial, err),
+            }
+        }
+    }
+
+    /// Returns the open device, if any
+    pub fn into_inner(self) -> Option<UsbXpress> {
+        self.handle
+    }
+
+    fn emit(&mut self, event: ReconnectEvent) {
+        if let Some(callback) = &mut self.callback {
+            callback(event);
+        }
+    }
+}
+
+impl Transport for ReconnectingHandle {
+    fn read(&mut self, max: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        ReconnectingHandle::read(self, max)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+        ReconnectingHandle::write(self, data)
+    }
+
+    /// Reports none while the device is away, as its queue went with it
+    fn pending_output(&mut self) -> Result<usize, SilabsUsbXpressError> {
+        match &mut self.handle {
+            Some(handle) => handle.tx_queue_len(),
+            None => Ok(0),
+        }
+    }
+}
+
+/// Returns whether `err` means the device went away
+///
+/// Reads from an unplugged device time out rather than fail, so a read that
+/// timed out without any data is followed by a status request to tell.
+fn is_disconnect(handle: &mut UsbXpress, err: &SilabsUsbXpressError) -> bool {
+    match err {
+        SilabsUsbXpressError::DeviceIoFailed
+        | SilabsUsbXpressError::WriteError
+        | SilabsUsbXpressError::ReadError
+        | SilabsUsbXpressError::ConnectionError => true,
+        SilabsUsbXpressError::ReadTimeOut { transferred: 0, .. } => handle.probe_status().is_err(),
+        SilabsUsbXpressError::IncompleteRead { data, .. } if data.is_empty() => {
+            handle.probe_status().is_err()
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_back_off_up_to_the_maximum() {
+        let policy = RetryPolicy::new()
+            .delay(Duration::from_millis(100))
+            .backoff(2.0, Duration::from_millis(500));
+        let delays: Vec<_> = (0..5).map(|attempt| policy.delay_before(attempt)).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 500, 500]
+                .iter()
+                .map(|&ms| Duration::from_millis(ms))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn attempts_are_limited_unless_forever() {
+        let policy = RetryPolicy::new().attempts(2);
+        assert!(policy.allows(1));
+        assert!(!policy.allows(2));
+        assert!(RetryPolicy::new().attempts(2).forever().allows(1000));
+    }
+}
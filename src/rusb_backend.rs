@@ -0,0 +1,1039 @@
+//! The SiUSBXp functions implemented on top of rusb
+//!
+//! Built with the `rust-backend` feature in place of the bundled
+//! `SiUSBXp.c`, so that it does not need to be compiled against
+//! libusb-compat.
+//! The functions keep the signatures, status codes and receive buffering of
+//! the C library, which lets the rest of the crate use either one.
+//!
+//! Device numbers count every USB device, in the order libusb-1.0 lists
+//! them, and paths are `bus/address` as in `001/004`. libusb-1.0 cannot name
+//! the kernel driver bound to a device, so `SI_GetDeviceDetails` reports
+//! none.
+#![allow(non_snake_case, clippy::missing_safety_doc)]
+
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::FromRawFd;
+use std::{
+    convert::TryFrom,
+    error::Error,
+    ffi::CStr,
+    fs::File,
+    os::raw::{c_char, c_int, c_void},
+    ptr, slice,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+#[cfg(target_os = "linux")]
+use rusb::UsbContext;
+use rusb::{
+    constants::LIBUSB_ERROR_TIMEOUT, ffi, Device, DeviceHandle, GlobalContext, TransferType,
+};
+
+use crate::backend::{
+    cancel, copy_string, forget_cancel, is_si_device, millis, register_cancel, sliced, split_path,
+    status, Sliced, BUF_SIZE, CP210X_GET_PARTNUM, CP210X_PURGE, CP210X_PURGE_RX, CP210X_PURGE_TX,
+    CP210X_REQTYPE_DEVICE_TO_HOST, CP210X_REQTYPE_HOST_TO_DEVICE, CP210X_VENDOR_SPECIFIC,
+    DT_STRING, ENDPOINT_IN, GET_DESCRIPTOR, LANG_US_ENGLISH, MAGIC, RX_TIMEOUT, TX_TIMEOUT,
+};
+pub use crate::backend::{SI_AddDeviceId, SI_ClearDeviceIds, SI_SetTimeouts};
+
+mod c {
+    include!("bindings.rs");
+}
+
+// The constants of the C library; its functions and handle type are
+// shadowed by the ones below and the shared ones of `backend`.
+pub use c::*;
+
+/// The devices found by the last enumeration, by device number
+static DEVICES: Mutex<Vec<Device<GlobalContext>>> = Mutex::new(Vec::new());
+
+pub struct SiPrivate {
+    magic: c_int,
+    udev: DeviceHandle<GlobalContext>,
+    /// The usbfs node `udev` was opened on, closed after it
+    node: Option<File>,
+    interface: u8,
+    ep_out: u8,
+    ep_in: u8,
+    /// Whether a kernel driver was detached at open and is reattached at close
+    reattach: bool,
+    /// Used by `SI_Read`/`SI_Write` and the control requests of this handle
+    rx_timeout: c_int,
+    tx_timeout: c_int,
+    /// Set by `SI_CancelIo`, possibly from another thread
+    cancelled: Arc<AtomicBool>,
+    /// Data received but not read yet, at most `BUF_SIZE` bytes
+    buffer: Vec<u8>,
+}
+
+impl SiPrivate {
+    /// Enables the UART and clears both endpoints, after opening or a reset
+    fn enable(&mut self) {
+        let timeout = millis(self.tx_timeout);
+        let index = u16::from(self.interface);
+        let _ = self
+            .udev
+            .write_control(0x40, 0x00, 0xFFFF, index, &[], timeout);
+        let _ = self.udev.clear_halt(self.ep_in);
+        let _ = self.udev.clear_halt(self.ep_out);
+        let _ = self
+            .udev
+            .write_control(0x40, 0x02, 0x0002, index, &[], timeout);
+        self.buffer.clear();
+    }
+
+    /// Receives into the buffer, returning libusb's status and the number
+    /// of bytes received
+    fn fill_buffer(&mut self, timeout: c_int) -> (c_int, usize) {
+        let filled = self.buffer.len();
+        if filled == BUF_SIZE {
+            return (0, 0);
+        }
+        self.buffer.resize(BUF_SIZE, 0);
+        let free = self.buffer[filled..].as_mut_ptr();
+        let (result, read) = self.bulk(self.ep_in, free, BUF_SIZE - filled, timeout);
+        self.buffer.truncate(filled + read);
+        (result, read)
+    }
+
+    /// Runs a bulk transfer, returning libusb's status and the number of
+    /// bytes transferred
+    ///
+    /// Unlike rusb's `read_bulk`/`write_bulk`, the count is kept when the
+    /// transfer times out part way through.
+    fn bulk(&self, endpoint: u8, data: *mut u8, len: usize, timeout: c_int) -> (c_int, usize) {
+        let mut transferred = 0;
+        let result = unsafe {
+            ffi::libusb_bulk_transfer(
+                self.udev.as_raw(),
+                endpoint,
+                data,
+                len as c_int,
+                &mut transferred,
+                timeout.max(0) as u32,
+            )
+        };
+        (result, transferred.max(0) as usize)
+    }
+
+    fn take_buffer(&mut self, out: &mut [u8]) -> usize {
+        let n = out.len().min(self.buffer.len());
+        out[..n].copy_from_slice(&self.buffer[..n]);
+        self.buffer.drain(..n);
+        n
+    }
+}
+
+fn enumerate() -> Vec<Device<GlobalContext>> {
+    match rusb::devices() {
+        Ok(devices) => devices.iter().collect(),
+        Err(err) => {
+            log::error!("unable to list USB devices: {}", err);
+            Vec::new()
+        }
+    }
+}
+
+fn device(device_num: c_int) -> Option<Device<GlobalContext>> {
+    let devices = DEVICES.lock().unwrap();
+    usize::try_from(device_num)
+        .ok()
+        .and_then(|ix| devices.get(ix).cloned())
+}
+
+fn ids(device: &Device<GlobalContext>) -> (u16, u16) {
+    device
+        .device_descriptor()
+        .map(|descriptor| (descriptor.vendor_id(), descriptor.product_id()))
+        .unwrap_or((0, 0))
+}
+
+pub(crate) fn device_path(device: &Device<GlobalContext>) -> String {
+    format!("{:03}/{:03}", device.bus_number(), device.address())
+}
+
+/// Number and endpoints of the first interface, the one `SI_Open` claims
+fn first_interface(device: &Device<GlobalContext>) -> Option<(u8, Vec<(u8, TransferType)>)> {
+    nth_interface(device, 0)
+}
+
+/// Number and endpoints of the `nth` interface of the first configuration
+fn nth_interface(
+    device: &Device<GlobalContext>,
+    nth: usize,
+) -> Option<(u8, Vec<(u8, TransferType)>)> {
+    let config = device.config_descriptor(0).ok()?;
+    let interface = config.interfaces().nth(nth)?;
+    let setting = interface.descriptors().next()?;
+    let endpoints = setting
+        .endpoint_descriptors()
+        .map(|endpoint| (endpoint.address(), endpoint.transfer_type()))
+        .collect();
+    Some((setting.interface_number(), endpoints))
+}
+
+unsafe fn valid<'a>(handle: *mut SiPrivate) -> Option<&'a mut SiPrivate> {
+    handle.as_mut().filter(|handle| handle.magic == MAGIC)
+}
+
+pub unsafe fn SI_GetNumDevices(num_devices: *mut c_int) -> c_int {
+    if num_devices.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let mut devices = DEVICES.lock().unwrap();
+    *devices = enumerate();
+    *num_devices = devices.len() as c_int;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetProductString(
+    device_num: c_int,
+    device_string: *mut c_char,
+    flags: c_int,
+) -> c_int {
+    if device_string.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    copy_string(device_string, "");
+    let device = match device(device_num) {
+        Some(device) => device,
+        None => return status(SI_DEVICE_NOT_FOUND),
+    };
+    let (vid, pid) = ids(&device);
+    let descriptor = match flags as u32 {
+        SI_RETURN_SERIAL_NUMBER => 3,
+        SI_RETURN_DESCRIPTION => 2,
+        SI_RETURN_LINK_NAME => 1,
+        SI_RETURN_VID => {
+            copy_string(device_string, &format!("{:x}", vid));
+            return status(SI_SUCCESS);
+        }
+        SI_RETURN_PID => {
+            copy_string(device_string, &format!("{:x}", pid));
+            return status(SI_SUCCESS);
+        }
+        _ => return status(SI_INVALID_PARAMETER),
+    };
+    if is_si_device(vid, pid) {
+        match device.open() {
+            Ok(udev) => match udev.read_string_descriptor_ascii(descriptor) {
+                Ok(string) => copy_string(device_string, &string),
+                Err(err) => log::error!("unable to read descriptor {}: {}", descriptor, err),
+            },
+            Err(err) => log::error!("unable to open USB device: {}", err),
+        }
+    }
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetDeviceNumByPath(path: *const c_char, device_num: *mut c_int) -> c_int {
+    if path.is_null() || device_num.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let path = CStr::from_ptr(path).to_string_lossy();
+    let (bus, address) = match split_path(&path) {
+        Some(components) => components,
+        None => return status(SI_INVALID_PARAMETER),
+    };
+    let (bus, address) = match (bus.parse::<u8>(), address.parse::<u8>()) {
+        (Ok(bus), Ok(address)) => (bus, address),
+        _ => return status(SI_DEVICE_NOT_FOUND),
+    };
+    let mut devices = DEVICES.lock().unwrap();
+    *devices = enumerate();
+    match devices
+        .iter()
+        .position(|device| device.bus_number() == bus && device.address() == address)
+    {
+        Some(ix) => {
+            *device_num = ix as c_int;
+            status(SI_SUCCESS)
+        }
+        None => status(SI_DEVICE_NOT_FOUND),
+    }
+}
+
+/// Opens `device`, on Linux through its usbfs node so that the descriptor
+/// libusb uses is known
+#[cfg(target_os = "linux")]
+fn open_node(
+    device: &Device<GlobalContext>,
+) -> Result<(DeviceHandle<GlobalContext>, Option<File>), Box<dyn Error>> {
+    let node = File::options()
+        .read(true)
+        .write(true)
+        .open(format!("/dev/bus/usb/{}", device_path(device)))?;
+    // libusb leaves the descriptor to us, `SiPrivate` keeps it open
+    let udev = unsafe { GlobalContext::default().open_device_with_fd(node.as_raw_fd())? };
+    Ok((udev, Some(node)))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_node(
+    device: &Device<GlobalContext>,
+) -> Result<(DeviceHandle<GlobalContext>, Option<File>), Box<dyn Error>> {
+    Ok((device.open()?, None))
+}
+
+fn open(
+    device: &Device<GlobalContext>,
+    nth: usize,
+    flags: u32,
+    timeouts: (c_int, c_int),
+) -> Result<SiPrivate, u32> {
+    nth_interface(device, nth).ok_or(SI_INVALID_PARAMETER)?;
+    let (udev, node) = open_node(device).map_err(|err| {
+        log::error!("unable to open USB device: {}", err);
+        SI_SYSTEM_ERROR_CODE
+    })?;
+    claim(node, udev, nth, flags, timeouts)
+}
+
+/// Claims the `nth` interface of the device `udev`, opened on `node`, and
+/// enables its UART
+///
+/// `node` comes first so that it is dropped after `udev` on failure.
+fn claim(
+    node: Option<File>,
+    udev: DeviceHandle<GlobalContext>,
+    nth: usize,
+    flags: u32,
+    (rx_timeout, tx_timeout): (c_int, c_int),
+) -> Result<SiPrivate, u32> {
+    let (interface, endpoints) = nth_interface(&udev.device(), nth).ok_or(SI_INVALID_PARAMETER)?;
+    let bulk = |direction: u8| {
+        endpoints
+            .iter()
+            .find(|(address, kind)| {
+                *kind == TransferType::Bulk && address & ENDPOINT_IN == direction
+            })
+            .map(|(address, _)| *address)
+    };
+    let (ep_in, ep_out) = match (bulk(ENDPOINT_IN), bulk(0)) {
+        (Some(ep_in), Some(ep_out)) => (ep_in, ep_out),
+        _ => {
+            log::error!("unable to identify bulk in/out endpoints");
+            return Err(SI_SYSTEM_ERROR_CODE);
+        }
+    };
+    // Detach a kernel driver bound to the interface, like cp210x on Linux;
+    // platforms where libusb cannot tell have none to detach
+    let mut detached = false;
+    if flags & SI_OPEN_DETACH_KERNEL_DRIVER != 0 && udev.kernel_driver_active(interface) == Ok(true)
+    {
+        if let Err(err) = udev.detach_kernel_driver(interface) {
+            log::error!("unable to detach kernel driver: {}", err);
+            return Err(SI_DRIVER_DETACH_FAILED);
+        }
+        detached = true;
+    }
+    let reattach = detached && flags & SI_OPEN_REATTACH_KERNEL_DRIVER != 0;
+    if let Err(err) = udev.claim_interface(interface) {
+        log::error!(
+            "unable to claim interface: {}. Ensure the device is not claimed by a kernel driver \
+             and that you may access it.",
+            err
+        );
+        if reattach {
+            let _ = udev.attach_kernel_driver(interface);
+        }
+        return Err(SI_DEVICE_IO_FAILED);
+    }
+
+    let mut handle = SiPrivate {
+        magic: MAGIC,
+        udev,
+        node,
+        interface,
+        ep_out,
+        ep_in,
+        reattach,
+        rx_timeout,
+        tx_timeout,
+        cancelled: Arc::new(AtomicBool::new(false)),
+        buffer: Vec::with_capacity(BUF_SIZE),
+    };
+    handle.enable();
+    handle.fill_buffer(100);
+    Ok(handle)
+}
+
+pub unsafe fn SI_Open(device_num: c_int, p_handle: *mut *mut SiPrivate) -> c_int {
+    SI_OpenInterface(device_num, 0, p_handle)
+}
+
+pub unsafe fn SI_OpenInterface(
+    device_num: c_int,
+    interface: c_int,
+    p_handle: *mut *mut SiPrivate,
+) -> c_int {
+    SI_OpenEx(
+        device_num,
+        interface,
+        0,
+        RX_TIMEOUT.load(Ordering::Relaxed),
+        TX_TIMEOUT.load(Ordering::Relaxed),
+        p_handle,
+    )
+}
+
+pub unsafe fn SI_OpenEx(
+    device_num: c_int,
+    interface: c_int,
+    flags: c_int,
+    read_timeout: c_int,
+    write_timeout: c_int,
+    p_handle: *mut *mut SiPrivate,
+) -> c_int {
+    let nth = match usize::try_from(interface) {
+        Ok(nth) if !p_handle.is_null() && read_timeout >= 0 && write_timeout >= 0 => nth,
+        _ => return status(SI_INVALID_PARAMETER),
+    };
+    let opened = device(device_num)
+        .ok_or(SI_SYSTEM_ERROR_CODE)
+        .and_then(|device| open(&device, nth, flags as u32, (read_timeout, write_timeout)));
+    hand_out(opened, p_handle)
+}
+
+/// Opens interface `interface` of the device whose usbfs node is open as
+/// `fd`, which the handle takes over
+///
+/// `fd` is closed again if opening fails.
+#[cfg(target_os = "linux")]
+pub unsafe fn SI_OpenFd(
+    fd: c_int,
+    interface: c_int,
+    flags: c_int,
+    read_timeout: c_int,
+    write_timeout: c_int,
+    p_handle: *mut *mut SiPrivate,
+) -> c_int {
+    if fd < 0 {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let node = File::from_raw_fd(fd);
+    let nth = match usize::try_from(interface) {
+        Ok(nth) if !p_handle.is_null() && read_timeout >= 0 && write_timeout >= 0 => nth,
+        _ => return status(SI_INVALID_PARAMETER),
+    };
+    let udev = match GlobalContext::default().open_device_with_fd(node.as_raw_fd()) {
+        Ok(udev) => udev,
+        Err(err) => {
+            log::error!("unable to open USB device: {}", err);
+            return status(SI_SYSTEM_ERROR_CODE);
+        }
+    };
+    let opened = claim(
+        Some(node),
+        udev,
+        nth,
+        flags as u32,
+        (read_timeout, write_timeout),
+    );
+    hand_out(opened, p_handle)
+}
+
+/// Stores the handle `opened` in `p_handle`, or returns why opening failed
+unsafe fn hand_out(opened: Result<SiPrivate, u32>, p_handle: *mut *mut SiPrivate) -> c_int {
+    match opened {
+        Ok(handle) => {
+            let cancelled = Arc::clone(&handle.cancelled);
+            *p_handle = Box::into_raw(Box::new(handle));
+            register_cancel(*p_handle as usize, cancelled);
+            status(SI_SUCCESS)
+        }
+        Err(code) => status(code),
+    }
+}
+
+pub unsafe fn SI_GetNumInterfaces(device_num: c_int, num_interfaces: *mut c_int) -> c_int {
+    if num_interfaces.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let device = match device(device_num) {
+        Some(device) => device,
+        None => return status(SI_DEVICE_NOT_FOUND),
+    };
+    *num_interfaces = device
+        .config_descriptor(0)
+        .map_or(0, |config| c_int::from(config.num_interfaces()));
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_Close(handle: *mut SiPrivate) -> c_int {
+    let timeout = match valid(handle) {
+        Some(handle) => millis(handle.tx_timeout),
+        None => return status(SI_INVALID_HANDLE),
+    };
+    forget_cancel(handle as usize);
+    let mut handle = Box::from_raw(handle);
+    let index = u16::from(handle.interface);
+    let _ = handle
+        .udev
+        .write_control(0x40, 0x02, 0x0004, index, &[], timeout);
+    let _ = handle.udev.release_interface(handle.interface);
+    if handle.reattach {
+        if let Err(err) = handle.udev.attach_kernel_driver(handle.interface) {
+            log::warn!("unable to reattach kernel driver: {}", err);
+        }
+    }
+    handle.magic = 0;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_Read(
+    handle: *mut SiPrivate,
+    buffer: *mut c_char,
+    bytes_to_read: c_int,
+    bytes_returned: *mut c_int,
+    _o: *mut c_void,
+) -> c_int {
+    let timeout = match valid(handle) {
+        Some(handle) => handle.rx_timeout,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    SI_ReadWithTimeout(handle, buffer, bytes_to_read, bytes_returned, timeout)
+}
+
+pub unsafe fn SI_Write(
+    handle: *mut SiPrivate,
+    buffer: *mut c_char,
+    bytes_to_write: c_int,
+    bytes_written: *mut c_int,
+    _o: *mut c_void,
+) -> c_int {
+    let timeout = match valid(handle) {
+        Some(handle) => handle.tx_timeout,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    SI_WriteWithTimeout(handle, buffer, bytes_to_write, bytes_written, timeout)
+}
+
+pub unsafe fn SI_ReadWithTimeout(
+    handle: *mut SiPrivate,
+    buffer: *mut c_char,
+    bytes_to_read: c_int,
+    bytes_returned: *mut c_int,
+    timeout: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if buffer.is_null() || bytes_returned.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let buffer = slice::from_raw_parts_mut(buffer as *mut u8, bytes_to_read.max(0) as usize);
+    if handle.buffer.len() < buffer.len() {
+        // Data that arrives after a cancellation stays buffered for the next
+        // read
+        let cancelled = Arc::clone(&handle.cancelled);
+        let ended = sliced(timeout, &cancelled, |slice| {
+            let (result, read) = handle.fill_buffer(slice);
+            result != LIBUSB_ERROR_TIMEOUT || read > 0
+        });
+        if ended == Sliced::Cancelled {
+            *bytes_returned = 0;
+            return status(SI_IO_CANCELLED);
+        }
+    }
+    let read = handle.take_buffer(buffer);
+    *bytes_returned = read as c_int;
+    if read > 0 {
+        status(SI_SUCCESS)
+    } else {
+        status(SI_READ_TIMED_OUT)
+    }
+}
+
+pub unsafe fn SI_WriteWithTimeout(
+    handle: *mut SiPrivate,
+    buffer: *mut c_char,
+    bytes_to_write: c_int,
+    bytes_written: *mut c_int,
+    timeout: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if buffer.is_null() || bytes_written.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let buffer = slice::from_raw_parts(buffer as *const u8, bytes_to_write.max(0) as usize);
+
+    // Never let the opportunistic receive eat more than the caller's budget
+    let fill_timeout = if timeout > 0 && timeout < 100 {
+        timeout
+    } else {
+        100
+    };
+    handle.fill_buffer(fill_timeout);
+    let cancelled = Arc::clone(&handle.cancelled);
+    let (mut written, mut failed) = (0, false);
+    let ended = sliced(timeout, &cancelled, |slice| {
+        let rest = &buffer[written..];
+        let (result, sent) =
+            handle.bulk(handle.ep_out, rest.as_ptr() as *mut u8, rest.len(), slice);
+        written += sent;
+        failed = result != 0 && result != LIBUSB_ERROR_TIMEOUT;
+        failed || written == buffer.len()
+    });
+    handle.fill_buffer(fill_timeout);
+
+    *bytes_written = written as c_int;
+    match ended {
+        _ if failed => status(SI_WRITE_ERROR),
+        Sliced::Done => status(SI_SUCCESS),
+        Sliced::TimedOut => status(SI_WRITE_TIMED_OUT),
+        Sliced::Cancelled => status(SI_IO_CANCELLED),
+    }
+}
+
+pub unsafe fn SI_CancelIo(handle: *mut SiPrivate) -> c_int {
+    // Picked up by the pending read or write, or else the next one
+    cancel(handle as usize)
+}
+
+pub unsafe fn SI_ResetDevice(handle: *mut SiPrivate) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    // libusb restores the claimed interfaces unless the descriptors changed,
+    // in which case the device re-enumerates and has to be opened anew
+    match handle.udev.reset() {
+        Ok(()) => {
+            handle.enable();
+            status(SI_SUCCESS)
+        }
+        Err(rusb::Error::NotFound) => {
+            handle.buffer.clear();
+            status(SI_DEVICE_REENUMERATED)
+        }
+        Err(err) => {
+            log::error!("unable to reset USB device: {}", err);
+            status(SI_DEVICE_IO_FAILED)
+        }
+    }
+}
+
+pub unsafe fn SI_GetDeviceStatus(
+    handle: *mut SiPrivate,
+    device_status: *mut c_int,
+    timeout: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if device_status.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    // Standard GET_STATUS request, answered by every USB device
+    let mut data = [0; 2];
+    match handle
+        .udev
+        .read_control(ENDPOINT_IN, 0x00, 0, 0, &mut data, millis(timeout))
+    {
+        Ok(_) => {
+            *device_status = c_int::from(u16::from_le_bytes(data));
+            status(SI_SUCCESS)
+        }
+        Err(_) => status(SI_DEVICE_IO_FAILED),
+    }
+}
+
+pub unsafe fn SI_GetDeviceDetails(
+    device_num: c_int,
+    path: *mut c_char,
+    driver: *mut c_char,
+    capabilities: *mut c_int,
+) -> c_int {
+    if path.is_null() || driver.is_null() || capabilities.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let device = match device(device_num) {
+        Some(device) => device,
+        None => return status(SI_DEVICE_NOT_FOUND),
+    };
+    copy_string(path, &device_path(&device));
+    copy_string(driver, "");
+
+    *capabilities = 0;
+    let (_, endpoints) = first_interface(&device).unwrap_or_default();
+    for (address, kind) in endpoints {
+        let input = address & ENDPOINT_IN != 0;
+        *capabilities |= match kind {
+            TransferType::Bulk if input => SI_CAP_BULK_IN,
+            TransferType::Bulk => SI_CAP_BULK_OUT,
+            TransferType::Interrupt if input => SI_CAP_INTERRUPT_IN,
+            _ => 0,
+        } as c_int;
+    }
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetDeviceInfo(
+    handle: *mut SiPrivate,
+    serial: *mut c_char,
+    description: *mut c_char,
+    vid: *mut c_int,
+    pid: *mut c_int,
+    path: *mut c_char,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if serial.is_null() || description.is_null() || vid.is_null() || pid.is_null() || path.is_null()
+    {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let device = handle.udev.device();
+    let (vendor_id, product_id) = ids(&device);
+    *vid = c_int::from(vendor_id);
+    *pid = c_int::from(product_id);
+
+    // Strings are optional, a device without them gets empty ones
+    let string = |index: Option<u8>| {
+        index
+            .and_then(|index| handle.udev.read_string_descriptor_ascii(index).ok())
+            .unwrap_or_default()
+    };
+    let descriptor = device.device_descriptor().ok();
+    copy_string(
+        serial,
+        &string(
+            descriptor
+                .as_ref()
+                .and_then(|d| d.serial_number_string_index()),
+        ),
+    );
+    copy_string(
+        description,
+        &string(descriptor.as_ref().and_then(|d| d.product_string_index())),
+    );
+    copy_string(path, &device_path(&device));
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetDeviceProductString(
+    handle: *mut SiPrivate,
+    product: *mut c_void,
+    length: *mut u8,
+    convert_to_ascii: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if product.is_null() || length.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    // A device without a product string gets an empty one
+    *length = 0;
+    let index = match handle
+        .udev
+        .device()
+        .device_descriptor()
+        .ok()
+        .and_then(|descriptor| descriptor.product_string_index())
+    {
+        Some(index) => index,
+        None => return status(SI_SUCCESS),
+    };
+    // The raw string descriptor in US English, UTF-16LE after a 2 byte header
+    let mut desc = [0; SI_MAX_DEVICE_STRLEN as usize];
+    let read = match handle.udev.read_control(
+        ENDPOINT_IN,
+        GET_DESCRIPTOR,
+        u16::from(DT_STRING) << 8 | u16::from(index),
+        LANG_US_ENGLISH,
+        &mut desc,
+        millis(handle.tx_timeout),
+    ) {
+        Ok(read) if read >= 2 && desc[1] == DT_STRING => read,
+        _ => return status(SI_DEVICE_IO_FAILED),
+    };
+    let string = &desc[2..usize::from(desc[0]).clamp(2, read)];
+
+    // Neither form is null terminated
+    let out = product as *mut u8;
+    if convert_to_ascii != 0 {
+        for (i, unit) in string.chunks_exact(2).enumerate() {
+            *out.add(i) = if unit[1] != 0 { b'?' } else { unit[0] };
+        }
+        *length = (string.len() / 2) as u8;
+    } else {
+        ptr::copy_nonoverlapping(string.as_ptr(), out, string.len());
+        *length = string.len() as u8;
+    }
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetInterfaceNumber(handle: *mut SiPrivate, interface: *mut u8) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if interface.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    *interface = handle.interface;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetLibusbHandle(handle: *mut SiPrivate, udev: *mut *mut c_void) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if udev.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    *udev = handle.udev.as_raw().cast();
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_GetDeviceFd(handle: *mut SiPrivate, fd: *mut c_int) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if fd.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    *fd = node_fd(handle.node.as_ref());
+    status(SI_SUCCESS)
+}
+
+#[cfg(unix)]
+fn node_fd(node: Option<&File>) -> c_int {
+    node.map_or(-1, |node| node.as_raw_fd())
+}
+
+#[cfg(not(unix))]
+fn node_fd(_node: Option<&File>) -> c_int {
+    -1
+}
+
+pub unsafe fn SI_GetPartNumber(handle: *mut SiPrivate, part_num: *mut u8) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if part_num.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    let mut data = [0; 1];
+    match handle.udev.read_control(
+        CP210X_REQTYPE_DEVICE_TO_HOST,
+        CP210X_VENDOR_SPECIFIC,
+        CP210X_GET_PARTNUM,
+        u16::from(handle.interface),
+        &mut data,
+        millis(handle.tx_timeout),
+    ) {
+        Ok(1) => {
+            *part_num = data[0];
+            status(SI_SUCCESS)
+        }
+        _ => status(SI_DEVICE_IO_FAILED),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn SI_ControlTransfer(
+    handle: *mut SiPrivate,
+    request_type: c_int,
+    request: c_int,
+    value: c_int,
+    index: c_int,
+    data: *mut c_char,
+    length: c_int,
+    bytes_transferred: *mut c_int,
+    timeout: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if bytes_transferred.is_null() || (length > 0 && data.is_null()) {
+        return status(SI_INVALID_PARAMETER);
+    }
+    // A negative index addresses the claimed interface
+    let index = if index < 0 {
+        u16::from(handle.interface)
+    } else {
+        index as u16
+    };
+    let data: &mut [u8] = if length > 0 {
+        slice::from_raw_parts_mut(data as *mut u8, length as usize)
+    } else {
+        &mut []
+    };
+    let (request_type, request, value) = (request_type as u8, request as u8, value as u16);
+    let timeout = millis(timeout);
+    let transferred = if request_type & ENDPOINT_IN != 0 {
+        handle
+            .udev
+            .read_control(request_type, request, value, index, data, timeout)
+    } else {
+        handle
+            .udev
+            .write_control(request_type, request, value, index, data, timeout)
+    };
+    match transferred {
+        Ok(transferred) => {
+            *bytes_transferred = transferred as c_int;
+            status(SI_SUCCESS)
+        }
+        Err(_) => {
+            *bytes_transferred = 0;
+            status(SI_DEVICE_IO_FAILED)
+        }
+    }
+}
+
+pub unsafe fn SI_DeviceIOControl(
+    handle: *mut SiPrivate,
+    _io_control_code: c_int,
+    _in_buffer: *mut c_char,
+    _bytes_to_read: c_int,
+    _out_buffer: *mut c_char,
+    _bytes_to_write: c_int,
+) -> c_int {
+    match valid(handle) {
+        Some(_) => status(SI_SUCCESS),
+        None => status(SI_INVALID_HANDLE),
+    }
+}
+
+pub unsafe fn SI_FlushBuffers(
+    handle: *mut SiPrivate,
+    flush_transmit: c_char,
+    flush_receive: c_char,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    let mut purge = 0;
+    if flush_receive != 0 {
+        handle.buffer.clear();
+        purge |= CP210X_PURGE_RX;
+    }
+    if flush_transmit != 0 {
+        purge |= CP210X_PURGE_TX;
+    }
+    if purge == 0 {
+        return status(SI_SUCCESS);
+    }
+    // Clear the queues of the device's UART as well
+    match handle.udev.write_control(
+        CP210X_REQTYPE_HOST_TO_DEVICE,
+        CP210X_PURGE,
+        purge,
+        u16::from(handle.interface),
+        &[],
+        millis(handle.tx_timeout),
+    ) {
+        Ok(_) => status(SI_SUCCESS),
+        Err(_) => status(SI_DEVICE_IO_FAILED),
+    }
+}
+
+pub unsafe fn SI_SetHandleTimeouts(
+    handle: *mut SiPrivate,
+    read_timeout: c_int,
+    write_timeout: c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if read_timeout < 0 || write_timeout < 0 {
+        return status(SI_INVALID_PARAMETER);
+    }
+    handle.rx_timeout = read_timeout;
+    handle.tx_timeout = write_timeout;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_FillRXQueue(
+    handle: *mut SiPrivate,
+    timeout: c_int,
+    num_bytes_in_queue: *mut c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if num_bytes_in_queue.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    // Pull whatever the device has ready into the buffer, waiting at most
+    // `timeout`
+    handle.fill_buffer(timeout);
+    *num_bytes_in_queue = handle.buffer.len() as c_int;
+    status(SI_SUCCESS)
+}
+
+pub unsafe fn SI_CheckRXQueue(
+    handle: *mut SiPrivate,
+    num_bytes_in_queue: *mut c_int,
+    queue_status: *mut c_int,
+) -> c_int {
+    let handle = match valid(handle) {
+        Some(handle) => handle,
+        None => return status(SI_INVALID_HANDLE),
+    };
+    if num_bytes_in_queue.is_null() || queue_status.is_null() {
+        return status(SI_INVALID_PARAMETER);
+    }
+    *num_bytes_in_queue = handle.buffer.len() as c_int;
+    *queue_status = (SI_RX_NO_OVERRUN
+        | if handle.buffer.is_empty() {
+            SI_RX_EMPTY
+        } else {
+            SI_RX_READY
+        }) as c_int;
+    status(SI_SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_handles_and_parameters_are_rejected() {
+        let mut n = 0;
+        let mut buffer = [0 as c_char; 4];
+        unsafe {
+            assert_eq!(
+                SI_ReadWithTimeout(ptr::null_mut(), buffer.as_mut_ptr(), 4, &mut n, 0),
+                status(SI_INVALID_HANDLE)
+            );
+            assert_eq!(SI_Close(ptr::null_mut()), status(SI_INVALID_HANDLE));
+            assert_eq!(SI_CancelIo(ptr::null_mut()), status(SI_INVALID_HANDLE));
+            assert_eq!(SI_Open(0, ptr::null_mut()), status(SI_INVALID_PARAMETER));
+            #[cfg(target_os = "linux")]
+            assert_eq!(
+                SI_OpenFd(-1, 0, 0, 1000, 1000, &mut ptr::null_mut()),
+                status(SI_INVALID_PARAMETER)
+            );
+            assert_eq!(
+                SI_GetDeviceNumByPath(b"004\0".as_ptr() as *const c_char, &mut n),
+                status(SI_INVALID_PARAMETER)
+            );
+        }
+    }
+}
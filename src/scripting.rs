@@ -0,0 +1,224 @@
+//! Automating devices with scripts
+//!
+//! Enabled with the `scripting` feature. [`engine`] returns a
+//! [rhai](https://rhai.rs) engine that can open devices and talk to them,
+//! for ad-hoc interactions that do not deserve a Rust program of their own.
+//! The `sixpress` tool runs such scripts with `sixpress run script.rhai`:
+//!
+//! ```js
+//! let dev = open("0001A2F3");
+//! dev.gpio_write(0x01, 0x01);
+//! dev.write("PING\n");
+//! dev.expect("PONG", 200);
+//! print(`GPIO latch: ${dev.gpio_read()}`);
+//! dev.close();
+//! ```
+//!
+//! | Function                      | Effect                                                |
+//! | ----                          | ----                                                  |
+//! | `open(serial)`                | opens the device with this serial number              |
+//! | `open_index(index)`           | opens the device at this index                        |
+//! | `dev.write(data)`             | writes a string or blob, returns the bytes written    |
+//! | `dev.read(len)`               | reads up to `len` bytes as a blob                     |
+//! | `dev.expect(pattern, ms)`     | waits for a string or blob, fails the script if absent|
+//! | `dev.gpio_write(mask, state)` | sets GPIO pins                                        |
+//! | `dev.gpio_read()`             | reads the GPIO latch                                  |
+//! | `dev.close()`                 | closes the device                                     |
+//! | `sleep(ms)`                   | waits                                                 |
+use std::{cell::RefCell, convert::TryFrom, path::Path, rc::Rc, thread, time::Duration};
+
+use rhai::{Blob, Engine, EvalAltResult};
+
+use crate::{selftest, SilabsUsbXpressError, UsbXpress};
+
+type ScriptResult<T> = Result<T, Box<EvalAltResult>>;
+
+/// An open device as seen by scripts
+#[derive(Clone)]
+struct Device(Rc<RefCell<DeviceState>>);
+
+struct DeviceState {
+    handle: Option<UsbXpress>,
+    /// Data read while waiting in `expect` and not consumed by it yet
+    received: Vec<u8>,
+}
+
+impl Device {
+    fn new(handle: UsbXpress) -> Self {
+        Device(Rc::new(RefCell::new(DeviceState {
+            handle: Some(handle),
+            received: Vec::new(),
+        })))
+    }
+
+    fn with<T, F>(&mut self, f: F) -> ScriptResult<T>
+    where
+        F: FnOnce(&mut UsbXpress, &mut Vec<u8>) -> Result<T, String>,
+    {
+        let mut state = self.0.borrow_mut();
+        let state = &mut *state;
+        match state.handle.as_mut() {
+            Some(handle) => f(handle, &mut state.received).map_err(Into::into),
+            None => Err("device is closed".into()),
+        }
+    }
+
+    fn write(&mut self, data: &[u8]) -> ScriptResult<i64> {
+        self.with(|handle, _| {
+            let written = handle.write(data).map_err(error)?;
+            Ok(written as i64)
+        })
+    }
+
+    fn read(&mut self, len: i64) -> ScriptResult<Blob> {
+        let len = usize::try_from(len).map_err(|err| err.to_string())?;
+        self.with(|handle, received| {
+            let mut data: Vec<u8> = received.drain(..len.min(received.len())).collect();
+            if data.len() < len {
+                data.extend(handle.read(len - data.len()).map_err(error)?);
+            }
+            Ok(data)
+        })
+    }
+
+    fn expect(&mut self, pattern: &[u8], millis: i64) -> ScriptResult<()> {
+        let within = Duration::from_millis(u64::try_from(millis).map_err(|err| err.to_string())?);
+        self.with(|handle, received| selftest::expect(handle, pattern, within, received))
+    }
+
+    fn gpio_write(&mut self, mask: i64, state: i64) -> ScriptResult<()> {
+        let mask = u8::try_from(mask).map_err(|err| err.to_string())?;
+        let state = u8::try_from(state).map_err(|err| err.to_string())?;
+        self.with(|handle, _| handle.write_gpio_latch(mask, state).map_err(error))
+    }
+
+    fn gpio_read(&mut self) -> ScriptResult<i64> {
+        self.with(|handle, _| handle.read_gpio_latch().map(i64::from).map_err(error))
+    }
+
+    fn close(&mut self) -> ScriptResult<()> {
+        match self.0.borrow_mut().handle.take() {
+            Some(handle) => handle.close().map_err(|err| error(err).into()),
+            None => Ok(()),
+        }
+    }
+}
+
+fn error(err: SilabsUsbXpressError) -> String {
+    err.to_string()
+}
+
+fn open(serial: &str) -> ScriptResult<Device> {
+    Ok(Device::new(
+        UsbXpress::open_by_serial(serial).map_err(error)?,
+    ))
+}
+
+fn open_index(index: i64) -> ScriptResult<Device> {
+    let index = usize::try_from(index).map_err(|err| err.to_string())?;
+    Ok(Device::new(UsbXpress::open(index).map_err(error)?))
+}
+
+/// Returns an engine with the device functions registered, see the
+/// [module documentation](self)
+pub fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .register_type_with_name::<Device>("Device")
+        .register_fn("open", open)
+        .register_fn("open_index", open_index)
+        .register_fn("sleep", |millis: i64| {
+            thread::sleep(Duration::from_millis(millis.max(0) as u64))
+        })
+        .register_fn("write", |dev: &mut Device, data: &str| {
+            dev.write(data.as_bytes())
+        })
+        .register_fn("write", |dev: &mut Device, data: Blob| dev.write(&data))
+        .register_fn("read", Device::read)
+        .register_fn("expect", |dev: &mut Device, pattern: &str, millis: i64| {
+            dev.expect(pattern.as_bytes(), millis)
+        })
+        .register_fn("expect", |dev: &mut Device, pattern: Blob, millis: i64| {
+            dev.expect(&pattern, millis)
+        })
+        .register_fn("gpio_write", Device::gpio_write)
+        .register_fn("gpio_read", Device::gpio_read)
+        .register_fn("close", Device::close);
+    engine
+}
+
+/// Runs `script`
+pub fn run(script: &str) -> Result<(), Box<EvalAltResult>> {
+    engine().run(script)
+}
+
+/// Runs the script in the file at `path`
+pub fn run_file<P: AsRef<Path>>(path: P) -> Result<(), Box<EvalAltResult>> {
+    engine().run_file(path.as_ref().to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhai::Scope;
+
+    /// Runs `script` with a closed device in `dev`, returning the error, if
+    /// any, as text
+    fn run_closed(script: &str) -> Result<(), String> {
+        let dev = Device(Rc::new(RefCell::new(DeviceState {
+            handle: None,
+            received: b"stale".to_vec(),
+        })));
+        let mut scope = Scope::new();
+        scope.push("dev", dev);
+        engine()
+            .run_with_scope(&mut scope, script)
+            .map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn closed_devices_fail_the_script() {
+        for script in [
+            r#"dev.write("PING\n")"#,
+            "dev.write(blob(2, 0x55))",
+            "dev.read(4)",
+            r#"dev.expect("PONG", 10)"#,
+            "dev.expect(blob(1, 0x06), 10)",
+            "dev.gpio_write(1, 1)",
+            "dev.gpio_read()",
+        ] {
+            let err = run_closed(script).unwrap_err();
+            assert!(err.contains("device is closed"), "{}: {}", script, err);
+        }
+        // Closing twice is fine
+        run_closed("dev.close(); dev.close();").unwrap();
+    }
+
+    #[test]
+    fn out_of_range_numbers_fail_the_script() {
+        for script in [
+            "dev.read(-1)",
+            r#"dev.expect("PONG", -1)"#,
+            "dev.gpio_write(256, 0)",
+            "dev.gpio_write(1, -1)",
+            "open_index(-1)",
+        ] {
+            let err = run_closed(script).unwrap_err();
+            assert!(!err.contains("device is closed"), "{}: {}", script, err);
+            assert!(!err.contains("Function not found"), "{}: {}", script, err);
+        }
+    }
+
+    #[test]
+    fn unknown_functions_are_reported() {
+        let err = run_closed("dev.flush()").unwrap_err();
+        assert!(err.contains("Function not found"), "{}", err);
+    }
+
+    #[test]
+    fn scripts_can_sleep() {
+        let started = std::time::Instant::now();
+        run("sleep(20); sleep(-5);").unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(20));
+    }
+}
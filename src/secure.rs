@@ -0,0 +1,349 @@
+//! Encrypted sessions over a device's byte stream
+//!
+//! Enabled with the `secure` feature. A [`Handshake`] runs a
+//! [Noise](https://noiseprotocol.org) handshake with the device firmware
+//! over its normal reads and writes and yields a [`SecureSession`] that
+//! encrypts and authenticates everything sent afterwards, so provisioning
+//! secrets stay confidential on the way to devices in the field.
+//!
+//! Every Noise message travels as its length, a little endian 16 bit word,
+//! followed by the message. Which handshake pattern is used, and which keys
+//! both sides need, is agreed with the firmware, for example a pre-shared
+//! key in `Noise_NNpsk0_25519_ChaChaPoly_BLAKE2s`:
+//!
+//! ```rust, ignore
+//! use silabs_usb_xpress::secure::Handshake;
+//!
+//! let mut session = Handshake::new("Noise_NNpsk0_25519_ChaChaPoly_BLAKE2s")
+//!     .psk(0, &device_psk)
+//!     .initiate(handle)?;
+//! session.write(b"SET KEY ...")?;
+//! let reply = session.read(Duration::from_secs(1))?;
+//! ```
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{transport::Transport, SilabsUsbXpressError};
+
+/// Largest Noise message
+const MAX_MESSAGE: usize = 65535;
+/// Length of the authentication tag of every encrypted message
+const TAG_LEN: usize = 16;
+/// Bytes requested from the device per read
+const READ_CHUNK: usize = 4096;
+
+/// Settings of a Noise handshake
+#[derive(Clone)]
+pub struct Handshake {
+    params: String,
+    local_private_key: Option<Vec<u8>>,
+    remote_public_key: Option<Vec<u8>>,
+    psks: Vec<(u8, Vec<u8>)>,
+    prologue: Vec<u8>,
+    timeout: Duration,
+}
+
+impl Handshake {
+    /// Runs the handshake named by `params`, like
+    /// `Noise_XX_25519_ChaChaPoly_BLAKE2s`
+    pub fn new<S: Into<String>>(params: S) -> Self {
+        Handshake {
+            params: params.into(),
+            local_private_key: None,
+            remote_public_key: None,
+            psks: Vec::new(),
+            prologue: Vec::new(),
+            timeout: Duration::from_secs(5),
+        }
+    }
+
+    /// Authenticates this side with the static private key `key`
+    pub fn local_private_key(mut self, key: &[u8]) -> Self {
+        self.local_private_key = Some(key.to_vec());
+        self
+    }
+
+    /// Expects the device to hold the private key matching `key`, for
+    /// patterns where its static key is known in advance
+    pub fn remote_public_key(mut self, key: &[u8]) -> Self {
+        self.remote_public_key = Some(key.to_vec());
+        self
+    }
+
+    /// Mixes the 32 byte pre-shared key `key` in at `location`, as the
+    /// `pskN` modifier of the pattern requires
+    pub fn psk(mut self, location: u8, key: &[u8]) -> Self {
+        self.psks.push((location, key.to_vec()));
+        self
+    }
+
+    /// Binds the session to `prologue`, data both sides know but do not send
+    pub fn prologue(mut self, prologue: &[u8]) -> Self {
+        self.prologue = prologue.to_vec();
+        self
+    }
+
+    /// Gives the whole handshake `timeout` to complete, five seconds by
+    /// default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Runs the handshake as the initiator, which is the usual role of the
+    /// host
+    pub fn initiate<T: Transport>(&self, transport: T) -> Result<SecureSession<T>, SecureError> {
+        self.run(transport, true)
+    }
+
+    /// Runs the handshake as the responder
+    pub fn respond<T: Transport>(&self, transport: T) -> Result<SecureSession<T>, SecureError> {
+        self.run(transport, false)
+    }
+
+    fn run<T: Transport>(
+        &self,
+        transport: T,
+        initiator: bool,
+    ) -> Result<SecureSession<T>, SecureError> {
+        let deadline = Instant::now() + self.timeout;
+        let mut builder = snow::Builder::new(self.params.parse().map_err(SecureError::Noise)?)
+            .prologue(&self.prologue);
+        if let Some(key) = &self.local_private_key {
+            builder = builder.local_private_key(key);
+        }
+        if let Some(key) = &self.remote_public_key {
+            builder = builder.remote_public_key(key);
+        }
+        for (location, key) in &self.psks {
+            builder = builder.psk(*location, key);
+        }
+        let mut noise = if initiator {
+            builder.build_initiator()
+        } else {
+            builder.build_responder()
+        }
+        .map_err(SecureError::Noise)?;
+
+        let mut framing = Framing {
+            transport,
+            received: Vec::new(),
+        };
+        let mut buf = vec![0; MAX_MESSAGE];
+        while !noise.is_handshake_finished() {
+            if noise.is_my_turn() {
+                let len = noise
+                    .write_message(&[], &mut buf)
+                    .map_err(SecureError::Noise)?;
+                framing.send(&buf[..len])?;
+            } else {
+                let message = framing
+                    .receive_until(deadline)?
+                    .ok_or(SecureError::HandshakeTimedOut)?;
+                noise
+                    .read_message(&message, &mut buf)
+                    .map_err(SecureError::Noise)?;
+            }
+        }
+        Ok(SecureSession {
+            framing,
+            noise: noise.into_transport_mode().map_err(SecureError::Noise)?,
+        })
+    }
+}
+
+impl fmt::Debug for Handshake {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Keys are left out on purpose.
+        f.debug_struct("Handshake")
+            .field("params", &self.params)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+/// Length prefixed messages over a transport
+struct Framing<T> {
+    transport: T,
+    received: Vec<u8>,
+}
+
+impl<T: Transport> Framing<T> {
+    fn send(&mut self, message: &[u8]) -> Result<(), SecureError> {
+        let mut frame = Vec::with_capacity(message.len() + 2);
+        frame.extend_from_slice(&(message.len() as u16).to_le_bytes());
+        frame.extend_from_slice(message);
+        let started = Instant::now();
+        let written = self.transport.write(&frame)?;
+        if written < frame.len() {
+            return Err(SecureError::Device(SilabsUsbXpressError::WriteTimeOut {
+                elapsed: started.elapsed(),
+                transferred: written,
+                remaining: frame.len() - written,
+                chunk: 0,
+            }));
+        }
+        Ok(())
+    }
+
+    /// Returns the next complete message, or `None` if none arrived before
+    /// `deadline`
+    fn receive_until(&mut self, deadline: Instant) -> Result<Option<Vec<u8>>, SecureError> {
+        loop {
+            if self.received.len() >= 2 {
+                let len = u16::from_le_bytes([self.received[0], self.received[1]]) as usize;
+                if self.received.len() >= 2 + len {
+                    let message = self.received[2..2 + len].to_vec();
+                    self.received.drain(..2 + len);
+                    return Ok(Some(message));
+                }
+            }
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+            match self.transport.read(READ_CHUNK) {
+                Ok(data) => self.received.extend_from_slice(&data),
+                Err(SilabsUsbXpressError::ReadTimeOut { .. }) => {}
+                Err(err) => return Err(SecureError::Device(err)),
+            }
+        }
+    }
+}
+
+/// An encrypted session with a device
+pub struct SecureSession<T> {
+    framing: Framing<T>,
+    noise: snow::TransportState,
+}
+
+impl<T: Transport> SecureSession<T> {
+    /// Encrypts and sends `data`, split into as many messages as needed
+    pub fn write(&mut self, data: &[u8]) -> Result<(), SecureError> {
+        let mut buf = vec![0; MAX_MESSAGE];
+        for chunk in data.chunks(MAX_MESSAGE - TAG_LEN) {
+            let len = self
+                .noise
+                .write_message(chunk, &mut buf)
+                .map_err(SecureError::Noise)?;
+            self.framing.send(&buf[..len])?;
+        }
+        Ok(())
+    }
+
+    /// Receives and decrypts the next message, waiting at most `timeout`
+    ///
+    /// Fails with [`SecureError::Noise`] if the message was not sent by the
+    /// other side of this session or was tampered with.
+    pub fn read(&mut self, timeout: Duration) -> Result<Vec<u8>, SecureError> {
+        let started = Instant::now();
+        let message = match self.framing.receive_until(started + timeout)? {
+            Some(message) => message,
+            None => {
+                return Err(SecureError::Device(SilabsUsbXpressError::ReadTimeOut {
+                    elapsed: started.elapsed(),
+                    transferred: self.framing.received.len(),
+                    remaining: 0,
+                }))
+            }
+        };
+        let mut buf = vec![0; message.len()];
+        let len = self
+            .noise
+            .read_message(&message, &mut buf)
+            .map_err(SecureError::Noise)?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Returns the device's static public key, if the pattern transmitted
+    /// or required one
+    pub fn remote_static(&self) -> Option<&[u8]> {
+        self.noise.get_remote_static()
+    }
+
+    /// Ends the session and returns the transport
+    pub fn into_inner(self) -> T {
+        self.framing.transport
+    }
+}
+
+#[derive(Debug)]
+pub enum SecureError {
+    Device(SilabsUsbXpressError),
+    /// The handshake failed, or a message did not decrypt
+    Noise(snow::Error),
+    HandshakeTimedOut,
+}
+
+impl From<SilabsUsbXpressError> for SecureError {
+    fn from(err: SilabsUsbXpressError) -> Self {
+        SecureError::Device(err)
+    }
+}
+
+impl fmt::Display for SecureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&format!("{:?}", self))
+    }
+}
+
+impl std::error::Error for SecureError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::mpsc, thread};
+
+    /// One end of an in-memory link
+    struct Pipe {
+        tx: mpsc::Sender<Vec<u8>>,
+        rx: mpsc::Receiver<Vec<u8>>,
+    }
+
+    fn pipes() -> (Pipe, Pipe) {
+        let (a_tx, a_rx) = mpsc::channel();
+        let (b_tx, b_rx) = mpsc::channel();
+        (Pipe { tx: a_tx, rx: b_rx }, Pipe { tx: b_tx, rx: a_rx })
+    }
+
+    impl Transport for Pipe {
+        fn read(&mut self, _max: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+            Ok(self
+                .rx
+                .recv_timeout(Duration::from_millis(10))
+                .unwrap_or_default())
+        }
+
+        fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+            let _ = self.tx.send(data.to_vec());
+            Ok(data.len())
+        }
+    }
+
+    #[test]
+    fn sessions_exchange_data_with_a_shared_psk() {
+        let handshake = Handshake::new("Noise_NNpsk0_25519_ChaChaPoly_BLAKE2s").psk(0, &[7; 32]);
+        let (host, device) = pipes();
+        let responder = handshake.clone();
+        let device = thread::spawn(move || {
+            let mut session = responder.respond(device).unwrap();
+            let request = session.read(Duration::from_secs(5)).unwrap();
+            session.write(&request.repeat(2)).unwrap();
+        });
+        let mut session = handshake.initiate(host).unwrap();
+        session.write(b"ping").unwrap();
+        assert_eq!(session.read(Duration::from_secs(5)).unwrap(), b"pingping");
+        device.join().unwrap();
+
+        let (host, device) = pipes();
+        let impostor = Handshake::new("Noise_NNpsk0_25519_ChaChaPoly_BLAKE2s").psk(0, &[8; 32]);
+        let device = thread::spawn(move || impostor.respond(device).map(|_| ()));
+        assert!(matches!(
+            handshake.timeout(Duration::from_millis(200)).initiate(host),
+            Err(SecureError::HandshakeTimedOut)
+        ));
+        assert!(matches!(device.join().unwrap(), Err(SecureError::Noise(_))));
+    }
+}
@@ -0,0 +1,300 @@
+//! Scripted self-tests of devices
+//!
+//! A [`Plan`] is a list of steps, such as sending a frame, expecting a
+//! response, driving GPIO pins and checking modem lines, that [`Plan::run`]
+//! executes against an open device. The result is a [`Report`] with a pass
+//! or fail verdict for every step, for incoming-inspection benches:
+//!
+//! ```rust, ignore
+//! use silabs_usb_xpress::{
+//!     selftest::{InputLine, Plan, Step},
+//!     ModemLine,
+//! };
+//!
+//! let plan = Plan::new("loopback board")
+//!     .step("status LED on", Step::WriteGpio { mask: 0x01, state: 0x01 })
+//!     .step("RTS looped to CTS", Step::SetModemLine { line: ModemLine::Rts, high: true })
+//!     .step("CTS follows RTS", Step::ExpectInputLine { line: InputLine::Cts, high: true })
+//!     .step("ping", Step::Send(b"PING\n".to_vec()))
+//!     .step("pong", Step::Expect { pattern: b"PONG".to_vec(), within: Duration::from_millis(200) });
+//! let report = plan.run(&mut handle);
+//! println!("{}", report);
+//! assert!(report.passed());
+//! ```
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+use crate::{ModemLine, SilabsUsbXpressError, UsbXpress};
+
+/// A CP210x modem status input
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InputLine {
+    /// Clear To Send
+    Cts,
+    /// Data Set Ready
+    Dsr,
+    /// Ring Indicator
+    Ri,
+    /// Data Carrier Detect
+    Dcd,
+}
+
+impl InputLine {
+    /// Bit of the line in the CP210x modem status byte
+    fn bit(self) -> u8 {
+        match self {
+            InputLine::Cts => 0x10,
+            InputLine::Dsr => 0x20,
+            InputLine::Ri => 0x40,
+            InputLine::Dcd => 0x80,
+        }
+    }
+}
+
+/// One step of a [`Plan`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Writes the bytes to the device
+    Send(Vec<u8>),
+    /// Passes once `pattern` appears in the data received since the previous
+    /// `Expect`; data up to the end of the pattern is consumed
+    Expect {
+        pattern: Vec<u8>,
+        within: Duration,
+    },
+    /// Sets GPIO pins, see [`UsbXpress::write_gpio_latch`]
+    WriteGpio {
+        mask: u8,
+        state: u8,
+    },
+    /// Passes if the GPIO pins in `mask` read as in `state`
+    ExpectGpio {
+        mask: u8,
+        state: u8,
+    },
+    SetModemLine {
+        line: ModemLine,
+        high: bool,
+    },
+    /// Passes if the modem status input is at the given level
+    ExpectInputLine {
+        line: InputLine,
+        high: bool,
+    },
+    /// Waits, e.g. for the device to react to a GPIO change
+    Delay(Duration),
+}
+
+/// What happened to a step
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Passed,
+    /// The step failed for the given reason
+    Failed(String),
+    /// An earlier step failed, so the step was not run
+    Skipped,
+}
+
+/// Verdict on one step of a plan
+#[derive(Clone, Debug)]
+pub struct StepReport {
+    pub name: String,
+    pub verdict: Verdict,
+    pub elapsed: Duration,
+}
+
+/// Result of running a [`Plan`]
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub plan: String,
+    pub serial: String,
+    pub steps: Vec<StepReport>,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    /// Returns whether every step passed
+    pub fn passed(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|step| step.verdict == Verdict::Passed)
+    }
+
+    /// Returns the first failed step
+    pub fn failure(&self) -> Option<&StepReport> {
+        self.steps
+            .iter()
+            .find(|step| matches!(step.verdict, Verdict::Failed(_)))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} on {:?}: {} in {:.2}s",
+            self.plan,
+            self.serial,
+            if self.passed() { "PASS" } else { "FAIL" },
+            self.elapsed.as_secs_f64()
+        )?;
+        for step in &self.steps {
+            match &step.verdict {
+                Verdict::Passed => writeln!(f, "  pass  {}", step.name)?,
+                Verdict::Failed(reason) => writeln!(f, "  FAIL  {}: {}", step.name, reason)?,
+                Verdict::Skipped => writeln!(f, "  skip  {}", step.name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A named sequence of test steps
+#[derive(Clone, Debug, Default)]
+pub struct Plan {
+    name: String,
+    steps: Vec<(String, Step)>,
+}
+
+impl Plan {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Plan {
+            name: name.into(),
+            steps: Vec::new(),
+        }
+    }
+
+    /// Appends a step, named for the report
+    pub fn step<S: Into<String>>(mut self, name: S, step: Step) -> Self {
+        self.steps.push((name.into(), step));
+        self
+    }
+
+    /// Runs the steps in order against `handle`
+    ///
+    /// Stale received data is flushed first. The first failing step ends
+    /// the run and the remaining steps are reported as skipped.
+    pub fn run(&self, handle: &mut UsbXpress) -> Report {
+        let started = Instant::now();
+        let mut received = Vec::new();
+        let mut failed = handle.flush(false, true).err().map(|err| err.to_string());
+        let mut steps = Vec::with_capacity(self.steps.len());
+        for (name, step) in &self.steps {
+            let step_started = Instant::now();
+            let verdict = match failed {
+                Some(_) => Verdict::Skipped,
+                None => match run_step(handle, step, &mut received) {
+                    Ok(()) => Verdict::Passed,
+                    Err(reason) => {
+                        failed = Some(reason.clone());
+                        Verdict::Failed(reason)
+                    }
+                },
+            };
+            steps.push(StepReport {
+                name: name.clone(),
+                verdict,
+                elapsed: step_started.elapsed(),
+            });
+        }
+        Report {
+            plan: self.name.clone(),
+            serial: handle.serial().to_owned(),
+            steps,
+            elapsed: started.elapsed(),
+        }
+    }
+}
+
+fn run_step(handle: &mut UsbXpress, step: &Step, received: &mut Vec<u8>) -> Result<(), String> {
+    let device = |err: SilabsUsbXpressError| err.to_string();
+    match step {
+        Step::Send(data) => {
+            let deadline = Instant::now() + handle.write_timeout_for(data.len());
+            handle
+                .write_until_deadline(data, deadline)
+                .map_err(device)?;
+        }
+        Step::Expect { pattern, within } => expect(handle, pattern, *within, received)?,
+        Step::WriteGpio { mask, state } => {
+            handle.write_gpio_latch(*mask, *state).map_err(device)?
+        }
+        Step::ExpectGpio { mask, state } => {
+            let latch = handle.read_gpio_latch().map_err(device)?;
+            if latch & mask != state & mask {
+                return Err(format!(
+                    "GPIO {:#04x} read {:#04x}, expected {:#04x}",
+                    mask,
+                    latch & mask,
+                    state & mask
+                ));
+            }
+        }
+        Step::SetModemLine { line, high } => handle.set_modem_line(*line, *high).map_err(device)?,
+        Step::ExpectInputLine { line, high } => {
+            let status = handle.modem_status().map_err(device)?;
+            if (status.bits() & line.bit() != 0) != *high {
+                return Err(format!(
+                    "{:?} is {}",
+                    line,
+                    if *high { "low" } else { "high" }
+                ));
+            }
+        }
+        Step::Delay(delay) => thread::sleep(*delay),
+    }
+    Ok(())
+}
+
+/// Reads until `pattern` shows up in `received`, then drops everything up to
+/// and including it
+pub(crate) fn expect(
+    handle: &mut UsbXpress,
+    pattern: &[u8],
+    within: Duration,
+    received: &mut Vec<u8>,
+) -> Result<(), String> {
+    let deadline = Instant::now() + within;
+    loop {
+        if let Some(end) = find(received, pattern) {
+            received.drain(..end);
+            return Ok(());
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Err(format!(
+                "{:02x?} not received within {:?}, got {:02x?}",
+                pattern, within, received
+            ));
+        }
+        let mut chunk = [0; 256];
+        let n = handle
+            .read_once(&mut chunk, deadline - now)
+            .map_err(|err| err.to_string())?;
+        received.extend_from_slice(&chunk[..n]);
+    }
+}
+
+/// Returns the end of the first occurrence of `pattern` in `data`
+fn find(data: &[u8], pattern: &[u8]) -> Option<usize> {
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    data.windows(pattern.len())
+        .position(|window| window == pattern)
+        .map(|start| start + pattern.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_returns_the_end_of_the_pattern() {
+        assert_eq!(find(b"xxPONG\r\n", b"PONG"), Some(6));
+        assert_eq!(find(b"PON", b"PONG"), None);
+        assert_eq!(find(b"abc", b""), Some(0));
+    }
+}
@@ -0,0 +1,572 @@
+//! HTTP device server
+//!
+//! Enabled with the `server` feature. A [`Server`] owns the devices of one
+//! host and exposes them over a small REST API, so that non-Rust clients
+//! and remote dashboards can drive them. Devices are addressed by serial
+//! number and opened on first use:
+//!
+//! | Request                             | Effect                                         |
+//! | ----                                | ----                                           |
+//! | `GET /devices`                      | inventory as JSON, see [`inventory`]           |
+//! | `POST /devices/{serial}/write`      | writes the request body                        |
+//! | `GET /devices/{serial}/read?len=N`  | reads up to `N` bytes, 4096 by default         |
+//! | `GET /devices/{serial}/stream`      | streams everything the device sends            |
+//! | `POST /devices/{serial}/gpio?mask=M&state=S` | sets GPIO pins                        |
+//! | `GET /devices/{serial}/gpio`        | reads the GPIO latch                           |
+//! | `POST /devices/{serial}/baud?rate=N`| configures the UART, see below                 |
+//! | `DELETE /devices/{serial}`          | closes the device                              |
+//!
+//! Data is transferred as `application/octet-stream`, everything else as
+//! JSON. Numbers in queries are decimal or `0x` prefixed hexadecimal. Device
+//! errors are answered with `404` for unknown devices, `409` for devices held
+//! by another driver, `504` for timeouts and `500` otherwise.
+//!
+//! `/baud` sets the baud rate with [`UsbXpress::set_baud_rate`]. The framing
+//! is changed as well when any of `data_bits` (5 to 8), `parity` (`none`,
+//! `odd`, `even`, `mark` or `space`) or `stop_bits` (`1`, `1.5` or `2`) is
+//! given, with 8N1 for the ones left out, and the hardware flow control when
+//! `flow` (`none`, `rts_cts` or `dtr_dsr`) is given.
+//!
+//! `/stream` answers with a chunked HTTP/1.1 body that carries the data of
+//! the device as it arrives, until the client disconnects or the device is
+//! closed with `DELETE`.
+//!
+//! Every request is served on its own thread. Requests to the same device
+//! take turns, requests to different devices run side by side, so a slow
+//! read or an open stream only holds up its own device.
+//!
+//! The API drives hardware and has no access control of its own. Bind it to
+//! `127.0.0.1` unless the network is trusted, or require a bearer token with
+//! [`Server::with_token`]:
+//!
+//! ```rust, ignore
+//! silabs_usb_xpress::server::Server::bind("127.0.0.1:8040")?.run();
+//! ```
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    io::{self, Write},
+    net::ToSocketAddrs,
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
+
+use tiny_http::{Header, Method, Request, Response};
+
+use crate::{
+    inventory, DataBits, FlowControl, LineControl, Parity, SilabsUsbXpressError, StopBits,
+    UsbXpress,
+};
+
+/// Largest read served by a single request
+const MAX_READ: usize = 65536;
+
+/// Largest chunk of a `/stream` body
+const STREAM_CHUNK: usize = 4096;
+
+/// How long a `/stream` waits for data before it checks whether the device
+/// was closed, and lets other requests to the device have their turn
+const STREAM_POLL: Duration = Duration::from_millis(100);
+
+/// An open device, shared by the requests addressing it
+type Device = Arc<Mutex<UsbXpress>>;
+
+/// A REST server for the devices of this host
+pub struct Server {
+    http: tiny_http::Server,
+    token: Option<String>,
+}
+
+/// The state every request thread works on
+struct Shared {
+    devices: Mutex<HashMap<String, Device>>,
+    token: Option<String>,
+}
+
+/// An error answer, with its HTTP status code
+struct Failure(u16, String);
+
+impl From<SilabsUsbXpressError> for Failure {
+    fn from(err: SilabsUsbXpressError) -> Self {
+        let status = match err {
+            SilabsUsbXpressError::DeviceNotFound
+            | SilabsUsbXpressError::SerialNotFound(_)
+            | SilabsUsbXpressError::VidPidNotFound { .. } => 404,
+            SilabsUsbXpressError::DeviceBusy
+            | SilabsUsbXpressError::KernelDriverConflict { .. }
+            | SilabsUsbXpressError::DuplicateSerial { .. } => 409,
+            SilabsUsbXpressError::ReadTimeOut { .. }
+            | SilabsUsbXpressError::IncompleteRead { .. }
+            | SilabsUsbXpressError::WriteTimeOut { .. }
+            | SilabsUsbXpressError::OpenTimedOut
+            | SilabsUsbXpressError::DeviceWaitTimedOut => 504,
+            SilabsUsbXpressError::NeedsReopen { .. } => 503,
+            _ => 500,
+        };
+        Failure(status, err.to_string())
+    }
+}
+
+type Answer = Result<Response<io::Cursor<Vec<u8>>>, Failure>;
+
+/// What a request asks for, see the table in the [module docs](self)
+#[derive(Debug, PartialEq, Eq)]
+enum Route<'a> {
+    Devices,
+    Close(&'a str),
+    Write(&'a str),
+    Read(&'a str),
+    Stream(&'a str),
+    Gpio(&'a str),
+    SetGpio(&'a str),
+    Baud(&'a str),
+}
+
+/// Returns the route of a `method` request to `path`, without its query
+fn route<'a>(method: &Method, path: &'a str) -> Option<Route<'a>> {
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let route = match (method, segments.as_slice()) {
+        (Method::Get, ["devices"]) => Route::Devices,
+        (Method::Delete, ["devices", serial]) => Route::Close(serial),
+        (Method::Post, ["devices", serial, "write"]) => Route::Write(serial),
+        (Method::Get, ["devices", serial, "read"]) => Route::Read(serial),
+        (Method::Get, ["devices", serial, "stream"]) => Route::Stream(serial),
+        (Method::Get, ["devices", serial, "gpio"]) => Route::Gpio(serial),
+        (Method::Post, ["devices", serial, "gpio"]) => Route::SetGpio(serial),
+        (Method::Post, ["devices", serial, "baud"]) => Route::Baud(serial),
+        _ => return None,
+    };
+    Some(route)
+}
+
+impl Server {
+    /// Listens on `addr`
+    pub fn bind<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let http = tiny_http::Server::http(addr).map_err(io::Error::other)?;
+        Ok(Server { http, token: None })
+    }
+
+    /// Requires every request to carry `Authorization: Bearer <token>`
+    ///
+    /// Requests without it are answered with `401`. The token travels in
+    /// plain text, so this keeps out other users of a shared network, not
+    /// anyone who can read the traffic.
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Serves requests forever, each on its own thread
+    pub fn run(self) {
+        let shared = Arc::new(Shared {
+            devices: Mutex::new(HashMap::new()),
+            token: self.token,
+        });
+        loop {
+            match self.http.recv() {
+                Ok(request) => {
+                    let shared = shared.clone();
+                    thread::spawn(move || shared.handle(request));
+                }
+                Err(err) => log::warn!("server: {}", err),
+            }
+        }
+    }
+}
+
+/// How a request is answered
+enum Reply {
+    Once(Response<io::Cursor<Vec<u8>>>),
+    /// Streams the device with the serial number, see [`Shared::stream`]
+    Stream(String, Device),
+}
+
+impl Shared {
+    fn handle(&self, mut request: Request) {
+        let response = match self.serve(&mut request) {
+            Ok(Reply::Once(response)) => response,
+            Ok(Reply::Stream(serial, device)) => return self.stream(request, &serial, &device),
+            Err(Failure(status, message)) => {
+                json(format!("{{\"error\": {:?}}}", message)).with_status_code(status)
+            }
+        };
+        if let Err(err) = request.respond(response) {
+            log::warn!("server: {}", err);
+        }
+    }
+
+    fn serve(&self, request: &mut Request) -> Result<Reply, Failure> {
+        if !authorized(self.token.as_deref(), request.headers()) {
+            return Err(Failure(401, "missing or wrong bearer token".to_owned()));
+        }
+        let url = request.url().to_owned();
+        let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+        match route(request.method(), path) {
+            Some(Route::Stream(serial)) => {
+                Ok(Reply::Stream(serial.to_owned(), self.device(serial)?))
+            }
+            Some(route) => self.answer(route, query, request).map(Reply::Once),
+            None => Err(Failure(
+                404,
+                format!("no route for {} {}", request.method(), path),
+            )),
+        }
+    }
+
+    fn answer(&self, route: Route, query: &str, request: &mut Request) -> Answer {
+        match route {
+            Route::Devices => Ok(json(inventory::export(inventory::Format::Json)?)),
+            Route::Close(serial) => {
+                let device = self.lock_devices().remove(serial);
+                // An open stream still holds the device; it notices that the
+                // device is gone and the handle closes when it lets go
+                if let Some(Ok(device)) = device.map(Arc::try_unwrap) {
+                    device
+                        .into_inner()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .close()?;
+                }
+                Ok(json("{}".to_owned()))
+            }
+            Route::Write(serial) => {
+                let mut data = Vec::new();
+                request
+                    .as_reader()
+                    .read_to_end(&mut data)
+                    .map_err(|err| Failure(400, err.to_string()))?;
+                let written = lock(&self.device(serial)?).write(&data)?;
+                Ok(json(format!("{{\"written\": {}}}", written)))
+            }
+            Route::Read(serial) => {
+                let len = param(query, "len")?.unwrap_or(4096).min(MAX_READ as u64);
+                let data = lock(&self.device(serial)?).read(len as usize)?;
+                Ok(Response::from_data(data).with_header(content_type("application/octet-stream")))
+            }
+            Route::Gpio(serial) => {
+                let latch = lock(&self.device(serial)?).read_gpio_latch()?;
+                Ok(json(format!("{{\"latch\": {}}}", latch)))
+            }
+            Route::SetGpio(serial) => {
+                let mask = byte(required(query, "mask")?)?;
+                let state = byte(required(query, "state")?)?;
+                lock(&self.device(serial)?).write_gpio_latch(mask, state)?;
+                Ok(json("{}".to_owned()))
+            }
+            Route::Baud(serial) => {
+                let rate = u32::try_from(required(query, "rate")?)
+                    .map_err(|err| Failure(400, err.to_string()))?;
+                let line_control = line_control(query)?;
+                let flow_control = flow_control(query)?;
+                let device = self.device(serial)?;
+                let mut device = lock(&device);
+                device.set_baud_rate(rate)?;
+                if let Some(line_control) = line_control {
+                    device.set_line_control(line_control)?;
+                }
+                if let Some(flow_control) = flow_control {
+                    device.set_flow_control(flow_control)?;
+                }
+                Ok(json("{}".to_owned()))
+            }
+            Route::Stream(_) => unreachable!("streams are not answered with a single response"),
+        }
+    }
+
+    /// Sends the data of `device` as it arrives, in a chunked body
+    fn stream(&self, request: Request, serial: &str, device: &Device) {
+        let mut out = request.into_writer();
+        if let Err(err) = self.pump(serial, device, &mut out) {
+            log::debug!("server: stream of {} ended: {}", serial, err);
+        }
+    }
+
+    fn pump(&self, serial: &str, device: &Device, out: &mut dyn Write) -> io::Result<()> {
+        out.write_all(
+            b"HTTP/1.1 200 OK\r\n\
+              Content-Type: application/octet-stream\r\n\
+              Transfer-Encoding: chunked\r\n\r\n",
+        )?;
+        out.flush()?;
+        let mut buf = vec![0; STREAM_CHUNK];
+        while self.is_open(serial, device) {
+            let received = match lock(device).read_into_timeout(&mut buf, STREAM_POLL) {
+                Ok(received) => received,
+                Err(SilabsUsbXpressError::ReadTimeOut { transferred, .. }) => transferred,
+                Err(err) => {
+                    log::warn!("server: stream of {}: {}", serial, err);
+                    break;
+                }
+            };
+            if received > 0 {
+                write!(out, "{:x}\r\n", received)?;
+                out.write_all(&buf[..received])?;
+                out.write_all(b"\r\n")?;
+                out.flush()?;
+            }
+        }
+        out.write_all(b"0\r\n\r\n")?;
+        out.flush()
+    }
+
+    /// Whether `device` is still the open handle of `serial`
+    fn is_open(&self, serial: &str, device: &Device) -> bool {
+        self.lock_devices()
+            .get(serial)
+            .is_some_and(|open| Arc::ptr_eq(open, device))
+    }
+
+    /// Returns the open handle of the device with serial number `serial`,
+    /// opening it first if needed
+    ///
+    /// The device is opened without holding the map, so requests to devices
+    /// already open are not held up meanwhile. If another request opened it
+    /// first, that handle is kept.
+    fn device(&self, serial: &str) -> Result<Device, Failure> {
+        if let Some(device) = self.lock_devices().get(serial) {
+            return Ok(device.clone());
+        }
+        let opened = UsbXpress::open_by_serial(serial);
+        let mut devices = self.lock_devices();
+        match opened {
+            Ok(handle) => Ok(devices
+                .entry(serial.to_owned())
+                .or_insert(Arc::new(Mutex::new(handle)))
+                .clone()),
+            // Opening fails while a handle another request just opened holds it
+            Err(err) => devices.get(serial).cloned().ok_or_else(|| err.into()),
+        }
+    }
+
+    fn lock_devices(&self) -> MutexGuard<'_, HashMap<String, Device>> {
+        self.devices
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn lock(device: &Device) -> MutexGuard<'_, UsbXpress> {
+    device
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Whether `headers` carry the bearer `token`, if the server requires one
+fn authorized(token: Option<&str>, headers: &[Header]) -> bool {
+    let token = match token {
+        Some(token) => token,
+        None => return true,
+    };
+    headers
+        .iter()
+        .find(|header| header.field.equiv("Authorization"))
+        .and_then(|header| header.value.as_str().strip_prefix("Bearer "))
+        == Some(token)
+}
+
+fn json(body: String) -> Response<io::Cursor<Vec<u8>>> {
+    Response::from_data(body.into_bytes()).with_header(content_type("application/json"))
+}
+
+fn content_type(value: &str) -> Header {
+    Header::from_bytes(&b"Content-Type"[..], value.as_bytes()).unwrap()
+}
+
+/// Returns the raw value of `name` in `query`
+fn text<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// Returns the value of `name` in `query`, decimal or `0x` hexadecimal
+fn param(query: &str, name: &str) -> Result<Option<u64>, Failure> {
+    let value = match text(query, name) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let parsed = match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+    parsed.map(Some).map_err(|_| invalid(name, value))
+}
+
+fn required(query: &str, name: &str) -> Result<u64, Failure> {
+    param(query, name)?.ok_or_else(|| Failure(400, format!("missing {}", name)))
+}
+
+fn byte(value: u64) -> Result<u8, Failure> {
+    u8::try_from(value).map_err(|err| Failure(400, err.to_string()))
+}
+
+fn invalid(name: &str, value: &str) -> Failure {
+    Failure(400, format!("invalid {}: {:?}", name, value))
+}
+
+/// The framing asked for by a `/baud` query, if it asks for any
+fn line_control(query: &str) -> Result<Option<LineControl>, Failure> {
+    let data_bits = text(query, "data_bits");
+    let parity = text(query, "parity");
+    let stop_bits = text(query, "stop_bits");
+    if data_bits.is_none() && parity.is_none() && stop_bits.is_none() {
+        return Ok(None);
+    }
+    let mut line_control = LineControl::new();
+    if let Some(value) = data_bits {
+        line_control = line_control.data_bits(match value {
+            "5" => DataBits::Five,
+            "6" => DataBits::Six,
+            "7" => DataBits::Seven,
+            "8" => DataBits::Eight,
+            _ => return Err(invalid("data_bits", value)),
+        });
+    }
+    if let Some(value) = parity {
+        line_control = line_control.parity(match value {
+            "none" => Parity::None,
+            "odd" => Parity::Odd,
+            "even" => Parity::Even,
+            "mark" => Parity::Mark,
+            "space" => Parity::Space,
+            _ => return Err(invalid("parity", value)),
+        });
+    }
+    if let Some(value) = stop_bits {
+        line_control = line_control.stop_bits(match value {
+            "1" => StopBits::One,
+            "1.5" => StopBits::OneAndAHalf,
+            "2" => StopBits::Two,
+            _ => return Err(invalid("stop_bits", value)),
+        });
+    }
+    Ok(Some(line_control))
+}
+
+/// The flow control asked for by a `/baud` query, if it asks for any
+fn flow_control(query: &str) -> Result<Option<FlowControl>, Failure> {
+    let flow_control = match text(query, "flow") {
+        None => return Ok(None),
+        Some("none") => FlowControl::none(),
+        Some("rts_cts") => FlowControl::rts_cts(),
+        Some("dtr_dsr") => FlowControl::dtr_dsr(),
+        Some(value) => return Err(invalid("flow", value)),
+    };
+    Ok(Some(flow_control))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_parameters_parse() {
+        assert_eq!(param("len=16&mask=0x0f", "mask").ok(), Some(Some(15)));
+        assert_eq!(param("len=16", "len").ok(), Some(Some(16)));
+        assert_eq!(param("len=16", "rate").ok(), Some(None));
+        assert!(param("len=lots", "len").is_err());
+    }
+
+    #[test]
+    fn requests_are_routed() {
+        assert_eq!(route(&Method::Get, "/devices"), Some(Route::Devices));
+        assert_eq!(route(&Method::Get, "/devices/"), Some(Route::Devices));
+        assert_eq!(
+            route(&Method::Delete, "/devices/0001"),
+            Some(Route::Close("0001"))
+        );
+        assert_eq!(
+            route(&Method::Post, "/devices/0001/write"),
+            Some(Route::Write("0001"))
+        );
+        assert_eq!(
+            route(&Method::Get, "/devices/0001/read"),
+            Some(Route::Read("0001"))
+        );
+        assert_eq!(
+            route(&Method::Get, "/devices/0001/stream"),
+            Some(Route::Stream("0001"))
+        );
+        assert_eq!(
+            route(&Method::Get, "/devices/0001/gpio"),
+            Some(Route::Gpio("0001"))
+        );
+        assert_eq!(
+            route(&Method::Post, "/devices/0001/gpio"),
+            Some(Route::SetGpio("0001"))
+        );
+        assert_eq!(
+            route(&Method::Post, "/devices/0001/baud"),
+            Some(Route::Baud("0001"))
+        );
+    }
+
+    #[test]
+    fn unknown_requests_have_no_route() {
+        assert_eq!(route(&Method::Post, "/devices"), None);
+        assert_eq!(route(&Method::Get, "/devices/0001/write"), None);
+        assert_eq!(route(&Method::Put, "/devices/0001/baud"), None);
+        assert_eq!(route(&Method::Get, "/devices/0001/flash"), None);
+        assert_eq!(route(&Method::Get, "/"), None);
+    }
+
+    #[test]
+    fn device_errors_map_to_status_codes() {
+        let status = |err| Failure::from(err).0;
+        assert_eq!(status(SilabsUsbXpressError::DeviceNotFound), 404);
+        assert_eq!(
+            status(SilabsUsbXpressError::SerialNotFound("0001".into())),
+            404
+        );
+        assert_eq!(status(SilabsUsbXpressError::DeviceBusy), 409);
+        assert_eq!(status(SilabsUsbXpressError::OpenTimedOut), 504);
+        assert_eq!(
+            status(SilabsUsbXpressError::ReadTimeOut {
+                elapsed: Duration::from_millis(10),
+                transferred: 0,
+                remaining: 1,
+            }),
+            504
+        );
+        assert_eq!(status(SilabsUsbXpressError::ReadError), 500);
+    }
+
+    #[test]
+    fn tokens_are_checked_when_required() {
+        let bearer = |value: &str| {
+            vec![Header::from_bytes(&b"Authorization"[..], value.as_bytes()).unwrap()]
+        };
+        assert!(authorized(None, &[]));
+        assert!(authorized(Some("sesame"), &bearer("Bearer sesame")));
+        assert!(!authorized(Some("sesame"), &[]));
+        assert!(!authorized(Some("sesame"), &bearer("Bearer open")));
+        assert!(!authorized(Some("sesame"), &bearer("sesame")));
+    }
+
+    #[test]
+    fn baud_queries_configure_the_uart() {
+        assert_eq!(line_control("rate=9600").ok(), Some(None));
+        assert_eq!(
+            line_control("rate=9600&data_bits=7&parity=even").ok(),
+            Some(Some(
+                LineControl::new()
+                    .data_bits(DataBits::Seven)
+                    .parity(Parity::Even)
+            ))
+        );
+        assert_eq!(
+            line_control("stop_bits=2").ok(),
+            Some(Some(LineControl::new().stop_bits(StopBits::Two)))
+        );
+        assert!(line_control("data_bits=9").is_err());
+        assert!(line_control("parity=sometimes").is_err());
+        assert_eq!(flow_control("rate=9600").ok(), Some(None));
+        assert_eq!(
+            flow_control("flow=rts_cts").ok(),
+            Some(Some(FlowControl::rts_cts()))
+        );
+        assert!(flow_control("flow=xon_xoff").is_err());
+    }
+}
@@ -0,0 +1,455 @@
+//! Regression tests against scripted device conversations
+//!
+//! A [`Transcript`] is the conversation a piece of host code is expected to
+//! have with a device: what it writes, what the device replies and how quickly
+//! the host must react. [`assert_transcript!`](crate::assert_transcript) runs
+//! host code against a [`MockDevice`] playing the device's part and fails the
+//! test on the first deviation, so a conversation captured from a field
+//! incident becomes a permanent regression test:
+//!
+//! ```rust, ignore
+//! use silabs_usb_xpress::{assert_transcript, transcript::Transcript};
+//!
+//! let transcript = Transcript::new()
+//!     .write(b"PING\n")
+//!     .reply(b"PONG\n")
+//!     .write(b"GET 1\n")
+//!     .within(Duration::from_millis(50));
+//! assert_transcript!(transcript, |device| poll_sensor(device));
+//! ```
+//!
+//! The host code sees the mock as a [`Transport`], so this works for code
+//! written against the `Transport` trait rather than a concrete handle.
+//!
+//! Timeout and race bugs usually depend on when data arrives rather than
+//! on what it is. [`Transcript::reply_after`] holds a reply back like the
+//! device did, and with a [read timeout](Transcript::read_timeout) the mock
+//! waits for it the way a device read would, so the original inter-frame
+//! delays are reproduced. [`Transcript::time_scale`] stretches or shrinks
+//! all of them at once.
+use std::{
+    collections::VecDeque,
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+use crate::{transport::Transport, SilabsUsbXpressError};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Event {
+    Write {
+        data: Vec<u8>,
+        within: Option<Duration>,
+    },
+    Reply {
+        data: Vec<u8>,
+        delay: Duration,
+    },
+}
+
+/// The expected conversation with a device
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transcript {
+    events: Vec<Event>,
+    time_scale: f64,
+    read_timeout: Duration,
+}
+
+impl Default for Transcript {
+    fn default() -> Self {
+        Transcript {
+            events: Vec::new(),
+            time_scale: 1.0,
+            read_timeout: Duration::ZERO,
+        }
+    }
+}
+
+impl Transcript {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Expects the host to write `data` next, in any number of writes
+    pub fn write(mut self, data: &[u8]) -> Self {
+        self.events.push(Event::Write {
+            data: data.to_vec(),
+            within: None,
+        });
+        self
+    }
+
+    /// Makes the device send `data` once the host wrote everything expected
+    /// before it
+    pub fn reply(self, data: &[u8]) -> Self {
+        self.reply_after(Duration::ZERO, data)
+    }
+
+    /// Makes the device send `data` `delay` after the reply before it, or
+    /// after the host wrote everything expected before it if this is the
+    /// first reply since
+    ///
+    /// Consecutive calls reproduce the gaps between the frames, or even the
+    /// bytes, of a recorded response.
+    pub fn reply_after(mut self, delay: Duration, data: &[u8]) -> Self {
+        self.events.push(Event::Reply {
+            data: data.to_vec(),
+            delay,
+        });
+        self
+    }
+
+    /// Multiplies every reply delay by `factor`, so `2.0` plays the device
+    /// at half speed; limits set with [`within`](Transcript::within) are
+    /// not scaled
+    ///
+    /// # Panics
+    ///
+    /// Panics if `factor` is negative, infinite or NaN.
+    pub fn time_scale(mut self, factor: f64) -> Self {
+        assert!(
+            factor.is_finite() && factor >= 0.0,
+            "time_scale() needs a finite, non-negative factor, got {}",
+            factor
+        );
+        self.time_scale = factor;
+        self
+    }
+
+    /// Makes reads wait up to `timeout` for a reply that is not due yet, as
+    /// a device read would, before failing with `ReadTimeOut`; zero by
+    /// default, which never waits
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Requires the write added last to be complete within `limit` of the
+    /// previous write, or of the start if there is none
+    ///
+    /// # Panics
+    ///
+    /// Panics if the last event added is not a write.
+    pub fn within(mut self, limit: Duration) -> Self {
+        match self.events.last_mut() {
+            Some(Event::Write { within, .. }) => *within = Some(limit),
+            _ => panic!("within() must follow write()"),
+        }
+        self
+    }
+
+    /// Runs `test` against a mock device following this transcript
+    ///
+    /// Returns the deviations, the first of which ends the conversation, or
+    /// writes the host left out.
+    pub fn run<F: FnOnce(&mut MockDevice)>(&self, test: F) -> Result<(), Mismatch> {
+        let mut device = MockDevice::new(self);
+        test(&mut device);
+        device.finish()
+    }
+}
+
+/// How the host deviated from a [`Transcript`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// The host wrote `written` where `expected` was due
+    UnexpectedWrite { expected: Vec<u8>, written: Vec<u8> },
+    /// The host wrote `written` after the transcript ended
+    ExtraWrite(Vec<u8>),
+    /// The expected write took `took`, more than `limit`
+    TooSlow {
+        expected: Vec<u8>,
+        limit: Duration,
+        took: Duration,
+    },
+    /// The host never wrote `expected`, and everything after it
+    MissingWrite(Vec<u8>),
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::UnexpectedWrite { expected, written } => write!(
+                f,
+                "expected write {:02x?}, host wrote {:02x?}",
+                expected, written
+            ),
+            Mismatch::ExtraWrite(written) => {
+                write!(f, "host wrote {:02x?} after the transcript ended", written)
+            }
+            Mismatch::TooSlow {
+                expected,
+                limit,
+                took,
+            } => write!(
+                f,
+                "write {:02x?} took {:?}, limit is {:?}",
+                expected, took, limit
+            ),
+            Mismatch::MissingWrite(expected) => {
+                write!(f, "host never wrote {:02x?}", expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+/// A device playing its part of a [`Transcript`]
+///
+/// Reads return the replies that are due, or `ReadTimeOut` if there are
+/// none within the read timeout. Once the host deviates, every write fails
+/// with `WriteError`.
+#[derive(Debug)]
+pub struct MockDevice {
+    events: Vec<Event>,
+    time_scale: f64,
+    read_timeout: Duration,
+    next: usize,
+    /// Bytes of the current expected write received so far
+    written: Vec<u8>,
+    /// Replies released by the host's writes and when they are due
+    pending: VecDeque<(Instant, Vec<u8>)>,
+    replies: VecDeque<u8>,
+    last_write: Instant,
+    mismatch: Option<Mismatch>,
+}
+
+impl MockDevice {
+    fn new(transcript: &Transcript) -> Self {
+        let mut device = MockDevice {
+            events: transcript.events.clone(),
+            time_scale: transcript.time_scale,
+            read_timeout: transcript.read_timeout,
+            next: 0,
+            written: Vec::new(),
+            pending: VecDeque::new(),
+            replies: VecDeque::new(),
+            last_write: Instant::now(),
+            mismatch: None,
+        };
+        device.release_replies();
+        device
+    }
+
+    /// Schedules the replies up to the next expected write
+    fn release_replies(&mut self) {
+        let mut due = Instant::now();
+        while let Some(Event::Reply { data, delay }) = self.events.get(self.next) {
+            due += delay.mul_f64(self.time_scale);
+            self.pending.push_back((due, data.clone()));
+            self.next += 1;
+        }
+    }
+
+    /// Makes the replies due by `now` readable
+    fn deliver(&mut self, now: Instant) {
+        while let Some((due, _)) = self.pending.front() {
+            if *due > now {
+                break;
+            }
+            let (_, data) = self.pending.pop_front().unwrap();
+            self.replies.extend(data);
+        }
+    }
+
+    fn accept(&mut self, data: &[u8]) -> Result<(), Mismatch> {
+        self.written.extend_from_slice(data);
+        while !self.written.is_empty() {
+            let (expected, within) = match self.events.get(self.next) {
+                Some(Event::Write { data, within }) => (data.clone(), *within),
+                _ => return Err(Mismatch::ExtraWrite(self.written.split_off(0))),
+            };
+            let matched = self.written.len().min(expected.len());
+            if self.written[..matched] != expected[..matched] {
+                return Err(Mismatch::UnexpectedWrite {
+                    expected,
+                    written: self.written.split_off(0),
+                });
+            }
+            if matched < expected.len() {
+                break;
+            }
+            let took = self.last_write.elapsed();
+            if let Some(limit) = within.filter(|&limit| took > limit) {
+                return Err(Mismatch::TooSlow {
+                    expected,
+                    limit,
+                    took,
+                });
+            }
+            self.written.drain(..matched);
+            self.last_write = Instant::now();
+            self.next += 1;
+            self.release_replies();
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), Mismatch> {
+        if let Some(mismatch) = self.mismatch {
+            return Err(mismatch);
+        }
+        let missing: Vec<u8> = self.events[self.next..]
+            .iter()
+            .filter_map(|event| match event {
+                Event::Write { data, .. } => Some(data.as_slice()),
+                Event::Reply { .. } => None,
+            })
+            .flatten()
+            .copied()
+            .collect();
+        if missing.len() > self.written.len() {
+            return Err(Mismatch::MissingWrite(
+                missing[self.written.len()..].to_vec(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Transport for MockDevice {
+    fn read(&mut self, max: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        let started = Instant::now();
+        self.deliver(started);
+        if self.replies.is_empty() {
+            let deadline = started + self.read_timeout;
+            match self.pending.front() {
+                Some(&(due, _)) if due <= deadline => {
+                    thread::sleep(due.saturating_duration_since(started));
+                    self.deliver(due);
+                }
+                _ => {
+                    thread::sleep(self.read_timeout);
+                    return Err(SilabsUsbXpressError::ReadTimeOut {
+                        elapsed: started.elapsed(),
+                        transferred: 0,
+                        remaining: max,
+                    });
+                }
+            }
+        }
+        let n = max.min(self.replies.len());
+        Ok(self.replies.drain(..n).collect())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+        if self.mismatch.is_some() {
+            return Err(SilabsUsbXpressError::WriteError);
+        }
+        match self.accept(data) {
+            Ok(()) => Ok(data.len()),
+            Err(mismatch) => {
+                self.mismatch = Some(mismatch);
+                Err(SilabsUsbXpressError::WriteError)
+            }
+        }
+    }
+}
+
+/// Runs host code against a [`Transcript`] and panics on any deviation
+///
+/// The first argument is the transcript, the second a closure taking a
+/// `&mut MockDevice`. See the [`transcript`](crate::transcript) module.
+#[macro_export]
+macro_rules! assert_transcript {
+    ($transcript:expr, $test:expr $(,)?) => {
+        if let Err(mismatch) = $crate::transcript::Transcript::run(&$transcript, $test) {
+            panic!("transcript mismatch: {}", mismatch);
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ping(device: &mut MockDevice) {
+        device.write(b"PI").unwrap();
+        device.write(b"NG\n").unwrap();
+        let pong = device.read(16).unwrap();
+        device.write(&pong.to_ascii_lowercase()).unwrap();
+    }
+
+    #[test]
+    fn transcripts_check_writes_and_timing() {
+        let transcript = Transcript::new()
+            .reply(b"READY\n")
+            .write(b"PING\n")
+            .reply(b"PONG\n")
+            .write(b"pong\n")
+            .within(Duration::from_secs(5));
+        assert_transcript!(transcript, |device| {
+            assert_eq!(device.read(16).unwrap(), b"READY\n");
+            ping(device);
+        });
+
+        let slow = Transcript::new()
+            .write(b"PING\n")
+            .within(Duration::from_millis(1));
+        let result = slow.run(|device| {
+            std::thread::sleep(Duration::from_millis(5));
+            let _ = device.write(b"PING\n");
+        });
+        assert!(matches!(result, Err(Mismatch::TooSlow { .. })));
+
+        let result = Transcript::new().write(b"PING\n").run(|device| {
+            assert!(device.write(b"PONG\n").is_err());
+        });
+        assert_eq!(
+            result,
+            Err(Mismatch::UnexpectedWrite {
+                expected: b"PING\n".to_vec(),
+                written: b"PONG\n".to_vec()
+            })
+        );
+        let result = Transcript::new()
+            .write(b"PING\n")
+            .write(b"X")
+            .run(|device| {
+                device.write(b"PI").unwrap();
+            });
+        assert_eq!(result, Err(Mismatch::MissingWrite(b"NG\nX".to_vec())));
+    }
+
+    #[test]
+    fn replies_keep_their_delays() {
+        let gap = Duration::from_millis(20);
+        let transcript = Transcript::new()
+            .write(b"GET\n")
+            .reply(b"HEAD")
+            .reply_after(gap, b"TAIL");
+        transcript
+            .run(|device| {
+                device.write(b"GET\n").unwrap();
+                assert_eq!(device.read(16).unwrap(), b"HEAD");
+                assert!(device.read(16).is_err());
+            })
+            .unwrap();
+
+        let transcript = transcript
+            .read_timeout(Duration::from_secs(1))
+            .time_scale(2.0);
+        transcript
+            .run(|device| {
+                device.write(b"GET\n").unwrap();
+                let started = Instant::now();
+                assert_eq!(device.read(16).unwrap(), b"HEAD");
+                assert_eq!(device.read(16).unwrap(), b"TAIL");
+                assert!(started.elapsed() >= gap * 2);
+            })
+            .unwrap();
+    }
+
+    #[test]
+    #[should_panic(expected = "non-negative factor")]
+    fn negative_time_scales_are_refused() {
+        let _ = Transcript::new().time_scale(-1.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "non-negative factor")]
+    fn nan_time_scales_are_refused() {
+        let _ = Transcript::new().time_scale(f64::NAN);
+    }
+}
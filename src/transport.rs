@@ -0,0 +1,196 @@
+//! Addressing devices of any backend by URI
+//!
+//! A [`Transport`] is anything bytes can be written to and read from. USB
+//! devices opened by this crate are transports, and applications can add
+//! their own, such as a proxy to devices on another host or a simulator, by
+//! registering a [`Connector`] for a URI scheme. [`open`] then picks the
+//! backend from the device specifier:
+//!
+//! | Specifier                      | Device                                  |
+//! | ----                           | ----                                    |
+//! | `usb://0001A2F3`               | USB device with this serial number      |
+//! | `usb:///dev/bus/usb/001/004`   | USB device at this device node          |
+//! | `remote://host/0001A2F3`       | whatever the `remote` connector returns |
+//!
+//! ```rust, ignore
+//! use silabs_usb_xpress::transport;
+//!
+//! transport::register("mock", |_: &str| Ok(Box::new(Loopback::default()) as _));
+//! let mut device = transport::open("mock://sensor")?;
+//! device.write(b"PING")?;
+//! ```
+use std::{collections::BTreeMap, sync::RwLock};
+
+use crate::{SilabsUsbXpressError, UsbXpress};
+
+/// A byte stream to a device
+pub trait Transport: Send {
+    /// Reads up to `max` bytes, waiting at most the transport's read timeout
+    fn read(&mut self, max: usize) -> Result<Vec<u8>, SilabsUsbXpressError>;
+
+    /// Writes `data` and returns the number of bytes written
+    fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError>;
+
+    /// Returns how many written bytes the device has yet to send on
+    ///
+    /// Transports that cannot tell report none, as if data left as soon as
+    /// it was written.
+    fn pending_output(&mut self) -> Result<usize, SilabsUsbXpressError> {
+        Ok(0)
+    }
+
+    /// Reads up to `buf.len()` bytes into `buf` and returns how many arrived
+    ///
+    /// By default this copies what [`read`](Transport::read) returns;
+    /// transports that can receive in place override it.
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, SilabsUsbXpressError> {
+        let data = self.read(buf.len())?;
+        let n = data.len().min(buf.len());
+        buf[..n].copy_from_slice(&data[..n]);
+        Ok(n)
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+    fn read(&mut self, max: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        (**self).read(max)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+        (**self).write(data)
+    }
+
+    fn pending_output(&mut self) -> Result<usize, SilabsUsbXpressError> {
+        (**self).pending_output()
+    }
+
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, SilabsUsbXpressError> {
+        (**self).read_into(buf)
+    }
+}
+
+impl Transport for UsbXpress {
+    fn read(&mut self, max: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        UsbXpress::read(self, max)
+    }
+
+    fn read_into(&mut self, buf: &mut [u8]) -> Result<usize, SilabsUsbXpressError> {
+        UsbXpress::read_into(self, buf)
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+        UsbXpress::write(self, data)
+    }
+
+    fn pending_output(&mut self) -> Result<usize, SilabsUsbXpressError> {
+        self.tx_queue_len()
+    }
+}
+
+/// Opens a transport from the part of a specifier after `scheme://`
+pub trait Connector: Send + Sync {
+    fn connect(&self, address: &str) -> Result<Box<dyn Transport>, SilabsUsbXpressError>;
+}
+
+impl<F> Connector for F
+where
+    F: Fn(&str) -> Result<Box<dyn Transport>, SilabsUsbXpressError> + Send + Sync,
+{
+    fn connect(&self, address: &str) -> Result<Box<dyn Transport>, SilabsUsbXpressError> {
+        self(address)
+    }
+}
+
+static CONNECTORS: RwLock<BTreeMap<String, Box<dyn Connector>>> = RwLock::new(BTreeMap::new());
+
+/// Opens devices with specifiers starting with `scheme://` with `connector`,
+/// replacing any connector registered for `scheme` before
+///
+/// Registering `usb` replaces the built-in USB backend.
+pub fn register<S: Into<String>, C: Connector + 'static>(scheme: S, connector: C) {
+    CONNECTORS
+        .write()
+        .unwrap()
+        .insert(scheme.into(), Box::new(connector));
+}
+
+/// Removes the connector registered for `scheme`
+pub fn unregister(scheme: &str) {
+    CONNECTORS.write().unwrap().remove(scheme);
+}
+
+/// Opens the device named by `spec`, see the [module documentation](self)
+///
+/// Specifiers without a registered scheme, or without a scheme at all, are
+/// reported as `DeviceNotFound`.
+pub fn open(spec: &str) -> Result<Box<dyn Transport>, SilabsUsbXpressError> {
+    let (scheme, address) = spec
+        .split_once("://")
+        .ok_or(SilabsUsbXpressError::DeviceNotFound)?;
+    if let Some(connector) = CONNECTORS.read().unwrap().get(scheme) {
+        return connector.connect(address);
+    }
+    match scheme {
+        "usb" => Ok(Box::new(open_usb(address)?)),
+        _ => {
+            log::debug!("no transport registered for {:?}", scheme);
+            Err(SilabsUsbXpressError::DeviceNotFound)
+        }
+    }
+}
+
+/// Opens a USB device by device node or, with the `enumeration` feature, by
+/// serial number, failing like [`UsbXpress::open_by_serial`]
+fn open_usb(address: &str) -> Result<UsbXpress, SilabsUsbXpressError> {
+    if address.starts_with('/') {
+        return UsbXpress::open_path(address);
+    }
+    #[cfg(feature = "enumeration")]
+    {
+        UsbXpress::open_by_serial(address)
+    }
+    #[cfg(not(feature = "enumeration"))]
+    {
+        Err(SilabsUsbXpressError::DeviceNotFound)
+    }
+}
+
+/// A transport reading back what was written to it, for tests
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct Loopback(pub(crate) Vec<u8>);
+
+#[cfg(test)]
+impl Transport for Loopback {
+    fn read(&mut self, max: usize) -> Result<Vec<u8>, SilabsUsbXpressError> {
+        let n = max.min(self.0.len());
+        Ok(self.0.drain(..n).collect())
+    }
+
+    fn write(&mut self, data: &[u8]) -> Result<usize, SilabsUsbXpressError> {
+        self.0.extend_from_slice(data);
+        Ok(data.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registered_schemes_are_opened_by_their_connector() {
+        register("loopback-test", |_: &str| {
+            Ok(Box::new(Loopback::default()) as Box<dyn Transport>)
+        });
+        let mut device = open("loopback-test://anything").unwrap();
+        assert_eq!(device.write(b"PING").unwrap(), 4);
+        assert_eq!(device.read(16).unwrap(), b"PING");
+
+        unregister("loopback-test");
+        assert!(matches!(
+            open("loopback-test://anything"),
+            Err(SilabsUsbXpressError::DeviceNotFound)
+        ));
+        assert!(open("no scheme").is_err());
+    }
+}
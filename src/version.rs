@@ -0,0 +1,162 @@
+//! Versions of the library and the USB stack underneath, for bug reports
+//!
+//! ```rust, ignore
+//! use silabs_usb_xpress::{library_version, UsbXpress};
+//!
+//! eprintln!("{}", library_version());
+//! let handle = UsbXpress::open(0)?;
+//! eprintln!("{}", handle.driver_info());
+//! ```
+use std::fmt;
+
+/// The implementation of the SiUSBXp functions the crate is built with
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The bundled `SiUSBXp.c`, on top of libusb 0.1 or libusb-compat
+    Bundled,
+    /// The `rust-backend` feature, on top of libusb-1.0 through rusb
+    Rust,
+    /// The `nusb-backend` feature, on top of the operating system's USB
+    /// API through nusb
+    Nusb,
+}
+
+impl Backend {
+    /// The backend this build uses
+    pub fn current() -> Self {
+        if cfg!(feature = "rust-backend") {
+            Backend::Rust
+        } else if cfg!(feature = "nusb-backend") {
+            Backend::Nusb
+        } else {
+            Backend::Bundled
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Backend::Bundled => write!(f, "SiUSBXp.c on libusb 0.1"),
+            Backend::Rust => write!(f, "rusb on libusb-1.0"),
+            Backend::Nusb => write!(f, "nusb"),
+        }
+    }
+}
+
+/// Versions returned by [`library_version`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LibraryVersion {
+    /// Version of this crate, which provides the SiUSBXp shim
+    pub shim: &'static str,
+    pub backend: Backend,
+    /// Version of libusb, `None` if it cannot be told
+    ///
+    /// With the `rust-backend` feature this is the libusb-1.0 loaded at run
+    /// time, as reported by `libusb_get_version`. libusb 0.1 has no such
+    /// call, so the bundled backend reports the version `pkg-config` found
+    /// at build time. The `nusb-backend` feature does not use libusb.
+    pub libusb: Option<String>,
+}
+
+impl fmt::Display for LibraryVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "silabs_usb_xpress {} ({}", self.shim, self.backend)?;
+        match &self.libusb {
+            Some(libusb) => write!(f, " {})", libusb),
+            None if self.backend == Backend::Nusb => write!(f, ", without libusb)"),
+            None => write!(f, ", unknown version)"),
+        }
+    }
+}
+
+/// Returns the versions of this library and of libusb
+pub fn library_version() -> LibraryVersion {
+    LibraryVersion {
+        shim: env!("CARGO_PKG_VERSION"),
+        backend: Backend::current(),
+        libusb: libusb_version(),
+    }
+}
+
+#[cfg(feature = "rust-backend")]
+fn libusb_version() -> Option<String> {
+    let version = rusb::version();
+    Some(format!(
+        "{}.{}.{}.{}{}",
+        version.major(),
+        version.minor(),
+        version.micro(),
+        version.nano(),
+        version.rc().unwrap_or("")
+    ))
+}
+
+#[cfg(all(feature = "nusb-backend", not(feature = "rust-backend")))]
+fn libusb_version() -> Option<String> {
+    None
+}
+
+#[cfg(not(any(feature = "rust-backend", feature = "nusb-backend")))]
+fn libusb_version() -> Option<String> {
+    option_env!("SIUSBXP_LIBUSB_VERSION").map(str::to_owned)
+}
+
+/// The driver stack of an open device, see
+/// [`UsbXpress::driver_info`](crate::UsbXpress::driver_info)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DriverInfo {
+    pub backend: Backend,
+    /// Kernel driver bound to the device once it was opened, `None` where
+    /// libusb cannot tell, which includes the `rust-backend` feature
+    ///
+    /// On Linux this is `usbfs` while libusb, or nusb with the
+    /// `nusb-backend` feature, holds the interface.
+    pub kernel_driver: Option<String>,
+}
+
+impl fmt::Display for DriverInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, kernel driver {}",
+            self.backend,
+            self.kernel_driver.as_deref().unwrap_or("unknown")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_read_well_in_bug_reports() {
+        let version = LibraryVersion {
+            shim: "0.3.1",
+            backend: Backend::Rust,
+            libusb: Some("1.0.27.11882".to_owned()),
+        };
+        assert_eq!(
+            version.to_string(),
+            "silabs_usb_xpress 0.3.1 (rusb on libusb-1.0 1.0.27.11882)"
+        );
+        let driver = DriverInfo {
+            backend: Backend::Bundled,
+            kernel_driver: None,
+        };
+        assert_eq!(
+            driver.to_string(),
+            "SiUSBXp.c on libusb 0.1, kernel driver unknown"
+        );
+        let version = LibraryVersion {
+            backend: Backend::Nusb,
+            libusb: None,
+            ..version
+        };
+        assert_eq!(
+            version.to_string(),
+            "silabs_usb_xpress 0.3.1 (nusb, without libusb)"
+        );
+    }
+}